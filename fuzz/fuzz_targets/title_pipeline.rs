@@ -0,0 +1,38 @@
+#![no_main]
+
+use handlebars::Handlebars;
+use libfuzzer_sys::fuzz_target;
+use polybar_title_module::app::{render, DisplayState, RenderContext};
+use polybar_title_module::{sanitize, templating, Config, WindowProperties};
+
+// Window titles are controlled by whatever is displayed in the window (e.g. web pages), so the whole pipeline from
+// raw property bytes to the printed line has to cope with arbitrary input.
+fuzz_target!(|data: &[u8]| {
+    let mut properties = data.splitn(3, |b| *b == 0).map(String::from_utf8_lossy);
+    let class = properties.next().unwrap_or_default();
+    let instance = properties.next().unwrap_or_default();
+    let name = properties.next().unwrap_or_default();
+
+    let config = Config::default();
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(templating::escape_polybar);
+    handlebars.register_template_string("template", &config.template).unwrap();
+
+    let resolved = config.resolver.resolve_properties(1, WindowProperties {
+        class: &sanitize(&class),
+        instance: &sanitize(&instance),
+        name: &sanitize(&name),
+        role: None,
+        child_process: None,
+        flatpak_id: None,
+        client_machine: None,
+        remote: None,
+        xwayland: None,
+    });
+    let state = DisplayState::default();
+    let mut context = RenderContext::default();
+    context.update(&resolved, &state, &config.resolver);
+
+    let rendered = render(&handlebars, &config, &context, &state).unwrap();
+    assert!(!rendered.contains(char::is_control), "output contains control characters: {rendered:?}");
+});
@@ -0,0 +1,82 @@
+//! Polybar click actions around the output (`[action]` in the config), e.g. closing the window with a middle click or
+//! opening a window switcher with a left click.
+
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use crate::app::RenderContext;
+
+/// The commands run when the output is clicked or scrolled on. Each is a handlebars template with the same variables
+/// as `template`, e.g. `"xdotool windowclose {{ window }}"`.
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Actions {
+    pub left_click: Option<String>,
+    pub middle_click: Option<String>,
+    pub right_click: Option<String>,
+    pub scroll_up: Option<String>,
+    pub scroll_down: Option<String>,
+    pub double_left_click: Option<String>,
+    pub double_middle_click: Option<String>,
+    pub double_right_click: Option<String>,
+}
+
+impl Actions {
+    fn buttons(&self) -> impl Iterator<Item = (u8, &'static str, &str)> {
+        [
+            (1, "action_left_click", &self.left_click),
+            (2, "action_middle_click", &self.middle_click),
+            (3, "action_right_click", &self.right_click),
+            (4, "action_scroll_up", &self.scroll_up),
+            (5, "action_scroll_down", &self.scroll_down),
+            (6, "action_double_left_click", &self.double_left_click),
+            (7, "action_double_middle_click", &self.double_middle_click),
+            (8, "action_double_right_click", &self.double_right_click),
+        ]
+            .into_iter()
+            .filter_map(|(button, name, command)| Some((button, name, command.as_deref()?)))
+    }
+
+    pub fn register(&self, handlebars: &mut Handlebars) -> anyhow::Result<()> {
+        for (_, name, command) in self.buttons() {
+            handlebars.register_template_string(name, command)
+                .with_context(|| format!("failed to register {name} template string"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Wraps `rendered` in polybar's action tags for every command, rendered for `context`. Without any commands,
+    /// or if `rendered` is empty, it's left as is.
+    pub fn wrap(&self, handlebars: &Handlebars, context: &RenderContext, mut rendered: String) -> anyhow::Result<String> {
+        if rendered.is_empty() {
+            return Ok(rendered)
+        }
+
+        for (button, name, _) in self.buttons() {
+            let command = handlebars.render(name, context).with_context(|| format!("failed to render {name}"))?;
+            rendered = format!("%{{A{button}:{}:}}{rendered}%{{A}}", escape(&command));
+        }
+
+        Ok(rendered)
+    }
+}
+
+/// Escapes the colons in `command`, which would otherwise end it early, and the backslashes, so that one right before
+/// a colon doesn't escape the escape.
+fn escape(command: &str) -> String {
+    command.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::truncate::tag_end;
+
+    #[test]
+    fn escaped_commands_end_where_they_should() {
+        for command in [r"notify-send a:b", r"printf '\\'", r"echo C:\", r"echo \:", r"echo {\}\\:}"] {
+            let tag = format!("A1:{}:}}Firefox%{{A}}", escape(command));
+            assert_eq!(&tag[tag_end(&tag).unwrap()..], "}Firefox%{A}", "{command}");
+        }
+    }
+}
@@ -0,0 +1,1955 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use handlebars::Handlebars;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use serde::Serialize;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Property, PropertyNotifyEvent, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use crate::atoms::Atoms;
+use crate::breaker::CircuitBreaker;
+use crate::client::ClientSearch;
+use crate::content::ContentWatcher;
+use crate::control::{Command, ControlServer, Request, Response, Status};
+use crate::desktop::{Desktop, DesktopName};
+use crate::enrich::Enricher;
+use crate::exit::Failure;
+use crate::fade::{Color, Fade};
+use crate::focus::LogicalFocus;
+use crate::hangup::Hangup;
+use crate::highlight::Highlight;
+use crate::hooks::{HookEnv, Hooks};
+use crate::ping::PingTracker;
+use crate::pointer::{PointerTracker, Track};
+use crate::machine::MachineInspector;
+use crate::metrics::{LatencyMetrics, Stage};
+use crate::monitor::Monitor;
+use crate::process::ProcessInspector;
+use crate::quirks::{self, Quirk, Quirks};
+use crate::reconnect::Backoff;
+use crate::record::{Record, Recorder};
+use crate::render_cache::RenderCache;
+use crate::scroll::Marquee;
+use crate::settle::Settle;
+use crate::similar::SimilarOutputs;
+use crate::urgent::UrgentWindows;
+use crate::usage::UsageTracker;
+use crate::watch::ConfigWatcher;
+use crate::output::{self, Output, OutputFormat};
+use crate::persist::{Fingerprint, PersistedState};
+use crate::outputs::ExtraOutputs;
+use crate::wm_state::{WmState, WmStateReader};
+use crate::{filter_template, is_connection_lost, is_window_gone, templating, truncate, window_pid, Config, DesktopBehavior, Filter, NonNullWindow, ResolvedWindow, Resolver, WindowIdentifierKind};
+
+#[derive(Default, Debug)]
+pub struct DisplayState {
+    pub desktop: Option<Desktop>,
+    pub stale: bool,
+    pub highlighted: bool,
+    pub alternating: bool,
+    pub focus_color: Option<Color>,
+    pub window: WmState,
+
+    /// Whether the X server is Xwayland, which makes every window it knows about an XWayland client.
+    pub xwayland: bool,
+}
+
+pub struct App {
+    config: Config,
+    handlebars: Handlebars<'static>,
+    connection: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    processes: ProcessInspector,
+    enricher: Option<Enricher>,
+    machines: MachineInspector,
+    track_desktop: bool,
+    state: DisplayState,
+    pings: Option<PingTracker>,
+    highlight: Option<Highlight>,
+    fade: Option<Fade>,
+    marquee: Option<Marquee>,
+    focus: LogicalFocus,
+    pointer: Option<PointerTracker>,
+    monitor: Option<Monitor>,
+    content: Option<ContentWatcher>,
+    wm_state: Option<WmStateReader>,
+    urgent: Option<UrgentWindows>,
+    wm_name: Option<String>,
+    quirks: Quirks,
+    hooks: Hooks,
+    watcher: Option<ConfigWatcher>,
+    hangup: Option<Hangup>,
+    control: Option<ControlServer>,
+    settle: Settle,
+    active_window: ActiveWindow,
+    unknown_windows: HashSet<Window>,
+    clients: ClientSearch,
+    breaker: CircuitBreaker,
+    recorder: Option<Recorder>,
+    similar: Option<SimilarOutputs>,
+    render_cache: RenderCache,
+    outputs: ExtraOutputs,
+    metrics: Option<LatencyMetrics>,
+    resolved: Option<ResolvedWindow>,
+    alternate: Option<ResolvedWindow>,
+    context: RenderContext,
+    renames: HashMap<Window, String>,
+    paused: bool,
+    persisted: Option<PersistedState>,
+    usage: UsageTracker,
+    output: Option<String>,
+
+    /// The last line which was written, formatted for the output. A line which is the same isn't written again
+    /// unless `always_print` is set.
+    written: Option<String>,
+    force_write: bool,
+
+    /// Whether the output is rendered again because the title of the displayed window changed, in which case it's
+    /// only written if it changed too, even with `always_print`. It doesn't when a filter replaces the name, for
+    /// example.
+    title_changed: bool,
+    failures: u32,
+}
+
+const FAILURES_BEFORE_ERROR_TEXT: u32 = 3;
+
+struct Connected {
+    connection: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    xwayland: bool,
+    wm_name: Option<String>,
+}
+
+impl Connected {
+    fn to(display_name: Option<&str>) -> anyhow::Result<Self> {
+        tracing::info!("establishing a connection to the X server");
+        let (connection, screen_num) = x11rb::connect(display_name)
+            .context(Failure::Backend)?;
+
+        tracing::debug!("get primary screen");
+        let root = connection.setup().roots[screen_num].root;
+
+        let atoms = Atoms::new(&connection)?;
+        let xwayland = connection
+            .extension_information("XWAYLAND")
+            .context("failed to query XWAYLAND extension")?
+            .is_some();
+        tracing::debug!(%xwayland, "checked whether the X server is Xwayland");
+
+        let wm_name = quirks::wm_name(&connection, &atoms, root).context("failed to get name of window manager")?;
+        tracing::debug!(?wm_name, "got name of window manager");
+
+        Ok(Self { connection, root, atoms, xwayland, wm_name })
+    }
+}
+
+impl App {
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let Connected { connection, root, atoms, xwayland, wm_name } = Connected::to(config.display_name.as_deref())?;
+
+        let watcher = ConfigWatcher::new(Config::paths())
+            .map_err(|error| tracing::warn!("not watching config files for changes: {error:#}"))
+            .ok();
+
+        let hangup = Hangup::install()
+            .map_err(|error| tracing::warn!("not reloading the config on SIGHUP: {error:#}"))
+            .ok();
+
+        let control = config.control_socket.as_deref().map(ControlServer::bind).transpose()?;
+
+        let mut app = Self {
+            config: Config::default(),
+            handlebars: Handlebars::new(),
+            connection,
+            root,
+            atoms,
+            processes: ProcessInspector::new("/proc", false),
+            enricher: None,
+            machines: MachineInspector::new(false),
+            track_desktop: false,
+            state: DisplayState {
+                xwayland,
+                ..DisplayState::default()
+            },
+            pings: None,
+            highlight: None,
+            fade: None,
+            marquee: None,
+            focus: LogicalFocus::new(Duration::ZERO, None),
+            pointer: None,
+            monitor: None,
+            content: None,
+            wm_state: None,
+            urgent: None,
+            wm_name,
+            quirks: Quirks::default(),
+            hooks: Hooks::spawn(&[]),
+            watcher,
+            hangup,
+            control,
+            settle: Settle::default(),
+            active_window: ActiveWindow::default(),
+            unknown_windows: HashSet::new(),
+            clients: ClientSearch::default(),
+            breaker: CircuitBreaker::default(),
+            recorder: None,
+            similar: None,
+            render_cache: RenderCache::new(&HashSet::new()),
+            outputs: ExtraOutputs::new(&Config::default())?,
+            metrics: None,
+            resolved: None,
+            alternate: None,
+            context: RenderContext::default(),
+            renames: HashMap::new(),
+            paused: false,
+            persisted: None,
+            usage: UsageTracker::default(),
+            output: None,
+            written: None,
+            force_write: false,
+            title_changed: false,
+            failures: 0,
+        };
+        app.configure(config)?;
+
+        Ok(app)
+    }
+
+    pub fn record_to(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.recorder = Some(Recorder::open(path)?);
+        Ok(())
+    }
+
+    /// Sets everything derived from the config up for `config`. Nothing is changed if this fails.
+    fn configure(&mut self, config: Config) -> anyhow::Result<()> {
+        let handlebars = templates(&config)?;
+        let outputs = ExtraOutputs::new(&config)?;
+        let template_variables = template_variables(&handlebars, &outputs);
+        let render_cache = RenderCache::new(
+            &handlebars.get_templates().values().flat_map(templating::variables).collect(),
+        );
+        let child_process = template_variables.contains("child_process") || config.resolver.matches_on(WindowIdentifierKind::ChildProcess);
+        let processes = ProcessInspector::new("/proc", child_process && !config.enrich_in_background);
+        let enrich = child_process && config.enrich_in_background;
+        let enricher = match (enrich, &self.enricher) {
+            (true, None) => Some(Enricher::spawn("/proc")?),
+            _ => None,
+        };
+        let machines = MachineInspector::new(
+            template_variables.contains("client_machine")
+                || template_variables.contains("remote")
+                || config.resolver.matches_on(WindowIdentifierKind::ClientMachine)
+                || config.resolver.matches_on(WindowIdentifierKind::Remote),
+        );
+
+        let track_stale = template_variables.contains("stale");
+        let event_mask = if track_stale {
+            // pongs are sent to the root window with the substructure masks
+            EventMask::PROPERTY_CHANGE | EventMask::SUBSTRUCTURE_NOTIFY
+        } else {
+            EventMask::PROPERTY_CHANGE
+        };
+
+        let track_desktop = config.workspace_prefix.is_some()
+            || config.resolver.desktop_name.as_ref().is_some_and(DesktopName::is_per_desktop)
+            || !config.on_change.is_empty()
+            || template_variables.contains("desktop")
+            || template_variables.contains("desktop_index")
+            || template_variables.contains("workspace")
+            || template_variables.contains("workspace_index");
+        let desktop = if track_desktop {
+            Desktop::current(&self.connection, &self.atoms, self.root).context("failed to get current desktop")?
+        } else {
+            None
+        };
+
+        let mut pings = if track_stale {
+            Some(PingTracker::new(&self.connection, self.root).context("failed to set up ping tracking")?)
+        } else {
+            None
+        };
+
+        if let (Some(pings), Some(resolved)) = (&mut pings, &self.resolved) {
+            pings.focus(&self.connection, resolved.window).context("failed to set up ping tracking for window")?;
+        }
+
+        let highlight = config.changed_template
+            .as_ref()
+            .map(|_| Highlight::new(config.highlight.get()));
+        let fade = config.focus_fade.clone().map(|focus_fade| Fade::new(focus_fade, Instant::now()));
+        let pointer = if config.track == Track::Pointer {
+            Some(PointerTracker::new(&self.connection, self.root, config.pointer_interval.get()).context("failed to set up pointer tracking")?)
+        } else {
+            None
+        };
+        let monitor = config.monitor
+            .clone()
+            .filter(|name| !name.is_empty())
+            .map(|name| Monitor::new(&self.connection, self.root, name))
+            .transpose()
+            .context("failed to set up monitor")?;
+        let wm_state = if config.respect_skip_taskbar || config.output.needs_window_state() {
+            Some(WmStateReader::new(&self.connection).context("failed to set up window state retrieval")?)
+        } else {
+            None
+        };
+
+        let window = match (&wm_state, &self.resolved) {
+            (Some(wm_state), Some(resolved)) if resolved.window != 0 => {
+                wm_state.get(&self.connection, resolved.window).context("failed to get state of window")?
+            }
+            _ => WmState::default(),
+        };
+
+        // the subscriptions of the previous config are taken over, so client windows aren't subscribed to again
+        let urgent = config.alternate_interval
+            .map(|interval| UrgentWindows::new(&self.connection, self.root, interval.get(), self.urgent.as_ref()))
+            .transpose()
+            .context("failed to set up urgent window tracking")?;
+
+        let events = ChangeWindowAttributesAux::new().event_mask(event_mask);
+
+        tracing::info!("setting up events");
+        self.connection
+            .change_window_attributes(self.root, &events)
+            .context("failed to make ChangeWindowAttributes reply")?
+            .check()
+            .context("ChangeWindowAttributes response failed")?;
+
+        // nothing below fails, so the new config is taken over completely
+        if let (None, Some(previous)) = (&urgent, self.urgent.take()) {
+            if let Err(error) = previous.stop(&self.connection) {
+                tracing::warn!("failed to stop tracking urgent windows: {error:#}");
+            }
+        }
+
+        self.state = DisplayState {
+            desktop,
+            stale: pings.as_ref().is_some_and(PingTracker::is_stale),
+            highlighted: false,
+            alternating: false,
+            focus_color: fade.as_ref().map(Fade::color),
+            window,
+            xwayland: self.state.xwayland,
+        };
+        self.quirks = Quirks::new(&config, self.wm_name.as_deref());
+
+        if !self.quirks.is_empty() {
+            tracing::info!(quirks = ?self.quirks.iter().map(|quirk| quirk.to_string()).collect::<Vec<_>>(), "working around window manager bugs");
+        }
+
+        self.hooks = Hooks::spawn(&config.on_change);
+        self.config = config;
+        self.handlebars = handlebars;
+        self.processes = processes;
+        if !enrich {
+            self.enricher = None;
+        } else if enricher.is_some() {
+            self.enricher = enricher;
+        }
+        self.machines = machines;
+        self.track_desktop = track_desktop;
+        self.pings = pings;
+        self.highlight = highlight;
+        self.fade = fade;
+        self.outputs = outputs;
+        self.similar = self.config.suppress_similar.clone().map(SimilarOutputs::new);
+        self.marquee = self.config.scroll.clone().map(|scroll| Marquee::new(scroll, self.handlebars.get_escape_fn()));
+        self.render_cache = render_cache;
+        self.metrics = match self.metrics.take() {
+            _ if !self.config.latency_metrics => None,
+            Some(metrics) => Some(metrics),
+            None => Some(LatencyMetrics::new(Instant::now())),
+        };
+        self.focus = LogicalFocus::new(
+            self.config.focus_settle.get(),
+            self.resolved.as_ref().map(|resolved| resolved.window),
+        );
+        self.pointer = pointer;
+        self.monitor = monitor;
+        self.content = self.config.content_change_detection
+            .clone()
+            .map(|detection| ContentWatcher::new(detection, Instant::now()));
+        self.wm_state = wm_state;
+        self.urgent = urgent;
+        self.alternate = None;
+
+        match (self.config.persist_state || self.config.usage_tracking, &self.persisted) {
+            (true, None) => match PersistedState::path() {
+                Some(path) => {
+                    let persisted = PersistedState::load(path, self.config.persist_max_age.get());
+                    self.paused |= self.config.persist_state && persisted.paused();
+                    self.persisted = Some(persisted);
+                }
+                None => tracing::warn!("could not get the state directory, not persisting state"),
+            },
+            (false, Some(_)) => self.persisted = None,
+            _ => {}
+        }
+
+        match (self.config.usage_tracking, &self.persisted) {
+            (true, Some(persisted)) => self.usage.start_saving(persisted.usage(), Instant::now()),
+            _ => self.usage.stop_saving(),
+        }
+
+        self.reset_alternation(Instant::now());
+
+        Ok(())
+    }
+
+    /// Switches over to a reloaded config and resolves the displayed window again with it. An invalid config is
+    /// logged and the previous one is kept. Returns whether the output needs to be rendered again.
+    fn reload(&mut self, config: anyhow::Result<Config>) -> anyhow::Result<bool> {
+        if let Err(error) = config.and_then(|config| self.configure(config)) {
+            tracing::error!("could not reload config, keeping the previous one: {error:#}");
+            return Ok(false)
+        }
+
+        self.redisplay("reload")
+    }
+
+    /// Handles events, connecting to the X server again whenever the connection is lost, until `reconnect` gives up.
+    /// Exits quietly once stdout is closed, as nobody reads what's written anymore.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let result = loop {
+            match self.run_connected() {
+                Err(error) if is_connection_lost(&error) => {
+                    if let Err(error) = self.reconnect(error) {
+                        break Err(error)
+                    }
+                }
+                result => break result,
+            }
+        };
+
+        match result {
+            Err(error) if output::is_stdout_closed(&error) => {
+                tracing::info!("stdout was closed, exiting");
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Handles events until the connection to the X server is lost. Anything else that goes wrong is logged and the
+    /// previous output stays, so that the module survives a window which misbehaves for a moment.
+    fn run_connected(&mut self) -> anyhow::Result<()> {
+        let startup = self.sync("startup").and_then(|changed| if changed { self.write() } else { Ok(()) });
+        self.on_result(startup)?;
+
+        loop {
+            let watched = Watched {
+                watcher: self.watcher.as_ref().map(AsRawFd::as_raw_fd),
+                hangup: self.hangup.as_ref().map(AsRawFd::as_raw_fd),
+                enricher: self.enricher.as_ref().map(AsRawFd::as_raw_fd),
+            };
+            let control = self.control.as_ref().map(AsRawFd::as_raw_fd);
+            let fds = [watched.watcher, watched.hangup, control, watched.enricher].into_iter().flatten().collect::<Vec<_>>();
+            let wakeup = wait_for_event(&self.connection, self.deadline(), &fds)?;
+
+            if let (Some(metrics), Wakeup::Event(_)) = (&mut self.metrics, &wakeup) {
+                metrics.received(Instant::now());
+            }
+
+            let result = self.on_wakeup(wakeup, watched);
+            self.title_changed = false;
+            self.on_result(result)?;
+        }
+    }
+
+    fn on_wakeup(&mut self, wakeup: Wakeup, Watched { watcher, hangup, enricher }: Watched) -> anyhow::Result<()> {
+        let mut error = None;
+        let changed = match wakeup {
+            Wakeup::Deadline => self.on_deadline(Instant::now(), &mut error),
+            Wakeup::Readable(fd) if Some(fd) == watcher => {
+                if let Some(watcher) = &mut self.watcher {
+                    watcher.on_readable(Instant::now()).context("failed to watch config files")?;
+                }
+
+                false
+            }
+            Wakeup::Readable(fd) if Some(fd) == hangup => {
+                if self.hangup.as_mut().is_some_and(Hangup::on_readable) {
+                    tracing::info!("received SIGHUP, reloading config");
+                    self.reload(Config::read())?
+                } else {
+                    false
+                }
+            }
+            Wakeup::Readable(fd) if Some(fd) == enricher => {
+                let windows = self.enricher.as_mut().map(|enricher| enricher.on_readable(Instant::now())).unwrap_or_default();
+                let displayed = self.resolved.as_ref().map(|resolved| resolved.window);
+
+                if displayed.is_some_and(|displayed| windows.contains(&displayed)) {
+                    self.redisplay("enrichment")?
+                } else {
+                    false
+                }
+            }
+            Wakeup::Readable(_) => {
+                let requests = self.control.as_mut().map(ControlServer::on_readable).unwrap_or_default();
+                let mut changed = false;
+
+                for request in requests {
+                    changed |= self.on_request(request)?;
+                }
+
+                changed
+            }
+            Wakeup::Event(Event::PropertyNotify(event)) => self.on_property_notify(&event)?,
+            Wakeup::Event(Event::ClientMessage(event)) => match &mut self.pings {
+                Some(pings) => {
+                    let changed = pings.on_client_message(&event);
+                    self.state.stale = pings.is_stale();
+                    changed
+                }
+                None => false,
+            },
+            Wakeup::Event(event) => {
+                tracing::debug!(?event, "received other event");
+                false
+            }
+        };
+
+        if changed {
+            self.write()?;
+        } else if let (Some(metrics), false) = (&mut self.metrics, self.settle.is_pending()) {
+            // nothing is waiting for related changes, so the event didn't lead to an output
+            metrics.discard();
+        }
+
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Gives up if the connection to the X server was lost, and otherwise logs the error of a failed event. Once
+    /// enough of them failed in a row, `error_text` is written if it's set.
+    fn on_result(&mut self, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        let error = match result {
+            Ok(()) => {
+                self.failures = 0;
+                return Ok(())
+            }
+            Err(error) if is_connection_lost(&error) || output::is_stdout_closed(&error) => return Err(error),
+            Err(error) => error,
+        };
+
+        tracing::warn!("failed to handle event, keeping previous output: {error:#}");
+        self.failures = self.failures.saturating_add(1);
+
+        if let (FAILURES_BEFORE_ERROR_TEXT, Some(text)) = (self.failures, &self.config.error_text) {
+            let nothing = ResolvedWindow::without_properties(0, String::new());
+            self.config.output.write(text, &nothing, &DisplayState::default(), self.config.output_format, false)
+                .context("failed to write error text")?;
+            // whatever is rendered next has to replace the error text
+            self.written = None;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `reconnect.text` and tries to connect to the X server again, waiting longer after every failed attempt.
+    /// Fails with `error`, which lost the connection, once there are no retries left.
+    fn reconnect(&mut self, error: anyhow::Error) -> anyhow::Result<()> {
+        tracing::error!("lost the connection to the X server: {error:#}");
+
+        let nothing = ResolvedWindow::without_properties(0, String::new());
+        self.config.output.write(&self.config.reconnect.text, &nothing, &DisplayState::default(), self.config.output_format, false)
+            .context("failed to write text while disconnected")?;
+        self.written = None;
+
+        for interval in Backoff::new(&self.config.reconnect) {
+            tracing::info!(?interval, "waiting before connecting to the X server again");
+            thread::sleep(interval);
+
+            match self.connect() {
+                Ok(()) => return Ok(()),
+                Err(error) => tracing::warn!("failed to connect to the X server again: {error:#}"),
+            }
+        }
+
+        Err(error).context("gave up connecting to the X server again")
+    }
+
+    fn connect(&mut self) -> anyhow::Result<()> {
+        let Connected { connection, root, atoms, xwayland, wm_name } = Connected::to(self.config.display_name.as_deref())?;
+
+        self.connection = connection;
+        self.root = root;
+        self.atoms = atoms;
+        self.state.xwayland = xwayland;
+        self.wm_name = wm_name;
+        self.resolved = None;
+        self.alternate = None;
+        self.urgent = None;
+        self.pings = None;
+        self.settle.cancel();
+        self.active_window = ActiveWindow::default();
+        self.unknown_windows.clear();
+        self.clients = ClientSearch::default();
+        self.breaker = CircuitBreaker::default();
+        self.renames.clear();
+
+        // sets up the events on the new root window, and everything else which talks to the X server
+        self.configure(self.config.clone())
+    }
+
+    fn write(&mut self) -> anyhow::Result<()> {
+        let displayed = self.alternate.as_ref().or(self.resolved.as_ref());
+
+        let (false, Some(resolved)) = (self.paused, displayed) else {
+            return Ok(())
+        };
+
+        self.state.alternating = self.alternate.is_some();
+        self.context.update(resolved, &self.state, &self.config.resolver);
+        let rendered_name = self.render_cache.get_or_render(&self.context, &self.state, || {
+            render_truncated(&self.handlebars, &self.config, &self.context, &self.state, self.config.max_output_length)
+        })?;
+        let rendered_name = match &mut self.marquee {
+            Some(marquee) => marquee.show(rendered_name, Instant::now()),
+            None => rendered_name,
+        };
+        let rendered_name = wrap_actions(&self.handlebars, &self.config, &self.context, rendered_name)?;
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reached(Stage::Rendered, Instant::now());
+        }
+
+        let line = self.config.output.line(&rendered_name, resolved, &self.state, self.config.output_format, output::styling(&self.config))?;
+        let forced = std::mem::take(&mut self.force_write);
+
+        if !forced && (self.title_changed || !self.config.always_print) && self.written.as_ref() == Some(&line) {
+            tracing::debug!("output stayed the same, not writing it again");
+
+            if let Some(metrics) = &mut self.metrics {
+                metrics.discard();
+            }
+
+            return Ok(())
+        }
+
+        if let Some(similar) = &mut self.similar {
+            if !similar.should_write(resolved.window, &rendered_name, Instant::now()) {
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.discard();
+                }
+
+                return Ok(())
+            }
+        }
+
+        self.config.output.send(&line).context("failed to write title")?;
+        self.written = Some(line);
+        self.output = Some(rendered_name);
+        self.outputs.write(&self.config, &self.context, &self.state, resolved);
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reached(Stage::Written, Instant::now());
+        }
+
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        [
+            self.pings.as_ref().and_then(PingTracker::deadline),
+            self.highlight.as_ref().and_then(Highlight::deadline),
+            self.fade.as_ref().and_then(Fade::deadline),
+            self.marquee.as_ref().and_then(Marquee::deadline),
+            self.similar.as_ref().and_then(SimilarOutputs::deadline),
+            self.outputs.deadline(),
+            self.urgent.as_ref().and_then(UrgentWindows::deadline),
+            self.metrics.as_ref().map(LatencyMetrics::deadline),
+            self.focus.deadline(),
+            self.pointer.as_ref().map(PointerTracker::deadline),
+            self.content.as_ref().map(ContentWatcher::deadline),
+            self.usage.deadline(),
+            self.watcher.as_ref().and_then(ConfigWatcher::deadline),
+            self.settle.deadline(),
+        ]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Runs the timers which are due, keeping the first error of a failing one in `error`. Returns whether the output
+    /// needs to be rendered again.
+    fn on_deadline(&mut self, now: Instant, error: &mut Option<anyhow::Error>) -> bool {
+        let mut changed = false;
+
+        if self.settle.on_deadline(now) {
+            changed |= keep(self.sync("_NET_ACTIVE_WINDOW"), error);
+        }
+
+        if let Some(config) = self.watcher.as_mut().and_then(|watcher| watcher.on_deadline(now)) {
+            changed |= keep(self.reload(config), error);
+        }
+
+        if let Some(pings) = &mut self.pings {
+            changed |= keep(pings.on_deadline(&self.connection, now).context("failed to ping window"), error);
+            self.state.stale = pings.is_stale();
+        }
+
+        if self.focus.on_deadline(now).is_some() {
+            changed |= self.on_focus_change(now);
+        }
+
+        if let Some(highlight) = &mut self.highlight {
+            // the revert must always be rendered, even if nothing else changed
+            changed |= highlight.on_deadline(now);
+            self.state.highlighted = highlight.is_active();
+        }
+
+        if let Some(fade) = &mut self.fade {
+            changed |= fade.on_deadline(now);
+            self.state.focus_color = Some(fade.color());
+        }
+
+        if let Some(marquee) = &mut self.marquee {
+            changed |= marquee.on_deadline(now);
+        }
+
+        if let Some(similar) = &mut self.similar {
+            changed |= similar.on_deadline(now);
+        }
+
+        self.outputs.on_deadline(now);
+
+        if let Some(urgent) = self.urgent.as_mut().filter(|urgent| urgent.clients_due(now)) {
+            keep(urgent.update_clients(&self.connection, self.root).context("failed to update client windows"), error);
+            changed |= self.on_urgency_change(now);
+        }
+
+        let focused = self.resolved.as_ref().map(|resolved| resolved.window);
+
+        if self.urgent.as_mut().is_some_and(|urgent| urgent.on_deadline(focused, now)) {
+            changed |= self.update_alternate();
+        }
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.on_deadline(now);
+        }
+
+        if let Some(pointer) = &mut self.pointer {
+            let displayed = self.resolved.as_ref().map(|resolved| resolved.window);
+
+            if let Some(window) = keep(pointer.on_deadline(&self.connection, now, displayed).context("failed to track pointer"), error) {
+                changed |= keep(self.display(window, "pointer"), error);
+            }
+        }
+
+        if let (Some(content), Some(resolved)) = (&mut self.content, &self.resolved) {
+            if content.on_deadline(&self.connection, resolved, now) {
+                changed |= keep(self.redisplay("content change"), error);
+            }
+        }
+
+        if let (Some(usage), Some(persisted)) = (self.usage.on_deadline(now), &mut self.persisted) {
+            persisted.set_usage(usage);
+        }
+
+        changed
+    }
+
+    fn on_request(&mut self, request: Request) -> anyhow::Result<bool> {
+        tracing::debug!(command = ?request.command, "got command from control socket");
+        let window = self.resolved.as_ref().map(|resolved| resolved.window);
+
+        let (response, changed) = match &request.command {
+            Command::Get => {
+                let status = Status {
+                    window,
+                    display_name: self.resolved.as_ref().map(|resolved| resolved.name.clone()),
+                    rendered: self.output.clone(),
+                    raw_title: self.resolved.as_ref().map(|resolved| resolved.wm_name.clone()),
+                    paused: self.paused,
+                    dropped: self.outputs.dropped(),
+                    latency: self.metrics.as_ref().map(LatencyMetrics::summaries),
+                };
+
+                (Response::status(status), false)
+            }
+            Command::Refresh => {
+                self.force_write = true;
+                (Response::ok(), self.redisplay("refresh")?)
+            }
+            Command::Pause => {
+                self.set_paused(true);
+                (Response::ok(), false)
+            }
+            Command::Resume => {
+                self.set_paused(false);
+                (Response::ok(), true)
+            }
+            Command::Reload => match Config::read() {
+                Ok(config) => (Response::ok(), self.reload(Ok(config))?),
+                Err(error) => (Response::error(&error), false),
+            },
+            Command::Rename(name) => match window {
+                Some(window) if window != 0 => {
+                    self.renames.insert(window, name.clone());
+                    self.persist_rename(Some(name.clone()));
+                    (Response::ok(), self.redisplay("rename")?)
+                }
+                _ => (Response::error(&anyhow::anyhow!("no window is displayed")), false),
+            },
+            Command::Unrename => match window {
+                Some(window) if self.renames.remove(&window).is_some() => {
+                    self.persist_rename(None);
+                    (Response::ok(), self.redisplay("unrename")?)
+                }
+                _ => (Response::error(&anyhow::anyhow!("the displayed window isn't renamed")), false),
+            },
+            Command::UsageStats => (Response::usage(self.usage.usage(Instant::now())), false),
+        };
+
+        request.reply(response);
+        Ok(changed)
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.track_usage(Instant::now());
+
+        if let (Some(persisted), true) = (&mut self.persisted, self.config.persist_state) {
+            persisted.set_paused(paused);
+        }
+    }
+
+    fn track_usage(&mut self, now: Instant) {
+        let class = self.resolved
+            .as_ref()
+            .filter(|resolved| resolved.window != 0 && !self.paused)
+            .map(|resolved| resolved.wm_class.as_str());
+
+        self.usage.focus(class, now);
+    }
+
+    fn persist_rename(&mut self, name: Option<String>) {
+        if !self.config.persist_state {
+            return
+        }
+
+        if let (Some(persisted), Some(resolved)) = (&mut self.persisted, &self.resolved) {
+            persisted.set_rename(Fingerprint::of(resolved), name);
+        }
+    }
+
+    fn redisplay(&mut self, trigger: &str) -> anyhow::Result<bool> {
+        match self.resolved.as_ref().map(|resolved| resolved.window) {
+            Some(window) => self.display(window, trigger),
+            None => Ok(false),
+        }
+    }
+
+    fn on_property_notify(&mut self, event: &PropertyNotifyEvent) -> anyhow::Result<bool> {
+        tracing::debug!("got property notify event");
+
+        if event.window != self.root {
+            return self.on_window_property_notify(event)
+        }
+
+        if let Some(urgent) = self.urgent.as_mut().filter(|urgent| urgent.is_client_list_atom(event.atom)) {
+            urgent.on_client_list_change(Instant::now());
+            return Ok(false)
+        }
+
+        if event.atom == self.atoms.net_active_window {
+            self.active_window.on_property_notify(event);
+
+            if !self.active_window.deleted && !self.settle.is_pending() && self.quirks.contains(Quirk::RepeatedActiveWindow) {
+                let displayed = self.resolved.as_ref().map(|resolved| resolved.window);
+
+                if displayed.is_some_and(|displayed| displayed != 0) && Some(self.active_window()?) == displayed {
+                    tracing::debug!("_NET_ACTIVE_WINDOW was set to the displayed window again, ignoring it");
+                    return Ok(false)
+                }
+            }
+        }
+
+        let focus_changed = event.atom == self.atoms.net_active_window && self.config.track == Track::Focus;
+        let desktop_changed = self.track_desktop
+            && (event.atom == self.atoms.net_current_desktop || event.atom == self.atoms.net_desktop_names);
+
+        if focus_changed || desktop_changed {
+            tracing::debug!(atom = event.atom, "active window or desktop changed, waiting for related changes");
+            self.settle.changed(Instant::now());
+        } else {
+            tracing::debug!(atom = event.atom, "other atom changed");
+        }
+
+        Ok(false)
+    }
+
+    fn on_window_property_notify(&mut self, event: &PropertyNotifyEvent) -> anyhow::Result<bool> {
+        let mut changed = false;
+
+        if self.urgent.as_mut().is_some_and(|urgent| urgent.on_property_notify(&self.connection, event)) {
+            changed |= self.on_urgency_change(Instant::now());
+        }
+
+        if self.resolved.as_ref().map(|resolved| resolved.window) != Some(event.window) {
+            return Ok(changed)
+        }
+
+        if event.atom == self.atoms.net_wm_name || event.atom == u32::from(AtomEnum::WM_NAME) {
+            tracing::debug!(window = event.window, "title of displayed window changed");
+            self.title_changed = true;
+            return Ok(self.redisplay("title change")? || changed)
+        }
+
+        let Some(wm_state) = self.wm_state.as_ref().filter(|wm_state| wm_state.is_state_atom(event.atom)) else {
+            return Ok(changed)
+        };
+
+        tracing::debug!(window = event.window, "state of displayed window changed");
+        let state = wm_state.get(&self.connection, event.window).context("failed to get state of window")?;
+        changed |= state != self.state.window;
+        self.state.window = state;
+
+        Ok(changed)
+    }
+
+    fn on_urgency_change(&mut self, now: Instant) -> bool {
+        let focused = self.resolved.as_ref().map(|resolved| resolved.window);
+
+        match &mut self.urgent {
+            Some(urgent) => urgent.reschedule(focused, now),
+            None => return false,
+        }
+
+        self.update_alternate()
+    }
+
+    fn reset_alternation(&mut self, now: Instant) {
+        let focused = self.resolved.as_ref().map(|resolved| resolved.window);
+
+        if let Some(urgent) = &mut self.urgent {
+            urgent.reset(focused, now);
+        }
+
+        self.alternate = None;
+    }
+
+    fn update_alternate(&mut self) -> bool {
+        let focused = self.resolved.as_ref().map(|resolved| resolved.window);
+        let window = self.urgent.as_ref().and_then(|urgent| urgent.current(focused));
+
+        if window == self.alternate.as_ref().map(|alternate| alternate.window) {
+            return false
+        }
+
+        self.alternate = window.and_then(|window| match self.query(window) {
+            Ok((_, resolved)) => Some(resolved),
+            Err(error) => {
+                tracing::warn!(%window, "failed to resolve urgent window, displaying the focused one: {error:#}");
+                None
+            }
+        });
+
+        true
+    }
+
+    /// Reads the current desktop and the active window together and displays the latter, both when the module starts
+    /// (so that the title shows up right away rather than with the first change) and once changes of them settled
+    /// (so that the output never combines the window of one desktop with the name of another). Starting up goes
+    /// through the same queries and subscriptions as any later change, so the state of a window which is already
+    /// fullscreen or urgent, or was renamed before a restart, is displayed from the start. Returns whether the output
+    /// needs to be rendered again.
+    fn sync(&mut self, trigger: &str) -> anyhow::Result<bool> {
+        if self.track_desktop {
+            tracing::debug!("retrieving current desktop");
+            self.state.desktop = Desktop::current(&self.connection, &self.atoms, self.root).context("failed to get current desktop")?;
+        }
+
+        if self.config.track != Track::Focus {
+            // the pointer is looked up with the next tick
+            return Ok(self.track_desktop)
+        }
+
+        let window = match self.active_window.take_deleted() {
+            true => 0,
+            false => self.active_window()?,
+        };
+
+        if self.display(window, trigger)? {
+            return Ok(true)
+        }
+
+        if self.resolved.is_some() {
+            return Ok(self.track_desktop)
+        }
+
+        // the active window might be gone already, but something has to be displayed
+        tracing::debug!(%window, "active window could not be displayed, displaying the desktop");
+        Ok(self.display(0, trigger)? || self.track_desktop)
+    }
+
+    /// Reads `_NET_ACTIVE_WINDOW` from the root window. Returns 0 if no window is active, or the property doesn't
+    /// exist.
+    fn active_window(&self) -> anyhow::Result<Window> {
+        tracing::debug!("making reply to X server for _NET_ACTIVE_WINDOW");
+        let property = self.connection
+            .get_property(false, self.root, self.atoms.net_active_window, AtomEnum::WINDOW, 0, 1)
+            .context("failed to make GetProperty reply")?
+            .reply()
+            .context("GetProperty response failed")?;
+
+        if property.format == 0 {
+            tracing::debug!("_NET_ACTIVE_WINDOW doesn't exist, no window is active");
+            return Ok(0)
+        }
+
+        let value = property
+            .value32()
+            .context("failed to get u32 value from atom")?
+            .next()
+            .unwrap_or_default();
+        tracing::debug!(%value, "u32 property value");
+
+        self.quirks.active_window(&self.connection, value)
+    }
+
+    /// Resolves the window which should now be displayed because of `trigger`, and if it differs from the one
+    /// displayed before, lets everything interested in that know. Returns whether the output needs to be rendered again, which isn't the
+    /// case if the window is skipped and the previous output is kept.
+    fn display(&mut self, window: Window, trigger: &str) -> anyhow::Result<bool> {
+        let now = Instant::now();
+        let window = self.on_monitor(window)?;
+
+        if window == 0 && self.config.resolver.desktop_behavior == DesktopBehavior::KeepLast && self.resolved.is_some() {
+            tracing::debug!("no window is active, keeping previous output");
+            // the previous window stays displayed, but it isn't focused anymore
+            self.usage.focus(None, now);
+            return Ok(false)
+        }
+
+        let (window_state, new) = if !self.breaker.allows(window, now) {
+            self.unresponsive(window)
+        } else {
+            match self.query(window) {
+                Ok((window_state, new)) => {
+                    self.breaker.succeeded(&new);
+                    (window_state, new)
+                }
+                Err(error) if window != 0 && is_window_gone(&error) => {
+                    tracing::debug!(%window, "window is gone already: {error:#}");
+                    return self.on_window_gone(window, trigger)
+                }
+                Err(error) => {
+                    tracing::warn!(%window, "failed to resolve window: {error:#}");
+
+                    if !self.breaker.failed(window, now) {
+                        return Ok(false)
+                    }
+
+                    self.unresponsive(window)
+                }
+            }
+        };
+
+        if self.config.respect_skip_taskbar && window_state.skip_taskbar {
+            tracing::debug!(%window, "window skips the taskbar, keeping previous output");
+            return Ok(false)
+        }
+
+        let Some(mut new) = self.config.resolver.apply_ignore(new, self.resolved.is_some(), self.state.desktop.as_ref()) else {
+            tracing::debug!(%window, "window is ignored, keeping previous output");
+            self.usage.focus(None, now);
+            return Ok(false)
+        };
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&Record::new(trigger, &new))?;
+        }
+
+        let persisted = self.persisted.as_ref().filter(|_| self.config.persist_state);
+
+        if let (Some(persisted), false) = (persisted, new.window == 0 || self.renames.contains_key(&new.window)) {
+            if let Some(name) = persisted.rename(&Fingerprint::of(&new)) {
+                tracing::debug!(window = new.window, %name, "window was renamed before the module was restarted");
+                self.renames.insert(new.window, name.to_owned());
+            }
+        }
+
+        if let Some(name) = self.renames.get(&new.window) {
+            tracing::debug!(window = new.window, %name, "window was renamed through the control socket");
+            new.name = name.clone();
+        }
+
+        let previous = self.resolved.as_ref().map(|resolved| resolved.window);
+
+        if previous != Some(new.window) {
+            self.follow(previous, new.window)?;
+
+            if let Some(pings) = &mut self.pings {
+                match pings.focus(&self.connection, new.window) {
+                    Err(error) if is_window_gone(&error) => tracing::debug!(window = new.window, "window is gone already, not pinging it"),
+                    result => result.context("failed to set up ping tracking for window")?,
+                }
+
+                self.state.stale = pings.is_stale();
+            }
+        }
+
+        let focus_changed = previous != Some(new.window) && self.focus.displayed(new.window, now).is_some();
+        let window_changed = previous != Some(new.window);
+        self.resolved = Some(new);
+        self.state.window = window_state;
+
+        if window_changed {
+            self.reset_alternation(now);
+        }
+
+        self.track_usage(now);
+
+        if focus_changed {
+            self.on_focus_change(now);
+        }
+
+        Ok(true)
+    }
+
+    /// `window`, or 0 if it isn't on `monitor`. A window which is gone is left to the usual handling.
+    fn on_monitor(&mut self, window: Window) -> anyhow::Result<Window> {
+        let Some(monitor) = self.monitor.as_mut().filter(|_| window != 0) else {
+            return Ok(window)
+        };
+
+        match monitor.contains(&self.connection, self.root, window) {
+            Ok(true) => Ok(window),
+            Ok(false) => {
+                tracing::debug!(%window, "window is on another monitor, no window is active on this one");
+                Ok(0)
+            }
+            Err(error) if is_window_gone(&error) => Ok(window),
+            Err(error) => Err(error).context("failed to find out which monitor window is on"),
+        }
+    }
+
+    /// Displays something else instead of `window`, which was destroyed before it could be resolved: the window
+    /// which is active now if that's another one, and the desktop otherwise.
+    fn on_window_gone(&mut self, window: Window, trigger: &str) -> anyhow::Result<bool> {
+        let active = match self.config.track {
+            Track::Focus => self.active_window()?,
+            Track::Pointer => 0,
+        };
+
+        if active != 0 && active != window {
+            tracing::debug!(%window, %active, "displaying the window which is active now instead");
+            self.display(active, trigger)
+        } else {
+            self.display(0, trigger)
+        }
+    }
+
+    fn query(&mut self, window: Window) -> anyhow::Result<(WmState, ResolvedWindow)> {
+        let window_state = match (&self.wm_state, window != 0) {
+            (Some(wm_state), true) => wm_state.get(&self.connection, window).context("failed to get state of window")?,
+            _ => WmState::default(),
+        };
+
+        tracing::debug!("resolving window name");
+        let mut fetched = self.config.resolver
+            .fetch(&self.connection, &self.atoms, &mut self.processes, &self.machines, window)
+            .context("failed to resolve name of window")?;
+
+        let frame_windows = window != 0 && self.quirks.contains(Quirk::FrameWindows);
+
+        if (fetched.is_unknown() && self.config.resolver.search_client_window) || frame_windows {
+            if let Some(client) = self.clients.find(&self.connection, window).context("failed to search for client window")? {
+                tracing::debug!(%window, %client, "resolving client window instead");
+                fetched = self.config.resolver
+                    .fetch(&self.connection, &self.atoms, &mut self.processes, &self.machines, client)
+                    .context("failed to resolve name of client window")?;
+            }
+        }
+
+        if let (Some(enricher), Some(window), None) = (&mut self.enricher, NonNullWindow::new(fetched.window), &fetched.flatpak_id) {
+            if let Some(pid) = window_pid(&self.connection, &self.atoms, window)? {
+                fetched.child_process = enricher.child_process(window.get(), pid, Instant::now());
+            }
+        }
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reached(Stage::Properties, Instant::now());
+        }
+
+        let new = match fetched.window {
+            0 => self.config.resolver.desktop_on(self.state.desktop.as_ref()),
+            _ => self.config.resolver.apply(fetched),
+        };
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reached(Stage::Filters, Instant::now());
+        }
+
+        if new.is_unknown() && self.unknown_windows.insert(new.window) {
+            tracing::info!(window = new.window, "window has no WM_CLASS, WM_NAME or child process, displaying it as unknown");
+        }
+
+        Ok((window_state, new))
+    }
+
+    fn unresponsive(&self, window: Window) -> (WmState, ResolvedWindow) {
+        let resolved = match (&self.config.unresponsive_text, self.breaker.last(window)) {
+            (Some(text), _) => ResolvedWindow::without_properties(window, text.clone()),
+            (None, Some(last)) => last.clone(),
+            (None, None) => ResolvedWindow::without_properties(window, self.config.resolver.unknown_window_text.clone()),
+        };
+
+        (WmState::default(), resolved)
+    }
+
+    fn on_focus_change(&mut self, now: Instant) -> bool {
+        let Some(resolved) = &self.resolved else {
+            return false
+        };
+
+        tracing::debug!("focused window changed, notifying hooks");
+        self.hooks.notify(&HookEnv {
+            class: resolved.wm_class.clone(),
+            name: resolved.name.clone(),
+            window_id: resolved.window,
+            desktop: self.state.desktop.as_ref().map(|desktop| desktop.index),
+        });
+
+        let mut changed = false;
+
+        if let Some(fade) = &mut self.fade {
+            fade.restart(now);
+            self.state.focus_color = Some(fade.color());
+            changed = true;
+        }
+
+        if let Some(highlight) = &mut self.highlight {
+            highlight.start(now);
+            self.state.highlighted = highlight.is_active();
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Moves the subscription to property changes from the `previous` displayed window over to `window`, so that
+    /// changes of its title and state are noticed, but not those of windows in the background.
+    fn follow(&self, previous: Option<Window>, window: Window) -> anyhow::Result<()> {
+        // the previous window might be gone already, which is fine
+        // client windows stay subscribed while urgent windows are tracked
+        let tracked = |window: Window| self.urgent.as_ref().is_some_and(|urgent| urgent.is_client(window));
+
+        if let Some(previous) = previous.filter(|previous| *previous != 0 && !tracked(*previous)) {
+            self.connection
+                .change_window_attributes(previous, &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT))
+                .context("failed to make ChangeWindowAttributes reply")?
+                .ignore_error();
+        }
+
+        if window != 0 {
+            self.connection
+                .change_window_attributes(window, &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE))
+                .context("failed to make ChangeWindowAttributes reply")?
+                .ignore_error();
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn templates(config: &Config) -> anyhow::Result<Handlebars<'static>> {
+    let escape = match (&config.output, config.output_format) {
+        // waybar renders the text as pango markup
+        (Output::Waybar(_), _) => handlebars::html_escape,
+        (Output::Stdout, OutputFormat::Json) => handlebars::no_escape,
+        (Output::Stdout | Output::PolybarIpc(_), _) => templating::escape_polybar,
+    };
+
+    let mut handlebars = registry(config, &config.template, escape, true)?;
+
+    if has_actions(config) {
+        config.action.register(&mut handlebars)?;
+    }
+
+    if config.strict_templates {
+        for (name, template) in handlebars.get_templates().iter().filter(|(name, _)| name.starts_with("action_")) {
+            templating::validate(name, template, VARIABLES, config.backend.unavailable_variables())?;
+        }
+
+        for (identifier, filter) in &config.resolver.filters {
+            if let Filter::Template(template) = &filter.method {
+                templating::validate(&format!("filter template of {identifier}"), template.template(), filter_template::VARIABLES, &[])?;
+            }
+        }
+    }
+
+    Ok(handlebars)
+}
+
+/// Every variable any template refers to, including the ones of `[[outputs]]`, which decides what gets looked up.
+fn template_variables(handlebars: &Handlebars, outputs: &ExtraOutputs) -> HashSet<String> {
+    let mut variables = handlebars.get_templates().values().flat_map(templating::variables).collect::<HashSet<_>>();
+    variables.extend(outputs.variables());
+    variables
+}
+
+/// Creates a template registry with `template` as the main template, escaping values with `escape`. The changed
+/// template is only registered if `changed` is set, otherwise the main template is rendered while highlighted too.
+pub(crate) fn registry(config: &Config, template: &str, escape: fn(&str) -> String, changed: bool) -> anyhow::Result<Handlebars<'static>> {
+    tracing::debug!("create template registry and register template from config");
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(escape);
+    templating::register_helpers(&mut handlebars);
+
+    handlebars.register_template_string("template", template)
+        .context("failed to register template string")?;
+
+    if let Some(workspace_prefix) = &config.workspace_prefix {
+        handlebars.register_template_string("workspace_prefix", &workspace_prefix.template)
+            .context("failed to register workspace prefix template string")?;
+    }
+
+    if let (true, Some(changed_template)) = (changed, &config.changed_template) {
+        handlebars.register_template_string("changed_template", changed_template)
+            .context("failed to register changed template string")?;
+    }
+
+    templating::check_partials(&handlebars)?;
+
+    if config.strict_templates {
+        for (name, template) in handlebars.get_templates() {
+            templating::validate(name, template, VARIABLES, config.backend.unavailable_variables())?;
+        }
+    }
+
+    Ok(handlebars)
+}
+
+/// The names of the variables in [`RenderContext`]. `name` is the resolved name, while `raw_name` and `raw_class`
+/// (also known as `wm_name` and `wm_class`) are what the window itself says, before any filter applied.
+const VARIABLES: &[&str] = &[
+    "name", "raw_name", "raw_class", "wm_name", "wm_class", "instance", "window", "stale", "xwayland", "child_process",
+    "flatpak_id", "desktop_index", "desktop", "workspace_index", "workspace", "app", "app_short_name", "app_icon", "app_color", "focus_color",
+    "client_machine", "remote",
+];
+
+/// The data passed to the templates. It's kept around between renders and updated in place, so that rendering
+/// doesn't allocate a new set of strings every time.
+#[derive(Serialize, Default, Hash, Debug)]
+pub struct RenderContext {
+    name: String,
+
+    /// The title and the class as read from the window (only sanitized), before any filter applied. They're empty
+    /// when no window is active.
+    raw_name: String,
+    raw_class: String,
+    wm_name: String,
+    wm_class: String,
+    instance: String,
+    window: Window,
+    stale: bool,
+    xwayland: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    child_process: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flatpak_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desktop_index: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desktop: Option<String>,
+
+    /// The same as `desktop_index`, and the name of the desktop as the window manager gives it, or its 1-based
+    /// number if it doesn't name desktops. Unlike `desktop`, `desktop_options` don't apply to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace_index: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_short_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_icon: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_color: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    focus_color: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_machine: Option<String>,
+    remote: bool,
+
+    #[serde(skip)]
+    is_desktop: bool,
+
+    #[serde(skip)]
+    is_ignored: bool,
+}
+
+impl RenderContext {
+    pub fn update(&mut self, resolved: &ResolvedWindow, state: &DisplayState, resolver: &Resolver) {
+        set(&mut self.name, &resolved.name);
+        set(&mut self.raw_name, &resolved.wm_name);
+        set(&mut self.raw_class, &resolved.wm_class);
+        set(&mut self.wm_name, &resolved.wm_name);
+        set(&mut self.wm_class, &resolved.wm_class);
+        set(&mut self.instance, &resolved.instance);
+        self.window = resolved.window;
+        self.is_desktop = resolved.window == 0;
+        self.is_ignored = resolved.ignored;
+        self.stale = state.stale;
+        self.xwayland = resolved.xwayland || state.xwayland;
+        set_option(&mut self.child_process, resolved.child_process.as_deref());
+        set_option(&mut self.flatpak_id, resolved.flatpak_id.as_deref());
+        set_option(&mut self.client_machine, resolved.client_machine.as_deref());
+        self.remote = resolved.remote;
+
+        let app = resolved.app.as_ref();
+        set_option(&mut self.app, app.map(|app| app.id.as_str()));
+        set_option(&mut self.app_short_name, app.and_then(|app| app.short_name.as_deref()));
+        set_option(&mut self.app_icon, app.and_then(|app| app.icon.as_deref()));
+        set_option(&mut self.app_color, app.and_then(|app| app.color.as_deref()));
+
+        match state.focus_color {
+            Some(color) => {
+                let focus_color = self.focus_color.get_or_insert_with(String::new);
+                focus_color.clear();
+                let _ = write!(focus_color, "{color}");
+            }
+            None => self.focus_color = None,
+        }
+
+        match &state.desktop {
+            Some(desktop) => {
+                self.desktop_index = Some(desktop.index);
+                self.workspace_index = Some(desktop.index);
+                set(self.workspace.get_or_insert_with(String::new), &desktop.display_name());
+
+                let name = self.desktop.get_or_insert_with(String::new);
+
+                match &desktop.name {
+                    Some(desktop_name) => set(name, &resolver.resolve_desktop_name(desktop_name)),
+                    None => {
+                        name.clear();
+                        let _ = write!(name, "{}", desktop.index + 1);
+                    }
+                }
+            }
+            None => {
+                self.desktop_index = None;
+                self.desktop = None;
+                self.workspace_index = None;
+                self.workspace = None;
+            }
+        }
+    }
+}
+
+fn set(target: &mut String, value: &str) {
+    target.clear();
+    target.push_str(value);
+}
+
+fn set_option(target: &mut Option<String>, value: Option<&str>) {
+    match value {
+        Some(value) => set(target.get_or_insert_with(String::new), value),
+        None => *target = None,
+    }
+}
+
+pub fn render(handlebars: &Handlebars, config: &Config, context: &RenderContext, state: &DisplayState) -> anyhow::Result<String> {
+    let rendered = render_truncated(handlebars, config, context, state, config.max_output_length)?;
+    wrap_actions(handlebars, config, context, rendered)
+}
+
+fn wrap_actions(handlebars: &Handlebars, config: &Config, context: &RenderContext, rendered: String) -> anyhow::Result<String> {
+    if has_actions(config) {
+        return config.action.wrap(handlebars, context, rendered)
+    }
+
+    Ok(rendered)
+}
+
+/// Whether the main output is wrapped in polybar's action tags. Plain output never is, see [`output::styling`].
+fn has_actions(config: &Config) -> bool {
+    output::styling(config) && matches!((&config.output, config.output_format), (Output::Stdout, OutputFormat::Polybar) | (Output::PolybarIpc(_), _))
+}
+
+pub fn render_truncated(
+    handlebars: &Handlebars,
+    config: &Config,
+    context: &RenderContext,
+    state: &DisplayState,
+    max_output_length: Option<usize>,
+) -> anyhow::Result<String> {
+    if context.is_desktop && config.resolver.desktop_behavior == DesktopBehavior::Empty {
+        tracing::debug!("no window is active, rendering nothing");
+        return Ok(String::new())
+    }
+
+    if context.is_ignored {
+        tracing::debug!("window is ignored, rendering nothing");
+        return Ok(String::new())
+    }
+
+    let template = if state.highlighted && handlebars.has_template("changed_template") { "changed_template" } else { "template" };
+
+    tracing::debug!(%template, "rendering resolved name");
+    let deadline = Instant::now() + config.render_budget.get();
+    let rendered = templating::render_before(handlebars, template, context, deadline).context("failed to render template")?;
+
+    let prefix = match (&config.workspace_prefix, &state.desktop) {
+        (Some(workspace_prefix), Some(desktop)) if workspace_prefix.applies_to(desktop) => {
+            tracing::debug!("rendering workspace prefix");
+            templating::render_before(handlebars, "workspace_prefix", context, deadline).context("failed to render workspace prefix")?
+        }
+        _ => Some(String::new()),
+    };
+
+    let mut rendered = match (prefix, rendered) {
+        (Some(prefix), Some(rendered)) => prefix + &rendered,
+        _ => {
+            tracing::warn!(budget = %config.render_budget, "rendering went over budget, falling back to just the name");
+            handlebars.get_escape_fn()(&context.name)
+        }
+    };
+
+    if let Some(max) = max_output_length {
+        rendered = truncate::truncate(&rendered, max);
+    }
+
+    Ok(rendered)
+}
+
+/// The value of `result`, or the default if it failed, in which case the error is kept in `error` unless an earlier
+/// one is already. Timers which fire together all go off even if one of them fails.
+fn keep<T: Default>(result: anyhow::Result<T>, error: &mut Option<anyhow::Error>) -> T {
+    result.unwrap_or_else(|failure| {
+        error.get_or_insert(failure);
+        T::default()
+    })
+}
+
+#[derive(Copy, Clone)]
+struct Watched {
+    watcher: Option<RawFd>,
+    hangup: Option<RawFd>,
+    enricher: Option<RawFd>,
+}
+
+/// Whether `_NET_ACTIVE_WINDOW` was deleted since the last time it was read, which some window managers do instead of
+/// setting it to 0 when the last window closes. Reading it then would only come back empty, so no window is active.
+#[derive(Default, Debug)]
+struct ActiveWindow {
+    deleted: bool,
+}
+
+impl ActiveWindow {
+    fn on_property_notify(&mut self, event: &PropertyNotifyEvent) {
+        self.deleted = event.state == Property::DELETE;
+    }
+
+    fn take_deleted(&mut self) -> bool {
+        let deleted = std::mem::take(&mut self.deleted);
+        if deleted {
+            tracing::debug!("_NET_ACTIVE_WINDOW was deleted, no window is active");
+        }
+
+        deleted
+    }
+}
+
+enum Wakeup {
+    Event(Event),
+    Deadline,
+    Readable(RawFd),
+}
+
+fn wait_for_event(connection: &RustConnection, deadline: Option<Instant>, watched: &[RawFd]) -> anyhow::Result<Wakeup> {
+    if deadline.is_none() && watched.is_empty() {
+        return connection.wait_for_event().map(Wakeup::Event).context("could not wait for event")
+    }
+
+    loop {
+        if let Some(event) = connection.poll_for_event().context("could not poll for event")? {
+            return Ok(Wakeup::Event(event))
+        }
+
+        connection.flush().context("failed to flush connection")?;
+
+        let timeout = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout.is_zero() {
+                    return Ok(Wakeup::Deadline)
+                }
+
+                i32::try_from(timeout.as_millis().max(1)).unwrap_or(i32::MAX)
+            }
+            None => -1,
+        };
+
+        let mut fds = vec![PollFd::new(connection.stream().as_raw_fd(), PollFlags::POLLIN)];
+        fds.extend(watched.iter().map(|fd| PollFd::new(*fd, PollFlags::POLLIN)));
+
+        match poll(&mut fds, timeout) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(error) => return Err(error).context("failed to poll X connection"),
+        }
+
+        let readable = fds[1..]
+            .iter()
+            .position(|fd| fd.revents().is_some_and(|revents| revents.contains(PollFlags::POLLIN)));
+
+        if let Some(index) = readable {
+            return Ok(Wakeup::Readable(watched[index]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::actions::Actions;
+    use crate::application::AppInfo;
+    use crate::outputs::{ExtraOutput, Format, Target};
+    use crate::units::Millis;
+    use crate::WindowProperties;
+
+    fn context(config: &Config, resolved: &ResolvedWindow) -> RenderContext {
+        let mut context = RenderContext::default();
+        context.update(resolved, &DisplayState::default(), &config.resolver);
+        context
+    }
+
+    #[test]
+    fn plain_output_has_no_actions() {
+        let config = Config {
+            template: "{{ name }}".to_owned(),
+            styling: false,
+            action: Actions { left_click: Some("xdotool windowactivate {{ window }}".to_owned()), ..Actions::default() },
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+        let resolved = ResolvedWindow::without_properties(1, "Firefox".to_owned());
+
+        let rendered = render(&handlebars, &config, &context(&config, &resolved), &DisplayState::default()).unwrap();
+
+        assert_eq!(rendered, "Firefox");
+        assert!(!rendered.contains("%{A"));
+    }
+
+    #[test]
+    fn highlighted_renders_changed_template() {
+        let config = Config {
+            template: "{{ name }}".to_owned(),
+            changed_template: Some("*{{ name }}*".to_owned()),
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+        let resolved = ResolvedWindow::without_properties(1, "Firefox".to_owned());
+        let context = context(&config, &resolved);
+        let highlighted = DisplayState { highlighted: true, ..DisplayState::default() };
+
+        assert_eq!(render(&handlebars, &config, &context, &highlighted).unwrap(), "*Firefox*");
+        assert_eq!(render(&handlebars, &config, &context, &DisplayState::default()).unwrap(), "Firefox");
+    }
+
+    #[test]
+    fn raw_values_diverge_from_resolved_ones() {
+        let config = Config::from_sources([r#"
+            template = "{{ name }} — {{ raw_name }} ({{ raw_class }})"
+            strict_templates = true
+
+            [resolver.filters]
+            "class=firefox" = { filter = "new_name", value = "Browser" }
+            "class=alacritty" = { filter = "template", value = "{{ raw_class }}: {{ raw_name }}" }
+        "#]).unwrap();
+        let handlebars = templates(&config).unwrap();
+        let render = |class: &str, title: &str| {
+            let properties = WindowProperties {
+                class,
+                instance: class,
+                name: title,
+                role: None,
+                child_process: None,
+                flatpak_id: None,
+                client_machine: None,
+                remote: None,
+                xwayland: None,
+            };
+            let resolved = config.resolver.resolve_properties(1, properties);
+            render(&handlebars, &config, &context(&config, &resolved), &DisplayState::default()).unwrap()
+        };
+
+        assert_eq!(render("firefox", "rust lang book"), "Browser — rust lang book (firefox)");
+        assert_eq!(render("alacritty", "vim"), "alacritty: vim — vim (alacritty)");
+    }
+
+    #[test]
+    fn branching_template() {
+        let config = Config {
+            template: r#"{{#if (contains name "YouTube" true)}}▶ {{ name }}{{else}}{{#eq wm_class "firefox"}}🦊 {{/eq}}{{ name }}{{/if}}"#.to_owned(),
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+        let render = |class: &str, title: &str| {
+            let resolved = ResolvedWindow { wm_class: class.to_owned(), ..ResolvedWindow::without_properties(1, title.to_owned()) };
+            render(&handlebars, &config, &context(&config, &resolved), &DisplayState::default()).unwrap()
+        };
+
+        assert_eq!(render("firefox", "Lo-fi beats - youtube"), "▶ Lo-fi beats - youtube");
+        assert_eq!(render("firefox", "Mozilla Firefox"), "🦊 Mozilla Firefox");
+        assert_eq!(render("alacritty", "vim"), "vim");
+    }
+
+    #[test]
+    fn render_over_budget_falls_back_to_name() {
+        let config = Config {
+            template: "{{ name }} and more".to_owned(),
+            render_budget: Millis::from_millis(0),
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+        let resolved = ResolvedWindow::without_properties(1, "100%".to_owned());
+
+        let rendered = render(&handlebars, &config, &context(&config, &resolved), &DisplayState::default()).unwrap();
+
+        assert_eq!(rendered, "100%%");
+    }
+
+    #[test]
+    fn reused_render_context_matches_a_fresh_one() {
+        let config = Config {
+            template: "{{ name }}|{{ instance }}|{{ app }}|{{ app_icon }}|{{ desktop }}|{{ workspace }}|{{ focus_color }}|{{ child_process }}".to_owned(),
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+
+        let firefox = ResolvedWindow {
+            instance: "Navigator".to_owned(),
+            child_process: Some("firefox-bin".to_owned()),
+            client_machine: Some("laptop".to_owned()),
+            app: Some(AppInfo { id: "firefox".to_owned(), short_name: Some("ff".to_owned()), icon: Some("".to_owned()), color: None }),
+            ..ResolvedWindow::without_properties(1, "Mozilla Firefox with a longer title".to_owned())
+        };
+        let terminal = ResolvedWindow {
+            flatpak_id: Some("org.wezfurlong.wezterm".to_owned()),
+            remote: true,
+            xwayland: true,
+            ..ResolvedWindow::without_properties(2, "vim".to_owned())
+        };
+        let desktop = ResolvedWindow::without_properties(0, String::new());
+
+        let named = DisplayState {
+            desktop: Some(Desktop { index: 2, name: Some("web".to_owned()) }),
+            focus_color: Some(Color { red: 0xff, green: 0x80, blue: 0 }),
+            stale: true,
+            ..DisplayState::default()
+        };
+        let unnamed = DisplayState { desktop: Some(Desktop { index: 9, name: None }), ..DisplayState::default() };
+
+        let events = [
+            (&firefox, &named),
+            (&terminal, &DisplayState::default()),
+            (&firefox, &unnamed),
+            (&desktop, &named),
+            (&terminal, &unnamed),
+            (&firefox, &DisplayState::default()),
+        ];
+        let mut reused = RenderContext::default();
+
+        for (resolved, state) in events {
+            let mut fresh = RenderContext::default();
+            fresh.update(resolved, state, &config.resolver);
+            reused.update(resolved, state, &config.resolver);
+
+            assert_eq!(serde_json::to_value(&reused).unwrap(), serde_json::to_value(&fresh).unwrap());
+            assert_eq!(render(&handlebars, &config, &reused, state).unwrap(), render(&handlebars, &config, &fresh, state).unwrap());
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture render_loop` to compare the two.
+    #[test]
+    #[ignore]
+    fn render_loop() {
+        let config = Config::default();
+        let handlebars = templates(&config).unwrap();
+        let state = DisplayState { desktop: Some(Desktop { index: 0, name: Some("web".to_owned()) }), ..DisplayState::default() };
+        let windows = [
+            ResolvedWindow::without_properties(1, "Mozilla Firefox".to_owned()),
+            ResolvedWindow::without_properties(2, "nvim ~/src/polybar-title-module/src/app.rs".to_owned()),
+        ];
+
+        let start = Instant::now();
+        for resolved in windows.iter().cycle().take(1000) {
+            let mut context = RenderContext::default();
+            context.update(resolved, &state, &config.resolver);
+            render(&handlebars, &config, &context, &state).unwrap();
+        }
+        println!("fresh context: {:?}", start.elapsed());
+
+        let start = Instant::now();
+        let mut context = RenderContext::default();
+        for resolved in windows.iter().cycle().take(1000) {
+            context.update(resolved, &state, &config.resolver);
+            render(&handlebars, &config, &context, &state).unwrap();
+        }
+        println!("reused context: {:?}", start.elapsed());
+    }
+
+    /// A bounded run of what the `title_pipeline` fuzz target does, with a few option combinations on top.
+    #[test]
+    fn arbitrary_titles_through_the_pipeline() {
+        use unicode_segmentation::UnicodeSegmentation;
+        use crate::testing::Rng;
+        use crate::{sanitize, WindowProperties};
+
+        let fragments: [&[u8]; 16] = [
+            b"a", b"Z", b" ", b"\0", b"\n", b"\t", b"\x1b[31m", b"%{F#f00}", b"%", b"{{", b"}}", b"\xff", b"\xe2\x80",
+            "é".as_bytes(), "\u{301}".as_bytes(), "👩‍👩‍👧".as_bytes(),
+        ];
+        let configs = ["first_letter", "all_words", "upper", "snake", "camel"].map(|capitalize| {
+            let config = Config::from_sources([&*format!(r#"
+                max_output_length = 20
+
+                [resolver]
+                filters = {{}}
+                global_options = {{ capitalize = "{capitalize}", max_length = 12, preserve_acronyms = true }}
+            "#)]).unwrap();
+            let handlebars = templates(&config).unwrap();
+            (config, handlebars)
+        });
+        let mut rng = Rng::new(211);
+
+        for _ in 0..2000 {
+            let bytes = (0..rng.below(12)).flat_map(|_| rng.pick(&fragments).iter().copied()).collect::<Vec<_>>();
+            let title = String::from_utf8_lossy(&bytes);
+            let title = sanitize(&title);
+            let (config, handlebars) = rng.pick(&configs);
+
+            let resolved = config.resolver.resolve_properties(1, WindowProperties {
+                class: &title,
+                instance: &title,
+                name: &title,
+                role: None,
+                child_process: None,
+                flatpak_id: None,
+                client_machine: None,
+                remote: None,
+                xwayland: None,
+            });
+            assert!(resolved.name.graphemes(true).count() <= 12, "{bytes:?} resolved to {:?}", resolved.name);
+
+            let state = DisplayState::default();
+            let rendered = render(handlebars, config, &context(config, &resolved), &state).unwrap();
+            assert!(!rendered.contains(char::is_control), "{bytes:?} rendered to {rendered:?}");
+            assert!(truncate::visible_len(&rendered) <= 20, "{bytes:?} rendered to {rendered:?}");
+        }
+    }
+
+    #[test]
+    fn variables_are_the_fields_of_render_context() {
+        let context = RenderContext {
+            name: String::new(),
+            raw_name: String::new(),
+            raw_class: String::new(),
+            wm_name: String::new(),
+            wm_class: String::new(),
+            instance: String::new(),
+            window: 0,
+            stale: false,
+            xwayland: false,
+            child_process: Some(String::new()),
+            flatpak_id: Some(String::new()),
+            desktop_index: Some(0),
+            desktop: Some(String::new()),
+            workspace_index: Some(0),
+            workspace: Some(String::new()),
+            app: Some(String::new()),
+            app_short_name: Some(String::new()),
+            app_icon: Some(String::new()),
+            app_color: Some(String::new()),
+            focus_color: Some(String::new()),
+            client_machine: Some(String::new()),
+            remote: false,
+            is_desktop: false,
+            is_ignored: false,
+        };
+        let serde_json::Value::Object(fields) = serde_json::to_value(&context).unwrap() else {
+            panic!("render context isn't serialized as an object")
+        };
+
+        let mut fields = fields.keys().map(String::as_str).collect::<Vec<_>>();
+        let mut variables = VARIABLES.to_vec();
+        fields.sort_unstable();
+        variables.sort_unstable();
+
+        assert_eq!(fields, variables);
+    }
+
+    #[test]
+    fn template_variables_cover_every_template() {
+        let config = Config {
+            template: "{{ name }}".to_owned(),
+            changed_template: Some("{{ child_process }}".to_owned()),
+            outputs: vec![ExtraOutput { target: Target::Stdout, template: Some("bar".to_owned()), format: Format::Plain, max_output_length: None }],
+            templates: BTreeMap::from([("bar".to_owned(), "{{ workspace }}".to_owned())]),
+            ..Config::default()
+        };
+        let handlebars = templates(&config).unwrap();
+        let outputs = ExtraOutputs::new(&config).unwrap();
+
+        let variables = template_variables(&handlebars, &outputs);
+
+        for variable in ["name", "child_process", "workspace"] {
+            assert!(variables.contains(variable), "{variable} is missing");
+        }
+    }
+
+    fn property_notify(state: Property) -> PropertyNotifyEvent {
+        PropertyNotifyEvent { window: 1, atom: 2, state, ..PropertyNotifyEvent::default() }
+    }
+
+    #[test]
+    fn deleted_active_window_isnt_read() {
+        let mut active_window = ActiveWindow::default();
+
+        active_window.on_property_notify(&property_notify(Property::DELETE));
+
+        assert!(active_window.take_deleted());
+        // the next change is read again
+        assert!(!active_window.take_deleted());
+    }
+
+    #[test]
+    fn new_active_window_is_read() {
+        let mut active_window = ActiveWindow::default();
+
+        active_window.on_property_notify(&property_notify(Property::NEW_VALUE));
+        assert!(!active_window.take_deleted());
+
+        active_window.on_property_notify(&property_notify(Property::DELETE));
+        active_window.on_property_notify(&property_notify(Property::NEW_VALUE));
+        assert!(!active_window.take_deleted());
+    }
+}
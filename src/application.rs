@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use crate::name_template::NameTemplate;
+use crate::pattern::Pattern;
+use crate::WindowIdentifier;
+
+/// A logical application, which groups several window identities (e.g. the classes of the different Firefox
+/// channels) under one set of display properties.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Application {
+    pub matchers: Vec<WindowIdentifier>,
+
+    /// The name windows of the application are displayed as. The global options apply to the class otherwise.
+    pub name: Option<NameTemplate>,
+    pub short_name: Option<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+
+    /// Overrides of the above depending on the title, e.g. a play icon for a music player while its title starts
+    /// with "▶". The first variant whose condition matches applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Variant {
+    #[serde(flatten)]
+    pub condition: Condition,
+    pub name: Option<NameTemplate>,
+    pub short_name: Option<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Contains(String),
+    StartsWith(String),
+    Regex(Pattern),
+}
+
+impl Condition {
+    pub fn matches(&self, title: &str) -> bool {
+        match self {
+            Self::Contains(value) => title.contains(value.as_str()),
+            Self::StartsWith(value) => title.starts_with(value.as_str()),
+            Self::Regex(pattern) => pattern.is_match(title),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AppInfo {
+    pub id: String,
+    pub short_name: Option<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+}
+
+impl Application {
+    pub fn variant(&self, title: &str) -> Option<&Variant> {
+        self.variants.iter().find(|variant| variant.condition.matches(title))
+    }
+
+    pub fn name(&self, title: &str) -> Option<&NameTemplate> {
+        self.variant(title).and_then(|variant| variant.name.as_ref()).or(self.name.as_ref())
+    }
+
+    pub fn info(&self, id: &str, title: &str) -> AppInfo {
+        let variant = self.variant(title);
+        let pick = |own: &Option<String>, overridden: Option<&Option<String>>| overridden.cloned().flatten().or_else(|| own.clone());
+
+        AppInfo {
+            id: id.to_owned(),
+            short_name: pick(&self.short_name, variant.map(|variant| &variant.short_name)),
+            icon: pick(&self.icon, variant.map(|variant| &variant.icon)),
+            color: pick(&self.color, variant.map(|variant| &variant.color)),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Applications {
+    applications: BTreeMap<String, Application>,
+    matchers: HashMap<WindowIdentifier, String>,
+}
+
+impl Applications {
+    pub fn get(&self, identifier: &WindowIdentifier) -> Option<(&str, &Application)> {
+        let id = self.matchers.get(identifier)?;
+        Some((id, &self.applications[id]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Applications {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let applications = BTreeMap::<String, Application>::deserialize(deserializer)?;
+        let mut matchers = HashMap::new();
+
+        for (id, application) in &applications {
+            for matcher in &application.matchers {
+                if let Some(other) = matchers.insert(matcher.clone(), id.clone()) {
+                    return Err(de::Error::custom(format_args!(
+                        "{matcher} is claimed by both the {other} and the {id} application"
+                    )))
+                }
+            }
+        }
+
+        Ok(Self { applications, matchers })
+    }
+}
+
+impl Serialize for Applications {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.applications.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applications(source: &str) -> Result<Applications, toml::de::Error> {
+        toml::from_str(source)
+    }
+
+    fn player() -> Application {
+        let applications = applications(r##"
+            [player]
+            matchers = ["class=spotify"]
+            name = "Music"
+            icon = "play"
+            color = "#1db954"
+            variants = [
+                { starts_with = "▶", icon = "playing" },
+                { regex = "(?i)paused", icon = "paused", color = "#888888", name = "Music (paused)" },
+                { contains = "▶", icon = "never" },
+            ]
+        "##).unwrap();
+
+        applications.get(&"class=spotify".parse().unwrap()).unwrap().1.clone()
+    }
+
+    #[test]
+    fn base_values_without_a_variant() {
+        let info = player().info("player", "Spotify Premium");
+
+        assert_eq!(info.id, "player");
+        assert_eq!(info.icon.as_deref(), Some("play"));
+        assert_eq!(info.color.as_deref(), Some("#1db954"));
+        assert_eq!(player().name("Spotify Premium").unwrap().to_string(), "Music");
+    }
+
+    #[test]
+    fn first_matching_variant_wins() {
+        let playing = player().info("player", "▶ Song - Paused Artist");
+        assert_eq!(playing.icon.as_deref(), Some("playing"));
+        // what the variant doesn't set is the application's own
+        assert_eq!(playing.color.as_deref(), Some("#1db954"));
+        assert_eq!(player().name("▶ Song - Paused Artist").unwrap().to_string(), "Music");
+
+        let paused = player().info("player", "Song - PAUSED");
+        assert_eq!(paused.icon.as_deref(), Some("paused"));
+        assert_eq!(paused.color.as_deref(), Some("#888888"));
+        assert_eq!(player().name("Song - PAUSED").unwrap().to_string(), "Music (paused)");
+    }
+
+    #[test]
+    fn conditions() {
+        assert!(Condition::Contains("▶".to_owned()).matches("Song ▶"));
+        assert!(!Condition::StartsWith("▶".to_owned()).matches("Song ▶"));
+        assert!(Condition::StartsWith("▶".to_owned()).matches("▶ Song"));
+        assert!(Condition::Regex("^\\d+%".parse().unwrap()).matches("42% done"));
+        assert!(!Condition::Regex("^\\d+%".parse().unwrap()).matches("done 42%"));
+    }
+
+    #[test]
+    fn identifier_in_two_applications() {
+        let error = applications(r#"
+            [chromium]
+            matchers = ["class=chromium"]
+
+            [chrome]
+            matchers = ["class=google-chrome", "class=chromium"]
+        "#).unwrap_err();
+
+        assert!(error.to_string().contains("is claimed by both the chrome and the chromium application"), "{error}");
+    }
+}
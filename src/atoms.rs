@@ -0,0 +1,57 @@
+use anyhow::Context;
+use x11rb::cookie::Cookie;
+use x11rb::errors::ConnectionError;
+use x11rb::protocol::xproto::{Atom, ConnectionExt, InternAtomReply};
+use x11rb::rust_connection::RustConnection;
+
+/// The atoms which are needed over and over, interned once when connecting, so that handling events and resolving
+/// windows never waits for the X server just to learn what an atom is called.
+#[derive(Copy, Clone, Debug)]
+pub struct Atoms {
+    pub net_active_window: Atom,
+    pub net_current_desktop: Atom,
+    pub net_desktop_names: Atom,
+    pub net_wm_pid: Atom,
+    pub net_wm_name: Atom,
+    pub utf8_string: Atom,
+    pub flatpak_id: Atom,
+    pub wm_window_role: Atom,
+    pub net_supporting_wm_check: Atom,
+}
+
+impl Atoms {
+    pub fn new(connection: &RustConnection) -> anyhow::Result<Self> {
+        // every request is sent before waiting for any reply, so they share a single round trip
+        let [net_active_window, net_current_desktop, net_desktop_names, net_wm_pid, net_wm_name, utf8_string, flatpak_id, wm_window_role, net_supporting_wm_check] = [
+            "_NET_ACTIVE_WINDOW",
+            "_NET_CURRENT_DESKTOP",
+            "_NET_DESKTOP_NAMES",
+            "_NET_WM_PID",
+            "_NET_WM_NAME",
+            "UTF8_STRING",
+            "_FLATPAK_ID",
+            "WM_WINDOW_ROLE",
+            "_NET_SUPPORTING_WM_CHECK",
+        ]
+            .map(|name| (name, connection.intern_atom(false, name.as_bytes())));
+        Ok(Self {
+            net_active_window: atom(net_active_window)?,
+            net_current_desktop: atom(net_current_desktop)?,
+            net_desktop_names: atom(net_desktop_names)?,
+            net_wm_pid: atom(net_wm_pid)?,
+            net_wm_name: atom(net_wm_name)?,
+            utf8_string: atom(utf8_string)?,
+            flatpak_id: atom(flatpak_id)?,
+            wm_window_role: atom(wm_window_role)?,
+            net_supporting_wm_check: atom(net_supporting_wm_check)?,
+        })
+    }
+}
+
+fn atom((name, cookie): (&str, Result<Cookie<'_, RustConnection, InternAtomReply>, ConnectionError>)) -> anyhow::Result<Atom> {
+    Ok(cookie
+        .with_context(|| format!("failed to make InternAtom reply for {name}"))?
+        .reply()
+        .with_context(|| format!("InternAtom response for {name} failed"))?
+        .atom)
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::Window;
+use crate::ResolvedWindow;
+
+const FAILURE_THRESHOLD: u32 = 3;
+
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+const MAX_TRACKED: usize = 256;
+
+/// Stops querying windows whose requests keep failing, so that one broken client doesn't make every focus change
+/// pay for failed requests.
+///
+/// After [`FAILURE_THRESHOLD`] failures in a row the breaker opens and the window isn't queried for [`COOLDOWN`].
+/// Once that passed, the next query is let through: if it succeeds the breaker closes again, otherwise it opens for
+/// another cooldown.
+#[derive(Default)]
+pub struct CircuitBreaker {
+    windows: HashMap<Window, WindowHealth>,
+}
+
+#[derive(Default)]
+struct WindowHealth {
+    failures: u32,
+    open_until: Option<Instant>,
+    last: Option<ResolvedWindow>,
+}
+
+impl CircuitBreaker {
+    pub fn allows(&self, window: Window, now: Instant) -> bool {
+        self.windows
+            .get(&window)
+            .and_then(|health| health.open_until)
+            .is_none_or(|until| now >= until)
+    }
+
+    pub fn succeeded(&mut self, resolved: &ResolvedWindow) {
+        let health = self.health(resolved.window);
+        health.failures = 0;
+        health.open_until = None;
+        health.last = Some(resolved.clone());
+    }
+
+    pub fn failed(&mut self, window: Window, now: Instant) -> bool {
+        let health = self.health(window);
+        health.failures += 1;
+
+        if health.failures >= FAILURE_THRESHOLD {
+            tracing::warn!(%window, failures = health.failures, "window keeps failing, not querying it for {COOLDOWN:?}");
+            health.open_until = Some(now + COOLDOWN);
+        }
+
+        health.open_until.is_some()
+    }
+
+    pub fn last(&self, window: Window) -> Option<&ResolvedWindow> {
+        self.windows.get(&window)?.last.as_ref()
+    }
+
+    fn health(&mut self, window: Window) -> &mut WindowHealth {
+        if self.windows.len() >= MAX_TRACKED && !self.windows.contains_key(&window) {
+            self.windows.clear()
+        }
+
+        self.windows.entry(window).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(window: Window) -> ResolvedWindow {
+        ResolvedWindow::without_properties(window, format!("window {window}"))
+    }
+
+    fn open(breaker: &mut CircuitBreaker, window: Window, now: Instant) {
+        for _ in 1..FAILURE_THRESHOLD {
+            assert!(!breaker.failed(window, now));
+        }
+
+        assert!(breaker.failed(window, now));
+    }
+
+    #[test]
+    fn opens_after_failures_in_a_row() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        assert!(breaker.allows(1, now));
+        open(&mut breaker, 1, now);
+        assert!(!breaker.allows(1, now));
+        assert!(!breaker.allows(1, now + COOLDOWN - Duration::from_millis(1)));
+
+        // other windows are still queried
+        assert!(breaker.allows(2, now));
+    }
+
+    #[test]
+    fn success_resets_the_failures() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.failed(1, now);
+        }
+
+        breaker.succeeded(&resolved(1));
+        assert!(!breaker.failed(1, now));
+        assert!(breaker.allows(1, now));
+    }
+
+    #[test]
+    fn half_open_after_cooldown() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+        open(&mut breaker, 1, now);
+
+        // the one query let through fails, which opens it for another cooldown
+        let half_open = now + COOLDOWN;
+        assert!(breaker.allows(1, half_open));
+        assert!(breaker.failed(1, half_open));
+        assert!(!breaker.allows(1, half_open));
+        assert!(!breaker.allows(1, half_open + COOLDOWN - Duration::from_millis(1)));
+
+        // the next one succeeds, which closes it
+        let half_open = half_open + COOLDOWN;
+        assert!(breaker.allows(1, half_open));
+        breaker.succeeded(&resolved(1));
+        assert!(breaker.allows(1, half_open));
+        assert!(!breaker.failed(1, half_open));
+    }
+
+    #[test]
+    fn remembers_the_last_success() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        assert!(breaker.last(1).is_none());
+        breaker.succeeded(&resolved(1));
+        open(&mut breaker, 1, now);
+
+        assert_eq!(breaker.last(1).map(|resolved| resolved.name.as_str()), Some("window 1"));
+    }
+}
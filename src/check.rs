@@ -0,0 +1,87 @@
+//! The `check` command, which shows how a window with the given properties would be resolved and rendered, without
+//! an X server, to find out why a filter doesn't match.
+
+use std::fmt::Write;
+use crate::app::{render, templates, DisplayState, RenderContext};
+use crate::{which, Config, WindowProperties};
+
+#[derive(Default, Debug)]
+pub struct CheckWindow {
+    pub class: String,
+    pub instance: String,
+    pub name: String,
+    pub role: Option<String>,
+    pub child_process: Option<String>,
+    pub flatpak_id: Option<String>,
+}
+
+const WINDOW: u32 = 1;
+
+pub fn check(config: &Config, window: &CheckWindow) -> anyhow::Result<String> {
+    let properties = WindowProperties {
+        class: &window.class,
+        instance: &window.instance,
+        name: &window.name,
+        role: window.role.as_deref(),
+        child_process: window.child_process.as_deref(),
+        flatpak_id: window.flatpak_id.as_deref(),
+        client_machine: None,
+        remote: None,
+        xwayland: None,
+    };
+
+    let mut report = which::human(&which::of(config, WINDOW, properties), config);
+    let resolved = config.resolver.resolve_properties(WINDOW, properties);
+
+    if resolved.ignored {
+        let _ = writeln!(report);
+        let _ = writeln!(report, "the window is ignored, on_ignore is {:?}", config.resolver.on_ignore);
+    }
+
+    // as if the module just started, so there's no previous output to keep
+    let resolved = config.resolver.apply_ignore(resolved, false, None).expect("nothing was displayed before");
+
+    let handlebars = templates(config)?;
+    let state = DisplayState::default();
+    let mut context = RenderContext::default();
+    context.update(&resolved, &state, &config.resolver);
+    let rendered = render(&handlebars, config, &context, &state)?;
+
+    let _ = writeln!(report);
+    let _ = writeln!(report, "resolved name: {:?}", resolved.name);
+    let _ = writeln!(report, "output: {rendered}");
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(on_ignore: &str) -> String {
+        let config = Config::from_sources([format!(r#"
+            template = "{{{{ name }}}}"
+
+            [resolver]
+            desktop_name = "Desktop"
+            on_ignore = "{on_ignore}"
+
+            [resolver.filters]
+            "wm_class=plank" = {{ filter = "ignore" }}
+        "#).as_str()]).unwrap();
+        let window = CheckWindow { class: "plank".to_owned(), ..CheckWindow::default() };
+
+        let report = check(&config, &window).unwrap();
+        report.lines().find_map(|line| line.strip_prefix("output: ")).unwrap().to_owned()
+    }
+
+    #[test]
+    fn ignored_window_shows_desktop_when_kept() {
+        assert_eq!(output("keep"), "Desktop");
+    }
+
+    #[test]
+    fn ignored_window_shows_nothing_when_cleared() {
+        assert_eq!(output("clear"), "");
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use anyhow::Context;
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+
+const MAX_CACHED: usize = 256;
+
+/// Finds the client window when a window manager reports one of its frames, or a child window of the client, as
+/// the active window. Only the parent and the direct children of the reported window are searched.
+#[derive(Default)]
+pub struct ClientSearch {
+    cache: HashMap<Window, Option<Window>>,
+}
+
+impl ClientSearch {
+    pub fn find(&mut self, connection: &impl RequestConnection, window: Window) -> anyhow::Result<Option<Window>> {
+        if let Some(client) = self.cache.get(&window) {
+            return Ok(*client)
+        }
+
+        let tree = connection
+            .query_tree(window)
+            .context("failed to make QueryTree reply")?
+            .reply()
+            .context("QueryTree response failed")?;
+        let parent = Some(tree.parent).filter(|parent| *parent != tree.root && *parent != 0);
+        let mut client = None;
+
+        for candidate in parent.into_iter().chain(tree.children) {
+            if has_wm_class(connection, candidate)? {
+                client = Some(candidate);
+                break
+            }
+        }
+
+        tracing::debug!(%window, ?client, "searched for client window");
+
+        if self.cache.len() >= MAX_CACHED {
+            self.cache.clear()
+        }
+
+        self.cache.insert(window, client);
+
+        Ok(client)
+    }
+}
+
+fn has_wm_class(connection: &impl RequestConnection, window: Window) -> anyhow::Result<bool> {
+    let reply = connection
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 0)
+        .context("failed to make GetProperty reply for retrieving WM_CLASS")?
+        .reply()
+        .context("GetProperty response for retrieving WM_CLASS failed")?;
+
+    Ok(reply.bytes_after > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{u32_at, Logged, MockConnection};
+
+    const ROOT: Window = 1;
+
+    fn query_tree(parent: Window, children: &[Window]) -> Vec<u8> {
+        let mut reply = vec![1, 0, 0, 0];
+        reply.extend_from_slice(&(children.len() as u32).to_ne_bytes());
+        reply.extend_from_slice(&ROOT.to_ne_bytes());
+        reply.extend_from_slice(&parent.to_ne_bytes());
+        reply.extend_from_slice(&(children.len() as u16).to_ne_bytes());
+        reply.resize(32, 0);
+        reply.extend(children.iter().flat_map(|child| child.to_ne_bytes()));
+        reply
+    }
+
+    /// The reply to reading no bytes of `WM_CLASS`, which tells whether the window has it by the bytes left.
+    fn wm_class(has: bool) -> Vec<u8> {
+        let mut reply = vec![1, 8, 0, 0, 0, 0, 0, 0];
+        reply.extend_from_slice(&u32::from(AtomEnum::STRING).to_ne_bytes());
+        reply.extend_from_slice(&u32::from(has).to_ne_bytes());
+        reply.resize(32, 0);
+        reply
+    }
+
+    fn requested(connection: &MockConnection) -> Vec<Window> {
+        connection.take().iter().filter_map(|logged| match logged {
+            Logged::Sent(request) => Some(u32_at(request, 4)),
+            Logged::Answered => None,
+        }).collect()
+    }
+
+    #[test]
+    fn client_under_reported_frame() {
+        let connection = MockConnection::default();
+        let mut search = ClientSearch::default();
+        connection.reply(query_tree(ROOT, &[11, 12]));
+        connection.reply(wm_class(false));
+        connection.reply(wm_class(true));
+
+        assert_eq!(search.find(&connection, 10).unwrap(), Some(12));
+        // the root isn't a candidate, and the search stops at the client
+        assert_eq!(requested(&connection), [10, 11, 12]);
+    }
+
+    #[test]
+    fn client_above_reported_child() {
+        let connection = MockConnection::default();
+        let mut search = ClientSearch::default();
+        connection.reply(query_tree(20, &[]));
+        connection.reply(wm_class(true));
+
+        assert_eq!(search.find(&connection, 21).unwrap(), Some(20));
+        assert_eq!(requested(&connection), [21, 20]);
+    }
+
+    #[test]
+    fn only_direct_children_are_searched() {
+        let connection = MockConnection::default();
+        let mut search = ClientSearch::default();
+        // 31 is a frame inside the reported window, with the client below it
+        connection.reply(query_tree(ROOT, &[31]));
+        connection.reply(wm_class(false));
+
+        assert_eq!(search.find(&connection, 30).unwrap(), None);
+        assert_eq!(requested(&connection), [30, 31]);
+    }
+
+    #[test]
+    fn results_are_cached_per_reported_window() {
+        let connection = MockConnection::default();
+        let mut search = ClientSearch::default();
+        connection.reply(query_tree(ROOT, &[41]));
+        connection.reply(wm_class(true));
+        connection.reply(query_tree(ROOT, &[]));
+
+        assert_eq!(search.find(&connection, 40).unwrap(), Some(41));
+        assert_eq!(search.find(&connection, 50).unwrap(), None);
+        connection.take();
+
+        assert_eq!(search.find(&connection, 40).unwrap(), Some(41));
+        assert_eq!(search.find(&connection, 50).unwrap(), None);
+        assert!(connection.take().is_empty());
+    }
+
+    #[test]
+    fn gone_window_fails_the_search() {
+        let connection = MockConnection::with_gone([60]);
+        assert!(ClientSearch::default().find(&connection, 60).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Polybar colors around the output, set by `foreground`, `background` and `underline` in options, e.g. to color
+//! terminals green and browsers orange.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use crate::Options;
+
+#[derive(DeserializeFromStr, SerializeDisplay, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BarColor(String);
+
+impl FromStr for BarColor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').ok_or_else(|| anyhow::anyhow!("color {s:?} doesn't start with #"))?;
+        anyhow::ensure!(hex.chars().all(|c| c.is_ascii_hexdigit()), "color {s:?} contains something other than hex digits");
+        anyhow::ensure!(matches!(hex.len(), 6 | 8), "color {s:?} is neither #rrggbb nor #aarrggbb");
+
+        Ok(Self(format!("#{}", hex.to_ascii_lowercase())))
+    }
+}
+
+impl fmt::Display for BarColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub struct Colors {
+    pub foreground: Option<BarColor>,
+    pub background: Option<BarColor>,
+    pub underline: Option<BarColor>,
+}
+
+impl Colors {
+    /// Takes every color from the first of `options` which sets it.
+    pub fn of<'o>(options: impl IntoIterator<Item = &'o Options> + Clone) -> Self {
+        let first = |color: fn(&Options) -> &Option<BarColor>| options.clone().into_iter().find_map(|options| color(options).clone());
+
+        Self {
+            foreground: first(|options| &options.foreground),
+            background: first(|options| &options.background),
+            underline: first(|options| &options.underline),
+        }
+    }
+
+    /// Wraps `rendered` in polybar's format tags for the colors, each of which is reset at the end so that it never
+    /// bleeds into the modules next to this one. An empty output stays empty.
+    pub fn wrap<'r>(&self, rendered: &'r str) -> Cow<'r, str> {
+        if rendered.is_empty() || *self == Self::default() {
+            return Cow::Borrowed(rendered)
+        }
+
+        let mut wrapped = String::new();
+
+        if let Some(underline) = &self.underline {
+            wrapped.push_str(&format!("%{{u{underline}}}%{{+u}}"));
+        }
+
+        if let Some(background) = &self.background {
+            wrapped.push_str(&format!("%{{B{background}}}"));
+        }
+
+        if let Some(foreground) = &self.foreground {
+            wrapped.push_str(&format!("%{{F{foreground}}}"));
+        }
+
+        wrapped.push_str(rendered);
+
+        if self.foreground.is_some() {
+            wrapped.push_str("%{F-}");
+        }
+
+        if self.background.is_some() {
+            wrapped.push_str("%{B-}");
+        }
+
+        if self.underline.is_some() {
+            wrapped.push_str("%{-u}%{u-}");
+        }
+
+        Cow::Owned(wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_colors() {
+        for (color, parsed) in [("#ff9900", "#ff9900"), ("#FF9900", "#ff9900"), ("#80ff9900", "#80ff9900")] {
+            assert_eq!(color.parse::<BarColor>().unwrap().to_string(), parsed, "{color}");
+        }
+    }
+
+    #[test]
+    fn rejected_colors() {
+        for (color, error) in [
+            ("", "doesn't start with #"),
+            ("ff9900", "doesn't start with #"),
+            ("#", "neither #rrggbb nor #aarrggbb"),
+            ("#f90", "neither #rrggbb nor #aarrggbb"),
+            ("#ff99001", "neither #rrggbb nor #aarrggbb"),
+            ("#ff99zz", "other than hex digits"),
+            ("#+f9900", "other than hex digits"),
+            ("#ff99٠٠", "other than hex digits"),
+        ] {
+            let parsed = color.parse::<BarColor>();
+            assert!(parsed.as_ref().is_err_and(|parsed| parsed.to_string().contains(error)), "{color}: {parsed:?}");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Noticing when the content of the displayed window changes without any of its properties changing
+//! (`content_change_detection`), as kiosk-style applications do, by periodically hashing a small sample of it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::time::Instant;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::xproto::{ConnectionExt, ImageFormat, Window};
+use crate::units::Millis;
+use crate::ResolvedWindow;
+
+/// How many rows, and pixels within each of them, are sampled. Only the sampled rows are transferred, so a sample
+/// costs 32 requests and 32 rows worth of pixels no matter how tall the window is.
+const SAMPLES: u16 = 32;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ContentChangeDetection {
+    #[serde(default = "interval", alias = "interval_ms")]
+    pub interval: Millis,
+
+    #[serde(default)]
+    pub ignore_classes: Vec<String>,
+}
+
+fn interval() -> Millis {
+    Millis::from_millis(2000)
+}
+
+#[derive(Debug)]
+pub struct ContentWatcher {
+    config: ContentChangeDetection,
+    next: Instant,
+    last: Option<(Window, u64)>,
+}
+
+impl ContentWatcher {
+    pub fn new(config: ContentChangeDetection, now: Instant) -> Self {
+        Self {
+            next: now + config.interval.get(),
+            config,
+            last: None,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Samples `resolved` if it's due. Returns whether its content changed since the last sample.
+    pub fn on_deadline(&mut self, connection: &impl RequestConnection, resolved: &ResolvedWindow, now: Instant) -> bool {
+        if now < self.next {
+            return false
+        }
+
+        self.next = now + self.config.interval.get();
+
+        if resolved.window == 0 || self.config.ignore_classes.contains(&resolved.wm_class) {
+            self.last = None;
+            return false
+        }
+
+        match sample(connection, resolved.window) {
+            Ok(rows) => self.update(resolved.window, hash(&rows)),
+            Err(error) => {
+                // unmapped or minimized windows have no content which could be read
+                tracing::debug!(window = resolved.window, "failed to sample content of window: {error:#}");
+                self.last = None;
+                false
+            }
+        }
+    }
+
+    /// Remembers `hash` as the one of `window`. Returns whether it differs from the previous hash of the same window.
+    fn update(&mut self, window: Window, hash: u64) -> bool {
+        let changed = matches!(self.last, Some((last_window, last_hash)) if last_window == window && last_hash != hash);
+
+        if changed {
+            tracing::debug!(%window, "content of window changed");
+        }
+
+        self.last = Some((window, hash));
+        changed
+    }
+}
+
+fn sample(connection: &impl RequestConnection, window: Window) -> anyhow::Result<Vec<Vec<u8>>> {
+    let geometry = connection
+        .get_geometry(window)
+        .context("failed to make GetGeometry reply")?
+        .reply()
+        .context("GetGeometry response failed")?;
+
+    if geometry.width == 0 || geometry.height == 0 {
+        return Ok(Vec::new())
+    }
+
+    let rows = u32::from(SAMPLES.min(geometry.height));
+    let cookies = (0..rows)
+        .map(|row| {
+            // the middle of each of `rows` equally tall bands
+            let y = ((2 * row + 1) * u32::from(geometry.height) / (2 * rows)) as i16;
+
+            connection
+                .get_image(ImageFormat::Z_PIXMAP, window, 0, y, geometry.width, 1, u32::MAX)
+                .context("failed to make GetImage reply")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    cookies
+        .into_iter()
+        .map(|cookie| Ok(sample_row(&cookie.reply().context("GetImage response failed")?.data, geometry.width)))
+        .collect()
+}
+
+fn sample_row(data: &[u8], width: u16) -> Vec<u8> {
+    let bytes_per_pixel = (data.len() / usize::from(width)).max(1);
+    let pixels = data.len() / bytes_per_pixel;
+    let samples = usize::from(SAMPLES).min(pixels);
+
+    (0..samples)
+        .flat_map(|sample| {
+            let pixel = (2 * sample + 1) * pixels / (2 * samples);
+            &data[pixel * bytes_per_pixel..(pixel + 1) * bytes_per_pixel]
+        })
+        .copied()
+        .collect()
+}
+
+fn hash(rows: &[Vec<u8>]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for row in rows {
+        hasher.write(row);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::testing::{Logged, MockConnection};
+
+    /// A row of 4 byte pixels, where each pixel's bytes are its index.
+    fn row(width: u16) -> Vec<u8> {
+        (0..u32::from(width)).flat_map(u32::to_le_bytes).collect()
+    }
+
+    fn watcher(ignore_classes: &[&str], now: Instant) -> ContentWatcher {
+        let config = ContentChangeDetection {
+            interval: interval(),
+            ignore_classes: ignore_classes.iter().map(|class| class.to_string()).collect(),
+        };
+
+        ContentWatcher::new(config, now)
+    }
+
+    #[test]
+    fn rows_are_sampled_evenly() {
+        let sampled = sample_row(&row(1920), 1920);
+
+        // 32 pixels of 4 bytes, from the middle of each of 32 bands 60 pixels wide
+        assert_eq!(sampled.len(), 32 * 4);
+        assert_eq!(sampled[..4], 30u32.to_le_bytes());
+        assert_eq!(sampled[4..8], 90u32.to_le_bytes());
+        assert_eq!(sampled[124..], 1890u32.to_le_bytes());
+
+        // narrow rows are taken as a whole
+        assert_eq!(sample_row(&row(10), 10), row(10));
+        assert!(sample_row(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn only_sampled_pixels_change_the_hash() {
+        let rows = vec![row(1920); 32];
+        let sampled = |rows: &[Vec<u8>]| hash(&rows.iter().map(|row| sample_row(row, 1920)).collect::<Vec<_>>());
+
+        let mut changed = rows.clone();
+        changed[7][30 * 4] ^= 0xff;
+        assert_ne!(sampled(&changed), sampled(&rows));
+
+        let mut between = rows.clone();
+        between[7][31 * 4] ^= 0xff;
+        assert_eq!(sampled(&between), sampled(&rows));
+    }
+
+    #[test]
+    fn changes_are_only_noticed_within_one_window() {
+        let mut watcher = watcher(&[], Instant::now());
+
+        assert!(!watcher.update(1, 10));
+        assert!(!watcher.update(1, 10));
+        assert!(watcher.update(1, 11));
+        // another window isn't a change of content
+        assert!(!watcher.update(2, 12));
+        assert!(watcher.update(2, 13));
+    }
+
+    #[test]
+    fn sampled_only_when_due_and_not_ignored() {
+        let start = Instant::now();
+        let connection = MockConnection::default();
+        let mut watcher = watcher(&["mpv"], start);
+        let video = ResolvedWindow { wm_class: "mpv".to_owned(), ..ResolvedWindow::without_properties(1, "video.mkv".to_owned()) };
+
+        assert!(!watcher.on_deadline(&connection, &video, start + Duration::from_secs(1)));
+        assert!(!watcher.on_deadline(&connection, &video, start + Duration::from_secs(2)));
+        assert_eq!(watcher.deadline(), start + Duration::from_secs(4));
+
+        let desktop = ResolvedWindow::without_properties(0, String::new());
+        assert!(!watcher.on_deadline(&connection, &desktop, start + Duration::from_secs(4)));
+        assert_eq!(connection.take(), []);
+    }
+
+    #[test]
+    fn windows_which_cant_be_sampled_are_skipped() {
+        let start = Instant::now();
+        let connection = MockConnection::with_gone([1]);
+        let mut watcher = watcher(&[], start);
+        watcher.update(1, 10);
+
+        let window = ResolvedWindow::without_properties(1, "kiosk".to_owned());
+        assert!(!watcher.on_deadline(&connection, &window, start + Duration::from_secs(2)));
+
+        assert_eq!(connection.take().last(), Some(&Logged::Answered));
+        assert_eq!(watcher.last, None);
+    }
+}
@@ -0,0 +1,485 @@
+//! The control socket, through which other programs (click actions, keybindings, scripts) query and steer the
+//! running module.
+//!
+//! Clients send one command per line and get one JSON object per line back. Every connection is served on its own
+//! thread, but the commands themselves are handed to the main loop and carried out there one after another, so they
+//! apply in the order they arrived and always see a consistent state.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use anyhow::Context;
+use serde::Serialize;
+use x11rb::protocol::xproto::Window;
+use crate::metrics::{Stage, Summary};
+use crate::usage::ClassUsage;
+
+/// The version of the shape of responses, which is raised whenever a field is removed or changes its meaning. Fields
+/// are added without raising it, so clients should ignore the ones they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub const MAX_LINE_LENGTH: usize = 4096;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Command {
+    Get,
+    Refresh,
+    Pause,
+    Resume,
+    Reload,
+    Rename(String),
+    Unrename,
+    UsageStats,
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, argument) = match s.trim().split_once(' ') {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+
+        match (command, argument) {
+            ("get", None) => Ok(Self::Get),
+            ("refresh", None) => Ok(Self::Refresh),
+            ("pause", None) => Ok(Self::Pause),
+            ("resume", None) => Ok(Self::Resume),
+            ("reload", None) => Ok(Self::Reload),
+            ("rename", Some(name)) if !name.is_empty() => Ok(Self::Rename(name.to_owned())),
+            ("rename", _) => anyhow::bail!("rename expects a name"),
+            ("unrename", None) => Ok(Self::Unrename),
+            ("stats", Some("usage")) => Ok(Self::UsageStats),
+            ("stats", _) => anyhow::bail!("stats expects what to report, which can only be usage"),
+            (command, Some(_)) if ["get", "refresh", "pause", "resume", "reload", "unrename"].contains(&command) => {
+                anyhow::bail!("{command} expects no arguments")
+            }
+            (command, _) => anyhow::bail!(
+                "unknown command {command:?}, expected get, refresh, pause, resume, reload, rename, unrename or stats"
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub window: Option<Window>,
+
+    /// The name the filters (or a rename) resolved the window to, before any template decorated it, e.g. `Firefox`.
+    pub display_name: Option<String>,
+    pub rendered: Option<String>,
+    pub raw_title: Option<String>,
+    pub paused: bool,
+    pub dropped: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<BTreeMap<Stage, Summary>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Vec<ClassUsage>>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self { version: SCHEMA_VERSION, ok: true, error: None, status: None, usage: None }
+    }
+
+    pub fn status(status: Status) -> Self {
+        Self { status: Some(status), ..Self::ok() }
+    }
+
+    pub fn usage(usage: Vec<ClassUsage>) -> Self {
+        Self { usage: Some(usage), ..Self::ok() }
+    }
+
+    pub fn error(error: &anyhow::Error) -> Self {
+        Self { ok: false, error: Some(format!("{error:#}")), ..Self::ok() }
+    }
+}
+
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Response>,
+}
+
+impl Request {
+    pub fn reply(self, response: Response) {
+        // the client might have given up waiting already
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Accepts connections on the control socket in the background and queues their commands for the main loop.
+///
+/// Whenever a command is queued, a byte is written to a socket pair whose other end is watched by the main loop,
+/// so it wakes up without having to poll.
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Request>,
+    wakeup: UnixStream,
+}
+
+impl ControlServer {
+    pub fn bind(path: &Path) -> anyhow::Result<Self> {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("another instance is already listening on {}", path.display())
+        }
+
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                return Err(error).with_context(|| format!("failed to remove stale control socket {}", path.display()))
+            }
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(path).with_context(|| format!("failed to bind control socket {}", path.display()))?;
+        let (wakeup, notify) = UnixStream::pair().context("failed to create wakeup socket pair")?;
+        wakeup.set_nonblocking(true).context("failed to make wakeup socket non-blocking")?;
+
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new()
+            .name("control".to_owned())
+            .spawn(move || accept(listener, sender, notify))
+            .context("failed to spawn control socket thread")?;
+
+        tracing::info!(path = %path.display(), "listening on control socket");
+
+        Ok(Self {
+            path: path.to_owned(),
+            requests,
+            wakeup,
+        })
+    }
+
+    pub fn on_readable(&mut self) -> Vec<Request> {
+        let mut buffer = [0; 64];
+        while matches!(self.wakeup.read(&mut buffer), Ok(read) if read > 0) {}
+
+        self.requests.try_iter().collect()
+    }
+}
+
+impl AsRawFd for ControlServer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.wakeup.as_raw_fd()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn accept(listener: UnixListener, sender: Sender<Request>, notify: UnixStream) {
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                tracing::warn!("failed to accept control connection: {error}");
+                continue
+            }
+        };
+
+        if connections.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            tracing::warn!("too many control connections, turning one away");
+            let _ = respond(&mut stream, &Response::error(&anyhow::anyhow!("too many connections")));
+            continue
+        }
+
+        let notify = match notify.try_clone() {
+            Ok(notify) => notify,
+            Err(error) => {
+                tracing::warn!("failed to clone wakeup socket: {error}");
+                continue
+            }
+        };
+        let sender = sender.clone();
+        connections.fetch_add(1, Ordering::SeqCst);
+
+        let spawned = thread::Builder::new().name("control connection".to_owned()).spawn({
+            let connections = Arc::clone(&connections);
+
+            move || {
+                if let Err(error) = serve(stream, &sender, &notify) {
+                    tracing::debug!("control connection ended: {error:#}");
+                }
+
+                connections.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+
+        if let Err(error) = spawned {
+            tracing::warn!("failed to spawn control connection thread: {error}");
+            connections.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+fn serve(stream: UnixStream, sender: &Sender<Request>, mut notify: &UnixStream) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT)).context("failed to set read timeout")?;
+    let mut reader = BufReader::new(stream.try_clone().context("failed to clone control connection")?);
+    let mut writer = stream;
+
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut reader)
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .context("failed to read command")?;
+
+        if read == 0 {
+            return Ok(())
+        }
+
+        if line.len() > MAX_LINE_LENGTH {
+            // closing the connection with the rest of the line unread would fail the client's write before it gets
+            // to read the response
+            if !line.ends_with(b"\n") {
+                reader.skip_until(b'\n').context("failed to skip the rest of a line which is too long")?;
+            }
+
+            respond(&mut writer, &Response::error(&anyhow::anyhow!("line is longer than {MAX_LINE_LENGTH} bytes")))?;
+            anyhow::bail!("client sent a line which is too long")
+        }
+
+        let command = std::str::from_utf8(&line)
+            .context("command is not valid utf-8")
+            .and_then(str::parse::<Command>);
+        let response = match command {
+            Ok(command) => {
+                let (reply, response) = mpsc::channel();
+                sender.send(Request { command, reply }).context("main loop is gone")?;
+                notify.write_all(&[0]).context("failed to wake main loop")?;
+
+                response
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Response::error(&anyhow::anyhow!("timed out waiting for the module")))
+            }
+            Err(error) => Response::error(&error),
+        };
+
+        respond(&mut writer, &response)?;
+    }
+}
+
+fn respond(stream: &mut UnixStream, response: &Response) -> io::Result<()> {
+    let mut line = serde_json::to_string(response).map_err(io::Error::from)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+pub fn send(path: &Path, command: &str) -> anyhow::Result<String> {
+    let mut stream = UnixStream::connect(path).with_context(|| format!("failed to connect to {}", path.display()))?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + READ_TIMEOUT)).context("failed to set read timeout")?;
+    writeln!(stream, "{}", command.replace('\n', " ")).context("failed to send command")?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).context("failed to read response")?;
+    anyhow::ensure!(!response.is_empty(), "the module closed the connection without responding");
+
+    Ok(response.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use super::*;
+    use crate::testing::TempDir;
+
+    const CLIENTS: usize = 12;
+    const COMMANDS: usize = 50;
+
+    fn status(name: Option<String>) -> Status {
+        Status {
+            window: Some(1),
+            display_name: name.clone(),
+            rendered: name,
+            raw_title: None,
+            paused: false,
+            dropped: 0,
+            latency: None,
+        }
+    }
+
+    /// Carries out commands like the main loop would until `done` is set, returning the final name and how many
+    /// commands were handled.
+    fn main_loop(mut server: ControlServer, done: Arc<AtomicBool>) -> (Option<String>, usize) {
+        let mut name = None;
+        let mut handled = 0;
+
+        while !done.load(Ordering::SeqCst) {
+            for request in server.on_readable() {
+                handled += 1;
+
+                let response = match &request.command {
+                    Command::Get => Response::status(status(name.clone())),
+                    Command::Rename(new) => {
+                        name = Some(new.clone());
+                        Response::ok()
+                    }
+                    _ => Response::ok(),
+                };
+                request.reply(response);
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        (name, handled)
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!("get".parse::<Command>().unwrap(), Command::Get);
+        assert_eq!(" rename  foo bar \n".parse::<Command>().unwrap(), Command::Rename("foo bar".to_owned()));
+        assert_eq!("stats usage".parse::<Command>().unwrap(), Command::UsageStats);
+        assert!("rename".parse::<Command>().is_err());
+        assert!("get now".parse::<Command>().is_err());
+        assert!("stats cpu".parse::<Command>().is_err());
+        assert!("explode".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn get_response_schema() {
+        let response = Response::status(Status {
+            window: Some(23068679),
+            display_name: Some("Firefox".to_owned()),
+            rendered: Some("%{F#ff7139}Firefox%{F-}".to_owned()),
+            raw_title: Some("Mozilla Firefox".to_owned()),
+            paused: false,
+            dropped: 0,
+            latency: None,
+        });
+        let fixture: serde_json::Value = serde_json::from_str(include_str!("../tests/fixtures/get.json")).unwrap();
+
+        assert_eq!(serde_json::to_value(&response).unwrap(), fixture);
+        assert_eq!(fixture["version"], SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn error_response_schema() {
+        let response = serde_json::to_value(Response::error(&anyhow::anyhow!("rename expects a name"))).unwrap();
+        assert_eq!(response, serde_json::json!({ "version": SCHEMA_VERSION, "ok": false, "error": "rename expects a name" }));
+    }
+
+    #[test]
+    fn many_clients_at_once() {
+        let dir = TempDir::new("control");
+        let path = dir.path().join("socket");
+        let server = ControlServer::bind(&path).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let main_loop = thread::spawn({
+            let done = Arc::clone(&done);
+            move || main_loop(server, done)
+        });
+
+        let clients = (0..CLIENTS)
+            .map(|client| {
+                let path = path.clone();
+
+                thread::spawn(move || {
+                    let stream = UnixStream::connect(&path).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut writer = stream;
+
+                    for i in 0..COMMANDS {
+                        let command = match i % 3 {
+                            0 => "get".to_owned(),
+                            1 => "refresh".to_owned(),
+                            _ => format!("rename client {client} #{i}"),
+                        };
+                        writeln!(writer, "{command}").unwrap();
+
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let response = serde_json::from_str::<serde_json::Value>(&line)
+                            .unwrap_or_else(|error| panic!("{line:?} is not a response: {error}"));
+
+                        assert_eq!(response["version"], SCHEMA_VERSION);
+                        assert_eq!(response["ok"], true, "{line}");
+                        assert_eq!(response.get("status").is_some(), command == "get", "{line}");
+                        if let Some(name) = response["status"]["display_name"].as_str() {
+                            assert!(name.starts_with("client "), "{line}");
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for client in clients {
+            client.join().unwrap();
+        }
+
+        // a fresh client sees the last rename, which is one of the clients' final ones
+        let response = send(&path, "get").unwrap();
+        done.store(true, Ordering::SeqCst);
+        let (name, handled) = main_loop.join().unwrap();
+        let name = name.unwrap();
+
+        assert_eq!(handled, CLIENTS * COMMANDS + 1);
+        let last = (0..COMMANDS).rev().find(|i| i % 3 == 2).unwrap();
+        assert!((0..CLIENTS).any(|client| name == format!("client {client} #{last}")), "{name}");
+        assert!(response.contains(&format!("\"display_name\":\"{name}\"")), "{response}");
+    }
+
+    #[test]
+    fn long_lines_are_rejected() {
+        let dir = TempDir::new("control-long");
+        let path = dir.path().join("socket");
+        let server = ControlServer::bind(&path).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let main_loop = thread::spawn({
+            let done = Arc::clone(&done);
+            move || main_loop(server, done)
+        });
+
+        let response = send(&path, &format!("rename {}", "x".repeat(MAX_LINE_LENGTH))).unwrap();
+        assert!(response.contains("is longer than"), "{response}");
+
+        // and the server keeps serving others
+        assert!(send(&path, "get").unwrap().contains("\"ok\":true"));
+
+        done.store(true, Ordering::SeqCst);
+        assert_eq!(main_loop.join().unwrap(), (None, 1));
+    }
+
+    #[test]
+    fn second_instance_is_refused() {
+        let dir = TempDir::new("control-twice");
+        let path = dir.path().join("socket");
+        let _server = ControlServer::bind(&path).unwrap();
+
+        assert!(ControlServer::bind(&path).is_err());
+    }
+}
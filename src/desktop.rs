@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::atoms::Atoms;
+
+#[derive(Clone, Debug)]
+pub struct Desktop {
+    pub index: u32,
+    pub name: Option<String>,
+}
+
+impl Desktop {
+    /// Reads `_NET_CURRENT_DESKTOP` and `_NET_DESKTOP_NAMES` from the root window. Returns `None` if the window
+    /// manager doesn't report a current desktop.
+    pub fn current(connection: &RustConnection, atoms: &Atoms, root: Window) -> anyhow::Result<Option<Self>> {
+        let index = connection
+            .get_property(false, root, atoms.net_current_desktop, AtomEnum::CARDINAL, 0, 1)
+            .context("failed to make GetProperty reply for retrieving _NET_CURRENT_DESKTOP")?
+            .reply()
+            .context("GetProperty response for retrieving _NET_CURRENT_DESKTOP failed")?
+            .value32()
+            .and_then(|mut value| value.next());
+
+        let Some(index) = index else {
+            tracing::debug!("window manager does not report _NET_CURRENT_DESKTOP");
+            return Ok(None)
+        };
+
+        let names = connection
+            .get_property(false, root, atoms.net_desktop_names, AtomEnum::ANY, 0, 1024)
+            .context("failed to make GetProperty reply for retrieving _NET_DESKTOP_NAMES")?
+            .reply()
+            .context("GetProperty response for retrieving _NET_DESKTOP_NAMES failed")?
+            .value;
+        let name = names
+            .split(|b| *b == 0)
+            .nth(index as usize)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned());
+
+        Ok(Some(Self { index, name }))
+    }
+
+    /// The name of the desktop, falling back to its 1-based number if the window manager doesn't name desktops.
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| (self.index + 1).to_string())
+    }
+
+    pub fn is(&self, desktop: &DesktopRef) -> bool {
+        match desktop {
+            DesktopRef::Index(index) => self.index == *index,
+            DesktopRef::Name(name) => self.name.as_ref() == Some(name),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum DesktopRef {
+    Index(u32),
+    Name(String),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum DesktopName {
+    Fixed(String),
+
+    /// Names by the 0-based index or the name of the desktop, looked up in that order, e.g.
+    /// `{ 0 = "Desktop 1 — web", code = "Desktop 2 — code", default = "Desktop" }`. Desktops which aren't listed
+    /// get the `default` entry, or the name the window manager gives them if there's none.
+    PerDesktop(BTreeMap<String, String>),
+}
+
+impl DesktopName {
+    pub fn of(&self, desktop: Option<&Desktop>) -> Cow<'_, str> {
+        let names = match self {
+            Self::Fixed(name) => return Cow::Borrowed(name),
+            Self::PerDesktop(names) => names,
+        };
+
+        let listed = desktop.and_then(|desktop| {
+            names
+                .get(&desktop.index.to_string())
+                .or_else(|| desktop.name.as_ref().and_then(|name| names.get(name)))
+        });
+
+        match (listed.or_else(|| names.get("default")), desktop) {
+            (Some(name), _) => Cow::Borrowed(name),
+            (None, Some(desktop)) => Cow::Owned(desktop.display_name()),
+            (None, None) => Cow::Borrowed(""),
+        }
+    }
+
+    pub fn is_per_desktop(&self) -> bool {
+        matches!(self, Self::PerDesktop(_))
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WorkspacePrefix {
+    pub template: String,
+
+    #[serde(default)]
+    pub except: Vec<DesktopRef>,
+}
+
+impl WorkspacePrefix {
+    pub fn applies_to(&self, desktop: &Desktop) -> bool {
+        !self.except.iter().any(|except| desktop.is(except))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop(index: u32, name: Option<&str>) -> Desktop {
+        Desktop { index, name: name.map(str::to_owned) }
+    }
+
+    #[test]
+    fn prefix_skips_excepted_desktops() {
+        let prefix: WorkspacePrefix = toml::from_str(r#"
+            template = "[{{ desktop }}] "
+            except = [2, "music"]
+        "#).unwrap();
+
+        assert!(matches!(prefix.except[..], [DesktopRef::Index(2), DesktopRef::Name(ref name)] if name == "music"));
+        assert!(prefix.applies_to(&desktop(0, Some("web"))));
+        assert!(!prefix.applies_to(&desktop(2, Some("code"))));
+        assert!(!prefix.applies_to(&desktop(5, Some("music"))));
+        assert!(prefix.applies_to(&desktop(5, None)));
+    }
+
+    #[test]
+    fn prefix_applies_everywhere_by_default() {
+        let prefix: WorkspacePrefix = toml::from_str(r#"template = "[{{ desktop }}] ""#).unwrap();
+
+        assert!(prefix.except.is_empty());
+        assert!(prefix.applies_to(&desktop(0, None)));
+    }
+
+    #[test]
+    fn names_dont_match_indices() {
+        // a desktop named "2" isn't the third one, and the third one isn't named "2"
+        assert!(!desktop(0, Some("2")).is(&DesktopRef::Index(2)));
+        assert!(!desktop(2, None).is(&DesktopRef::Name("2".to_owned())));
+        assert!(desktop(0, Some("2")).is(&DesktopRef::Name("2".to_owned())));
+    }
+
+    fn desktop_name(value: &str) -> DesktopName {
+        #[derive(Deserialize)]
+        struct Value {
+            value: DesktopName,
+        }
+
+        toml::from_str::<Value>(&format!("value = {value}")).unwrap().value
+    }
+
+    #[test]
+    fn fixed_or_per_desktop_names() {
+        assert!(matches!(desktop_name(r#""Desktop""#), DesktopName::Fixed(ref name) if name == "Desktop"));
+        assert!(desktop_name(r#"{ 0 = "web" }"#).is_per_desktop());
+        assert_eq!(desktop_name(r#""Desktop""#).of(Some(&desktop(3, Some("code")))), "Desktop");
+        assert_eq!(desktop_name(r#""Desktop""#).of(None), "Desktop");
+    }
+
+    #[test]
+    fn per_desktop_names_fall_back() {
+        let names = desktop_name(r#"{ 0 = "Desktop 1 — web", code = "Desktop 2 — code" }"#);
+
+        assert_eq!(names.of(Some(&desktop(0, Some("code")))), "Desktop 1 — web");
+        assert_eq!(names.of(Some(&desktop(1, Some("code")))), "Desktop 2 — code");
+        assert_eq!(names.of(Some(&desktop(2, Some("music")))), "music");
+        assert_eq!(names.of(Some(&desktop(2, None))), "3");
+        assert_eq!(names.of(None), "");
+
+        let names = desktop_name(r#"{ 0 = "web", default = "Desktop" }"#);
+
+        assert_eq!(names.of(Some(&desktop(1, Some("code")))), "Desktop");
+        assert_eq!(names.of(None), "Desktop");
+    }
+}
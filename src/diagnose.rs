@@ -0,0 +1,224 @@
+//! The `diagnose` command, which gathers what's needed to make sense of a bug report.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::env;
+use anyhow::Context;
+use figment::value::Dict;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::atoms::Atoms;
+use crate::machine::MachineInspector;
+use crate::process::ProcessInspector;
+use crate::{features, intern_atom, quirks, Config, NonNullWindow};
+
+const RELEVANT_ATOMS: &[&str] = &[
+    "_NET_ACTIVE_WINDOW",
+    "_NET_CURRENT_DESKTOP",
+    "_NET_DESKTOP_NAMES",
+    "_NET_SUPPORTING_WM_CHECK",
+    "_NET_WM_NAME",
+    "_NET_WM_PID",
+    "_NET_WM_PING",
+    "_NET_WM_STATE",
+    "_NET_WM_STATE_SKIP_TASKBAR",
+    "_NET_WM_STATE_FULLSCREEN",
+    "_NET_WM_STATE_DEMANDS_ATTENTION",
+];
+
+/// Builds the report as a markdown code block, describing `window` or the active window. Every section which can't
+/// be gathered says why instead, so this never fails, not even without an X server.
+pub fn report(redact_titles: bool, window: Option<NonNullWindow>) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "```");
+    let _ = writeln!(report, "polybar-title-module {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report);
+
+    let figment = Config::figment();
+    let config = Config::extract(&figment);
+
+    match env::var("DISPLAY") {
+        Ok(display) => { let _ = writeln!(report, "backend: x11 (DISPLAY={display})"); }
+        Err(_) => { let _ = writeln!(report, "backend: x11 (DISPLAY is not set)"); }
+    }
+
+    let display_name = config.as_ref().ok().and_then(|config| config.display_name.as_deref());
+
+    if let Err(error) = x11(&mut report, display_name, config.as_ref().ok(), redact_titles, window) {
+        let _ = writeln!(report, "X server: unreachable ({error:#})");
+    }
+
+    let _ = writeln!(report);
+    let _ = write!(report, "{}", features::report());
+
+    let _ = writeln!(report);
+    let _ = writeln!(report, "config files:");
+
+    for path in Config::paths() {
+        let state = if path.exists() { "found" } else { "missing" };
+        let _ = writeln!(report, "  {} ({state})", path.display());
+    }
+
+    if let Ok(values) = figment.extract::<Dict>() {
+        for key in values.keys() {
+            let source = figment
+                .find_metadata(key)
+                .and_then(|metadata| metadata.source.as_ref())
+                .map_or_else(|| "default".to_owned(), ToString::to_string);
+            let _ = writeln!(report, "  {key} from {source}");
+        }
+    }
+
+    let _ = writeln!(report);
+
+    match config.context("failed to get config").and_then(|config| toml::to_string(&config).context("failed to serialize config")) {
+        Ok(config) => { let _ = write!(report, "merged config:\n{config}"); }
+        Err(error) => { let _ = writeln!(report, "merged config: invalid ({error:#})"); }
+    }
+
+    let _ = writeln!(report, "```");
+
+    report
+}
+
+fn x11(
+    report: &mut String,
+    display_name: Option<&str>,
+    config: Option<&Config>,
+    redact_titles: bool,
+    window: Option<NonNullWindow>,
+) -> anyhow::Result<()> {
+    let (connection, screen_num) = x11rb::connect(display_name).context("failed to establish a connection to the X server")?;
+    let setup = connection.setup();
+    let root = setup.roots[screen_num].root;
+
+    writeln!(
+        report,
+        "X server: {} {} (protocol {}.{})",
+        String::from_utf8_lossy(&setup.vendor),
+        setup.release_number,
+        setup.protocol_major_version,
+        setup.protocol_minor_version,
+    )?;
+
+    let wm_check = intern_atom(&connection, "_NET_SUPPORTING_WM_CHECK")?;
+    let wm_name = match window_property(&connection, root, wm_check, AtomEnum::WINDOW.into())?.first() {
+        Some(&window) => utf8_property(&connection, window, "_NET_WM_NAME")?,
+        None => None,
+    };
+    writeln!(report, "window manager: {}", wm_name.as_deref().unwrap_or("unknown"))?;
+
+    let quirks = quirks::detect(wm_name.as_deref()).map(|quirk| quirk.to_string()).collect::<Vec<_>>();
+    writeln!(report, "known quirks: {}", if quirks.is_empty() { "none".to_owned() } else { quirks.join(", ") })?;
+
+    let net_supported = intern_atom(&connection, "_NET_SUPPORTED")?;
+    let supported = window_property(&connection, root, net_supported, AtomEnum::ATOM.into())?;
+    writeln!(report, "_NET_SUPPORTED:")?;
+
+    for name in RELEVANT_ATOMS {
+        let mark = if supported.contains(&intern_atom(&connection, name)?) { "x" } else { " " };
+        writeln!(report, "  [{mark}] {name}")?;
+    }
+
+    let (label, window) = match window {
+        Some(window) => ("window", window.get()),
+        None => {
+            let net_active_window = intern_atom(&connection, "_NET_ACTIVE_WINDOW")?;
+            let active = window_property(&connection, root, net_active_window, AtomEnum::WINDOW.into())?;
+            let Some(&window) = active.first().filter(|window| **window != 0) else {
+                writeln!(report, "active window: none")?;
+                return Ok(())
+            };
+
+            ("active window", window)
+        }
+    };
+
+    let title = |value: Option<String>| match value {
+        Some(value) if redact_titles => {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            format!("<redacted {:016x}>", hasher.finish())
+        }
+        Some(value) => format!("{value:?}"),
+        None => "not set".to_owned(),
+    };
+
+    writeln!(report, "{label}: {window:#x}")?;
+
+    let wm_class = connection
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+        .reply()?
+        .value;
+    let mut wm_class = wm_class.split(|b| *b == 0).map(String::from_utf8_lossy);
+    writeln!(report, "  WM_CLASS instance: {:?}", wm_class.next().unwrap_or_default())?;
+    writeln!(report, "  WM_CLASS class: {:?}", wm_class.next().unwrap_or_default())?;
+
+    let wm_name = connection
+        .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::ANY, 0, 1024)?
+        .reply()?;
+    let wm_name = (wm_name.type_ != u32::from(AtomEnum::NONE)).then(|| String::from_utf8_lossy(&wm_name.value).into_owned());
+    writeln!(report, "  WM_NAME: {}", title(wm_name))?;
+    writeln!(report, "  _NET_WM_NAME: {}", title(utf8_property(&connection, window, "_NET_WM_NAME")?))?;
+
+    let wm_window_role = intern_atom(&connection, "WM_WINDOW_ROLE")?;
+    let role = connection
+        .get_property(false, window, wm_window_role, AtomEnum::STRING, 0, 256)?
+        .reply()?;
+    if role.type_ == u32::from(AtomEnum::NONE) {
+        writeln!(report, "  WM_WINDOW_ROLE: not set")?;
+    } else {
+        writeln!(report, "  WM_WINDOW_ROLE: {:?}", String::from_utf8_lossy(&role.value))?;
+    }
+
+    let net_wm_pid = intern_atom(&connection, "_NET_WM_PID")?;
+    match window_property(&connection, window, net_wm_pid, AtomEnum::CARDINAL.into())?.first() {
+        Some(pid) => writeln!(report, "  _NET_WM_PID: {pid}")?,
+        None => writeln!(report, "  _NET_WM_PID: not set")?,
+    }
+
+    let flatpak_id = utf8_property(&connection, window, "_FLATPAK_ID")?;
+    writeln!(report, "  _FLATPAK_ID: {}", flatpak_id.as_deref().unwrap_or("not set"))?;
+
+    if let Some(config) = config {
+        let mut processes = ProcessInspector::new("/proc", true);
+        let machines = MachineInspector::new(true);
+
+        let atoms = Atoms::new(&connection)?;
+
+        match config.resolver.resolve(&connection, &atoms, &mut processes, &machines, window) {
+            Ok(resolved) => {
+                writeln!(report, "  resolved name: {}", title(Some(resolved.name)))?;
+                writeln!(report, "  child process: {}", resolved.child_process.as_deref().unwrap_or("none"))?;
+                writeln!(report, "  client machine: {} (remote: {})", resolved.client_machine.as_deref().unwrap_or("not set"), resolved.remote)?;
+            }
+            Err(error) => writeln!(report, "  resolved name: failed ({error:#})")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn window_property(connection: &RustConnection, window: Window, property: Atom, type_: Atom) -> anyhow::Result<Vec<u32>> {
+    let reply = connection
+        .get_property(false, window, property, type_, 0, 1024)
+        .context("failed to make GetProperty reply")?
+        .reply()
+        .context("GetProperty response failed")?;
+
+    Ok(reply.value32().into_iter().flatten().collect())
+}
+
+fn utf8_property(connection: &RustConnection, window: Window, name: &str) -> anyhow::Result<Option<String>> {
+    let utf8_string = intern_atom(connection, "UTF8_STRING")?;
+    let property = intern_atom(connection, name)?;
+    let reply = connection
+        .get_property(false, window, property, utf8_string, 0, 1024)
+        .context("failed to make GetProperty reply")?
+        .reply()
+        .context("GetProperty response failed")?;
+
+    Ok((reply.type_ == utf8_string).then(|| String::from_utf8_lossy(&reply.value).into_owned()))
+}
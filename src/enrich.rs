@@ -0,0 +1,227 @@
+//! Looking up the foreground child process of windows on a worker thread (`enrich_in_background`), so that walking
+//! `/proc` on a cold cache never delays an output.
+//!
+//! A window is displayed right away with the child process it had last time, or none, and displayed again once the
+//! worker found out what it is now. Every lookup gets a generation, and only the results of the latest one are taken,
+//! so a slow lookup for a window which lost focus in the meantime can't overwrite anything.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use x11rb::protocol::xproto::Window;
+use crate::process::ProcessInspector;
+
+const FRESH_FOR: Duration = Duration::from_secs(2);
+
+/// Known child processes are forgotten after this long, so windows which are gone don't pile up.
+const FORGET_AFTER: Duration = Duration::from_secs(60);
+
+struct Lookup {
+    generation: u64,
+    window: Window,
+    pid: u32,
+}
+
+struct Found {
+    generation: u64,
+    window: Window,
+    child_process: Option<String>,
+}
+
+pub struct Enricher {
+    lookups: Sender<Lookup>,
+    found: Receiver<Found>,
+    wakeup: UnixStream,
+    generation: u64,
+    pending: Option<Window>,
+    known: HashMap<Window, (Instant, Option<String>)>,
+}
+
+impl Enricher {
+    pub fn spawn(proc_root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let mut processes = ProcessInspector::new(proc_root, true);
+        Self::with_lookup(move |window, pid| processes.foreground_child(window, pid))
+    }
+
+    fn with_lookup(lookup: impl FnMut(Window, u32) -> Option<String> + Send + 'static) -> anyhow::Result<Self> {
+        let (wakeup, notify) = UnixStream::pair().context("failed to create wakeup socket pair")?;
+        wakeup.set_nonblocking(true).context("failed to make wakeup socket non-blocking")?;
+
+        let (lookups, receiver) = mpsc::channel();
+        let (sender, found) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("enrich".to_owned())
+            .spawn(move || work(lookup, &receiver, &sender, notify))
+            .context("failed to spawn enrichment thread")?;
+
+        Ok(Self {
+            lookups,
+            found,
+            wakeup,
+            generation: 0,
+            pending: None,
+            known: HashMap::new(),
+        })
+    }
+
+    /// The child process of `window`, whose process is `pid`, as far as it's known right now. Unless it was looked
+    /// up recently, it's looked up again in the background.
+    pub fn child_process(&mut self, window: Window, pid: u32, now: Instant) -> Option<String> {
+        let (fresh, child_process) = match self.known.get(&window) {
+            Some((looked_up_at, child_process)) => (now.saturating_duration_since(*looked_up_at) < FRESH_FOR, child_process.clone()),
+            None => (false, None),
+        };
+
+        if !fresh && self.pending != Some(window) {
+            self.generation += 1;
+            self.pending = Some(window);
+            tracing::debug!(%window, generation = self.generation, "looking up child process in the background");
+
+            if self.lookups.send(Lookup { generation: self.generation, window, pid }).is_err() {
+                tracing::warn!("enrichment thread is gone, child processes aren't updated anymore");
+            }
+        }
+
+        child_process
+    }
+
+    pub fn on_readable(&mut self, now: Instant) -> Vec<Window> {
+        let mut buffer = [0; 64];
+        while matches!(self.wakeup.read(&mut buffer), Ok(read) if read > 0) {}
+
+        let mut changed = Vec::new();
+
+        for found in self.found.try_iter() {
+            if found.generation != self.generation {
+                tracing::debug!(window = found.window, generation = found.generation, "dropping outdated child process lookup");
+                continue
+            }
+
+            self.pending = None;
+            let previous = self.known.insert(found.window, (now, found.child_process.clone()));
+
+            if previous.map(|(_, child_process)| child_process) != Some(found.child_process) {
+                changed.push(found.window);
+            }
+        }
+
+        self.known.retain(|_, (looked_up_at, _)| now.saturating_duration_since(*looked_up_at) < FORGET_AFTER);
+        changed
+    }
+}
+
+impl AsRawFd for Enricher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.wakeup.as_raw_fd()
+    }
+}
+
+/// Looks up child processes until the main loop is gone. When several lookups are queued, only the latest one is
+/// carried out, as the others would be dropped anyway.
+fn work(mut lookup_child: impl FnMut(Window, u32) -> Option<String>, lookups: &Receiver<Lookup>, found: &Sender<Found>, mut notify: UnixStream) {
+    while let Ok(lookup) = lookups.recv() {
+        let lookup = lookups.try_iter().last().unwrap_or(lookup);
+        let child_process = lookup_child(lookup.window, lookup.pid);
+
+        let result = Found {
+            generation: lookup.generation,
+            window: lookup.window,
+            child_process,
+        };
+
+        if found.send(result).is_err() || notify.write_all(&[0]).is_err() {
+            return
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use super::*;
+
+    /// Takes the results of finished lookups until one changed a child process, like the main loop would.
+    fn wait_for_change(enricher: &mut Enricher) -> Vec<Window> {
+        let started = Instant::now();
+
+        while started.elapsed() < Duration::from_secs(5) {
+            let changed = enricher.on_readable(Instant::now());
+            if !changed.is_empty() {
+                return changed
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        panic!("no lookup finished")
+    }
+
+    fn slow(window: Window, pid: u32) -> Option<String> {
+        thread::sleep(Duration::from_millis(50));
+        Some(format!("child of {window} ({pid})"))
+    }
+
+    #[test]
+    fn displayed_right_away_and_again_once_looked_up() {
+        let mut enricher = Enricher::with_lookup(slow).unwrap();
+        let start = Instant::now();
+
+        assert_eq!(enricher.child_process(1, 100, start), None);
+        assert_eq!(wait_for_change(&mut enricher), [1]);
+        assert_eq!(enricher.child_process(1, 100, start).as_deref(), Some("child of 1 (100)"));
+    }
+
+    #[test]
+    fn recent_lookups_are_reused() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let mut enricher = Enricher::with_lookup({
+            let lookups = Arc::clone(&lookups);
+            move |window, pid| {
+                lookups.fetch_add(1, Ordering::SeqCst);
+                slow(window, pid)
+            }
+        }).unwrap();
+        let start = Instant::now();
+
+        enricher.child_process(1, 100, start);
+        // already being looked up
+        enricher.child_process(1, 100, start);
+        wait_for_change(&mut enricher);
+        let looked_up_at = Instant::now();
+
+        assert!(enricher.child_process(1, 100, looked_up_at + FRESH_FOR / 2).is_some());
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        // stale, so it's displayed as it was and looked up again
+        assert!(enricher.child_process(1, 100, looked_up_at + FRESH_FOR).is_some());
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn newer_focus_change_invalidates_pending_lookup() {
+        let (release, gate) = mpsc::channel::<()>();
+        let mut enricher = Enricher::with_lookup(move |window, pid| {
+            gate.recv().unwrap();
+            slow(window, pid)
+        }).unwrap();
+        let start = Instant::now();
+
+        enricher.child_process(1, 100, start);
+        thread::sleep(Duration::from_millis(20));
+        enricher.child_process(2, 200, start);
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+
+        assert_eq!(wait_for_change(&mut enricher), [2]);
+        assert!(!enricher.known.contains_key(&1));
+    }
+}
@@ -0,0 +1,67 @@
+//! The exit codes of the binary, which scripts (e.g. polybar's `exec-if` running `--probe`) can tell failures apart
+//! by: 0 on success, 1 for anything not listed here, and the codes of [`Failure`].
+
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use anyhow::Context;
+use crate::Config;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A kind of failure with an exit code of its own. It's attached to errors as context, where it also describes what
+/// failed, and `main` picks the exit code by it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Failure {
+    /// The X server can't be reached.
+    Backend,
+    Config,
+
+    /// The control socket of a running module can't be reached.
+    Unreachable,
+}
+
+impl Failure {
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref().copied()
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Backend => 2,
+            Self::Config => 3,
+            Self::Unreachable => 4,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Backend => "failed to establish a connection to the X server",
+            Self::Config => "invalid config",
+            Self::Unreachable => "failed to reach the module through its control socket",
+        })
+    }
+}
+
+/// Checks that the module could run: that the config is valid and the X server can be connected to, giving up on
+/// the latter after [`PROBE_TIMEOUT`]. Nothing is resolved and no events are waited for.
+pub fn probe() -> anyhow::Result<()> {
+    let config = Config::read().context(Failure::Config)?;
+    let (sender, receiver) = mpsc::channel();
+
+    // connecting can hang on an unresponsive server, and the thread is left behind if it does
+    thread::Builder::new()
+        .name("probe".to_owned())
+        .spawn(move || {
+            let _ = sender.send(x11rb::connect(config.display_name.as_deref()).map(drop));
+        })
+        .context("failed to spawn probe thread")?;
+
+    match receiver.recv_timeout(PROBE_TIMEOUT) {
+        Ok(result) => result.context(Failure::Backend),
+        Err(_) => Err(anyhow::anyhow!("the X server didn't answer within {PROBE_TIMEOUT:?}")).context(Failure::Backend),
+    }
+}
@@ -0,0 +1,127 @@
+use std::env;
+use anyhow::Context;
+
+/// Expands `$VAR` and `${VAR}` to the value of the environment variable, and `$$` to a literal `$`. A variable which
+/// isn't set is an error, rather than silently expanding to nothing. A `$` which isn't followed by a variable name is
+/// kept as is.
+pub fn expand_env(value: &str) -> anyhow::Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').with_context(|| format!("unterminated ${{ in {value:?}"))?;
+            (&braced[..end], &braced[end + 1..])
+        } else if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue
+        } else {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if name.is_empty() {
+            expanded.push('$');
+            continue
+        }
+
+        let variable = env::var(name).with_context(|| format!("environment variable {name} in {value:?} is not set"))?;
+        expanded.push_str(&variable);
+        rest = after;
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+pub fn expand_path(value: &str) -> anyhow::Result<String> {
+    let expanded = expand_env(value)?;
+
+    match expanded.strip_prefix("~/") {
+        Some(rest) => {
+            let home = directories::BaseDirs::new().context("could not find the home directory to expand ~ with")?;
+            Ok(format!("{}/{rest}", home.home_dir().display()))
+        }
+        None => Ok(expanded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::{Config, Filter};
+
+    fn home() -> String {
+        directories::BaseDirs::new().unwrap().home_dir().display().to_string()
+    }
+
+    #[test]
+    fn variables() {
+        env::set_var("PTM_TEST_EXPAND_RUNTIME", "/run/user/1000");
+
+        assert_eq!(expand_env("$PTM_TEST_EXPAND_RUNTIME/title").unwrap(), "/run/user/1000/title");
+        assert_eq!(expand_env("${PTM_TEST_EXPAND_RUNTIME}title").unwrap(), "/run/user/1000title");
+        assert_eq!(expand_env("costs $$5").unwrap(), "costs $5");
+        assert_eq!(expand_env("$ and $-").unwrap(), "$ and $-");
+        assert_eq!(expand_env("no variables").unwrap(), "no variables");
+    }
+
+    #[test]
+    fn unknown_variables_are_errors() {
+        let error = expand_env("$PTM_TEST_EXPAND_UNSET/x").unwrap_err().to_string();
+        assert!(error.contains("PTM_TEST_EXPAND_UNSET"), "{error}");
+
+        assert!(expand_env("${PTM_TEST_EXPAND_UNSET").unwrap_err().to_string().contains("unterminated"));
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(expand_path("~/.cache/title.sock").unwrap(), format!("{}/.cache/title.sock", home()));
+        assert_eq!(expand_path("/tmp/~/x").unwrap(), "/tmp/~/x");
+        assert_eq!(expand_path("~user/x").unwrap(), "~user/x");
+    }
+
+    #[test]
+    fn config_fields() {
+        env::set_var("PTM_TEST_EXPAND_ICON", "");
+
+        let config = Config::from_sources([r#"
+            expand_env = true
+            template = "$PTM_TEST_EXPAND_ICON {{ name }}"
+            on_change = ["~/bin/layout $PTM_TEST_EXPAND_ICON"]
+            control_socket = "~/title.sock"
+
+            [resolver]
+            desktop_name = "$HOME"
+
+            [resolver.filters]
+            "wm_name=$PTM_TEST_EXPAND_ICON" = { filter = "new_name", value = "$PTM_TEST_EXPAND_ICON" }
+        "#]).unwrap();
+
+        assert_eq!(config.template, " {{ name }}");
+        assert_eq!(config.on_change, [format!("{}/bin/layout ", home())]);
+        assert_eq!(config.control_socket, Some(PathBuf::from(home()).join("title.sock")));
+
+        assert_eq!(config.resolver.desktop().name, "$HOME");
+
+        let (identifier, rule) = config.resolver.filters.iter().next().unwrap();
+        assert_eq!(identifier.value, "$PTM_TEST_EXPAND_ICON");
+        assert!(matches!(&rule.method, Filter::NewName(name) if name.to_string() == "$PTM_TEST_EXPAND_ICON"));
+    }
+
+    #[test]
+    fn config_without_expansion() {
+        let config = Config::from_sources([r#"
+            template = "$PTM_TEST_EXPAND_UNSET {{ name }}"
+            [resolver]
+            filters = {}
+        "#]).unwrap();
+
+        assert_eq!(config.template, "$PTM_TEST_EXPAND_UNSET {{ name }}");
+    }
+}
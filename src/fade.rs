@@ -0,0 +1,205 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use crate::units::Seconds;
+
+/// How many steps a fade is recomputed in at most. Every channel has 256 values, so more steps couldn't be told apart.
+const STEPS: u32 = 256;
+
+/// Recomputing the color more often than this isn't worth the renders, even for very short fades.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FocusFade {
+    pub from: Color,
+    pub to: Color,
+    pub over: Seconds,
+}
+
+#[derive(DeserializeFromStr, SerializeDisplay, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    /// The color `progress` (from 0 to 1) of the way from `self` to `to`, interpolating every sRGB component on its
+    /// own. Progress outside of that range is clamped.
+    pub fn lerp(self, to: Self, progress: f64) -> Self {
+        let progress = progress.clamp(0.0, 1.0);
+        let channel = |from: u8, to: u8| (f64::from(from) + (f64::from(to) - f64::from(from)) * progress).round() as u8;
+
+        Self {
+            red: channel(self.red, to.red),
+            green: channel(self.green, to.green),
+            blue: channel(self.blue, to.blue),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').ok_or_else(|| anyhow::anyhow!("color {s:?} doesn't start with #"))?;
+        anyhow::ensure!(hex.chars().all(|c| c.is_ascii_hexdigit()), "color {s:?} contains something other than hex digits");
+
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).expect("digits are hex");
+
+        match hex.len() {
+            3 => Ok(Self {
+                red: channel(&hex[0..1]) * 0x11,
+                green: channel(&hex[1..2]) * 0x11,
+                blue: channel(&hex[2..3]) * 0x11,
+            }),
+            6 => Ok(Self {
+                red: channel(&hex[0..2]),
+                green: channel(&hex[2..4]),
+                blue: channel(&hex[4..6]),
+            }),
+            _ => anyhow::bail!("color {s:?} is neither #rgb nor #rrggbb"),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+/// Keeps track of how far the fade of the focused window got.
+///
+/// The color is only recomputed every so often, and not at all anymore once it reached the end, so an unchanged
+/// color never causes a render.
+#[derive(Debug)]
+pub struct Fade {
+    config: FocusFade,
+    interval: Duration,
+    since: Instant,
+    next: Option<Instant>,
+    color: Color,
+}
+
+impl Fade {
+    pub fn new(config: FocusFade, now: Instant) -> Self {
+        Self {
+            interval: (config.over.get() / STEPS).max(MIN_INTERVAL),
+            since: now,
+            next: Some(now),
+            color: config.from,
+            config,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn restart(&mut self, now: Instant) {
+        self.since = now;
+        self.next = Some(now + self.interval);
+        self.color = self.config.from;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) -> bool {
+        match self.next {
+            Some(next) if now >= next => {}
+            _ => return false,
+        }
+
+        let elapsed = now.saturating_duration_since(self.since);
+        let progress = if self.config.over.get().is_zero() { 1.0 } else { elapsed.as_secs_f64() / self.config.over.get().as_secs_f64() };
+        let color = self.config.from.lerp(self.config.to, progress);
+
+        self.next = (progress < 1.0).then(|| now + self.interval);
+
+        let changed = color != self.color;
+        self.color = color;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color { red: 0xff, green: 0xff, blue: 0xff };
+    const RED: Color = Color { red: 0xff, green: 0x55, blue: 0x55 };
+
+    fn fade() -> (Fade, Instant) {
+        let config: FocusFade = toml::from_str("from = \"#ffffff\"\nto = \"#f55\"\nover = \"30m\"").unwrap();
+        let start = Instant::now();
+        (Fade::new(config, start), start)
+    }
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn lerp() {
+        assert_eq!(WHITE.lerp(RED, 0.0), WHITE);
+        assert_eq!(WHITE.lerp(RED, 0.5), Color { red: 0xff, green: 0xaa, blue: 0xaa });
+        assert_eq!(WHITE.lerp(RED, 1.0), RED);
+        assert_eq!(WHITE.lerp(RED, 2.0), RED);
+        assert_eq!(WHITE.lerp(RED, -1.0), WHITE);
+    }
+
+    #[test]
+    fn fade_over_time() {
+        let (mut fade, start) = fade();
+        assert_eq!(fade.color(), WHITE);
+
+        assert!(!fade.on_deadline(start));
+        assert_eq!(fade.color(), WHITE);
+
+        assert!(fade.on_deadline(start + minutes(15)));
+        assert_eq!(fade.color().to_string(), "#ffaaaa");
+
+        assert!(fade.on_deadline(start + minutes(30)));
+        assert_eq!(fade.color(), RED);
+        assert_eq!(fade.deadline(), None);
+    }
+
+    #[test]
+    fn fade_past_the_end() {
+        let (mut fade, start) = fade();
+
+        assert!(fade.on_deadline(start + minutes(40)));
+        assert_eq!(fade.color(), RED);
+
+        // nothing is recomputed once it reached the end
+        assert_eq!(fade.deadline(), None);
+        assert!(!fade.on_deadline(start + minutes(60)));
+    }
+
+    #[test]
+    fn restart_goes_back_to_the_start() {
+        let (mut fade, start) = fade();
+        fade.on_deadline(start + minutes(40));
+
+        fade.restart(start + minutes(40));
+        assert_eq!(fade.color(), WHITE);
+        assert!(fade.deadline().is_some());
+        assert!(!fade.on_deadline(start + minutes(40) + Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!("#f55".parse::<Color>().unwrap(), RED);
+        assert_eq!("#FF5555".parse::<Color>().unwrap(), RED);
+        assert_eq!(RED.to_string(), "#ff5555");
+
+        for invalid in ["ff5555", "#ff555", "#ff55zz", "#", "#ffff5555"] {
+            assert!(invalid.parse::<Color>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! What this build supports, listed by the `features` command and `diagnose`.
+//!
+//! Everything is listed here in one place. None of it is behind a cargo feature yet; once something is, its entry
+//! gets `cfg!(feature = "...")` as `compiled` and the feature's name, so that both places tell which build lacks it.
+
+use std::fmt::Write;
+
+#[derive(Debug)]
+pub struct Feature {
+    pub category: &'static str,
+    pub name: &'static str,
+    pub cargo_feature: Option<&'static str>,
+    pub compiled: bool,
+}
+
+const fn builtin(category: &'static str, name: &'static str) -> Feature {
+    Feature { category, name, cargo_feature: None, compiled: true }
+}
+
+pub const FEATURES: &[Feature] = &[
+    builtin("backend", "x11"),
+    builtin("backend", "stdin"),
+    builtin("output", "stdout"),
+    builtin("output", "polybar_ipc"),
+    builtin("output", "waybar"),
+    builtin("output format", "plain"),
+    builtin("output format", "polybar"),
+    builtin("output format", "json"),
+    builtin("outputs target", "stdout"),
+    builtin("outputs target", "fifo"),
+    builtin("outputs target", "file"),
+    builtin("filter", "options"),
+    builtin("filter", "new_name"),
+    builtin("filter", "template"),
+    builtin("filter", "ignore"),
+    builtin("variant condition", "contains"),
+    builtin("variant condition", "starts_with"),
+    builtin("variant condition", "regex"),
+];
+
+pub fn report() -> String {
+    list(FEATURES)
+}
+
+fn list(features: &[Feature]) -> String {
+    let mut report = String::new();
+    let mut category = None;
+
+    for feature in features {
+        if category != Some(feature.category) {
+            let _ = writeln!(report, "{}:", feature.category);
+            category = Some(feature.category);
+        }
+
+        let mark = if feature.compiled { "x" } else { " " };
+        let _ = match (feature.compiled, feature.cargo_feature) {
+            (false, Some(cargo_feature)) => writeln!(report, "  [{mark}] {} (compiled without feature {cargo_feature})", feature.name),
+            _ => writeln!(report, "  [{mark}] {}", feature.name),
+        };
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputFormat;
+    use crate::stdin::Backend;
+
+    #[test]
+    fn everything_is_compiled_in() {
+        // nothing is behind a cargo feature yet, see the module docs
+        assert!(FEATURES.iter().all(|feature| feature.compiled && feature.cargo_feature.is_none()));
+        assert!(!report().contains("compiled without"));
+    }
+
+    #[test]
+    fn report_groups_by_category() {
+        let report = report();
+
+        assert!(report.starts_with("backend:\n  [x] x11\n  [x] stdin\noutput:\n"), "{report}");
+        assert_eq!(report.lines().filter(|line| !line.starts_with(' ')).count(), 6);
+        assert_eq!(report.lines().filter(|line| line.starts_with("  [x] ")).count(), FEATURES.len());
+    }
+
+    #[test]
+    fn missing_feature_is_named() {
+        let features = [
+            Feature { category: "backend", name: "wayland", cargo_feature: Some("wayland"), compiled: false },
+            builtin("backend", "x11"),
+        ];
+
+        assert_eq!(list(&features), "backend:\n  [ ] wayland (compiled without feature wayland)\n  [x] x11\n");
+    }
+
+    #[test]
+    fn listed_names_are_accepted_by_the_config() {
+        for feature in FEATURES {
+            let name = format!("\"{}\"", feature.name);
+
+            match feature.category {
+                "backend" => assert!(serde_json::from_str::<Backend>(&name).is_ok(), "{name}"),
+                "output format" => assert!(serde_json::from_str::<OutputFormat>(&name).is_ok(), "{name}"),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{self, Formatter};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use handlebars::{Context, Handlebars, RenderContext, Renderable, StringOutput, Template, TemplateError};
+use serde::Serialize;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use x11rb::protocol::xproto::Window;
+use crate::{templating, WindowProperties};
+
+pub const VARIABLES: &[&str] = &["wm_class", "wm_name", "raw_class", "raw_name", "instance", "window_id"];
+
+#[derive(Serialize)]
+struct FilterContext<'p> {
+    wm_class: &'p str,
+    wm_name: &'p str,
+
+    /// The same as `wm_class` and `wm_name`, under the names the main template knows them by.
+    raw_class: &'p str,
+    raw_name: &'p str,
+    instance: &'p str,
+    window_id: Window,
+}
+
+/// The name of a `template` filter: a handlebars template like `"{{ wm_class }} — {{ wm_name }}"`, with the
+/// variables in [`VARIABLES`] and the same helpers as the main template. It's compiled when the config is read, so a
+/// syntax error is reported right away, and only rendered afterwards.
+///
+/// Nothing is escaped, as the result is the resolved name, which the main template escapes anyway. Partials aren't
+/// available.
+#[derive(DeserializeFromStr, SerializeDisplay, Clone, Debug)]
+pub struct FilterTemplate {
+    source: String,
+    template: Template,
+}
+
+impl FilterTemplate {
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+
+    pub fn render(&self, window: Window, properties: WindowProperties) -> anyhow::Result<String> {
+        let context = Context::wraps(FilterContext {
+            wm_class: properties.class,
+            wm_name: properties.name,
+            raw_class: properties.class,
+            raw_name: properties.name,
+            instance: properties.instance,
+            window_id: window,
+        })?;
+        let mut output = StringOutput::new();
+
+        self.template.render(registry(), &context, &mut RenderContext::new(None), &mut output)?;
+        Ok(output.into_string()?)
+    }
+}
+
+fn registry() -> &'static Handlebars<'static> {
+    static REGISTRY: OnceLock<Handlebars<'static>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        templating::register_helpers(&mut handlebars);
+        handlebars
+    })
+}
+
+impl FromStr for FilterTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: s.to_owned(),
+            template: Template::compile(s)?,
+        })
+    }
+}
+
+impl fmt::Display for FilterTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
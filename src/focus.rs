@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::Window;
+
+/// Tells apart the displayed window changing from a logical focus change, which is what hooks and highlights react
+/// to. A window only becomes logically focused once it stayed displayed for `delay`, so focus bouncing to a window
+/// and back (e.g. a dialog closing itself right away) isn't a change at all.
+#[derive(Debug)]
+pub struct LogicalFocus {
+    delay: Duration,
+    focused: Option<Window>,
+    pending: Option<(Window, Instant)>,
+}
+
+impl LogicalFocus {
+    pub fn new(delay: Duration, focused: Option<Window>) -> Self {
+        Self {
+            delay,
+            focused,
+            pending: None,
+        }
+    }
+
+    /// Handles the displayed window changing to `window`. Returns the window if that's a logical change right away,
+    /// which is the case if there's no delay.
+    pub fn displayed(&mut self, window: Window, now: Instant) -> Option<Window> {
+        if self.focused == Some(window) {
+            self.pending = None;
+            return None
+        }
+
+        if self.delay.is_zero() {
+            self.focused = Some(window);
+            return Some(window)
+        }
+
+        self.pending = Some((window, now + self.delay));
+        None
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|(_, until)| until)
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) -> Option<Window> {
+        match self.pending {
+            Some((window, until)) if now >= until => {
+                self.pending = None;
+                self.focused = Some(window);
+                Some(window)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(50);
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn bounce_is_no_change() {
+        let start = Instant::now();
+        let mut focus = LogicalFocus::new(DELAY, Some(1));
+
+        assert_eq!(focus.displayed(2, ms(start, 0)), None);
+        assert_eq!(focus.displayed(1, ms(start, 20)), None);
+        assert_eq!(focus.deadline(), None);
+        assert_eq!(focus.on_deadline(ms(start, 100)), None);
+    }
+
+    #[test]
+    fn genuine_switch_after_delay() {
+        let start = Instant::now();
+        let mut focus = LogicalFocus::new(DELAY, Some(1));
+
+        assert_eq!(focus.displayed(2, ms(start, 0)), None);
+        assert_eq!(focus.deadline(), Some(ms(start, 50)));
+        assert_eq!(focus.on_deadline(ms(start, 49)), None);
+        assert_eq!(focus.on_deadline(ms(start, 50)), Some(2));
+        assert_eq!(focus.deadline(), None);
+
+        // switching back is a change again
+        assert_eq!(focus.displayed(1, ms(start, 60)), None);
+        assert_eq!(focus.on_deadline(ms(start, 110)), Some(1));
+    }
+
+    #[test]
+    fn only_the_last_of_several_switches_counts() {
+        let start = Instant::now();
+        let mut focus = LogicalFocus::new(DELAY, Some(1));
+
+        focus.displayed(2, ms(start, 0));
+        focus.displayed(3, ms(start, 30));
+        assert_eq!(focus.on_deadline(ms(start, 50)), None);
+        assert_eq!(focus.on_deadline(ms(start, 80)), Some(3));
+    }
+
+    #[test]
+    fn no_delay_changes_right_away() {
+        let start = Instant::now();
+        let mut focus = LogicalFocus::new(Duration::ZERO, None);
+
+        assert_eq!(focus.displayed(1, start), Some(1));
+        assert_eq!(focus.displayed(1, start), None);
+        assert_eq!(focus.displayed(2, start), Some(2));
+        assert_eq!(focus.deadline(), None);
+    }
+}
@@ -0,0 +1,70 @@
+//! Reloading the config on `SIGHUP`, like daemons do.
+//!
+//! The signal handler only writes a byte to a socket pair whose other end is watched by the main loop, which is all
+//! it can safely do. The main loop then reloads the config like it does when the files change.
+
+use std::io::{ErrorKind, Read};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicI32, Ordering};
+use anyhow::Context;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+/// The end of the socket pair the handler writes to, or -1 before it's installed.
+static NOTIFY: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_hangup(_: nix::libc::c_int) {
+    let fd = NOTIFY.load(Ordering::Relaxed);
+
+    if fd >= 0 {
+        // nothing can be done about a failure here, and a full socket means the main loop has yet to wake up anyway
+        // SAFETY: write is async-signal-safe, and the buffer outlives the call
+        unsafe { nix::libc::write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+}
+
+pub struct Hangup {
+    wakeup: UnixStream,
+
+    /// Kept open for the handler, which only knows its file descriptor.
+    _notify: UnixStream,
+}
+
+impl Hangup {
+    /// Installs the handler. The process is no longer terminated by `SIGHUP` from then on.
+    pub fn install() -> anyhow::Result<Self> {
+        let (wakeup, notify) = UnixStream::pair().context("failed to create wakeup socket pair")?;
+        wakeup.set_nonblocking(true).context("failed to make wakeup socket non-blocking")?;
+        notify.set_nonblocking(true).context("failed to make notify socket non-blocking")?;
+        NOTIFY.store(notify.as_raw_fd(), Ordering::Relaxed);
+
+        let action = SigAction::new(SigHandler::Handler(on_hangup), SaFlags::SA_RESTART, SigSet::empty());
+
+        // SAFETY: the handler only loads an atomic and calls write
+        unsafe { signal::sigaction(Signal::SIGHUP, &action) }.context("failed to install SIGHUP handler")?;
+
+        Ok(Self { wakeup, _notify: notify })
+    }
+
+    pub fn on_readable(&mut self) -> bool {
+        let mut received = false;
+        let mut buffer = [0; 64];
+
+        loop {
+            match self.wakeup.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => received = true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+
+        received
+    }
+}
+
+impl AsRawFd for Hangup {
+    fn as_raw_fd(&self) -> RawFd {
+        self.wakeup.as_raw_fd()
+    }
+}
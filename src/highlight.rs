@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+/// Keeps track of whether the output is currently highlighted because the focused window just changed.
+///
+/// Starting a new highlight while one is still pending replaces it, so the revert only ever happens
+/// `duration` after the latest change.
+#[derive(Debug)]
+pub struct Highlight {
+    duration: Duration,
+    until: Option<Instant>,
+}
+
+impl Highlight {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            until: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.until.is_some()
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.until = Some(now + self.duration)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.until
+    }
+
+    /// Ends the highlight if it's due. Returns whether it ended, in which case the output needs to be rendered
+    /// again with the normal template.
+    pub fn on_deadline(&mut self, now: Instant) -> bool {
+        match self.until {
+            Some(until) if now >= until => {
+                self.until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATION: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn reverts_after_the_duration() {
+        let start = Instant::now();
+        let mut highlight = Highlight::new(DURATION);
+        assert!(!highlight.is_active());
+
+        highlight.start(start);
+        assert!(highlight.is_active());
+        assert_eq!(highlight.deadline(), Some(start + DURATION));
+
+        assert!(!highlight.on_deadline(start + DURATION - Duration::from_millis(1)));
+        assert!(highlight.is_active());
+
+        assert!(highlight.on_deadline(start + DURATION));
+        assert!(!highlight.is_active());
+        assert_eq!(highlight.deadline(), None);
+
+        // the revert only happens once
+        assert!(!highlight.on_deadline(start + DURATION * 2));
+    }
+
+    #[test]
+    fn new_change_cancels_pending_revert() {
+        let start = Instant::now();
+        let mut highlight = Highlight::new(DURATION);
+
+        highlight.start(start);
+        highlight.start(start + Duration::from_millis(400));
+        assert_eq!(highlight.deadline(), Some(start + Duration::from_millis(900)));
+
+        // the first change's revert would have been due here
+        assert!(!highlight.on_deadline(start + DURATION));
+        assert!(highlight.is_active());
+
+        assert!(highlight.on_deadline(start + Duration::from_millis(900)));
+        assert!(!highlight.is_active());
+    }
+}
@@ -0,0 +1,152 @@
+use std::process::Command;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+#[derive(Clone, Debug)]
+pub struct HookEnv {
+    pub class: String,
+    pub name: String,
+    pub window_id: u32,
+    pub desktop: Option<u32>,
+}
+
+impl HookEnv {
+    fn apply(&self, command: &mut Command) {
+        command
+            .env("PTM_CLASS", &self.class)
+            .env("PTM_NAME", &self.name)
+            .env("PTM_WINDOW_ID", self.window_id.to_string())
+            .env("PTM_DESKTOP", self.desktop.map(|desktop| desktop.to_string()).unwrap_or_default());
+    }
+}
+
+/// Runs the `on_change` commands, each on its own thread so the event loop never waits on them.
+///
+/// At most one instance of every hook runs at a time. Changes which arrive while a hook is still running are
+/// coalesced, so that once it finishes it only runs again for the latest change.
+pub struct Hooks {
+    senders: Vec<Sender<HookEnv>>,
+}
+
+impl Hooks {
+    pub fn spawn(commands: &[String]) -> Self {
+        let senders = commands
+            .iter()
+            .cloned()
+            .map(|command| {
+                let (sender, receiver) = mpsc::channel::<HookEnv>();
+
+                thread::spawn(move || {
+                    while let Ok(mut env) = receiver.recv() {
+                        for newer in receiver.try_iter() {
+                            tracing::debug!(%command, "coalescing pending hook run");
+                            env = newer;
+                        }
+
+                        run(&command, &env)
+                    }
+                });
+
+                sender
+            })
+            .collect();
+
+        Self { senders }
+    }
+
+    pub fn notify(&self, env: &HookEnv) {
+        for sender in &self.senders {
+            // the thread only ever exits once the sender is dropped, so this can't fail
+            let _ = sender.send(env.clone());
+        }
+    }
+}
+
+fn run(command: &str, env: &HookEnv) {
+    tracing::debug!(%command, ?env, "running on_change hook");
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    env.apply(&mut cmd);
+
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!(%command, %status, "on_change hook failed"),
+        Err(error) => tracing::warn!(%command, "could not run on_change hook: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn env(class: &str, window_id: u32, desktop: Option<u32>) -> HookEnv {
+        HookEnv { class: class.into(), name: format!("{class} window"), window_id, desktop }
+    }
+
+    fn wait_for_lines(path: &Path, lines: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        loop {
+            let contents = fs::read_to_string(path).unwrap_or_default();
+            let found: Vec<_> = contents.lines().map(str::to_owned).collect();
+
+            if found.len() >= lines || Instant::now() > deadline {
+                return found
+            }
+
+            thread::sleep(Duration::from_millis(10))
+        }
+    }
+
+    #[test]
+    fn hooks_see_the_window_in_their_environment() {
+        let dir = TempDir::new("hooks-env");
+        let log = dir.path().join("log");
+        let hooks = Hooks::spawn(&[format!(r#"echo "$PTM_CLASS|$PTM_NAME|$PTM_WINDOW_ID|$PTM_DESKTOP" >> '{}'"#, log.display())]);
+
+        hooks.notify(&env("firefox", 42, Some(3)));
+        assert_eq!(wait_for_lines(&log, 1), ["firefox|firefox window|42|3"]);
+
+        hooks.notify(&env("it's \"quoted\"", 7, None));
+        assert_eq!(wait_for_lines(&log, 2)[1], "it's \"quoted\"|it's \"quoted\" window|7|");
+    }
+
+    #[test]
+    fn changes_while_a_hook_runs_are_coalesced() {
+        let dir = TempDir::new("hooks-coalesce");
+        let log = dir.path().join("log");
+        let hooks = Hooks::spawn(&[format!(r#"echo "$PTM_WINDOW_ID" >> '{}'; sleep 0.3"#, log.display())]);
+
+        hooks.notify(&env("a", 1, None));
+        wait_for_lines(&log, 1);
+
+        for window_id in 2..=5 {
+            hooks.notify(&env("a", window_id, None))
+        }
+
+        assert_eq!(wait_for_lines(&log, 2), ["1", "5"]);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "1\n5\n");
+    }
+
+    #[test]
+    fn failing_hooks_keep_running() {
+        let dir = TempDir::new("hooks-fail");
+        let log = dir.path().join("log");
+        let hooks = Hooks::spawn(&[
+            "exit 1".to_owned(),
+            format!(r#"echo "$PTM_WINDOW_ID" >> '{}'; exit 3"#, log.display()),
+        ]);
+
+        hooks.notify(&env("a", 1, None));
+        wait_for_lines(&log, 1);
+        hooks.notify(&env("a", 2, None));
+
+        assert_eq!(wait_for_lines(&log, 2), ["1", "2"]);
+    }
+}
@@ -0,0 +1,2357 @@
+mod actions;
+pub mod app;
+mod application;
+mod atoms;
+mod breaker;
+pub mod check;
+mod client;
+mod colors;
+mod content;
+pub mod control;
+mod desktop;
+mod enrich;
+pub mod exit;
+pub mod diagnose;
+mod expand;
+mod fade;
+mod filter_template;
+pub mod features;
+mod focus;
+mod hangup;
+mod highlight;
+mod hooks;
+mod machine;
+mod metrics;
+mod monitor;
+mod name_template;
+mod oneshot;
+pub mod output;
+mod outputs;
+mod pattern;
+mod persist;
+mod ping;
+mod pointer;
+mod process;
+mod quirks;
+mod reconnect;
+pub mod record;
+mod render_cache;
+mod scroll;
+mod script;
+mod settle;
+mod similar;
+pub mod stdin;
+mod subscriptions;
+pub mod templating;
+#[cfg(test)]
+mod testing;
+mod truncate;
+mod units;
+mod urgent;
+mod usage;
+mod watch;
+pub mod which;
+mod wm_state;
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use anyhow::Context;
+use directories::ProjectDirs;
+use figment::providers::{Format, Serialized, Toml};
+use figment::Figment;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::{env, fmt, fs, str};
+use std::fmt::Formatter;
+use std::str::FromStr;
+use convert_case::{Case, Casing};
+use unicode_segmentation::UnicodeSegmentation;
+use x11rb::connection::RequestConnection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::properties::WmClass;
+use x11rb::protocol::ErrorKind;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, GetPropertyReply, Window};
+use x11rb::rust_connection::RustConnection;
+use serde_with::{serde_as, DeserializeFromStr, SerializeDisplay};
+use crate::actions::Actions;
+use crate::application::{AppInfo, Application, Applications};
+use crate::atoms::Atoms;
+use crate::colors::{BarColor, Colors};
+use crate::content::ContentChangeDetection;
+use crate::desktop::{Desktop, DesktopName, WorkspacePrefix};
+use crate::fade::FocusFade;
+use crate::machine::MachineInspector;
+use crate::name_template::NameTemplate;
+use crate::output::{Output, OutputFormat};
+use crate::outputs::ExtraOutput;
+use crate::filter_template::FilterTemplate;
+use crate::pattern::Pattern;
+use crate::pointer::Track;
+use crate::process::ProcessInspector;
+use crate::quirks::Quirk;
+use crate::script::{Script, Transliterate};
+use crate::scroll::Scroll;
+use crate::reconnect::Reconnect;
+use crate::similar::SuppressSimilar;
+use crate::stdin::Backend;
+use crate::units::{ByteSize, Millis, Seconds};
+
+pub use crate::oneshot::{resolve_active, resolve_window, resolve_window_on, ResolvedTitle};
+
+pub type NonNullWindow = NonZeroU32;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Config {
+    pub display_name: Option<String>,
+
+    #[serde(default)]
+    pub backend: Backend,
+
+    #[serde(default = "template")]
+    pub template: String,
+    pub workspace_prefix: Option<WorkspacePrefix>,
+
+    /// Template used instead of `template` for `highlight` after the focused window changes.
+    pub changed_template: Option<String>,
+
+    #[serde(default = "highlight", alias = "highlight_ms")]
+    pub highlight: Millis,
+
+    /// How long a newly displayed window has to stay displayed before hooks run and the highlight starts for it.
+    /// The output itself is updated right away.
+    #[serde(default, alias = "focus_settle_ms")]
+    pub focus_settle: Millis,
+
+    #[serde(default)]
+    pub track: Track,
+
+    #[serde(default = "pointer_interval", alias = "pointer_interval_ms")]
+    pub pointer_interval: Millis,
+
+    /// Only displays windows on the monitor with this RandR name, e.g. `"DP-1"`, for a bar on every monitor. Windows
+    /// on other monitors count as no window being active, so `desktop_behavior` decides what's displayed instead.
+    /// Defaults to `$MONITOR`, which polybar sets for its modules. An empty name displays windows on every monitor.
+    #[serde(default = "monitor")]
+    pub monitor: Option<String>,
+
+    /// While windows other than the focused one are urgent, alternate between the focused window and each of them
+    /// (as "⚠ <name>") this often, e.g. `"3s"`.
+    #[serde(alias = "alternate_interval_ms")]
+    pub alternate_interval: Option<Millis>,
+
+    /// Keep the previous output when a window with `_NET_WM_STATE_SKIP_TASKBAR` is focused, like taskbars do.
+    #[serde(default)]
+    pub respect_skip_taskbar: bool,
+
+    #[serde(default)]
+    pub quirks: Vec<Quirk>,
+
+    #[serde(default)]
+    pub disable_quirks: Vec<Quirk>,
+
+    #[serde(default)]
+    pub on_change: Vec<String>,
+
+    /// Displayed for windows which keep failing to answer property requests, instead of their last known name.
+    pub unresponsive_text: Option<String>,
+
+    /// Rendered and written instead of the title when the module crashes, with the error as `error`.
+    pub crash_template: Option<String>,
+
+    /// Written once an event failed to be handled a few times in a row, e.g. because the active window can't be
+    /// resolved or the template fails to render. Until then, and without it, the previous output stays.
+    pub error_text: Option<String>,
+
+    #[serde(default)]
+    pub reconnect: Reconnect,
+
+    /// Truncates the output to this many characters. Characters made up of several code points count as one and are
+    /// never cut apart. Polybar formatting tags don't count, are never cut into and get closed if the cut leaves them
+    /// open.
+    pub max_output_length: Option<usize>,
+    pub scroll: Option<Scroll>,
+
+    /// Doesn't write outputs which are very similar to the previous one for the same window for a while, to stop
+    /// constantly changing titles (e.g. progress percentages) from flickering.
+    pub suppress_similar: Option<SuppressSimilar>,
+    pub focus_fade: Option<FocusFade>,
+
+    #[serde(default)]
+    pub output: Output,
+
+    #[serde(default)]
+    pub output_format: OutputFormat,
+
+    /// Writes every update, even if it's the same as the line written last, like some window managers cause by
+    /// setting `_NET_ACTIVE_WINDOW` twice. Such lines are skipped by default so that the bar doesn't lay itself out
+    /// again for nothing.
+    #[serde(default)]
+    pub always_print: bool,
+
+    /// Lets the module format what it writes on its own: the prefix of urgent windows and waybar's classes. Turning it
+    /// off (or running with `--plain` or `NO_COLOR` set) writes the rendered templates as they are, which is easier to
+    /// read in logs and to compare.
+    #[serde(default = "styling")]
+    pub styling: bool,
+
+    #[serde(default)]
+    pub outputs: Vec<ExtraOutput>,
+
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+
+    /// Listen for commands (e.g. from click actions or keybindings) on a Unix socket at this path. `polybar-title-module
+    /// msg <command>` sends commands to it.
+    pub control_socket: Option<PathBuf>,
+
+    /// Commands run when the output is clicked or scrolled on, through polybar's action tags around it. Ignored by
+    /// waybar.
+    #[serde(default)]
+    pub action: Actions,
+
+    /// Makes templates referring to variables which don't exist an error, rather than rendering them as nothing.
+    /// Variables which exist, but which the backend has no values for, are warned about instead.
+    #[serde(default)]
+    pub strict_templates: bool,
+
+    /// When rendering the templates for a window takes longer than this, e.g. because of an `{{#each}}` over a huge
+    /// value, rendering is cut short and the output is just the name instead.
+    #[serde(default = "render_budget")]
+    pub render_budget: Millis,
+
+    /// Expands environment variables (`$VAR` or `${VAR}`, `$$` for a literal `$`) in the templates, the `on_change`
+    /// commands and `control_socket`, plus a leading `~/` in the latter two. Filters and names are never expanded. A
+    /// variable which isn't set is an error.
+    #[serde(default)]
+    pub expand_env: bool,
+
+    /// Measures how long it takes from an X event to fetching the properties of the window, applying the filters,
+    /// rendering the template and writing the output. A summary is logged every 5 minutes, and `get` on the control
+    /// socket replies with the histograms.
+    #[serde(default)]
+    pub latency_metrics: bool,
+
+    /// Looks up the child process of windows on a worker thread, so that walking `/proc` never delays the output.
+    /// Windows are displayed with the child process they had before right away, and again once it was looked up,
+    /// which can briefly show an outdated name if filters match on the child process.
+    #[serde(default)]
+    pub enrich_in_background: bool,
+
+    /// Resolves the displayed window again when its content changes without any of its properties changing, which
+    /// some kiosk-style applications do. It's noticed by hashing a small sample of the window every `interval`. Off
+    /// by default, as it costs a few requests per interval.
+    pub content_change_detection: Option<ContentChangeDetection>,
+
+    /// Keeps renames and pausing done through the control socket in a state file in `$XDG_STATE_HOME`, so that they
+    /// survive restarts. Renames are matched to windows by their class, instance and the start of their title.
+    #[serde(default)]
+    pub persist_state: bool,
+
+    #[serde(default = "persist_max_age")]
+    pub persist_max_age: Seconds,
+
+    /// Keeps how long windows of each class were focused (`stats usage` on the control socket) in the state file,
+    /// so that it adds up across restarts. It's saved once a minute.
+    #[serde(default)]
+    pub usage_tracking: bool,
+    pub resolver: Resolver,
+}
+
+/// What the command line overrides in the config (`--config`, `--display`, `--template` and `--format`), whenever
+/// it's read, including when it's reloaded.
+#[derive(Clone, Default, Debug)]
+pub struct Overrides {
+    /// The only file the config is read from, which has to exist.
+    pub config: Option<PathBuf>,
+    pub display_name: Option<String>,
+    pub template: Option<String>,
+    pub output_format: Option<String>,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+impl Overrides {
+    /// Applies the overrides to the config from now on. Only the first call has an effect.
+    pub fn set(self) {
+        let _ = OVERRIDES.set(self);
+    }
+
+    fn get() -> Option<&'static Self> {
+        OVERRIDES.get()
+    }
+}
+
+impl Config {
+    /// The files the config is read from. Earlier files take precedence over later ones.
+    pub fn paths() -> Vec<PathBuf> {
+        if let Some(config) = Overrides::get().and_then(|overrides| overrides.config.clone()) {
+            return vec![config]
+        }
+
+        let config_toml = ProjectDirs::from("", "ALinuxPerson", "polybar-title-module")
+            .map(|pd| pd.config_dir().join("config.toml"));
+
+        if config_toml.is_none() {
+            tracing::warn!("could not get project directories");
+        }
+
+        config_toml
+            .into_iter()
+            .chain([PathBuf::from("polybar-title-module.toml")])
+            .collect()
+    }
+
+    pub fn read() -> anyhow::Result<Self> {
+        if let Some(config) = Overrides::get().and_then(|overrides| overrides.config.as_ref()) {
+            anyhow::ensure!(config.is_file(), "config file {} does not exist", config.display());
+        }
+
+        Self::extract(&Self::figment())
+    }
+
+    /// The default config with what the command line overrides, for when the config files are invalid.
+    pub fn fallback() -> Self {
+        let mut config = Self::default();
+
+        if let Some(overrides) = Overrides::get() {
+            config.display_name = overrides.display_name.clone().or(config.display_name);
+            config.template = overrides.template.clone().unwrap_or(config.template);
+        }
+
+        config
+    }
+
+    /// Whether the config file was given on the command line, in which case it has to be valid.
+    pub fn is_overridden() -> bool {
+        Overrides::get().is_some_and(|overrides| overrides.config.is_some())
+    }
+
+    pub fn extract(figment: &Figment) -> anyhow::Result<Self> {
+        let files = figment
+            .metadata()
+            .filter_map(|metadata| metadata.source.as_ref()?.file_path())
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .collect::<Vec<_>>();
+
+        Self::extract_from(figment, files.iter().map(String::as_str))
+    }
+
+    fn extract_from<'s>(figment: &Figment, sources: impl IntoIterator<Item = &'s str>) -> anyhow::Result<Self> {
+        let mut config = figment.extract::<Self>().context("failed to get config")?;
+        config.resolver.declared = declaration_order(sources);
+
+        if config.expand_env {
+            config.expand().context("failed to expand environment variables in config")?;
+        }
+
+        Ok(config)
+    }
+
+    fn expand(&mut self) -> anyhow::Result<()> {
+        let templates = [Some(&mut self.template), self.changed_template.as_mut(), self.crash_template.as_mut()]
+            .into_iter()
+            .flatten()
+            .chain(self.workspace_prefix.as_mut().map(|workspace_prefix| &mut workspace_prefix.template))
+            .chain(self.templates.values_mut());
+
+        for template in templates {
+            *template = expand::expand_env(template)?;
+        }
+
+        for command in &mut self.on_change {
+            *command = expand::expand_path(command)?;
+        }
+
+        if let Some(control_socket) = &mut self.control_socket {
+            let path = control_socket.to_str().context("control_socket is not valid utf-8")?;
+            *control_socket = PathBuf::from(expand::expand_path(path)?);
+        }
+
+        Ok(())
+    }
+
+    pub fn figment() -> Figment {
+        let figment = Self::paths()
+            .into_iter()
+            .fold(Figment::new(), |figment, path| figment.join(Toml::file(path)));
+
+        let Some(overrides) = Overrides::get() else {
+            return figment
+        };
+
+        [
+            ("display_name", &overrides.display_name),
+            ("template", &overrides.template),
+            ("output_format", &overrides.output_format),
+        ]
+            .into_iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| (key, value)))
+            .fold(figment, |figment, (key, value)| figment.merge(Serialized::default(key, value)))
+    }
+
+    pub fn from_sources<'s>(sources: impl IntoIterator<Item = &'s str> + Clone) -> anyhow::Result<Self> {
+        let figment = sources
+            .clone()
+            .into_iter()
+            .fold(Figment::new(), |figment, source| figment.join(Toml::string(source)));
+
+        Self::extract_from(&figment, sources)
+    }
+}
+
+/// The position every filter is declared at in `sources`, the contents of the config files in the order they're
+/// merged in. Figment keeps tables sorted by their keys, so the order is read from the files themselves. Files which
+/// aren't valid are skipped, as figment reports them anyway.
+fn declaration_order<'s>(sources: impl IntoIterator<Item = &'s str>) -> HashMap<WindowIdentifier, usize> {
+    let mut declared = HashMap::new();
+
+    for source in sources {
+        let Ok(table) = toml::from_str::<toml::Table>(source) else {
+            continue
+        };
+        let filters = table
+            .get("resolver")
+            .and_then(|resolver| resolver.get("filters"))
+            .and_then(toml::Value::as_table);
+
+        for identifier in filters.into_iter().flat_map(toml::Table::keys).filter_map(|key| key.parse().ok()) {
+            let position = declared.len();
+            declared.entry(identifier).or_insert(position);
+        }
+    }
+
+    declared
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            display_name: None,
+            backend: Backend::default(),
+            template: template(),
+            workspace_prefix: None,
+            changed_template: None,
+            highlight: highlight(),
+            focus_settle: Millis::default(),
+            track: Track::default(),
+            monitor: monitor(),
+            pointer_interval: pointer_interval(),
+            alternate_interval: None,
+            respect_skip_taskbar: false,
+            quirks: Vec::new(),
+            disable_quirks: Vec::new(),
+            on_change: Vec::new(),
+            unresponsive_text: None,
+            crash_template: None,
+            error_text: None,
+            reconnect: Reconnect::default(),
+            max_output_length: None,
+            scroll: None,
+            focus_fade: None,
+            suppress_similar: None,
+            output: Output::default(),
+            output_format: OutputFormat::default(),
+            always_print: false,
+            styling: true,
+            outputs: Vec::new(),
+            templates: BTreeMap::new(),
+            control_socket: None,
+            action: Actions::default(),
+            strict_templates: false,
+            render_budget: render_budget(),
+            expand_env: false,
+            latency_metrics: false,
+            enrich_in_background: false,
+            content_change_detection: None,
+            persist_state: false,
+            persist_max_age: persist_max_age(),
+            usage_tracking: false,
+            resolver: Resolver::default(),
+        }
+    }
+}
+
+#[derive(DeserializeFromStr, SerializeDisplay, Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum WindowIdentifierKind {
+    Class,
+
+    /// The instance, the first part of `WM_CLASS`, which some applications let be set per window (e.g. `st -n` or
+    /// Chromium profiles) while the class stays the same. It's looked up right before the class by default.
+    Instance,
+
+    /// The title, from `_NET_WM_NAME`, or `WM_NAME` if the window doesn't set that.
+    Name,
+
+    /// `WM_WINDOW_ROLE`, which tells windows of the same application apart, e.g. `gimp-toolbox` and
+    /// `gimp-image-window`. Windows without one are skipped by this kind.
+    Role,
+    ChildProcess,
+    FlatpakId,
+    ClientMachine,
+    Remote,
+    Xwayland,
+
+    /// A regular expression the class has to match, e.g. `class_regex=jetbrains-.*`. Regex filters are tried after
+    /// the exact ones, but only if the property they match on is in `match_order`.
+    ClassRegex,
+    NameRegex,
+}
+
+impl WindowIdentifierKind {
+    pub fn regex_of(self) -> Option<Self> {
+        match self {
+            Self::ClassRegex => Some(Self::Class),
+            Self::NameRegex => Some(Self::Name),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for WindowIdentifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wm_class" | "wmc" | "wc" | "c" | "cls" | "wcls" | "class" => Ok(Self::Class),
+            "instance" | "inst" | "i" => Ok(Self::Instance),
+            "wm_name" | "wmn" | "wn" | "n" | "name" => Ok(Self::Name),
+            "role" | "r" => Ok(Self::Role),
+            "child_process" | "child" | "cp" => Ok(Self::ChildProcess),
+            "flatpak_id" | "flatpak" | "fp" => Ok(Self::FlatpakId),
+            "client_machine" | "machine" | "wcm" => Ok(Self::ClientMachine),
+            "remote" => Ok(Self::Remote),
+            "xwayland" | "xw" => Ok(Self::Xwayland),
+            "class_regex" | "wm_class_regex" | "cr" => Ok(Self::ClassRegex),
+            "name_regex" | "wm_name_regex" | "nr" => Ok(Self::NameRegex),
+            _ => anyhow::bail!("unknown window identifier kind"),
+        }
+    }
+}
+
+#[derive(DeserializeFromStr, SerializeDisplay, Eq, PartialEq, Hash, Clone, Debug)]
+pub struct WindowIdentifier {
+    pub kind: WindowIdentifierKind,
+    pub value: String,
+}
+
+impl WindowIdentifier {
+    /// Whether this is an exact kind whose value has `*` or `?` wildcards, which match like in a shell glob.
+    pub fn is_wildcard(&self) -> bool {
+        self.kind.regex_of().is_none() && self.value.contains(['*', '?'])
+    }
+
+    fn literal_prefix_len(&self) -> usize {
+        self.value.chars().take_while(|c| !matches!(c, '*' | '?')).count()
+    }
+}
+
+impl fmt::Display for WindowIdentifierKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Class => f.write_str("wm_class"),
+            Self::Instance => f.write_str("instance"),
+            Self::Name => f.write_str("wm_name"),
+            Self::Role => f.write_str("role"),
+            Self::ChildProcess => f.write_str("child_process"),
+            Self::FlatpakId => f.write_str("flatpak_id"),
+            Self::ClientMachine => f.write_str("client_machine"),
+            Self::Remote => f.write_str("remote"),
+            Self::Xwayland => f.write_str("xwayland"),
+            Self::ClassRegex => f.write_str("class_regex"),
+            Self::NameRegex => f.write_str("name_regex"),
+        }
+    }
+}
+
+/// Parses a window identifier of the form `kind=value`.
+///
+/// The value may contain `\=`, `\"` and `\\` escapes, and may be quoted (`wm_name="a = b"`) in order to keep
+/// leading or trailing whitespace. Backslashes which don't start one of those escapes are taken literally.
+impl FromStr for WindowIdentifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let separator = unescaped_equals(s).context("no '=' in window identifier")?;
+        let (discriminant, value) = (&s[..separator], &s[separator + 1..]);
+        let kind = WindowIdentifierKind::from_str(discriminant.trim()).context("failed to resolve discriminant as a window identifier kind")?;
+
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let quoted = quoted
+                    .strip_suffix('"')
+                    .filter(|quoted| !ends_with_escape(quoted))
+                    .context("unterminated quote in window identifier value")?;
+                unescape(quoted)
+            }
+            None => unescape(value),
+        };
+
+        if kind.regex_of().is_some() {
+            value.parse::<Pattern>().with_context(|| format!("invalid regex {value:?} in window identifier"))?;
+        }
+
+        Ok(Self { kind, value })
+    }
+}
+
+impl fmt::Display for WindowIdentifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let needs_quotes = self.value.starts_with(char::is_whitespace)
+            || self.value.ends_with(char::is_whitespace)
+            || self.value.starts_with('"')
+            || self.value.contains('=');
+
+        if needs_quotes {
+            let escaped = self.value.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(f, "{}=\"{escaped}\"", self.kind)
+        } else {
+            write!(f, "{}={}", self.kind, self.value.replace('\\', "\\\\"))
+        }
+    }
+}
+
+fn unescaped_equals(s: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn ends_with_escape(s: &str) -> bool {
+    s.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue
+        }
+
+        match chars.next() {
+            Some(escaped @ ('=' | '"' | '\\')) => unescaped.push(escaped),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Resolver {
+    pub global_options: Option<Options>,
+
+    /// The name displayed when no window is active. It can be a table with a name for every desktop instead.
+    pub desktop_name: Option<DesktopName>,
+
+    /// Applied to `desktop_name` and the names of desktops reported by the window manager (the `desktop` variable),
+    /// e.g. to capitalize a workspace named `www`. Desktop names are left as they are if this isn't set.
+    #[serde(default)]
+    pub desktop_options: Option<Options>,
+
+    #[serde(default)]
+    pub desktop_behavior: DesktopBehavior,
+
+    #[serde(default)]
+    pub on_ignore: OnIgnore,
+
+    #[serde(default = "unknown_window_text")]
+    pub unknown_window_text: String,
+
+    /// The name of windows whose name is empty, or only whitespace, once filters and options are applied, e.g.
+    /// `"Untitled"` for splash screens which have yet to set their class. Such windows are displayed with an empty
+    /// name if this isn't set. The desktop still uses `desktop_name`.
+    #[serde(default)]
+    pub empty_title: Option<String>,
+
+    /// If a window has none of the properties it could be identified by, look for a window carrying them one level
+    /// above and below it. Some window managers report frame or child windows as active.
+    #[serde(default = "search_client_window")]
+    pub search_client_window: bool,
+
+    #[serde(default = "max_title_size")]
+    pub max_title_size: ByteSize,
+
+    /// How properties which aren't valid UTF-8 are read, like the Latin-1 `WM_NAME` of old Java applications or
+    /// xterm. Filters match on what they're read as.
+    #[serde(default)]
+    pub invalid_utf8: InvalidUtf8,
+
+    /// The filters by the windows they apply to. The value of an exact kind can have `*` and `?` wildcards, e.g.
+    /// `wm_class=gimp*` or `wm_name=* - mpv`, which are tried after the exact filters. A literal `*` or `?` can't
+    /// be matched on its own, but a wildcard matches it as well.
+    pub filters: HashMap<WindowIdentifier, FilterRule>,
+
+    /// Logical applications, which are matched like filters. A filter for the same window identifier as one of an
+    /// application takes precedence.
+    #[serde(default)]
+    pub applications: Applications,
+
+    /// The order in which the kinds of window identifiers are looked up in `filters`; the first kind with a matching
+    /// filter wins. Defaults to `["child_process", "flatpak_id", "instance", "wm_class", "wm_name", "role",
+    /// "client_machine", "remote", "xwayland"]`. Kinds which aren't listed are never looked up, and neither are the
+    /// regex filters on them, so an empty list skips every filter and application and goes straight to
+    /// `global_options`.
+    #[serde(default = "match_order", deserialize_with = "deserialize_match_order")]
+    pub match_order: Vec<WindowIdentifierKind>,
+
+    #[serde(skip)]
+    declared: HashMap<WindowIdentifier, usize>,
+
+    /// The wildcard and regex filters in the order they're tried, compiled the first time a window is resolved.
+    #[serde(skip)]
+    pattern_filters: OnceLock<Vec<(WindowIdentifier, Pattern)>>,
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DesktopBehavior {
+    #[default]
+    Name,
+    Empty,
+
+    /// Keep the output of the previously active window, or display `desktop_name` if there is none yet.
+    KeepLast,
+}
+
+/// What to display when the active window is matched by an `ignore` filter, e.g. a dock or a notification daemon
+/// which briefly takes focus.
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OnIgnore {
+    /// Keep the output of the previously active window, or display `desktop_name` if there is none yet.
+    #[default]
+    Keep,
+    Clear,
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidUtf8 {
+    #[default]
+    Lossy,
+
+    /// Read the whole property as Latin-1, which is what `WM_NAME` is meant to be in.
+    Latin1,
+    Error,
+}
+
+impl InvalidUtf8 {
+    pub fn decode(self, bytes: Vec<u8>) -> anyhow::Result<String> {
+        let error = match String::from_utf8(bytes) {
+            Ok(string) => return Ok(string),
+            Err(error) => error,
+        };
+
+        match self {
+            Self::Lossy => Ok(String::from_utf8_lossy(error.as_bytes()).into_owned()),
+            Self::Latin1 => Ok(error.as_bytes().iter().map(|byte| char::from(*byte)).collect()),
+            Self::Error => Err(error.into()),
+        }
+    }
+}
+
+fn unknown_window_text() -> String {
+    "Unknown".to_owned()
+}
+
+fn search_client_window() -> bool {
+    true
+}
+
+fn match_order() -> Vec<WindowIdentifierKind> {
+    vec![
+        WindowIdentifierKind::ChildProcess,
+        WindowIdentifierKind::FlatpakId,
+        WindowIdentifierKind::Instance,
+        WindowIdentifierKind::Class,
+        WindowIdentifierKind::Name,
+        WindowIdentifierKind::Role,
+        WindowIdentifierKind::ClientMachine,
+        WindowIdentifierKind::Remote,
+        WindowIdentifierKind::Xwayland,
+    ]
+}
+
+/// Rejects a `match_order` which lists a kind twice, or a regex kind, as regex filters are always tried after the exact
+/// ones.
+fn deserialize_match_order<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<WindowIdentifierKind>, D::Error> {
+    let match_order = Vec::<WindowIdentifierKind>::deserialize(deserializer)?;
+
+    for (index, kind) in match_order.iter().enumerate() {
+        if match_order[..index].contains(kind) {
+            return Err(de::Error::custom(format_args!("`{kind}` appears more than once in match_order")))
+        }
+
+        if kind.regex_of().is_some() {
+            return Err(de::Error::custom(format_args!("`{kind}` can't be in match_order, regex filters are always tried after the exact ones")))
+        }
+    }
+
+    Ok(match_order)
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedWindow {
+    pub window: Window,
+    pub name: String,
+    pub wm_class: String,
+
+    /// The title, from `_NET_WM_NAME`, or `WM_NAME` if the window doesn't set that.
+    pub wm_name: String,
+    pub instance: String,
+    pub role: Option<String>,
+    pub child_process: Option<String>,
+    pub flatpak_id: Option<String>,
+    pub client_machine: Option<String>,
+    pub remote: bool,
+    pub xwayland: bool,
+    pub app: Option<AppInfo>,
+    pub colors: Colors,
+
+    /// Whether the window is matched by an `ignore` filter, in which case `on_ignore` decides what's displayed
+    /// rather than `name`.
+    pub ignored: bool,
+}
+
+impl ResolvedWindow {
+    pub fn properties(&self) -> WindowProperties<'_> {
+        WindowProperties {
+            class: &self.wm_class,
+            instance: &self.instance,
+            name: &self.wm_name,
+            role: self.role.as_deref(),
+            child_process: self.child_process.as_deref(),
+            flatpak_id: self.flatpak_id.as_deref(),
+            client_machine: self.client_machine.as_deref(),
+            remote: self.client_machine.is_some().then_some(self.remote),
+            xwayland: Some(self.xwayland),
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        self.window != 0
+            && self.wm_class.is_empty()
+            && self.wm_name.is_empty()
+            && self.instance.is_empty()
+            && self.child_process.is_none()
+            && self.flatpak_id.is_none()
+    }
+
+    pub(crate) fn desktop(name: String) -> Self {
+        Self::without_properties(0, name)
+    }
+
+    pub(crate) fn without_properties(window: Window, name: String) -> Self {
+        Self {
+            window,
+            name,
+            wm_class: String::new(),
+            wm_name: String::new(),
+            instance: String::new(),
+            role: None,
+            child_process: None,
+            flatpak_id: None,
+            client_machine: None,
+            remote: false,
+            xwayland: false,
+            app: None,
+            colors: Colors::default(),
+            ignored: false,
+        }
+    }
+}
+
+impl Resolver {
+    pub fn matches_on(&self, kind: WindowIdentifierKind) -> bool {
+        self.match_order.contains(&kind) && self.filters.keys().any(|identifier| identifier.kind == kind)
+    }
+
+    pub fn resolve(
+        &self,
+        connection: &impl RequestConnection,
+        atoms: &Atoms,
+        processes: &mut ProcessInspector,
+        machines: &MachineInspector,
+        window: Window,
+    ) -> anyhow::Result<ResolvedWindow> {
+        let fetched = self.fetch(connection, atoms, processes, machines, window)?;
+        Ok(self.apply(fetched))
+    }
+
+    /// Fetches the properties of `window`, without resolving its name yet, which [`Self::apply`] does. The desktop
+    /// is resolved right away, as it has no properties.
+    pub fn fetch(
+        &self,
+        connection: &impl RequestConnection,
+        atoms: &Atoms,
+        processes: &mut ProcessInspector,
+        machines: &MachineInspector,
+        window: Window,
+    ) -> anyhow::Result<ResolvedWindow> {
+        let Some(window) = NonNullWindow::new(window) else {
+            tracing::debug!("window was 0, assuming it's desktop");
+            return Ok(self.desktop())
+        };
+
+        // titles are read in units of 4 bytes
+        let title_length = u32::try_from(self.max_title_size.bytes().div_ceil(4)).unwrap_or(u32::MAX);
+
+        // every request is sent before waiting for any reply, so they share a single round trip
+        tracing::debug!("retrieve WM_CLASS, _NET_WM_NAME, WM_NAME, WM_WINDOW_ROLE and _FLATPAK_ID of window");
+        let wm_class = WmClass::get(connection, window.get()).context("failed to make WmClass reply")?;
+        let net_wm_name = connection
+            .get_property(false, window.get(), atoms.net_wm_name, atoms.utf8_string, 0, title_length)
+            .context("failed to make GetProperty reply for retrieving _NET_WM_NAME")?;
+        let wm_name = connection
+            .get_property(false, window.get(), AtomEnum::WM_NAME, AtomEnum::STRING, 0, title_length)
+            .context("failed to make GetProperty reply for retrieving WM_NAME")?;
+        let role = connection
+            .get_property(false, window.get(), atoms.wm_window_role, AtomEnum::STRING, 0, 256)
+            .context("failed to make GetProperty reply for retrieving WM_WINDOW_ROLE")?;
+        let flatpak_id = connection
+            .get_property(false, window.get(), atoms.flatpak_id, AtomEnum::ANY, 0, 256)
+            .context("failed to make GetProperty reply for retrieving _FLATPAK_ID")?;
+        let client_machine = machines
+            .is_enabled()
+            .then(|| connection.get_property(false, window.get(), AtomEnum::WM_CLIENT_MACHINE, AtomEnum::STRING, 0, 256))
+            .transpose()
+            .context("failed to make GetProperty reply for retrieving WM_CLIENT_MACHINE")?;
+
+        let decode = self.invalid_utf8;
+        let wm_class = wm_class.reply().context("WmClass response failed")?;
+        let instance = decode.decode(wm_class.instance().to_vec()).context("WM_CLASS instance contains invalid utf-8")?;
+        let wm_class = decode.decode(wm_class.class().to_vec()).context("WM_CLASS contains invalid utf-8")?;
+        tracing::debug!(%wm_class, %instance, "WM_CLASS of window");
+
+        // most applications keep the UTF-8 _NET_WM_NAME up to date, which WM_NAME often isn't
+        let net_wm_name = net_wm_name.reply().context("GetProperty response for retrieving _NET_WM_NAME failed")?;
+        let net_wm_name = string_property(net_wm_name, decode).context("_NET_WM_NAME contains invalid utf-8")?;
+        let wm_name = wm_name.reply().context("GetProperty response for retrieving WM_NAME failed")?.value;
+        let wm_name = match net_wm_name {
+            Some(net_wm_name) => {
+                tracing::debug!(%net_wm_name, "_NET_WM_NAME of window");
+                net_wm_name
+            }
+            None => {
+                let wm_name = decode.decode(wm_name).context("WM_NAME contains invalid utf-8")?;
+                tracing::debug!(%wm_name, "window has no _NET_WM_NAME, WM_NAME of window");
+                wm_name
+            }
+        };
+
+        let role = role.reply().context("GetProperty response for retrieving WM_WINDOW_ROLE failed")?;
+        let role = string_property(role, decode).context("WM_WINDOW_ROLE contains invalid utf-8")?;
+        tracing::debug!(?role, "WM_WINDOW_ROLE of window");
+
+        let flatpak_id = flatpak_id.reply().context("GetProperty response for retrieving _FLATPAK_ID failed")?;
+        let flatpak_id = string_property(flatpak_id, decode).context("_FLATPAK_ID contains invalid utf-8")?;
+        tracing::debug!(?flatpak_id, "_FLATPAK_ID of window");
+
+        let client_machine = match client_machine {
+            Some(client_machine) => {
+                let client_machine = client_machine.reply().context("GetProperty response for retrieving WM_CLIENT_MACHINE failed")?;
+                string_property(client_machine, decode).context("WM_CLIENT_MACHINE contains invalid utf-8")?
+            }
+            None => None,
+        };
+        let remote = client_machine.as_deref().map(|client_machine| machines.is_remote(client_machine));
+        tracing::debug!(?client_machine, ?remote, "WM_CLIENT_MACHINE of window");
+
+        // the connection remembers this, so only the first window costs a round trip
+        let xwayland = connection
+            .extension_information("XWAYLAND")
+            .context("failed to query XWAYLAND extension")?
+            .is_some();
+
+        let child_process = if flatpak_id.is_some() {
+            // the pid of a sandboxed window is from within its sandbox, so it'd name an unrelated process here
+            None
+        } else if processes.is_enabled() {
+            tracing::debug!("retrieve foreground child process of window");
+            child_process(connection, atoms, processes, window).context("failed to retrieve foreground child process")?
+        } else {
+            None
+        };
+        tracing::debug!(?child_process, "foreground child process of window");
+
+        Ok(ResolvedWindow {
+            wm_class: sanitize(&wm_class).into_owned(),
+            instance: sanitize(&instance).into_owned(),
+            wm_name: sanitize(&wm_name).into_owned(),
+            role: role.as_deref().map(|role| sanitize(role).into_owned()),
+            child_process,
+            flatpak_id: flatpak_id.as_deref().map(|flatpak_id| sanitize(flatpak_id).into_owned()),
+            client_machine: client_machine.as_deref().map(|client_machine| sanitize(client_machine).into_owned()),
+            remote: remote.unwrap_or_default(),
+            xwayland,
+            ..ResolvedWindow::without_properties(window.get(), String::new())
+        })
+    }
+
+    pub fn desktop(&self) -> ResolvedWindow {
+        self.desktop_on(None)
+    }
+
+    pub fn desktop_on(&self, desktop: Option<&Desktop>) -> ResolvedWindow {
+        let name = self.desktop_name.as_ref().map(|name| name.of(desktop)).unwrap_or_default();
+
+        ResolvedWindow {
+            colors: Colors::of(self.desktop_options.iter().chain(&self.global_options)),
+            ..ResolvedWindow::desktop(self.resolve_desktop_name(&name).into_owned())
+        }
+    }
+
+    /// What's displayed for `resolved` according to `on_ignore`, if it's ignored. `None` keeps the previous output,
+    /// which is only possible if something was `displayed` before; the desktop is displayed otherwise.
+    pub fn apply_ignore(&self, resolved: ResolvedWindow, displayed: bool, desktop: Option<&Desktop>) -> Option<ResolvedWindow> {
+        match (resolved.ignored, self.on_ignore) {
+            (true, OnIgnore::Keep) if displayed => None,
+            (true, OnIgnore::Keep) => Some(self.desktop_on(desktop)),
+            _ => Some(resolved),
+        }
+    }
+
+    pub fn resolve_desktop_name<'n>(&self, name: &'n str) -> Cow<'n, str> {
+        match &self.desktop_options {
+            Some(options) => options.resolve(name),
+            None => Cow::Borrowed(name),
+        }
+    }
+
+    pub fn apply(&self, fetched: ResolvedWindow) -> ResolvedWindow {
+        if fetched.window == 0 {
+            return fetched
+        }
+
+        self.resolve_properties(fetched.window, fetched.properties())
+    }
+
+    /// The wildcard filters, then the regex filters. Wildcard filters are ordered by the length of their literal
+    /// prefix, longest first, as the longer it is the more specific the filter tends to be. Both are then ordered by
+    /// the position of the property they match on in `match_order`, then by the order they're declared in.
+    fn pattern_filters(&self) -> &[(WindowIdentifier, Pattern)] {
+        self.pattern_filters.get_or_init(|| {
+            let position = |kind: WindowIdentifierKind| {
+                let property = kind.regex_of().unwrap_or(kind);
+                self.match_order.iter().position(|kind| *kind == property)
+            };
+
+            let mut pattern_filters = self.filters
+                .keys()
+                .filter_map(|identifier| {
+                    let pattern = match identifier.kind.regex_of() {
+                        // like exact filters, wildcard and regex ones only apply to kinds which are looked up
+                        _ if position(identifier.kind).is_none() => return None,
+                        // the regex was already checked when the identifier was parsed
+                        Some(_) => identifier.value.parse().ok()?,
+                        None if identifier.is_wildcard() => Pattern::wildcard(&identifier.value),
+                        None => return None,
+                    };
+
+                    Some((identifier.clone(), pattern))
+                })
+                .collect::<Vec<_>>();
+            pattern_filters.sort_by_cached_key(|(identifier, _)| (
+                identifier.kind.regex_of().is_some(),
+                Reverse(if identifier.is_wildcard() { identifier.literal_prefix_len() } else { 0 }),
+                position(identifier.kind).unwrap_or(usize::MAX),
+                self.declared.get(identifier).copied().unwrap_or(usize::MAX),
+                identifier.to_string(),
+            ));
+            pattern_filters
+        })
+    }
+
+    /// Every filter and application matching `properties`, in the order they're evaluated in. The first one is what
+    /// the window gets resolved with.
+    ///
+    /// The order is deterministic: by priority (applications have 0), then by the kind's position in `match_order`,
+    /// with a filter before an application for the same identifier. Wildcard and regex filters come after all exact
+    /// ones with the same priority, in the order of [`Self::pattern_filters`].
+    pub(crate) fn matches<'r>(&'r self, properties: WindowProperties<'r>) -> Vec<(WindowIdentifier, Matched<'r>)> {
+        let mut matches = self.match_order
+            .iter()
+            .filter_map(move |kind| {
+                let value = properties.get(*kind)?;
+                tracing::debug!(%kind, %value, "find filter by window identifier");
+
+                Some(WindowIdentifier {
+                    kind: *kind,
+                    value: value.to_owned(),
+                })
+            })
+            .flat_map(|identifier| {
+                let filter = self.filters.get(&identifier).map(Matched::Filter);
+                let application = self.applications.get(&identifier).map(|(id, application)| Matched::Application(id, application));
+
+                [filter, application].into_iter().flatten().map(move |matched| (identifier.clone(), matched))
+            })
+            .collect::<Vec<_>>();
+
+        for (identifier, pattern) in self.pattern_filters() {
+            let matching = properties.get(identifier.kind).is_some_and(|value| pattern.is_match(value))
+                // a wildcard filter is also found by an exact lookup of a value which has the wildcards literally
+                && !matches.iter().any(|(matched, _)| matched == identifier);
+
+            if let (true, Some(filter)) = (matching, self.filters.get(identifier)) {
+                tracing::debug!(%identifier, "pattern filter matches");
+                matches.push((identifier.clone(), Matched::Filter(filter)));
+            }
+        }
+
+        // stable, so ties keep the order from above
+        matches.sort_by_key(|(_, matched)| Reverse(matched.priority()));
+        matches
+    }
+
+    pub fn resolve_properties(&self, window: Window, properties: WindowProperties) -> ResolvedWindow {
+        if properties.is_empty() {
+            tracing::debug!(%window, "window has no identifying properties");
+
+            return ResolvedWindow {
+                colors: Colors::of(&self.global_options),
+                ..ResolvedWindow::without_properties(window, self.unknown_window_text.clone())
+            }
+        }
+
+        let matched = self.matches(properties).into_iter().next().map(|(_, matched)| matched);
+        let application_name = match matched {
+            Some(Matched::Application(id, application)) => application.name(properties.name).map(|name| (id, name)),
+            _ => None,
+        };
+        let name = match (matched, application_name) {
+            (Some(Matched::Filter(filter)), _) => {
+                tracing::debug!("resolve with filters");
+                let name = filter.resolve(window, properties);
+
+                // a filter's own max_length wins, otherwise the global one still applies
+                match (filter.max_length(), &self.global_options) {
+                    (Some(_), _) | (_, None) => name,
+                    (None, Some(options)) => options.truncate(name),
+                }
+            }
+            (_, Some((id, name))) => {
+                tracing::debug!(%id, "resolve with application name");
+                let name = Cow::Owned(name.render(properties.name, properties.class, properties.instance));
+
+                match &self.global_options {
+                    Some(options) => options.truncate(name),
+                    None => name,
+                }
+            }
+            _ => match &self.global_options {
+                Some(options) => {
+                    tracing::debug!("falling back to global options");
+                    options.resolve(properties.class)
+                }
+                None => {
+                    tracing::debug!("no filters found, leaving WM_CLASS as is");
+                    Cow::Borrowed(properties.class)
+                }
+            },
+        };
+        let empty_title = match matched {
+            Some(Matched::Filter(filter)) => filter.empty_title.as_ref().or(self.empty_title.as_ref()),
+            _ => self.empty_title.as_ref(),
+        };
+        let name = match empty_title {
+            Some(empty_title) if name.trim().is_empty() => {
+                tracing::debug!(%window, "resolved name is empty, using empty_title");
+                Cow::Borrowed(empty_title.as_str())
+            }
+            _ => name,
+        };
+        let app = match matched {
+            Some(Matched::Application(id, application)) => Some(application.info(id, properties.name)),
+            _ => None,
+        };
+        let filter_options = match matched {
+            Some(Matched::Filter(filter)) => filter.options(),
+            _ => None,
+        };
+
+        ResolvedWindow {
+            window,
+            name: name.into_owned(),
+            wm_class: properties.class.to_owned(),
+            wm_name: properties.name.to_owned(),
+            instance: properties.instance.to_owned(),
+            role: properties.role.map(str::to_owned),
+            child_process: properties.child_process.map(str::to_owned),
+            flatpak_id: properties.flatpak_id.map(str::to_owned),
+            client_machine: properties.client_machine.map(str::to_owned),
+            remote: properties.remote.unwrap_or(false),
+            xwayland: properties.xwayland.unwrap_or(false),
+            app,
+            colors: Colors::of(filter_options.into_iter().chain(&self.global_options)),
+            ignored: matches!(matched, Some(Matched::Filter(FilterRule { method: Filter::Ignore, .. }))),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(crate) enum Matched<'r> {
+    Filter(&'r FilterRule),
+    Application(&'r str, &'r Application),
+}
+
+impl Matched<'_> {
+    fn priority(&self) -> i32 {
+        match self {
+            Self::Filter(filter) => filter.priority,
+            Self::Application(..) => 0,
+        }
+    }
+}
+
+/// Removes control characters from a value read from a window, since they would break the line based output.
+/// Whitespace control characters (tabs, newlines) are replaced by a space instead.
+pub fn sanitize(value: &str) -> Cow<'_, str> {
+    if !value.contains(char::is_control) {
+        return Cow::Borrowed(value)
+    }
+
+    Cow::Owned(value
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_whitespace() && c.is_control() => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect())
+}
+
+/// Reads `_NET_WM_PID` of the window and looks up the foreground descendant of that process, which for terminals
+/// is usually the command currently running in it.
+fn child_process(
+    connection: &impl RequestConnection,
+    atoms: &Atoms,
+    processes: &mut ProcessInspector,
+    window: NonNullWindow,
+) -> anyhow::Result<Option<String>> {
+    let Some(pid) = window_pid(connection, atoms, window)? else {
+        tracing::debug!("window has no _NET_WM_PID");
+        return Ok(None)
+    };
+
+    Ok(processes.foreground_child(window.get(), pid))
+}
+
+pub(crate) fn window_pid(connection: &impl RequestConnection, atoms: &Atoms, window: NonNullWindow) -> anyhow::Result<Option<u32>> {
+    Ok(connection
+        .get_property(false, window.get(), atoms.net_wm_pid, AtomEnum::CARDINAL, 0, 1)
+        .context("failed to make GetProperty reply for retrieving _NET_WM_PID")?
+        .reply()
+        .context("GetProperty response for retrieving _NET_WM_PID failed")?
+        .value32()
+        .and_then(|mut value| value.next()))
+}
+
+fn string_property(reply: GetPropertyReply, decode: InvalidUtf8) -> anyhow::Result<Option<String>> {
+    if reply.type_ == u32::from(AtomEnum::NONE) || reply.value.is_empty() {
+        return Ok(None)
+    }
+
+    decode.decode(reply.value).map(Some)
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self {
+            global_options: Some(Options {
+                capitalize: Some(CapitalizeMode::default()),
+                transliterate: None,
+                transliterate_scripts: None,
+                preserve_acronyms: false,
+                acronyms: Vec::new(),
+                lowercase_words: None,
+                max_length: None,
+                ellipsis: None,
+                prefix: None,
+                suffix: None,
+                foreground: None,
+                background: None,
+                underline: None,
+                strip_prefixes: None,
+                strip_suffixes: None,
+                replace: None,
+            }),
+            desktop_name: Some(DesktopName::Fixed("Desktop".to_owned())),
+            desktop_options: None,
+            desktop_behavior: DesktopBehavior::default(),
+            on_ignore: OnIgnore::default(),
+            unknown_window_text: unknown_window_text(),
+            empty_title: None,
+            search_client_window: search_client_window(),
+            max_title_size: max_title_size(),
+            invalid_utf8: InvalidUtf8::default(),
+            filters: HashMap::new(),
+            applications: Applications::default(),
+            match_order: match_order(),
+            declared: HashMap::new(),
+            pattern_filters: OnceLock::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FilterRule {
+    #[serde(flatten)]
+    pub method: Filter,
+
+    /// When several filters match a window, the one with the highest priority wins. Ties go to the filter whose
+    /// kind comes first in `match_order`, then to the one declared first.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+
+    /// Applied to the name a `new_name` or `template` filter resolves to, e.g. to capitalize it. They take the place
+    /// of `global_options`, which never transform such names; only the `max_length` of `global_options` applies if
+    /// these don't set one. An `options` filter ignores them, as its value is the options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<Options>,
+
+    /// Takes the place of the resolver's `empty_title` for the windows this filter matches, e.g. `""` to keep a
+    /// filter which hides windows by giving them an empty name working.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub empty_title: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "filter", content = "value", rename_all = "snake_case")]
+pub enum Filter {
+    Options(Box<Options>),
+    NewName(NameTemplate),
+    Template(FilterTemplate),
+
+    /// Hides the window, see `on_ignore`. It takes no value.
+    Ignore,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WindowProperties<'p> {
+    pub class: &'p str,
+    pub instance: &'p str,
+    pub name: &'p str,
+    pub role: Option<&'p str>,
+    pub child_process: Option<&'p str>,
+    pub flatpak_id: Option<&'p str>,
+    pub client_machine: Option<&'p str>,
+    pub remote: Option<bool>,
+    pub xwayland: Option<bool>,
+}
+
+impl<'p> WindowProperties<'p> {
+    pub fn get(&self, kind: WindowIdentifierKind) -> Option<&'p str> {
+        match kind {
+            WindowIdentifierKind::Class | WindowIdentifierKind::ClassRegex => Some(self.class),
+            WindowIdentifierKind::Instance => Some(self.instance),
+            WindowIdentifierKind::Role => self.role,
+            WindowIdentifierKind::Name | WindowIdentifierKind::NameRegex => Some(self.name),
+            WindowIdentifierKind::ChildProcess => self.child_process,
+            WindowIdentifierKind::FlatpakId => self.flatpak_id,
+            WindowIdentifierKind::ClientMachine => self.client_machine,
+            WindowIdentifierKind::Remote => self.remote.map(|remote| if remote { "true" } else { "false" }),
+            WindowIdentifierKind::Xwayland => self.xwayland.map(|xwayland| if xwayland { "true" } else { "false" }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.class.is_empty()
+            && self.instance.is_empty()
+            && self.name.is_empty()
+            && self.child_process.is_none_or(str::is_empty)
+            && self.flatpak_id.is_none_or(str::is_empty)
+    }
+}
+
+impl FilterRule {
+    pub fn resolve<'p>(&self, window: Window, properties: WindowProperties<'p>) -> Cow<'p, str> {
+        match &self.method {
+            Filter::Options(options) => {
+                tracing::debug!("resolving filter with options method");
+                options.resolve(properties.class)
+            },
+            Filter::NewName(name) => {
+                tracing::debug!(%name, "resolving filter with new name method");
+                self.apply_options(name.render(properties.name, properties.class, properties.instance))
+            },
+            Filter::Template(template) => {
+                tracing::debug!(%template, "resolving filter with template method");
+
+                match template.render(window, properties) {
+                    Ok(name) => self.apply_options(name),
+                    Err(error) => {
+                        tracing::warn!(%template, "failed to render filter template, leaving WM_CLASS as is: {error:#}");
+                        Cow::Borrowed(properties.class)
+                    }
+                }
+            }
+            Filter::Ignore => {
+                tracing::debug!("window is ignored by filter");
+                Cow::Borrowed("")
+            }
+        }
+    }
+
+    pub fn options(&self) -> Option<&Options> {
+        match &self.method {
+            Filter::Options(options) => Some(options.as_ref()),
+            _ => self.options.as_ref(),
+        }
+    }
+
+    pub fn max_length(&self) -> Option<usize> {
+        self.options().and_then(|options| options.max_length)
+    }
+
+    fn apply_options<'p>(&self, name: String) -> Cow<'p, str> {
+        match &self.options {
+            Some(options) => {
+                tracing::debug!("applying filter options to resolved name");
+                Cow::Owned(options.resolve(&name).into_owned())
+            }
+            None => Cow::Owned(name),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Options {
+    /// Cut off the start of the value while it starts with any of these, before anything else, e.g.
+    /// `["[Private] "]`.
+    pub strip_prefixes: Option<Vec<String>>,
+
+    /// Cut off the end of the value while it ends with any of these, after `strip_prefixes`, e.g.
+    /// `[" - Mozilla Firefox", " — Visual Studio Code"]`.
+    pub strip_suffixes: Option<Vec<String>>,
+
+    /// Pairs of a text and what to replace every occurrence of it with, one after another after stripping, e.g.
+    /// `[["_", " "]]`. The texts are matched literally.
+    pub replace: Option<Vec<(String, String)>>,
+    pub capitalize: Option<CapitalizeMode>,
+
+    /// Replaces (or strips) non-ASCII characters, for fonts which can't render them.
+    pub transliterate: Option<Transliterate>,
+
+    /// Restricts `transliterate` to runs of characters in these scripts, e.g. `["Han", "Hiragana", "Katakana"]`.
+    /// All non-ASCII characters are transliterated if this isn't set.
+    pub transliterate_scripts: Option<Vec<Script>>,
+
+    #[serde(default)]
+    pub preserve_acronyms: bool,
+
+    /// Words which are given this exact casing after capitalizing, no matter how they're cased in the value, e.g.
+    /// `["VLC", "IDE"]`.
+    #[serde(default)]
+    pub acronyms: Vec<String>,
+
+    /// Words which `all_words` capitalization keeps lowercase unless they're the first or the last word. Defaults
+    /// to English articles, conjunctions and short prepositions (`a`, `the`, `and`, `of`, ...), an empty list
+    /// capitalizes every word.
+    pub lowercase_words: Option<Vec<String>>,
+
+    /// Cuts the value down to this many characters, counting the ellipsis, after capitalizing. Characters made up of
+    /// several code points (emoji, letters with combining accents) count as one and are never cut apart. Without one
+    /// in a filter, the one in `global_options` applies.
+    pub max_length: Option<usize>,
+
+    /// Put at the end of values which were cut by `max_length`. Defaults to `"…"`. It's left out if it doesn't fit.
+    pub ellipsis: Option<String>,
+
+    /// Put in front of the value after capitalizing, e.g. a glyph like `" "`. It counts towards `max_length`. Filter
+    /// options replace the global ones as a whole, so a filter's prefix (even an empty one) is never combined with
+    /// the global prefix.
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+
+    /// The color of the text of the output, `#rrggbb` or `#aarrggbb`. The rendered templates are wrapped in
+    /// polybar's format tags for it, which is reset at the end. Colors a filter's options don't set are taken from
+    /// `global_options`. Ignored by waybar and without styling.
+    pub foreground: Option<BarColor>,
+    pub background: Option<BarColor>,
+    pub underline: Option<BarColor>,
+}
+
+fn capitalize_first(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|first_letter| first_letter.to_uppercase())
+        .into_iter()
+        .flatten()
+        .chain(chars)
+        .collect()
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CapitalizeMode {
+    #[default]
+    FirstLetter,
+    AllWords,
+    Lower,
+    Upper,
+    Snake,
+    Kebab,
+    Camel,
+    Pascal,
+}
+
+impl CapitalizeMode {
+    pub fn transform(&self, value: &str) -> String {
+        match self {
+            Self::FirstLetter => capitalize_first(value),
+            Self::AllWords => value.to_case(Case::Title),
+            Self::Lower => value.to_lowercase(),
+            Self::Upper => value.to_uppercase(),
+            Self::Snake => value.to_case(Case::Snake),
+            Self::Kebab => value.to_case(Case::Kebab),
+            Self::Camel => value.to_case(Case::Camel),
+            Self::Pascal => value.to_case(Case::Pascal),
+        }
+    }
+
+    /// Whether the mode capitalizes words, which `preserve_acronyms` and `acronyms` apply to. The other modes give
+    /// every letter the casing they ask for.
+    fn capitalizes(&self) -> bool {
+        matches!(self, Self::FirstLetter | Self::AllWords)
+    }
+}
+
+impl Options {
+    pub fn resolve<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        let mut new_value = Cow::Borrowed(self.strip(value));
+
+        if let Some(replace) = &self.replace {
+            tracing::debug!("replace in value");
+
+            for (from, to) in replace.iter().filter(|(from, _)| !from.is_empty()) {
+                if new_value.contains(from.as_str()) {
+                    new_value = Cow::Owned(new_value.replace(from.as_str(), to));
+                }
+            }
+        }
+
+        if let Some(transliterate) = &self.transliterate {
+            tracing::debug!("transliterate value");
+            new_value = Cow::Owned(transliterate.apply(&new_value, self.transliterate_scripts.as_deref()).into_owned())
+        }
+
+        if let Some(capitalize) = &self.capitalize {
+            tracing::debug!("capitalize value");
+            let mut capitalized = capitalize.transform(&new_value);
+
+            if let CapitalizeMode::AllWords = capitalize {
+                capitalized = self.lowercase_words(capitalized);
+            }
+
+            if capitalize.capitalizes() {
+                capitalized = self.restore_acronyms(&new_value, capitalized);
+            }
+
+            new_value = Cow::Owned(capitalized)
+        }
+
+        if self.prefix.is_some() || self.suffix.is_some() {
+            tracing::debug!("decorate value with prefix and suffix");
+            let prefix = self.prefix.as_deref().unwrap_or_default();
+            let suffix = self.suffix.as_deref().unwrap_or_default();
+            new_value = Cow::Owned(format!("{prefix}{new_value}{suffix}"))
+        }
+
+        self.truncate(new_value)
+    }
+
+    pub fn truncate<'v>(&self, value: Cow<'v, str>) -> Cow<'v, str> {
+        let Some(max_length) = self.max_length else {
+            return value
+        };
+
+        if value.graphemes(true).nth(max_length).is_none() {
+            return value
+        }
+
+        let ellipsis = self.ellipsis.as_deref().unwrap_or(DEFAULT_ELLIPSIS);
+        let ellipsis_length = ellipsis.graphemes(true).count();
+
+        // an ellipsis which doesn't fit would take the place of the whole value
+        let (kept, ellipsis) = match max_length.checked_sub(ellipsis_length) {
+            Some(kept) if kept > 0 => (kept, ellipsis),
+            _ => (max_length, ""),
+        };
+        let end = value.grapheme_indices(true).nth(kept).map_or(value.len(), |(index, _)| index);
+        tracing::debug!(%max_length, "truncate value");
+
+        Cow::Owned(format!("{}{ellipsis}", value[..end].trim_end()))
+    }
+
+    /// Cuts `strip_prefixes` off the start and `strip_suffixes` off the end of `value` until neither is there anymore,
+    /// which can leave nothing.
+    fn strip<'v>(&self, mut value: &'v str) -> &'v str {
+        let prefixes = self.strip_prefixes.iter().flatten().filter(|prefix| !prefix.is_empty());
+        let suffixes = self.strip_suffixes.iter().flatten().filter(|suffix| !suffix.is_empty());
+
+        while let Some(stripped) = prefixes.clone().find_map(|prefix| value.strip_prefix(prefix.as_str())) {
+            value = stripped;
+        }
+
+        while let Some(stripped) = suffixes.clone().find_map(|suffix| value.strip_suffix(suffix.as_str())) {
+            value = stripped;
+        }
+
+        value
+    }
+
+    /// Gives the words of `capitalized` which are acronyms their casing back, as capitalizing lowercases the rest of
+    /// every word. `original` is the value before capitalizing.
+    fn restore_acronyms(&self, original: &str, capitalized: String) -> String {
+        if !self.preserve_acronyms && self.acronyms.is_empty() {
+            return capitalized
+        }
+
+        let uppercase_words = if self.preserve_acronyms {
+            words(original)
+                .filter(|word| word.chars().count() > 1 && word.chars().all(|c| !c.is_lowercase()) && word.chars().any(char::is_uppercase))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        map_words(&capitalized, |word, _| {
+            let acronym = self.acronyms
+                .iter()
+                .map(String::as_str)
+                .chain(uppercase_words.iter().copied())
+                .find(|acronym| acronym.to_lowercase() == word.to_lowercase());
+
+            Cow::Borrowed(acronym.unwrap_or(word))
+        })
+    }
+
+    fn lowercase_words(&self, capitalized: String) -> String {
+        let lowercase_words = match &self.lowercase_words {
+            Some(words) => words.iter().map(String::as_str).collect::<Vec<_>>(),
+            None => LOWERCASE_WORDS.to_vec(),
+        };
+
+        if lowercase_words.is_empty() {
+            return capitalized
+        }
+
+        map_words(&capitalized, |word, position| {
+            let lowercase = word.to_lowercase();
+
+            if position == WordPosition::Middle && lowercase_words.iter().any(|exception| exception.to_lowercase() == lowercase) {
+                Cow::Owned(lowercase)
+            } else {
+                Cow::Borrowed(word)
+            }
+        })
+    }
+}
+
+const DEFAULT_ELLIPSIS: &str = "…";
+
+const LOWERCASE_WORDS: &[&str] = &[
+    "a", "an", "the", "and", "but", "or", "nor", "for", "so", "yet", "as", "at", "by", "in", "of", "off", "on", "per",
+    "to", "up", "via", "vs",
+];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum WordPosition {
+    First,
+    Middle,
+    Last,
+}
+
+fn words(value: &str) -> impl Iterator<Item = &str> {
+    value.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+fn map_words<'v>(value: &'v str, mut f: impl FnMut(&'v str, WordPosition) -> Cow<'v, str>) -> String {
+    let count = words(value).count();
+    let mut mapped = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut index = 0;
+
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        mapped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+        let position = match index {
+            0 => WordPosition::First,
+            _ if index + 1 == count => WordPosition::Last,
+            _ => WordPosition::Middle,
+        };
+
+        mapped.push_str(&f(&rest[..end], position));
+        rest = &rest[end..];
+        index += 1;
+    }
+
+    mapped.push_str(rest);
+    mapped
+}
+
+fn template() -> String {
+    "{{ name }}".to_owned()
+}
+
+fn styling() -> bool {
+    true
+}
+
+fn highlight() -> Millis {
+    Millis::from_millis(500)
+}
+
+fn monitor() -> Option<String> {
+    env::var("MONITOR").ok()
+}
+
+fn pointer_interval() -> Millis {
+    Millis::from_millis(100)
+}
+
+fn render_budget() -> Millis {
+    Millis::from_millis(50)
+}
+
+fn persist_max_age() -> Seconds {
+    Seconds::from_secs(7 * 24 * 60 * 60)
+}
+
+fn max_title_size() -> ByteSize {
+    ByteSize::from_bytes(4096)
+}
+
+/// Parses a window id given by a user or a script, in decimal or `0x` prefixed hexadecimal, ignoring surrounding
+/// whitespace (as in the output of `xdotool getactivewindow`).
+pub fn parse_window_id(s: &str) -> anyhow::Result<NonNullWindow> {
+    let s = s.trim();
+    let id = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => Window::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .with_context(|| format!("{s:?} is not a window id, expected a decimal or 0x prefixed hexadecimal number"))?;
+
+    NonNullWindow::new(id).context("window id 0 stands for the desktop rather than a window")
+}
+
+pub fn intern_atom(connection: &RustConnection, name: &str) -> anyhow::Result<Atom> {
+    Ok(connection
+        .intern_atom(false, name.as_bytes())
+        .with_context(|| format!("failed to make InternAtom reply for {name}"))?
+        .reply()
+        .with_context(|| format!("InternAtom response for {name} failed"))?
+        .atom)
+}
+
+/// Whether `error` came from a request about a window which doesn't exist (anymore). Windows can be destroyed at any
+/// time, e.g. right after they were focused, so that's to be expected rather than a reason to give up.
+pub(crate) fn is_window_gone(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        // requests taking any drawable, like GetGeometry, fail with BadDrawable instead
+        matches!(
+            cause.downcast_ref::<ReplyError>(),
+            Some(ReplyError::X11Error(error)) if matches!(error.error_kind, ErrorKind::Window | ErrorKind::Drawable)
+        )
+    })
+}
+
+pub(crate) fn is_connection_lost(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<ConnectionError>() || matches!(cause.downcast_ref::<ReplyError>(), Some(ReplyError::ConnectionError(_)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Logged, MockConnection};
+
+    fn properties(class: &str) -> WindowProperties<'_> {
+        WindowProperties {
+            class,
+            instance: "",
+            name: "",
+            role: None,
+            child_process: None,
+            flatpak_id: None,
+            client_machine: None,
+            remote: None,
+            xwayland: None,
+        }
+    }
+
+    fn resolve(config: &str, class: &str) -> String {
+        let config = Config::from_sources([config]).unwrap();
+        config.resolver.resolve_properties(1, properties(class)).name
+    }
+
+    fn identifier(s: &str) -> (WindowIdentifierKind, String) {
+        let identifier = s.parse::<WindowIdentifier>().unwrap();
+        (identifier.kind, identifier.value)
+    }
+
+    #[test]
+    fn simple_identifiers_parse_as_before() {
+        assert_eq!(identifier("wm_class=firefox"), (WindowIdentifierKind::Class, "firefox".into()));
+        assert_eq!(identifier(" n =Mozilla Firefox"), (WindowIdentifierKind::Name, "Mozilla Firefox".into()));
+        assert_eq!(identifier("cp=nvim"), (WindowIdentifierKind::ChildProcess, "nvim".into()));
+        assert_eq!(identifier("c=C:\\dir"), (WindowIdentifierKind::Class, "C:\\dir".into()));
+        assert_eq!(identifier("c="), (WindowIdentifierKind::Class, String::new()));
+    }
+
+    #[test]
+    fn identifier_escapes_and_quotes() {
+        assert_eq!(identifier("wm_name=key=value editor"), (WindowIdentifierKind::Name, "key=value editor".into()));
+        assert_eq!(identifier("wm_name=a\\=b"), (WindowIdentifierKind::Name, "a=b".into()));
+        assert_eq!(identifier("wm_name=a\\\\b"), (WindowIdentifierKind::Name, "a\\b".into()));
+        assert_eq!(identifier("wm_name=\" a = b \""), (WindowIdentifierKind::Name, " a = b ".into()));
+        assert_eq!(identifier("wm_name=\"say \\\"hi\\\"\""), (WindowIdentifierKind::Name, "say \"hi\"".into()));
+
+        assert!("wm_name=\"open".parse::<WindowIdentifier>().is_err());
+        assert!("wm_name=\"escaped\\\"".parse::<WindowIdentifier>().is_err());
+        assert!("wm_name\\=value".parse::<WindowIdentifier>().is_err());
+        assert!("nonsense=value".parse::<WindowIdentifier>().is_err());
+    }
+
+    #[test]
+    fn identifier_display_round_trips() {
+        use crate::testing::Rng;
+
+        let kinds = [
+            WindowIdentifierKind::Class,
+            WindowIdentifierKind::Instance,
+            WindowIdentifierKind::Name,
+            WindowIdentifierKind::Role,
+            WindowIdentifierKind::ChildProcess,
+            WindowIdentifierKind::FlatpakId,
+            WindowIdentifierKind::ClientMachine,
+            WindowIdentifierKind::Remote,
+            WindowIdentifierKind::Xwayland,
+        ];
+        let alphabet = ['a', 'Z', '0', ' ', '\t', '=', '"', '\\', '*', '?', 'é', '日', '\n'];
+        let mut rng = Rng::new(0x5eed);
+
+        for _ in 0..10_000 {
+            let identifier = WindowIdentifier { kind: *rng.pick(&kinds), value: rng.string(&alphabet, 12) };
+            let displayed = identifier.to_string();
+            let parsed = displayed.parse::<WindowIdentifier>();
+
+            assert_eq!(parsed.ok().as_ref(), Some(&identifier), "{identifier:?} displayed as {displayed:?}");
+        }
+    }
+
+    fn options(source: &str) -> Options {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn acronyms_keep_their_casing() {
+        let preserve = options(r#"capitalize = "all_words"
+preserve_acronyms = true"#);
+        assert_eq!(preserve.resolve("GIMP"), "GIMP");
+        assert_eq!(preserve.resolve("VLC media player"), "VLC Media Player");
+        assert_eq!(preserve.resolve("my IDE settings"), "My IDE Settings");
+        // single letters aren't acronyms
+        assert_eq!(preserve.resolve("A plan"), "A Plan");
+
+        let listed = options(r#"capitalize = "all_words"
+acronyms = ["VLC", "GIMP", "IDE", "KDE"]"#);
+        assert_eq!(listed.resolve("vlc media player"), "VLC Media Player");
+        assert_eq!(listed.resolve("Gimp"), "GIMP");
+        assert_eq!(listed.resolve("kde connect"), "KDE Connect");
+        assert_eq!(listed.resolve("HTML editor"), "Html Editor");
+
+        let first_letter = options(r#"capitalize = "first_letter"
+acronyms = ["VLC"]"#);
+        assert_eq!(first_letter.resolve("vlc media player"), "VLC media player");
+    }
+
+    #[test]
+    fn acronyms_dont_apply_to_other_modes() {
+        let lower = options(r#"capitalize = "lower"
+preserve_acronyms = true
+acronyms = ["VLC"]"#);
+        assert_eq!(lower.resolve("VLC Media Player"), "vlc media player");
+
+        let without = options(r#"capitalize = "all_words""#);
+        assert_eq!(without.resolve("VLC media player"), "Vlc Media Player");
+    }
+
+    #[test]
+    fn lowercase_words_stay_lowercase() {
+        let default = options(r#"capitalize = "all_words""#);
+        assert_eq!(default.resolve("music for everyone"), "Music for Everyone");
+        assert_eq!(default.resolve("THE LORD OF THE RINGS"), "The Lord of the Rings");
+        // except for the first and the last word
+        assert_eq!(default.resolve("the thing to look up"), "The Thing to Look Up");
+        assert_eq!(default.resolve("of"), "Of");
+
+        let listed = options(r#"capitalize = "all_words"
+lowercase_words = ["with", "DER"]"#);
+        assert_eq!(listed.resolve("songs with friends of der zeit"), "Songs with Friends Of der Zeit");
+
+        let none = options(r#"capitalize = "all_words"
+lowercase_words = []"#);
+        assert_eq!(none.resolve("music for everyone"), "Music For Everyone");
+
+        let acronyms = options(r#"capitalize = "all_words"
+acronyms = ["OR"]"#);
+        assert_eq!(acronyms.resolve("this or that"), "This OR That");
+    }
+
+    #[test]
+    fn overlapping_replacements_apply_in_order() {
+        // "aaa" is replaced left to right without overlapping matches, and later pairs see earlier replacements
+        let chained = options(r#"replace = [["aa", "b"], ["ba", "c"], ["b", "aa"]]"#);
+        assert_eq!(chained.resolve("aaa"), "c");
+        assert_eq!(chained.resolve("aaaa"), "aaaa");
+
+        let swapped = options(r#"replace = [["ab", "ba"], ["ba", "ab"]]"#);
+        assert_eq!(swapped.resolve("abab"), "abab");
+
+        let empty = options(r#"replace = [["", "x"], ["_", " "]]"#);
+        assert_eq!(empty.resolve("snake_case_name"), "snake case name");
+    }
+
+    #[test]
+    fn stripping_repeats_until_nothing_matches() {
+        let firefox = options(r#"strip_prefixes = ["[Private] ", "● "]
+strip_suffixes = [" - Mozilla Firefox", " (1)"]
+capitalize = "all_words""#);
+
+        assert_eq!(firefox.resolve("● [Private] ● github - Mozilla Firefox (1) (1)"), "Github");
+        // a suffix which is the entire string leaves nothing
+        assert_eq!(firefox.resolve(" - Mozilla Firefox"), "");
+        assert_eq!(firefox.resolve(" (1) (1)"), "");
+        assert_eq!(firefox.resolve("[Private] "), "");
+    }
+
+    #[test]
+    fn max_length_and_ellipsis() {
+        let default = options("max_length = 8");
+        assert_eq!(default.resolve("Firefox"), "Firefox");
+        // exactly as long as allowed
+        assert_eq!(default.resolve("Alacritt"), "Alacritt");
+        assert_eq!(default.resolve("Alacritty"), "Alacrit…");
+        // whitespace isn't left in front of the ellipsis
+        assert_eq!(default.resolve("Google Chrome"), "Google…");
+
+        let custom = options(r#"max_length = 8
+ellipsis = "..."
+capitalize = "upper""#);
+        assert_eq!(custom.resolve("alacritty"), "ALACR...");
+        let empty = options(r#"max_length = 3
+ellipsis = """#);
+        assert_eq!(empty.resolve("alacritty"), "ala");
+    }
+
+    #[test]
+    fn max_length_counts_graphemes() {
+        let four = options("max_length = 4");
+
+        assert_eq!(four.resolve("日本語のタイトル"), "日本語…");
+        assert_eq!(four.resolve("日本語の"), "日本語の");
+        assert_eq!(four.resolve("🇯🇵🇯🇵🇯🇵🇯🇵🇯🇵"), "🇯🇵🇯🇵🇯🇵…");
+        assert_eq!(four.resolve("e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}"), "e\u{301}e\u{301}e\u{301}…");
+    }
+
+    #[test]
+    fn max_length_shorter_than_the_ellipsis() {
+        let shorter = options(r#"max_length = 2
+ellipsis = "...""#);
+        assert_eq!(shorter.resolve("Firefox"), "Fi");
+
+        let fits_exactly = options(r#"max_length = 3
+ellipsis = "...""#);
+        assert_eq!(fits_exactly.resolve("Firefox"), "Fir");
+
+        let one = options("max_length = 1");
+        assert_eq!(one.resolve("Firefox"), "F");
+
+        let zero = options("max_length = 0");
+        assert_eq!(zero.resolve("Firefox"), "");
+    }
+
+    #[test]
+    fn max_length_of_filter_wins() {
+        let config = r#"
+            [resolver.global_options]
+            max_length = 5
+
+            [resolver.filters]
+            "class=firefox" = { filter = "new_name", value = "Mozilla Firefox", options = { max_length = 10 } }
+            "class=chromium" = { filter = "new_name", value = "Chromium Browser" }
+        "#;
+
+        assert_eq!(resolve(config, "firefox"), "Mozilla F…");
+        // the global one applies to filters without their own
+        assert_eq!(resolve(config, "chromium"), "Chro…");
+        assert_eq!(resolve(config, "alacritty"), "alac…");
+    }
+
+    #[test]
+    fn new_name_options_round_trip() {
+        let source = r#"
+            "class=org.wezfurlong.wezterm" = { filter = "new_name", value = "wezterm", options = { capitalize = "all_words" } }
+            "class=firefox" = { filter = "new_name", value = "Firefox" }
+        "#;
+        let rules: BTreeMap<String, FilterRule> = toml::from_str(source).unwrap();
+        let serialized = toml::to_string(&rules).unwrap();
+        let reparsed: BTreeMap<String, FilterRule> = toml::from_str(&serialized).unwrap();
+
+        assert_eq!(toml::to_string(&reparsed).unwrap(), serialized);
+        assert!(matches!(&reparsed["class=org.wezfurlong.wezterm"].method, Filter::NewName(name) if name.to_string() == "wezterm"));
+        assert!(matches!(reparsed["class=org.wezfurlong.wezterm"].options, Some(Options { capitalize: Some(CapitalizeMode::AllWords), .. })));
+        assert!(reparsed["class=firefox"].options.is_none());
+        assert!(!serialized.contains("priority"));
+    }
+
+    #[test]
+    fn new_name_is_only_transformed_by_its_own_options() {
+        let config = r#"
+            [resolver.global_options]
+            capitalize = "all_words"
+
+            [resolver.filters]
+            "class=org.wezfurlong.wezterm" = { filter = "new_name", value = "wezterm", options = { capitalize = "all_words" } }
+            "class=kitty" = { filter = "new_name", value = "kitty" }
+        "#;
+
+        assert_eq!(resolve(config, "org.wezfurlong.wezterm"), "Wezterm");
+        assert_eq!(resolve(config, "kitty"), "kitty");
+        assert_eq!(resolve(config, "firefox"), "Firefox");
+    }
+
+    #[test]
+    fn desktop_options_apply_to_the_name_of_the_desktop() {
+        let config = Config::from_sources([r#"
+            [resolver]
+            desktop_name = {}
+            desktop_options = { capitalize = "all_words" }
+            filters = {}
+        "#]).unwrap();
+        let www = Desktop { index: 0, name: Some("www".to_owned()) };
+
+        assert_eq!(config.resolver.desktop_on(Some(&www)).name, "Www");
+        // windows go through the global options only
+        assert_eq!(config.resolver.resolve_properties(1, properties("firefox")).name, "firefox");
+    }
+
+    #[test]
+    fn desktop_options_truncate_a_fixed_name() {
+        let config = Config::from_sources([r#"
+            [resolver]
+            desktop_name = "Nothing is focused right now"
+            desktop_options = { max_length = 10 }
+            filters = {}
+        "#]).unwrap();
+
+        assert_eq!(config.resolver.desktop().name, "Nothing i…");
+    }
+
+    #[test]
+    fn desktop_without_options_is_untouched() {
+        let config = Config::from_sources([r#"
+            [resolver]
+            desktop_name = {}
+            filters = {}
+        "#]).unwrap();
+        let www = Desktop { index: 0, name: Some("www".to_owned()) };
+
+        assert_eq!(config.resolver.desktop_on(Some(&www)).name, "www");
+    }
+
+    #[test]
+    fn invalid_utf8_in_properties() {
+        // "Café - xterm" with the é in Latin-1, then a lone continuation byte and a truncated sequence
+        let latin1 = b"Caf\xe9 - xterm".to_vec();
+        let broken = b"a\x80b\xe2\x82".to_vec();
+
+        assert_eq!(InvalidUtf8::Lossy.decode(latin1.clone()).unwrap(), "Caf\u{fffd} - xterm");
+        assert_eq!(InvalidUtf8::Lossy.decode(broken.clone()).unwrap(), "a\u{fffd}b\u{fffd}");
+        assert_eq!(InvalidUtf8::Latin1.decode(latin1.clone()).unwrap(), "Café - xterm");
+        assert_eq!(InvalidUtf8::Latin1.decode(broken).unwrap(), "a\u{80}b\u{e2}\u{82}");
+        assert!(InvalidUtf8::Error.decode(latin1).is_err());
+        // valid UTF-8 is read the same way by all of them
+        assert_eq!(InvalidUtf8::Error.decode("Café".as_bytes().to_vec()).unwrap(), "Café");
+    }
+
+    #[test]
+    fn filters_match_decoded_names() {
+        let config = Config::from_sources([r#"
+            [resolver.filters]
+            "name=Café - xterm" = { filter = "new_name", value = "Latin-1" }
+            "name_regex=\uFFFD" = { filter = "new_name", value = "Lossy" }
+        "#]).unwrap();
+        let name = |decode: InvalidUtf8| {
+            let name = decode.decode(b"Caf\xe9 - xterm".to_vec()).unwrap();
+            config.resolver.resolve_properties(1, WindowProperties { name: &name, ..properties("xterm") }).name
+        };
+
+        assert_eq!(name(InvalidUtf8::Latin1), "Latin-1");
+        assert_eq!(name(InvalidUtf8::Lossy), "Lossy");
+    }
+
+    #[test]
+    fn window_without_properties_is_unknown() {
+        let config = Config::from_sources([r#"
+            [resolver]
+            desktop_name = "Desktop"
+            unknown_window_text = "(no name)"
+
+            [resolver.filters]
+            "class=" = { filter = "new_name", value = "Empty class" }
+        "#]).unwrap();
+        let empty = WindowProperties { child_process: Some(""), ..properties("") };
+
+        let unknown = config.resolver.resolve_properties(7, empty);
+        assert_eq!(unknown.name, "(no name)");
+        assert!(unknown.is_unknown());
+
+        let desktop = config.resolver.desktop();
+        assert_eq!(desktop.name, "Desktop");
+        assert!(!desktop.is_unknown());
+
+        // any one property is enough to go through the filters
+        let titled = config.resolver.resolve_properties(7, WindowProperties { name: "untitled", ..properties("") });
+        assert_eq!(titled.name, "Empty class");
+        assert!(!titled.is_unknown());
+    }
+
+    #[test]
+    fn unknown_window_text_defaults_to_unknown() {
+        assert_eq!(Resolver::default().resolve_properties(7, properties("")).name, "Unknown");
+    }
+
+    #[test]
+    fn window_ids() {
+        for (input, id) in [("42", 42), (" 42\n", 42), ("0x2a", 42), ("0X2A", 42), ("\t0x0040000b ", 0x40000b), ("4294967295", u32::MAX)] {
+            assert_eq!(parse_window_id(input).unwrap().get(), id, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_window_ids() {
+        for input in ["", " ", "0x", "-1", "abc", "4294967296", "42 43", "0x1g", "1.5", "0b101"] {
+            let error = parse_window_id(input).unwrap_err().to_string();
+            assert!(error.contains("is not a window id"), "{input:?}: {error}");
+        }
+
+        for input in ["0", "0x0", " 00 "] {
+            let error = parse_window_id(input).unwrap_err().to_string();
+            assert!(error.contains("desktop"), "{input:?}: {error}");
+        }
+    }
+
+    #[test]
+    fn overlapping_regex_filters_go_by_declaration_order() {
+        let fire_first = r#"
+            [resolver.filters]
+            "class_regex=^fire" = { filter = "new_name", value = "fire" }
+            "class_regex=fox$" = { filter = "new_name", value = "fox" }
+        "#;
+        let fox_first = r#"
+            [resolver.filters]
+            "class_regex=fox$" = { filter = "new_name", value = "fox" }
+            "class_regex=^fire" = { filter = "new_name", value = "fire" }
+        "#;
+
+        for _ in 0..10 {
+            assert_eq!(resolve(fire_first, "firefox"), "fire");
+            assert_eq!(resolve(fox_first, "firefox"), "fox");
+        }
+    }
+
+    #[test]
+    fn priority_wins_over_declaration_order() {
+        let config = r#"
+            [resolver.filters]
+            "class_regex=^fire" = { filter = "new_name", value = "fire" }
+            "class_regex=fox$" = { filter = "new_name", value = "fox", priority = 1 }
+        "#;
+
+        assert_eq!(resolve(config, "firefox"), "fox");
+    }
+
+    #[test]
+    fn match_order_decides_between_kinds() {
+        let filters = r#"
+            [resolver.filters]
+            "wm_class=firefox" = { filter = "new_name", value = "by class" }
+            "instance=Navigator" = { filter = "new_name", value = "by instance" }
+        "#;
+        let resolve = |match_order: &str| {
+            let config = Config::from_sources([filters, match_order]).unwrap();
+            config.resolver.resolve_properties(1, WindowProperties { instance: "Navigator", ..properties("firefox") }).name
+        };
+
+        assert_eq!(resolve(""), "by instance");
+        assert_eq!(resolve(r#"resolver.match_order = ["instance", "class"]"#), "by instance");
+        assert_eq!(resolve(r#"resolver.match_order = ["class", "instance"]"#), "by class");
+        assert_eq!(resolve(r#"resolver.match_order = ["name", "wm_class"]"#), "by class");
+    }
+
+    #[test]
+    fn match_order_rejects_unknown_and_regex_kinds() {
+        for match_order in [r#"["class", "exe"]"#, r#"["class_regex"]"#] {
+            let error = Config::from_sources([&*format!("resolver.match_order = {match_order}")]).unwrap_err();
+            assert!(format!("{error:#}").contains("match_order"), "{error:#}");
+        }
+    }
+
+    #[test]
+    fn name_can_win_over_class() {
+        let filters = r#"
+            [resolver.filters]
+            "wm_class=firefox" = { filter = "new_name", value = "Firefox" }
+            "wm_name=Picture-in-Picture" = { filter = "new_name", value = "PiP" }
+        "#;
+        let resolve = |match_order: &str| {
+            let config = Config::from_sources([filters, match_order]).unwrap();
+            config.resolver.resolve_properties(1, WindowProperties { name: "Picture-in-Picture", ..properties("firefox") }).name
+        };
+
+        assert_eq!(resolve(""), "Firefox");
+        assert_eq!(resolve(r#"resolver.match_order = ["name", "instance", "class"]"#), "PiP");
+    }
+
+    #[test]
+    fn empty_match_order_goes_straight_to_global_options() {
+        let config = r#"
+            [resolver]
+            match_order = []
+            global_options = { capitalize = "all_words" }
+
+            [resolver.filters]
+            "wm_class=firefox" = { filter = "new_name", value = "Browser" }
+            "class_regex=^fire" = { filter = "new_name", value = "Regex" }
+            "class=conky" = { filter = "ignore" }
+        "#;
+
+        assert_eq!(resolve(config, "firefox"), "Firefox");
+        assert_eq!(resolve(config, "conky"), "Conky");
+    }
+
+    #[test]
+    fn match_order_rejects_duplicates() {
+        for match_order in [r#"["class", "name", "class"]"#, r#"["class", "wm_class"]"#] {
+            let error = Config::from_sources([&*format!("resolver.match_order = {match_order}")]).unwrap_err();
+            assert!(format!("{error:#}").contains("appears more than once in match_order"), "{error:#}");
+        }
+    }
+
+    #[test]
+    fn filters_match_on_flatpak_id_without_a_process() {
+        let config = Config::from_sources([r#"
+            [resolver.filters]
+            "flatpak_id=org.mozilla.firefox" = { filter = "new_name", value = "Firefox" }
+        "#]).unwrap();
+        let properties = WindowProperties { flatpak_id: Some("org.mozilla.firefox"), ..properties("firefox") };
+
+        assert_eq!(config.resolver.resolve_properties(1, properties).name, "Firefox");
+        assert_eq!(resolve(r#"resolver.filters = { "flatpak_id=org.mozilla.firefox" = { filter = "new_name", value = "Firefox" } }"#, "firefox"), "firefox");
+    }
+
+    #[test]
+    fn filters_match_on_xwayland() {
+        let config = Config::from_sources([r#"
+            [resolver.filters]
+            "xwayland=true" = { filter = "new_name", value = "XWayland" }
+        "#]).unwrap();
+        let resolve = |xwayland| config.resolver.resolve_properties(1, WindowProperties { xwayland, ..properties("firefox") });
+
+        let xwayland = resolve(Some(true));
+        assert_eq!(xwayland.name, "XWayland");
+        assert!(xwayland.xwayland);
+        assert_eq!(resolve(Some(false)).name, "firefox");
+        assert_eq!(resolve(None).name, "firefox");
+    }
+
+    fn atoms() -> Atoms {
+        Atoms {
+            net_active_window: 300,
+            net_current_desktop: 301,
+            net_desktop_names: 302,
+            net_wm_pid: 303,
+            net_wm_name: 304,
+            utf8_string: 305,
+            flatpak_id: 306,
+            wm_window_role: 307,
+            net_supporting_wm_check: 308,
+        }
+    }
+
+    #[test]
+    fn stale_window_is_gone() {
+        let connection = MockConnection::with_gone([0x1400003]);
+        let resolver = Resolver::default();
+        let mut processes = ProcessInspector::new("/proc", true);
+
+        let error = resolver
+            .resolve(&connection, &atoms(), &mut processes, &MachineInspector::new(true), 0x1400003)
+            .unwrap_err();
+
+        // which isn't fatal, but makes the window which is active now be displayed instead
+        assert!(is_window_gone(&error), "{error:#}");
+        assert!(!is_connection_lost(&error), "{error:#}");
+
+        // and was found out with a single round trip
+        let log = connection.take();
+        let sent = log.iter().take_while(|logged| matches!(logged, Logged::Sent(_))).count();
+        assert_eq!(sent, 6);
+        assert_eq!(log[sent..], [Logged::Answered]);
+    }
+
+    #[test]
+    fn desktop_is_resolved_without_requests() {
+        let connection = MockConnection::default();
+        let resolver = Resolver { desktop_name: Some(DesktopName::Fixed("Desktop".to_owned())), ..Resolver::default() };
+
+        let resolved = resolver
+            .resolve(&connection, &atoms(), &mut ProcessInspector::new("/proc", true), &MachineInspector::new(true), 0)
+            .unwrap();
+
+        assert_eq!(resolved.name, "Desktop");
+        assert_eq!(connection.take(), []);
+    }
+
+    #[test]
+    fn other_errors_arent_windows_being_gone() {
+        let connection = MockConnection::default();
+        // BadAtom
+        let error = connection.parse_error(&[[0, 5].as_slice(), &[0; 30]].concat()).unwrap();
+
+        assert!(!is_window_gone(&anyhow::Error::from(ReplyError::from(error))));
+        assert!(!is_window_gone(&anyhow::anyhow!("failed to resolve window")));
+        assert!(is_connection_lost(&anyhow::Error::from(ConnectionError::UnknownError)));
+    }
+}
@@ -0,0 +1,49 @@
+use nix::unistd;
+
+/// Tells windows of X clients running on other hosts (e.g. through X forwarding) apart from local ones, by comparing
+/// their `WM_CLIENT_MACHINE` to the local hostname. Reading `WM_CLIENT_MACHINE` is skipped entirely unless enabled,
+/// as nothing needs it otherwise.
+#[derive(Debug)]
+pub struct MachineInspector {
+    enabled: bool,
+    hostname: Option<String>,
+}
+
+impl MachineInspector {
+    pub fn new(enabled: bool) -> Self {
+        let hostname = if enabled {
+            unistd::gethostname()
+                .map_err(|error| tracing::warn!("could not get hostname, treating every window as local: {error}"))
+                .ok()
+                .and_then(|hostname| hostname.into_string().ok())
+        } else {
+            None
+        };
+
+        Self { enabled, hostname }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether a client on `client_machine` runs on another host. Hostnames are compared case insensitively, and
+    /// only by their first label if either of them is a short name, so `box` and `box.example.com` are the same.
+    pub fn is_remote(&self, client_machine: &str) -> bool {
+        match &self.hostname {
+            Some(hostname) => !same_host(hostname, client_machine),
+            None => false,
+        }
+    }
+}
+
+fn same_host(a: &str, b: &str) -> bool {
+    let (a, b) = (a.to_lowercase(), b.to_lowercase());
+    let short = |host: &str| host.split('.').next().unwrap_or_default().to_owned();
+
+    if a.contains('.') && b.contains('.') {
+        a == b
+    } else {
+        short(&a) == short(&b)
+    }
+}
@@ -1,436 +1,220 @@
-mod process;
-mod templating;
-
-use std::borrow::Cow;
-use std::collections::HashMap;
-use anyhow::Context;
-use directories::ProjectDirs;
-use figment::providers::{Format, Toml};
-use figment::Figment;
-use serde::{Deserialize, Serialize};
-use std::num::NonZeroU32;
+use std::{env, io};
+use std::path::PathBuf;
 use std::process::ExitCode;
-use std::{env, fmt, str};
-use std::fmt::Formatter;
-use std::str::FromStr;
-use convert_case::{Case, Casing};
-use handlebars::Handlebars;
-use x11rb::connection::Connection;
-use x11rb::properties::WmClass;
-use x11rb::protocol::xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window};
-use x11rb::protocol::Event;
-use x11rb::rust_connection::RustConnection;
-use serde_with::{serde_as, DeserializeFromStr, SerializeDisplay};
+use polybar_title_module::app::{App, DisplayState};
+use polybar_title_module::output::{self, CrashOutput};
+use polybar_title_module::exit::{self, Failure};
+use polybar_title_module::{check, control, diagnose, features, parse_window_id, record, stdin, which};
+use polybar_title_module::check::CheckWindow;
+use polybar_title_module::stdin::Backend;
+use polybar_title_module::{resolve_active, Config, Overrides};
+use anyhow::Context;
 use tracing::Level;
-use crate::process::ProcessInspector;
-
-pub type NonNullWindow = NonZeroU32;
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Config {
-    pub display_name: Option<String>,
-
-    #[serde(default = "template")]
-    pub template: String,
-    pub resolver: Resolver,
-}
-
-impl Config {
-    pub fn read() -> anyhow::Result<Self> {
-        let config_toml = ProjectDirs::from("", "ALinuxPerson", "polybar-title-module")
-            .map(|pd| pd.config_dir().join("config.toml"));
-
-        if config_toml.is_none() {
-            tracing::warn!("could not get project directories");
-        }
-
-        let mut figment = Figment::new();
 
-        if let Some(config_toml) = config_toml {
-            figment = figment.join(Toml::file(config_toml))
-        }
-
-        figment = figment.join(Toml::file("polybar-title-module.toml"));
-
-        figment.extract().context("failed to get config")
-    }
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            display_name: None,
-            template: template(),
-            resolver: Resolver::default(),
+#[derive(Default)]
+struct GlobalOptions {
+    plain: bool,
+    log_level: Option<Level>,
+    overrides: Overrides,
+}
+
+fn global_options(mut args: impl Iterator<Item = String>) -> anyhow::Result<(GlobalOptions, Vec<String>)> {
+    let mut options = GlobalOptions::default();
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} expects a value"));
+
+        match arg.as_str() {
+            "--plain" => options.plain = true,
+            "--config" => options.overrides.config = Some(PathBuf::from(value()?)),
+            "--display" => options.overrides.display_name = Some(value()?),
+            "--template" => options.overrides.template = Some(value()?),
+            "--format" => options.overrides.output_format = Some(value()?),
+            "--log-level" => {
+                let level = value()?;
+                options.log_level = Some(level.parse().map_err(|_| {
+                    anyhow::anyhow!("unknown log level {level:?}, expected error, warn, info, debug or trace")
+                })?);
+            }
+            _ => rest.push(arg),
         }
     }
-}
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
-pub enum WindowIdentifierKind {
-    Class,
-    Name,
-    ChildProcess,
+    Ok((options, rest))
 }
 
-impl FromStr for WindowIdentifierKind {
-    type Err = anyhow::Error;
+fn real_main(crash: &mut CrashOutput) -> anyhow::Result<()> {
+    let global = global_options(env::args().skip(1));
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "wm_class" | "wmc" | "wc" | "c" | "cls" | "wcls" | "class" => Ok(Self::Class),
-            "wm_name" | "wmn" | "wn" | "n" | "name" => Ok(Self::Name),
-            "child_process" | "child" | "cp" => Ok(Self::ChildProcess),
-            _ => anyhow::bail!("unknown window identifier kind"),
-        }
+    // logging is set up first, so that invalid global options are reported too. It goes to stderr, as bars read
+    // every line on stdout as output
+    match global.as_ref().ok().and_then(|(options, _)| options.log_level) {
+        Some(level) => tracing_subscriber::fmt().with_writer(io::stderr).with_max_level(level).init(),
+        None if env::var("RUST_LOG").is_ok() => tracing_subscriber::fmt().with_writer(io::stderr).init(),
+        None => tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(Level::ERROR)
+            .init(),
     }
-}
-
-#[derive(DeserializeFromStr, SerializeDisplay, Eq, PartialEq, Hash, Debug)]
-pub struct WindowIdentifier {
-    pub kind: WindowIdentifierKind,
-    pub value: String,
-}
-
-impl FromStr for WindowIdentifier {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (discriminant, value) = s.split_once('=').context("no '=' in window identifier")?;
-        let kind = WindowIdentifierKind::from_str(discriminant).context("failed to resolve discriminant as a window identifier kind")?;
 
-        Ok(Self {
-            kind,
-            value: value.to_string(),
-        })
-    }
-}
+    let (options, args) = global?;
+    let mut args = args.into_iter();
 
-impl fmt::Display for WindowIdentifier {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self.kind {
-            WindowIdentifierKind::Class => write!(f, "wm_class={}", self.value),
-            WindowIdentifierKind::Name => write!(f, "wm_name={}", self.value),
-            WindowIdentifierKind::ChildProcess => write!(f, "child_process={}", self.value),
-        }
+    if options.plain {
+        output::force_plain();
     }
-}
-
-#[serde_as]
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Resolver {
-    pub global_options: Option<Options>,
-    pub desktop_name: Option<String>,
-    pub filters: HashMap<WindowIdentifier, Filter>,
-}
 
-#[derive(Debug)]
-pub struct ResolvedWindow {
-    pub name: String,
-    pub child_process: Option<String>,
-}
+    options.overrides.set();
+    let mut record = None;
 
-impl ResolvedWindow {
-    fn named(name: String) -> Self {
-        Self {
-            name,
-            child_process: None,
+    match args.next().as_deref() {
+        None => {}
+        Some("--record") => {
+            record = Some(PathBuf::from(args.next().context("--record expects a file to record to")?));
+            anyhow::ensure!(args.next().is_none(), "--record expects no further arguments");
         }
-    }
-}
-
-impl Resolver {
-    pub fn matches_on(&self, kind: WindowIdentifierKind) -> bool {
-        self.filters.keys().any(|identifier| identifier.kind == kind)
-    }
-
-    pub fn resolve(&self, connection: &RustConnection, processes: &mut ProcessInspector, window: Window) -> anyhow::Result<ResolvedWindow> {
-        let Some(window) = NonNullWindow::new(window) else {
-            tracing::debug!("window was 0, assuming it's desktop");
-            return Ok(ResolvedWindow::named(self.desktop_name.clone().unwrap_or_default()))
-        };
-
-        tracing::debug!("retrieve WM_CLASS of window");
-        let wm_class = WmClass::get(connection, window.get())
-            .context("failed to make WmClass reply")?
-            .reply()
-            .context("WmClass response failed")?;
-        let wm_class = str::from_utf8(wm_class.class()).context("WM_CLASS contains invalid utf-8")?;
-        tracing::debug!(%wm_class, "WM_CLASS of window");
-
-        tracing::debug!("retrieve WM_NAME of window");
-        let wm_name = connection
-            .get_property(false, window.get(), AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)
-            .context("failed to make GetProperty reply for retrieving WM_NAME")?
-            .reply()
-            .context("GetProperty response for retrieving WM_NAME failed")?
-            .value;
-        let wm_name = String::from_utf8(wm_name).context("WM_NAME contains invalid utf-8")?;
-        tracing::debug!(%wm_name, "WM_NAME of window");
-
-        let child_process = if processes.is_enabled() {
-            tracing::debug!("retrieve foreground child process of window");
-            child_process(connection, processes, window).context("failed to retrieve foreground child process")?
-        } else {
-            None
-        };
-        tracing::debug!(?child_process, "foreground child process of window");
-
-        tracing::debug!("find filter by child process");
-        let filter = child_process
-            .as_ref()
-            .and_then(|child_process| self.filters.get(&WindowIdentifier {
-                kind: WindowIdentifierKind::ChildProcess,
-                value: child_process.clone(),
-            }))
-            .or_else(|| {
-                tracing::debug!("find filter by WM_CLASS");
-
-                self.filters.get(&WindowIdentifier {
-                    kind: WindowIdentifierKind::Class,
-                    value: wm_class.to_string(),
-                })
-            })
-            .or_else(|| {
-                tracing::debug!("find filter by WM_NAME");
-
-                self.filters.get(&WindowIdentifier {
-                    kind: WindowIdentifierKind::Name,
-                    value: wm_name.clone(),
-                })
-            })
-            .map(Cow::Borrowed)
-            .or_else(|| {
-                tracing::debug!("falling back to global options");
-
-                Some(Cow::Owned(Filter::Options(self.global_options?)))
-            });
-        let wm_class = if let Some(filter) = filter {
-            tracing::debug!("resolve with filters");
-            filter.resolve(wm_class)
-        } else {
-            tracing::debug!("no filters found, leaving WM_CLASS as is");
-            Cow::Borrowed(wm_class)
-        };
-
-        Ok(ResolvedWindow {
-            name: wm_class.to_string(),
-            child_process,
-        })
-    }
-}
-
-/// Reads `_NET_WM_PID` of the window and looks up the foreground descendant of that process, which for terminals
-/// is usually the command currently running in it.
-fn child_process(connection: &RustConnection, processes: &mut ProcessInspector, window: NonNullWindow) -> anyhow::Result<Option<String>> {
-    let net_wm_pid = connection
-        .intern_atom(false, b"_NET_WM_PID")
-        .context("failed to make InternAtom reply for _NET_WM_PID")?
-        .reply()
-        .context("InternAtom response for _NET_WM_PID failed")?
-        .atom;
-    let pid = connection
-        .get_property(false, window.get(), net_wm_pid, AtomEnum::CARDINAL, 0, 1)
-        .context("failed to make GetProperty reply for retrieving _NET_WM_PID")?
-        .reply()
-        .context("GetProperty response for retrieving _NET_WM_PID failed")?
-        .value32()
-        .and_then(|mut value| value.next());
-
-    let Some(pid) = pid else {
-        tracing::debug!("window has no _NET_WM_PID");
-        return Ok(None)
-    };
+        Some("replay") => {
+            let mut path = None;
+            let mut diff = false;
+
+            for arg in args {
+                match arg.as_str() {
+                    "--diff" => diff = true,
+                    _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                    _ => anyhow::bail!("unknown argument {arg:?} for replay, expected a file and optionally --diff"),
+                }
+            }
 
-    Ok(processes.foreground_child(window.get(), pid))
-}
+            let config = Config::read().context(Failure::Config)?;
+            *crash = CrashOutput::new(&config);
+            return record::replay(&path.context("replay expects a file to replay")?, &config, diff)
+        }
+        Some("diagnose") => {
+            let mut redact_titles = false;
+            let mut window = None;
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--redact-titles" => redact_titles = true,
+                    "--window" => window = Some(parse_window_id(&args.next().context("--window expects a window id")?)?),
+                    _ => anyhow::bail!("unknown argument {arg:?} for diagnose, expected --redact-titles or --window"),
+                }
+            }
 
-impl Default for Resolver {
-    fn default() -> Self {
-        Self {
-            global_options: Some(Options {
-                capitalize: Some(CapitalizeMode::default()),
-            }),
-            desktop_name: Some("Desktop".to_owned()),
-            filters: HashMap::new(),
+            print!("{}", diagnose::report(redact_titles, window));
+            return Ok(())
         }
-    }
-}
+        Some("--once") => {
+            anyhow::ensure!(args.next().is_none(), "--once expects no further arguments");
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-#[serde(tag = "filter", content = "value", rename_all = "snake_case")]
-pub enum Filter {
-    Options(Options),
-    NewName(String),
-}
+            let config = Config::read().context(Failure::Config)?;
+            *crash = CrashOutput::new(&config);
 
-impl Filter {
-    pub fn resolve<'wc>(&self, wm_class: &'wc str) -> Cow<'wc, str> {
-        match self {
-            Self::Options(options) => {
-                tracing::debug!("resolving filter with options method");
-                options.resolve(wm_class)
-            },
-            Self::NewName(name) => {
-                tracing::debug!(%name, "resolving filter with new name method");
-                Cow::Owned(name.clone())
-            },
+            let title = resolve_active(&config)?;
+            let styling = output::styling(&config);
+            return config.output.write(&title.rendered, &title.window, &DisplayState::default(), config.output_format, styling).context("failed to write title")
         }
-    }
-}
+        Some("--probe") => {
+            anyhow::ensure!(args.next().is_none(), "--probe expects no further arguments");
+            *crash = CrashOutput::silent();
+            return exit::probe()
+        }
+        Some("features") => {
+            anyhow::ensure!(args.next().is_none(), "features expects no arguments");
+            print!("{}", features::report());
+            return Ok(())
+        }
+        Some("which") => {
+            let mut window = None;
+            let mut json = false;
+
+            for arg in args {
+                match arg.as_str() {
+                    "--active" => window = None,
+                    "--json" => json = true,
+                    _ => window = Some(parse_window_id(&arg)?),
+                }
+            }
 
-#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
-pub struct Options {
-    pub capitalize: Option<CapitalizeMode>,
-}
+            let config = Config::read().context(Failure::Config)?;
+            let which = which::which(&config, window)?;
 
-fn capitalize_first(s: &str) -> String {
-    let mut chars = s.chars();
-    chars
-        .next()
-        .map(|first_letter| first_letter.to_uppercase())
-        .into_iter()
-        .flatten()
-        .chain(chars)
-        .collect()
-}
+            if json {
+                println!("{}", serde_json::to_string_pretty(&which).context("failed to serialize matches")?);
+            } else {
+                print!("{}", which::human(&which, &config));
+            }
 
-#[derive(Deserialize, Serialize, Copy, Clone, Default, Debug)]
-#[serde(rename_all = "snake_case")]
-pub enum CapitalizeMode {
-    #[default]
-    FirstLetter,
-    AllWords,
-}
+            return Ok(())
+        }
+        Some("check") => {
+            let mut window = CheckWindow::default();
+
+            while let Some(arg) = args.next() {
+                let mut value = || args.next().with_context(|| format!("{arg} expects a value"));
+
+                match arg.as_str() {
+                    "--class" => window.class = value()?,
+                    "--instance" => window.instance = value()?,
+                    "--name" => window.name = value()?,
+                    "--role" => window.role = Some(value()?),
+                    "--child-process" => window.child_process = Some(value()?),
+                    "--flatpak-id" => window.flatpak_id = Some(value()?),
+                    _ => anyhow::bail!(
+                        "unknown argument {arg:?} for check, expected --class, --instance, --name, --role, --child-process or --flatpak-id"
+                    ),
+                }
+            }
 
-impl CapitalizeMode {
-    pub fn capitalize(&self, value: &str) -> String {
-        match self {
-            Self::FirstLetter => capitalize_first(value),
-            Self::AllWords => value.to_case(Case::Title),
+            let config = Config::read().context(Failure::Config)?;
+            print!("{}", check::check(&config, &window)?);
+            return Ok(())
         }
+        Some("msg") => {
+            let command = args.collect::<Vec<_>>().join(" ");
+            anyhow::ensure!(!command.is_empty(), "msg expects a command to send");
+
+            let config = Config::read().context(Failure::Config)?;
+            let path = config.control_socket.context("control_socket is not set in the config").context(Failure::Config)?;
+            println!("{}", control::send(&path, &command).context(Failure::Unreachable)?);
+            return Ok(())
+        }
+        Some(command) => anyhow::bail!("unknown command {command:?}, expected --record, --once, --probe, replay, diagnose, features, which, check or msg"),
     }
-}
 
-impl Options {
-    pub fn resolve<'v>(&self, value: &'v str) -> Cow<'v, str> {
-        let mut new_value = Cow::Borrowed(value);
-
-        if let Some(capitalize) = &self.capitalize {
-            tracing::debug!("capitalize value");
-            new_value = Cow::Owned(capitalize.capitalize(&new_value))
+    tracing::debug!("parsing config");
+    let config = match Config::read() {
+        Ok(config) => config,
+        Err(error) if Config::is_overridden() => return Err(error).context(Failure::Config),
+        Err(error) => {
+            tracing::warn!("could not parse config: {error:#}");
+            Config::fallback()
         }
+    };
+    *crash = CrashOutput::new(&config);
 
-        new_value
+    if config.backend == Backend::Stdin {
+        anyhow::ensure!(record.is_none(), "--record only works with the x11 backend");
+        return stdin::run(&config)
     }
-}
 
-fn template() -> String {
-    "{{ name }}".to_owned()
-}
+    let mut app = App::new(config)?;
 
-fn real_main() -> anyhow::Result<()> {
-    if env::var("RUST_LOG").is_ok() {
-        tracing_subscriber::fmt::init()
-    } else {
-        tracing_subscriber::fmt()
-            .with_max_level(Level::ERROR)
-            .init()
+    if let Some(record) = record {
+        app.record_to(&record)?;
     }
 
-    tracing::debug!("parsing config");
-    let config = Config::read().unwrap_or_else(|e| {
-        tracing::warn!("could not parse config: {e:#}");
-        Config::default()
-    });
-
-    tracing::debug!("create template registry and register template from config");
-    let mut handlebars = Handlebars::new();
-    handlebars.register_template_string("template", &config.template)
-        .context("failed to register template string")?;
-
-    let template_variables = templating::variables(handlebars.get_template("template").context("template was not registered")?);
-    let mut processes = ProcessInspector::new(
-        "/proc",
-        template_variables.contains("child_process") || config.resolver.matches_on(WindowIdentifierKind::ChildProcess),
-    );
-
-    tracing::info!("establishing a connection to the X server");
-    let (connection, screen_num) = x11rb::connect(config.display_name.as_deref())
-        .context("failed to establish a connection to the X server")?;
-
-    tracing::debug!("get primary screen");
-    let screen = &connection.setup().roots[screen_num];
-
-    let events = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
-
-    tracing::info!("setting up events");
-    connection
-        .change_window_attributes(screen.root, &events)
-        .context("failed to make ChangeWindowAttributes reply")?
-        .check()
-        .context("ChangeWindowAttributes response failed")?;
-
-
-    loop {
-        let event = connection
-            .wait_for_event()
-            .context("could not wait for event")?;
-
-        if let Event::PropertyNotify(event) = event {
-            tracing::debug!("got property notify event");
-            let atom = connection
-                .get_atom_name(event.atom)
-                .context("failed to make GetAtomName reply")?
-                .reply()
-                .context("GetAtomName response failed")?;
-            let atom_name =
-                String::from_utf8(atom.name).context("atom name contains invalid utf-8")?;
-
-            if atom_name == "_NET_ACTIVE_WINDOW" {
-                tracing::debug!("atom name is _NET_ACTIVE_WINDOW, making reply to X server for properties");
-                let property = connection
-                    .get_property(false, event.window, event.atom, 33u32, 0, 4)
-                    .context("failed to make GetProperty reply")?
-                    .reply()
-                    .context("GetProperty response failed")?;
-                let value = property
-                    .value32()
-                    .context("failed to get u32 value from atom")?
-                    .next()
-                    .context("missing u32 value from atom")?;
-                tracing::debug!(%value, "u32 property value");
-
-                let window = NonNullWindow::new(value);
-
-                tracing::debug!("resolving window name");
-                let resolved = config.resolver
-                    .resolve(&connection, &mut processes, window.map(|w| w.get()).unwrap_or_default())
-                    .context("failed to resolve name of window")?;
-                let mut data = HashMap::with_capacity(2);
-                data.insert("name", resolved.name);
-
-                if let Some(child_process) = resolved.child_process {
-                    data.insert("child_process", child_process);
-                }
-
-                tracing::debug!("rendering resolved name");
-                let rendered_name = handlebars.render("template", &data).context("failed to render template")?;
-                println!("{rendered_name}")
-            } else {
-                tracing::debug!(%atom_name, "other atom name was received")
-            }
-        } else {
-            tracing::debug!(?event, "received other event")
-        }
-    }
+    app.run()
 }
 
 fn main() -> ExitCode {
-    if let Err(error) = real_main() {
+    let mut crash = CrashOutput::default();
+
+    if let Err(error) = real_main(&mut crash) {
         tracing::error!("{error:#}");
-        println!("PolyBar title module crashed!");
-        ExitCode::FAILURE
+        crash.write(&error);
+        ExitCode::from(Failure::of(&error).map_or(1, Failure::exit_code))
     } else {
         ExitCode::SUCCESS
     }
@@ -0,0 +1,282 @@
+//! Opt-in latency metrics (`latency_metrics`), telling how long it takes from an X event to each stage of turning it
+//! into an output, to find out where a bar lagging behind focus changes spends its time.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The upper bounds of the histogram buckets in microseconds. Anything slower lands in an extra last bucket.
+const BUCKETS: [u64; 13] = [100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000];
+
+/// A stage of the pipeline, measured from when the event which started it was received.
+#[derive(Serialize, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Properties,
+    Filters,
+    Rendered,
+    Written,
+}
+
+impl Stage {
+    const ALL: [Self; 4] = [Self::Properties, Self::Filters, Self::Rendered, Self::Written];
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Histogram {
+    counts: [u64; BUCKETS.len() + 1],
+    total_us: u64,
+    max_us: u64,
+}
+
+#[derive(Serialize, Clone, Eq, PartialEq, Debug)]
+pub struct Summary {
+    pub count: u64,
+    pub mean_us: u64,
+
+    /// Percentiles, as the upper bound of the bucket they fall into, or the maximum if that's lower.
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+
+    /// The number of latencies up to each bucket's upper bound, in microseconds, skipping empty buckets. The
+    /// bucket without an upper bound is `null`.
+    pub buckets: Vec<(Option<u64>, u64)>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, latency: Duration) {
+        let us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKETS.iter().position(|bound| us <= *bound).unwrap_or(BUCKETS.len());
+
+        self.counts[bucket] += 1;
+        self.total_us = self.total_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn percentile(&self, percentile: u64) -> u64 {
+        let count = self.count();
+
+        if count == 0 {
+            return 0
+        }
+
+        // the rank of the latency which is at the percentile, rounded up, starting at 1
+        let rank = (count * percentile).div_ceil(100).max(1);
+        let mut seen = 0;
+
+        for (bucket, bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+
+            if seen >= rank {
+                return BUCKETS.get(bucket).map_or(self.max_us, |bound| (*bound).min(self.max_us))
+            }
+        }
+
+        self.max_us
+    }
+
+    pub fn summary(&self) -> Summary {
+        let count = self.count();
+
+        Summary {
+            count,
+            mean_us: self.total_us.checked_div(count).unwrap_or(0),
+            p50_us: self.percentile(50),
+            p90_us: self.percentile(90),
+            p99_us: self.percentile(99),
+            max_us: self.max_us,
+            buckets: self.counts
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(bucket, count)| (BUCKETS.get(bucket).copied(), *count))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LatencyMetrics {
+    histograms: BTreeMap<Stage, Histogram>,
+    received: Option<Instant>,
+    next_summary: Instant,
+}
+
+impl LatencyMetrics {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            histograms: Stage::ALL.into_iter().map(|stage| (stage, Histogram::default())).collect(),
+            received: None,
+            next_summary: now + SUMMARY_INTERVAL,
+        }
+    }
+
+    /// Starts measuring from `now`, unless an earlier event hasn't been written yet, as that's what the output is
+    /// lagging behind since.
+    pub fn received(&mut self, now: Instant) {
+        self.received.get_or_insert(now);
+    }
+
+    /// Records that `stage` was reached, if an event is being measured. Reaching [`Stage::Written`] ends it.
+    pub fn reached(&mut self, stage: Stage, now: Instant) {
+        let Some(received) = self.received else {
+            return
+        };
+
+        if let Some(histogram) = self.histograms.get_mut(&stage) {
+            histogram.add(now.saturating_duration_since(received));
+        }
+
+        if stage == Stage::Written {
+            self.received = None;
+        }
+    }
+
+    pub fn discard(&mut self) {
+        self.received = None;
+    }
+
+    pub fn summaries(&self) -> BTreeMap<Stage, Summary> {
+        self.histograms.iter().map(|(stage, histogram)| (*stage, histogram.summary())).collect()
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.next_summary
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) {
+        if now < self.next_summary {
+            return
+        }
+
+        self.next_summary = now + SUMMARY_INTERVAL;
+        let mut summary = String::new();
+
+        for (stage, histogram) in &self.summaries() {
+            let _ = write!(
+                summary,
+                "{}{stage:?}: n={} mean={}us p50={}us p90={}us p99={}us max={}us",
+                if summary.is_empty() { "" } else { ", " },
+                histogram.count,
+                histogram.mean_us,
+                histogram.p50_us,
+                histogram.p90_us,
+                histogram.p99_us,
+                histogram.max_us,
+            );
+        }
+
+        tracing::info!("latency since event: {summary}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(latencies_us: &[u64]) -> Histogram {
+        let mut histogram = Histogram::default();
+        for us in latencies_us {
+            histogram.add(Duration::from_micros(*us));
+        }
+
+        histogram
+    }
+
+    #[test]
+    fn empty_histogram() {
+        assert_eq!(
+            Histogram::default().summary(),
+            Summary { count: 0, mean_us: 0, p50_us: 0, p90_us: 0, p99_us: 0, max_us: 0, buckets: Vec::new() },
+        );
+    }
+
+    #[test]
+    fn histogram_arithmetic() {
+        // 90 fast ones, 9 slower ones and one which is slower than every bucket
+        let latencies = [[50; 90].as_slice(), &[3_000; 9], &[2_000_000]].concat();
+        let summary = histogram(&latencies).summary();
+
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.mean_us, (90 * 50 + 9 * 3_000 + 2_000_000) / 100);
+        // percentiles are only as precise as the buckets
+        assert_eq!(summary.p50_us, 100);
+        assert_eq!(summary.p90_us, 100);
+        assert_eq!(summary.p99_us, 5_000);
+        assert_eq!(summary.max_us, 2_000_000);
+        assert_eq!(summary.buckets, vec![(Some(100), 90), (Some(5_000), 9), (None, 1)]);
+    }
+
+    #[test]
+    fn bucket_bounds_are_inclusive() {
+        let summary = histogram(&[100, 101, 1_000_000, 1_000_001]).summary();
+
+        assert_eq!(summary.buckets, vec![(Some(100), 1), (Some(250), 1), (Some(1_000_000), 1), (None, 1)]);
+        assert_eq!(summary.p50_us, 250);
+    }
+
+    #[test]
+    fn stages_are_measured_from_the_first_unwritten_event() {
+        let start = Instant::now();
+        let mut metrics = LatencyMetrics::new(start);
+
+        // not measuring anything yet
+        metrics.reached(Stage::Properties, start);
+
+        metrics.received(start);
+        metrics.received(start + Duration::from_millis(5));
+        metrics.reached(Stage::Properties, start + Duration::from_millis(1));
+        metrics.reached(Stage::Written, start + Duration::from_millis(10));
+        metrics.reached(Stage::Written, start + Duration::from_millis(20));
+
+        metrics.received(start + Duration::from_millis(30));
+        metrics.discard();
+        metrics.reached(Stage::Written, start + Duration::from_millis(40));
+
+        let summaries = metrics.summaries();
+        assert_eq!(summaries[&Stage::Properties].buckets, vec![(Some(1_000), 1)]);
+        assert_eq!(summaries[&Stage::Filters].count, 0);
+        assert_eq!(summaries[&Stage::Written].buckets, vec![(Some(10_000), 1)]);
+        assert_eq!(summaries[&Stage::Written].max_us, 10_000);
+    }
+
+    #[test]
+    fn serialization_is_stable() {
+        let mut metrics = LatencyMetrics::new(Instant::now());
+        let start = Instant::now();
+        metrics.received(start);
+        metrics.reached(Stage::Filters, start + Duration::from_micros(300));
+        metrics.reached(Stage::Written, start + Duration::from_micros(1_200));
+
+        assert_eq!(
+            serde_json::to_string(&metrics.summaries()).unwrap(),
+            concat!(
+                r#"{"properties":{"count":0,"mean_us":0,"p50_us":0,"p90_us":0,"p99_us":0,"max_us":0,"buckets":[]},"#,
+                r#""filters":{"count":1,"mean_us":300,"p50_us":300,"p90_us":300,"p99_us":300,"max_us":300,"buckets":[[500,1]]},"#,
+                r#""rendered":{"count":0,"mean_us":0,"p50_us":0,"p90_us":0,"p99_us":0,"max_us":0,"buckets":[]},"#,
+                r#""written":{"count":1,"mean_us":1200,"p50_us":1200,"p90_us":1200,"p99_us":1200,"max_us":1200,"buckets":[[2500,1]]}}"#,
+            ),
+        );
+    }
+
+    #[test]
+    fn summary_is_due_every_interval() {
+        let start = Instant::now();
+        let mut metrics = LatencyMetrics::new(start);
+
+        metrics.on_deadline(start + Duration::from_secs(1));
+        assert_eq!(metrics.deadline(), start + SUMMARY_INTERVAL);
+
+        metrics.on_deadline(start + SUMMARY_INTERVAL);
+        assert_eq!(metrics.deadline(), start + SUMMARY_INTERVAL * 2);
+    }
+}
@@ -0,0 +1,185 @@
+//! Only displaying windows on one monitor (`monitor` in the config), for setups with a bar on every monitor.
+//!
+//! Monitors are looked up through RandR by the name polybar knows them by too, e.g. `DP-1`. A window is on the
+//! monitor its center is on.
+
+use anyhow::Context;
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{ConnectionExt as _, Window};
+use x11rb::rust_connection::RustConnection;
+
+#[derive(Copy, Clone, Debug)]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Geometry {
+    fn contains(self, (x, y): (i32, i32)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+#[derive(Debug)]
+pub struct Monitor {
+    name: String,
+
+    /// Where the monitor was last time, or `None` if it wasn't connected.
+    geometry: Option<Geometry>,
+}
+
+impl Monitor {
+    pub fn new(connection: &RustConnection, root: Window, name: String) -> anyhow::Result<Self> {
+        connection
+            .extension_information(randr::X11_EXTENSION_NAME)
+            .context("failed to query RandR extension")?
+            .context("the X server doesn't support RandR, which is needed to find monitors")?;
+        connection
+            .randr_query_version(1, 5)
+            .context("failed to make RandR QueryVersion request")?
+            .reply()
+            .context("RandR QueryVersion response failed")?;
+
+        let mut monitor = Self { name, geometry: None };
+        monitor.update(connection, root)?;
+
+        if monitor.geometry.is_none() {
+            tracing::warn!(monitor = %monitor.name, "monitor isn't connected, no window is displayed until it is");
+        }
+
+        Ok(monitor)
+    }
+
+    /// Whether the center of `window` is on the monitor. Monitors are looked up again if it isn't, as they may
+    /// have been connected, disconnected or moved since.
+    pub fn contains(&mut self, connection: &RustConnection, root: Window, window: Window) -> anyhow::Result<bool> {
+        let center = center(connection, root, window)?;
+
+        if self.geometry.is_some_and(|geometry| geometry.contains(center)) {
+            return Ok(true)
+        }
+
+        self.update(connection, root)?;
+        Ok(self.geometry.is_some_and(|geometry| geometry.contains(center)))
+    }
+
+    fn update(&mut self, connection: &impl RequestConnection, root: Window) -> anyhow::Result<()> {
+        let monitors = connection
+            .randr_get_monitors(root, true)
+            .context("failed to make RandR GetMonitors request")?
+            .reply()
+            .context("RandR GetMonitors response failed")?
+            .monitors;
+
+        let names = monitors
+            .iter()
+            .map(|monitor| connection.get_atom_name(monitor.name).context("failed to make GetAtomName request"))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.geometry = None;
+
+        for (monitor, name) in monitors.iter().zip(names) {
+            let name = name.reply().context("GetAtomName response for monitor failed")?.name;
+
+            if name == self.name.as_bytes() {
+                self.geometry = Some(Geometry {
+                    x: monitor.x.into(),
+                    y: monitor.y.into(),
+                    width: monitor.width.into(),
+                    height: monitor.height.into(),
+                });
+            }
+        }
+
+        tracing::debug!(monitor = %self.name, geometry = ?self.geometry, "looked up monitor");
+        Ok(())
+    }
+}
+
+fn center(connection: &RustConnection, root: Window, window: Window) -> anyhow::Result<(i32, i32)> {
+    let geometry = connection
+        .get_geometry(window)
+        .context("failed to make GetGeometry request")?;
+    let position = connection
+        .translate_coordinates(window, root, 0, 0)
+        .context("failed to make TranslateCoordinates request")?;
+
+    let geometry = geometry.reply().context("GetGeometry response failed")?;
+    let position = position.reply().context("TranslateCoordinates response failed")?;
+
+    Ok((
+        i32::from(position.dst_x) + i32::from(geometry.width) / 2,
+        i32::from(position.dst_y) + i32::from(geometry.height) / 2,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockConnection;
+
+    const ROOT: Window = 1;
+
+    /// The reply to GetMonitors with monitors at `(name atom, x, y, width, height)`.
+    fn monitors(monitors: &[(u32, i16, i16, u16, u16)]) -> Vec<u8> {
+        let mut reply = vec![1, 0, 0, 0];
+        reply.extend_from_slice(&(monitors.len() as u32 * 6).to_ne_bytes());
+        reply.extend_from_slice(&0u32.to_ne_bytes());
+        reply.extend_from_slice(&(monitors.len() as u32).to_ne_bytes());
+        reply.resize(32, 0);
+
+        for &(name, x, y, width, height) in monitors {
+            reply.extend_from_slice(&name.to_ne_bytes());
+            reply.extend_from_slice(&[0, 1, 0, 0]);
+            reply.extend_from_slice(&x.to_ne_bytes());
+            reply.extend_from_slice(&y.to_ne_bytes());
+            reply.extend_from_slice(&width.to_ne_bytes());
+            reply.extend_from_slice(&height.to_ne_bytes());
+            reply.extend_from_slice(&[0; 8]);
+        }
+
+        reply
+    }
+
+    fn atom_name(name: &str) -> Vec<u8> {
+        let mut reply = vec![1, 0, 0, 0];
+        reply.extend_from_slice(&(name.len().div_ceil(4) as u32).to_ne_bytes());
+        reply.extend_from_slice(&(name.len() as u16).to_ne_bytes());
+        reply.resize(32, 0);
+        reply.extend_from_slice(name.as_bytes());
+        reply.resize(32 + name.len().div_ceil(4) * 4, 0);
+        reply
+    }
+
+    fn look_up(name: &str) -> Monitor {
+        let connection = MockConnection::with_extensions();
+        connection.reply(monitors(&[(100, 0, 0, 1920, 1080), (101, 1920, 0, 2560, 1440)]));
+        connection.reply(atom_name("eDP-1"));
+        connection.reply(atom_name("DP-1"));
+
+        let mut monitor = Monitor { name: name.to_owned(), geometry: None };
+        monitor.update(&connection, ROOT).unwrap();
+        monitor
+    }
+
+    #[test]
+    fn monitor_is_found_by_output_name() {
+        let geometry = look_up("DP-1").geometry.unwrap();
+
+        assert_eq!((geometry.x, geometry.y, geometry.width, geometry.height), (1920, 0, 2560, 1440));
+        assert!(geometry.contains((1920, 0)));
+        assert!(geometry.contains((4479, 1439)));
+        assert!(!geometry.contains((1919, 500)));
+        assert!(!geometry.contains((3000, 1440)));
+    }
+
+    #[test]
+    fn disconnected_monitor_has_no_geometry() {
+        assert!(look_up("HDMI-1").geometry.is_none());
+        // names are matched exactly, not by prefix
+        assert!(look_up("DP").geometry.is_none());
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt::{self, Formatter};
+use std::str::FromStr;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Placeholder {
+    Name,
+    Class,
+    Instance,
+
+    /// `{raw_name}`, the same as `{name}`, under the name the handlebars templates know it by.
+    RawName,
+
+    /// `{raw_class}`, the same as `{class}`, under the name the handlebars templates know it by.
+    RawClass,
+}
+
+impl Placeholder {
+    const ALL: [Self; 5] = [Self::Name, Self::Class, Self::Instance, Self::RawName, Self::RawClass];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Class => "class",
+            Self::Instance => "instance",
+            Self::RawName => "raw_name",
+            Self::RawClass => "raw_class",
+        }
+    }
+}
+
+impl FromStr for Placeholder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|placeholder| placeholder.as_str() == s)
+            .ok_or_else(|| {
+                let valid = Self::ALL.map(Self::as_str).join(", ");
+                anyhow::anyhow!("unknown placeholder `{{{s}}}`, valid placeholders are: {valid}")
+            })
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// The replacement name of a `new_name` filter.
+///
+/// It's mostly static text, but may contain `{name}`, `{class}` and `{instance}` placeholders (`{raw_name}` and
+/// `{raw_class}` work too, as none of them have been through any filter yet), e.g.
+/// `"Firefox — {name}"`. Literal braces are written as `{{` and `}}`. This is deliberately much simpler than a
+/// handlebars template; unknown placeholders are rejected when the config is read.
+#[derive(DeserializeFromStr, SerializeDisplay, Clone, Eq, PartialEq, Debug)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl NameTemplate {
+    pub fn render(&self, name: &str, class: &str, instance: &str) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::Placeholder(Placeholder::Name | Placeholder::RawName) => name,
+                Segment::Placeholder(Placeholder::Class | Placeholder::RawClass) => class,
+                Segment::Placeholder(Placeholder::Instance) => instance,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => literal.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => literal.push('}'),
+                '{' => {
+                    let mut placeholder = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => anyhow::bail!("unterminated placeholder `{{{placeholder}` (use `{{{{` for a literal brace)"),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)))
+                    }
+
+                    segments.push(Segment::Placeholder(placeholder.trim().parse()?))
+                }
+                '}' => anyhow::bail!("unmatched `}}` (use `}}}}` for a literal brace)"),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal))
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => f.write_str(&literal.replace('{', "{{").replace('}', "}}"))?,
+                Segment::Placeholder(placeholder) => write!(f, "{{{}}}", placeholder.as_str())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn render(template: &str) -> String {
+        template.parse::<NameTemplate>().unwrap().render("Inbox", "Thunderbird", "Mail")
+    }
+
+    fn error(template: &str) -> String {
+        template.parse::<NameTemplate>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(render("Static"), "Static");
+        assert_eq!(render("Thunderbird — {name}"), "Thunderbird — Inbox");
+        assert_eq!(render("{class}:{instance}:{name}"), "Thunderbird:Mail:Inbox");
+        assert_eq!(render("{ name }{raw_class}"), "InboxThunderbird");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn brace_escapes() {
+        assert_eq!(render("{{name}}"), "{name}");
+        assert_eq!(render("{{{name}}}"), "{Inbox}");
+        assert_eq!(render("}}{{"), "}{");
+        assert_eq!(render("a {{ b"), "a { b");
+    }
+
+    #[test]
+    fn escapes_round_trip_through_display() {
+        for template in ["{{name}}", "{{{class}}} — {name}", "plain", "}}{instance}{{"] {
+            let parsed = template.parse::<NameTemplate>().unwrap();
+            assert_eq!(parsed.to_string(), template);
+            assert_eq!(parsed.to_string().parse::<NameTemplate>().unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert_eq!(error("{title}"), "unknown placeholder `{title}`, valid placeholders are: name, class, instance, raw_name, raw_class");
+        assert!(error("{}").starts_with("unknown placeholder `{}`"));
+        assert!(error("{name").starts_with("unterminated placeholder"));
+        assert!(error("name}").starts_with("unmatched `}`"));
+        assert!(error("{na{me}").starts_with("unknown placeholder"));
+    }
+
+    #[test]
+    fn unknown_placeholders_fail_the_config() {
+        let error = Config::from_sources([r#"
+            [resolver.filters]
+            "wm_class=firefox" = { filter = "new_name", value = "Firefox — {title}" }
+        "#])
+        .unwrap_err();
+
+        assert!(format!("{error:#}").contains("valid placeholders are: name, class, instance"), "{error:#}");
+    }
+}
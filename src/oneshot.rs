@@ -0,0 +1,174 @@
+//! Resolving a single window without running the module, for using this crate as a library.
+//!
+//! [`resolve_active`], [`resolve_window`], [`resolve_window_on`] and [`ResolvedTitle`] are the supported library
+//! API and follow semver. Everything else which happens to be public exists for the binary and may change in any
+//! release.
+
+use anyhow::Context;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::app::{render, templates, DisplayState, RenderContext};
+use crate::atoms::Atoms;
+use crate::client::ClientSearch;
+use crate::desktop::Desktop;
+use crate::exit::Failure;
+use crate::machine::MachineInspector;
+use crate::process::ProcessInspector;
+use crate::{intern_atom, Config, NonNullWindow, ResolvedWindow};
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ResolvedTitle {
+    pub window: ResolvedWindow,
+    pub rendered: String,
+}
+
+impl ResolvedTitle {
+    pub fn name(&self) -> &str {
+        &self.window.name
+    }
+
+    /// The title as read from the window, before any filter applied, `raw_name` in templates.
+    pub fn raw_name(&self) -> &str {
+        &self.window.wm_name
+    }
+
+    /// The class as read from the window, before any filter applied, `raw_class` in templates.
+    pub fn raw_class(&self) -> &str {
+        &self.window.wm_class
+    }
+}
+
+/// Resolves the active window, or the desktop if there is none.
+///
+/// ```no_run
+/// use polybar_title_module::{resolve_active, Config};
+///
+/// let title = resolve_active(&Config::read()?)?;
+/// println!("{}", title.rendered);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// Fails if the X server can't be reached, e.g. because `display_name` names a display which doesn't exist:
+///
+/// ```
+/// use polybar_title_module::{resolve_active, Config};
+///
+/// let config = Config { display_name: Some(":4242".to_owned()), ..Config::default() };
+/// assert!(resolve_active(&config).is_err());
+/// ```
+pub fn resolve_active(config: &Config) -> anyhow::Result<ResolvedTitle> {
+    let (connection, screen) = connect(config)?;
+    let root = connection.setup().roots[screen].root;
+    let window = active_window(&connection, root)?;
+
+    resolve_window_on(&connection, screen, config, window)
+}
+
+/// Resolves the window with the id `window`.
+///
+/// ```no_run
+/// use polybar_title_module::{parse_window_id, resolve_window, Config};
+///
+/// let title = resolve_window(&Config::default(), parse_window_id("0x1a00003")?)?;
+/// println!("{} ({})", title.rendered, title.window.wm_class);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// Ids given by users or scripts are best parsed with [`parse_window_id`](crate::parse_window_id), which takes
+/// them in decimal or hexadecimal, and rejects 0 as it stands for the desktop rather than a window:
+///
+/// ```
+/// use polybar_title_module::parse_window_id;
+///
+/// assert_eq!(parse_window_id("0x1a00003")?.get(), 27262979);
+/// assert_eq!(parse_window_id(" 27262979\n")?.get(), 27262979);
+/// assert!(parse_window_id("0").is_err());
+/// assert!(parse_window_id("firefox").is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn resolve_window(config: &Config, window: NonNullWindow) -> anyhow::Result<ResolvedTitle> {
+    let (connection, screen) = connect(config)?;
+    resolve_window_on(&connection, screen, config, window.get())
+}
+
+/// Resolves `window` using an existing connection, whose screen `screen` the window is on. A `window` of 0 resolves
+/// the desktop.
+pub fn resolve_window_on(connection: &RustConnection, screen: usize, config: &Config, window: Window) -> anyhow::Result<ResolvedTitle> {
+    let handlebars = templates(config)?;
+    let root = connection.setup().roots.get(screen).context("no such screen")?.root;
+    let mut processes = ProcessInspector::new("/proc", true);
+    let machines = MachineInspector::new(true);
+
+    let atoms = Atoms::new(connection)?;
+
+    let mut resolved = config.resolver
+        .resolve(connection, &atoms, &mut processes, &machines, window)
+        .context("failed to resolve name of window")?;
+
+    // like the module does, some window managers report frame or child windows as active
+    if resolved.is_unknown() && config.resolver.search_client_window {
+        if let Some(client) = ClientSearch::default().find(connection, window).context("failed to search for client window")? {
+            resolved = config.resolver
+                .resolve(connection, &atoms, &mut processes, &machines, client)
+                .context("failed to resolve name of client window")?;
+        }
+    }
+
+    let state = DisplayState {
+        desktop: Desktop::current(connection, &atoms, root).context("failed to get current desktop")?,
+        ..DisplayState::default()
+    };
+
+    if resolved.window == 0 {
+        resolved = config.resolver.desktop_on(state.desktop.as_ref());
+    }
+
+    // there's no previous output to keep
+    let resolved = config.resolver.apply_ignore(resolved, false, state.desktop.as_ref()).expect("nothing was displayed before");
+
+    let mut context = RenderContext::default();
+    context.update(&resolved, &state, &config.resolver);
+    let rendered = render(&handlebars, config, &context, &state)?;
+
+    Ok(ResolvedTitle { window: resolved, rendered })
+}
+
+/// Reads `_NET_ACTIVE_WINDOW` from `root`. Returns 0 if no window is active.
+pub(crate) fn active_window(connection: &RustConnection, root: Window) -> anyhow::Result<Window> {
+    let net_active_window = intern_atom(connection, "_NET_ACTIVE_WINDOW")?;
+
+    Ok(connection
+        .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)
+        .context("failed to make GetProperty reply")?
+        .reply()
+        .context("GetProperty response failed")?
+        .value32()
+        .and_then(|mut value| value.next())
+        .unwrap_or_default())
+}
+
+pub(crate) fn connect(config: &Config) -> anyhow::Result<(RustConnection, usize)> {
+    x11rb::connect(config.display_name.as_deref()).context(Failure::Backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on_display(display_name: &str) -> Config {
+        Config { display_name: Some(display_name.to_owned()), ..Config::default() }
+    }
+
+    #[test]
+    fn unreachable_x_server_is_a_backend_failure() {
+        for config in [on_display(":4242"), on_display("not a display")] {
+            let window = NonNullWindow::new(0x1a00003).unwrap();
+
+            for error in [resolve_active(&config).unwrap_err(), resolve_window(&config, window).unwrap_err()] {
+                assert_eq!(Failure::of(&error), Some(Failure::Backend), "{error:#}");
+            }
+        }
+    }
+}
@@ -0,0 +1,698 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+use std::{env, fs, io, thread};
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use x11rb::protocol::xproto::Window;
+use crate::app::DisplayState;
+use crate::wm_state::WmState;
+use crate::{Config, ResolvedWindow};
+
+const IPC_MAGIC: &[u8; 7] = b"polyipc";
+
+/// Version 0 message type for actions.
+const IPC_ACTION: u8 = 2;
+
+/// Version 0 message type for successful responses.
+const IPC_OK: u8 = 0;
+
+const IPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+const DEFAULT_CRASH_TEXT: &str = "PolyBar title module crashed!";
+
+pub const URGENT_PREFIX: &str = "⚠ ";
+
+/// Set by `--plain`, which turns styling off no matter what the config says, even after it's reloaded.
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+pub fn force_plain() {
+    PLAIN.store(true, Ordering::Relaxed)
+}
+
+/// Whether the module formats what it writes on its own, i.e. puts [`URGENT_PREFIX`] in front of urgent windows,
+/// wraps the output in click actions and gives waybar classes. It's off with `styling = false`, `--plain` or a non-empty `NO_COLOR` (see
+/// <https://no-color.org>). Either way, the rendered templates are left as they are.
+pub fn styling(config: &Config) -> bool {
+    config.styling
+        && !PLAIN.load(Ordering::Relaxed)
+        && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Plain,
+
+    #[default]
+    Polybar,
+
+    /// A JSON object with the rendered templates and what they were rendered from, for scripts. Nothing is escaped
+    /// in the templates.
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    text: &'a str,
+    name: &'a str,
+    class: &'a str,
+    title: &'a str,
+    window: Window,
+    xwayland: bool,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    crashed: bool,
+}
+
+impl<'a> JsonLine<'a> {
+    fn new(text: &'a str, resolved: &'a ResolvedWindow) -> Self {
+        Self {
+            text,
+            name: &resolved.name,
+            class: &resolved.wm_class,
+            title: &resolved.wm_name,
+            window: resolved.window,
+            xwayland: resolved.xwayland,
+            crashed: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Output {
+    #[default]
+    Stdout,
+    PolybarIpc(PolybarIpc),
+    Waybar(Waybar),
+}
+
+impl Output {
+    pub fn needs_window_state(&self) -> bool {
+        matches!(self, Self::Waybar(waybar) if !waybar.extra_classes.is_empty())
+    }
+
+    pub fn write(&self, rendered: &str, resolved: &ResolvedWindow, state: &DisplayState, format: OutputFormat, styling: bool) -> anyhow::Result<()> {
+        self.send(&self.line(rendered, resolved, state, format, styling)?)
+    }
+
+    /// What [`Self::write`] writes for `rendered`, which is the same for the same arguments.
+    pub fn line(&self, rendered: &str, resolved: &ResolvedWindow, state: &DisplayState, format: OutputFormat, styling: bool) -> anyhow::Result<String> {
+        let rendered = decorate(rendered, state, styling);
+
+        match (self, format) {
+            (Self::Stdout, OutputFormat::Plain) => Ok(rendered.into_owned()),
+            (Self::Stdout, OutputFormat::Json) => {
+                serde_json::to_string(&JsonLine::new(&rendered, resolved)).context("failed to serialize json output")
+            }
+            (Self::Stdout, OutputFormat::Polybar) | (Self::PolybarIpc(_), _) => Ok(colorize(&rendered, resolved, styling).into_owned()),
+            (Self::Waybar(waybar), _) => waybar.line(&rendered, resolved, state, styling),
+        }
+    }
+
+    pub fn send(&self, line: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Stdout | Self::Waybar(_) => print(line),
+            Self::PolybarIpc(ipc) => {
+                ipc.send(line);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn write_crash(&self, text: &str, format: OutputFormat, styling: bool) -> anyhow::Result<()> {
+        match (self, format) {
+            (Self::Stdout, OutputFormat::Json) => {
+                let none = ResolvedWindow::without_properties(0, String::new());
+                let line = JsonLine { crashed: true, ..JsonLine::new(text, &none) };
+                print(&serde_json::to_string(&line).context("failed to serialize json output")?)
+            }
+            (Self::Stdout, _) => print(text),
+            (Self::PolybarIpc(ipc), _) => {
+                // the module exits right after crashing, which would leave a queued title unsent
+                flush();
+                ipc.deliver(text);
+                Ok(())
+            }
+            (Self::Waybar(_), _) => {
+                let class = if styling { vec![Cow::Borrowed("crashed")] } else { Vec::new() };
+                let line = WaybarLine { text, alt: None, tooltip: None, class };
+                print(&serde_json::to_string(&line).context("failed to serialize waybar output")?)
+            }
+        }
+    }
+}
+
+/// Writes `line` to stdout and flushes it, so that it's delivered right away however stdout is buffered. Unlike
+/// `println!`, this doesn't panic once the reader is gone, which [`is_stdout_closed`] tells.
+pub fn print(line: &str) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{line}").and_then(|()| stdout.flush()).context("failed to write to stdout")
+}
+
+/// Whether `error` came from writing to stdout after whatever read it, like polybar, exited. There's nobody left to
+/// show anything to then, so it's a reason to exit quietly.
+pub fn is_stdout_closed(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.downcast_ref::<io::Error>().is_some_and(|error| error.kind() == io::ErrorKind::BrokenPipe))
+}
+
+pub fn decorate<'r>(rendered: &'r str, state: &DisplayState, styling: bool) -> Cow<'r, str> {
+    if styling && state.alternating {
+        Cow::Owned(format!("{URGENT_PREFIX}{rendered}"))
+    } else {
+        Cow::Borrowed(rendered)
+    }
+}
+
+fn colorize<'r>(rendered: &'r str, resolved: &ResolvedWindow, styling: bool) -> Cow<'r, str> {
+    if styling {
+        resolved.colors.wrap(rendered)
+    } else {
+        Cow::Borrowed(rendered)
+    }
+}
+
+/// What gets written when the module crashes. It's taken from the config as soon as that's loaded, so that it's
+/// still around no matter where the crash happens, and falls back to a fixed line on stdout before that.
+#[derive(Default, Debug)]
+pub struct CrashOutput {
+    output: Output,
+    format: OutputFormat,
+    template: Option<String>,
+    styling: bool,
+    silent: bool,
+}
+
+impl CrashOutput {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            output: config.output.clone(),
+            format: config.output_format,
+            template: config.crash_template.clone(),
+            styling: styling(config),
+            silent: false,
+        }
+    }
+
+    pub fn silent() -> Self {
+        Self { silent: true, ..Self::default() }
+    }
+
+    pub fn write(&self, error: &anyhow::Error) {
+        if self.silent {
+            return
+        }
+
+        let text = self.template
+            .as_ref()
+            .and_then(|template| {
+                let mut handlebars = Handlebars::new();
+                handlebars.register_escape_fn(handlebars::no_escape);
+                handlebars
+                    .render_template(template, &serde_json::json!({ "error": format!("{error:#}") }))
+                    .map_err(|error| tracing::error!("failed to render crash template: {error}"))
+                    .ok()
+            })
+            .unwrap_or_else(|| DEFAULT_CRASH_TEXT.to_owned());
+
+        if let Err(error) = self.output.write_crash(&text, self.format, self.styling) {
+            tracing::error!("failed to write crash output: {error:#}");
+            let _ = print(DEFAULT_CRASH_TEXT);
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StateClass {
+    Fullscreen,
+    Maximized,
+    Urgent,
+}
+
+impl StateClass {
+    fn applies_to(self, state: &WmState) -> bool {
+        match self {
+            Self::Fullscreen => state.fullscreen,
+            Self::Maximized => state.maximized,
+            Self::Urgent => state.urgent,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Fullscreen => "fullscreen",
+            Self::Maximized => "maximized",
+            Self::Urgent => "urgent",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Waybar {
+    #[serde(default)]
+    pub extra_classes: Vec<StateClass>,
+
+    #[serde(default)]
+    pub tooltip: bool,
+}
+
+#[derive(Serialize)]
+struct WaybarLine<'a> {
+    text: &'a str,
+
+    /// The application the window belongs to, which waybar can pick a `format-icons` entry by.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alt: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tooltip: Option<String>,
+    class: Vec<Cow<'a, str>>,
+}
+
+impl Waybar {
+    pub fn line(&self, rendered: &str, resolved: &ResolvedWindow, state: &DisplayState, styling: bool) -> anyhow::Result<String> {
+        serde_json::to_string(&self.json(rendered, resolved, state, styling)).context("failed to serialize waybar output")
+    }
+
+    fn json<'a>(&self, rendered: &'a str, resolved: &'a ResolvedWindow, state: &DisplayState, styling: bool) -> WaybarLine<'a> {
+        let app = resolved.app.as_ref().map(|app| app.id.as_str());
+
+        // the tooltip is pango markup like the text, but isn't escaped by the templates
+        let tooltip = self.tooltip.then(|| {
+            format!("{}\n{}", handlebars::html_escape(&resolved.name), handlebars::html_escape(&resolved.wm_name))
+        });
+
+        if !styling {
+            return WaybarLine { text: rendered, alt: app, tooltip, class: Vec::new() }
+        }
+
+        let class = [Some(&resolved.wm_class[..]), app]
+            .into_iter()
+            .flatten()
+            .map(slug)
+            .filter(|slug| !slug.is_empty())
+            .map(Cow::Owned)
+            .chain(
+                self.extra_classes
+                    .iter()
+                    .filter(|class| class.applies_to(&state.window))
+                    .map(|class| Cow::Borrowed(class.name()))
+            )
+            .collect();
+
+        WaybarLine { text: rendered, alt: app, tooltip, class }
+    }
+}
+
+/// Turns `value` into something usable as a CSS class: lowercase alphanumeric words separated by single dashes,
+/// which never starts with a digit. E.g. `org.gnome.Nautilus` becomes `org-gnome-nautilus`.
+pub fn slug(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+
+    for word in value.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        if !slug.is_empty() {
+            slug.push('-')
+        }
+
+        slug.extend(word.chars().flat_map(char::to_lowercase));
+    }
+
+    if slug.starts_with(|c: char| c.is_ascii_digit()) {
+        slug.insert(0, '_')
+    }
+
+    slug
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PolybarIpc {
+    /// The name of the `custom/ipc` module, without the `module/` prefix.
+    pub module: String,
+    pub bar: Option<String>,
+}
+
+impl PolybarIpc {
+    /// Sends `rendered` to every matching bar in the background. Bars which can't be reached are logged and
+    /// skipped, as they might just not be running (yet).
+    pub fn send(&self, rendered: &str) {
+        let ipc = self.clone();
+        let rendered = rendered.to_owned();
+        Courier::global().queue(move || ipc.deliver(&rendered));
+    }
+
+    fn deliver(&self, rendered: &str) {
+        // the payload ends at the end of the message, so only line breaks would confuse polybar
+        let action = format!("#{}.send.{}", self.module, rendered.replace(['\n', '\r'], " "));
+        let sockets = sockets();
+
+        if sockets.is_empty() {
+            tracing::debug!("no polybar IPC sockets found, falling back to polybar-msg");
+
+            if self.bar.is_some() {
+                tracing::warn!("can't find the bar to send the title to, is polybar running with enable-ipc = true?");
+            } else if let Err(error) = polybar_msg(None, &action) {
+                tracing::warn!("could not send title to polybar: {error:#}");
+            }
+
+            return
+        }
+
+        for socket in sockets {
+            if let Some(bar) = &self.bar {
+                if !runs_bar(socket.pid, bar) {
+                    continue
+                }
+            }
+
+            if let Err(error) = socket.send(&action) {
+                tracing::debug!(pid = socket.pid, "could not write to polybar IPC socket, falling back to polybar-msg: {error:#}");
+
+                if let Err(error) = polybar_msg(Some(socket.pid), &action) {
+                    tracing::warn!(pid = socket.pid, "could not send title to polybar: {error:#}");
+                }
+            }
+        }
+    }
+}
+
+static COURIER: OnceLock<&'static Courier> = OnceLock::new();
+
+/// Sends the titles to polybar on a thread of its own, as every bar can take up to [`IPC_TIMEOUT`] to answer, and the
+/// event loop would be stuck meanwhile. Only the latest title waits while another one is being sent, since anything
+/// older would be replaced right away anyway.
+struct Courier {
+    state: Mutex<CourierState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct CourierState {
+    pending: Option<Box<dyn FnOnce() + Send>>,
+    busy: bool,
+}
+
+impl Courier {
+    fn global() -> &'static Self {
+        COURIER.get_or_init(Self::spawn)
+    }
+
+    fn spawn() -> &'static Self {
+        let courier: &'static Self = Box::leak(Box::new(Self { state: Mutex::default(), changed: Condvar::new() }));
+        thread::spawn(move || courier.run());
+        courier
+    }
+
+    fn queue(&self, delivery: impl FnOnce() + Send + 'static) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if state.pending.replace(Box::new(delivery)).is_some() {
+            tracing::debug!("polybar is still busy with a previous title, dropping the one before");
+        }
+
+        self.changed.notify_all()
+    }
+
+    /// Waits until everything queued has been sent.
+    fn flush(&self) {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        drop(self.changed.wait_while(state, |state| state.busy || state.pending.is_some()));
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            state.busy = false;
+            self.changed.notify_all();
+            state = self.changed.wait_while(state, |state| state.pending.is_none()).unwrap_or_else(PoisonError::into_inner);
+
+            let delivery = state.pending.take().expect("woken up without a pending title");
+            state.busy = true;
+            drop(state);
+            delivery();
+            state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// Waits until the titles sent to polybar in the background actually went out, for when the module is about to exit.
+pub fn flush() {
+    if let Some(courier) = COURIER.get() {
+        courier.flush()
+    }
+}
+
+struct Socket {
+    pid: u32,
+    path: PathBuf,
+
+    /// Whether this is the named pipe of polybar < 3.6 rather than the socket of newer versions.
+    legacy: bool,
+}
+
+impl Socket {
+    fn send(&self, action: &str) -> anyhow::Result<()> {
+        if self.legacy {
+            // a pipe left behind by a bar which died has no reader, and opening it would block until it has one
+            let mut pipe = match fs::OpenOptions::new().write(true).custom_flags(nix::libc::O_NONBLOCK).open(&self.path) {
+                Ok(pipe) => pipe,
+                Err(error) if error.raw_os_error() == Some(nix::libc::ENXIO) => {
+                    tracing::debug!(pid = self.pid, "nothing is reading the polybar IPC pipe, skipping it");
+                    return Ok(())
+                }
+                Err(error) => return Err(error).with_context(|| format!("failed to open {}", self.path.display())),
+            };
+
+            return pipe
+                .write_all(format!("action:{action}\n").as_bytes())
+                .context("failed to write to polybar IPC pipe")
+        }
+
+        let mut stream = UnixStream::connect(&self.path)
+            .with_context(|| format!("failed to connect to {}", self.path.display()))?;
+        stream.set_read_timeout(Some(IPC_TIMEOUT)).context("failed to set socket timeout")?;
+
+        let size = u32::try_from(action.len()).context("title is too long to send")?;
+        let mut message = Vec::with_capacity(13 + action.len());
+        message.extend_from_slice(IPC_MAGIC);
+        message.push(0);
+        message.extend_from_slice(&size.to_ne_bytes());
+        message.push(IPC_ACTION);
+        message.extend_from_slice(action.as_bytes());
+        stream.write_all(&message).context("failed to write to polybar IPC socket")?;
+
+        let mut header = [0; 13];
+        stream.read_exact(&mut header).context("failed to read response from polybar IPC socket")?;
+
+        if &header[..7] != IPC_MAGIC || header[12] != IPC_OK {
+            let size = u32::from_ne_bytes([header[8], header[9], header[10], header[11]]);
+            let mut reason = Vec::new();
+            let _ = stream.take(size.into()).read_to_end(&mut reason);
+            anyhow::bail!("polybar rejected the action: {}", String::from_utf8_lossy(&reason))
+        }
+
+        Ok(())
+    }
+}
+
+fn sockets() -> Vec<Socket> {
+    let uid = nix::unistd::getuid();
+    let directories = env::var_os("XDG_RUNTIME_DIR")
+        .map(|runtime| Path::new(&runtime).join("polybar"))
+        .into_iter()
+        .chain([PathBuf::from(format!("/tmp/polybar-{uid}"))]);
+    let mut sockets = Vec::new();
+
+    for directory in directories {
+        sockets.extend(entries(&directory, "ipc.", ".sock").map(|(pid, path)| Socket { pid, path, legacy: false }));
+
+        // both directories can be used at the same time, and the first one takes precedence in polybar too
+        if !sockets.is_empty() {
+            break
+        }
+    }
+
+    let known = sockets.iter().map(|socket| socket.pid).collect::<Vec<_>>();
+    sockets.extend(
+        entries(Path::new("/tmp"), "polybar_mqueue.", "")
+            .filter(|(pid, _)| !known.contains(pid))
+            .map(|(pid, path)| Socket { pid, path, legacy: true })
+    );
+
+    sockets
+}
+
+fn entries<'a>(directory: &Path, prefix: &'a str, suffix: &'a str) -> impl Iterator<Item = (u32, PathBuf)> + 'a {
+    fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(move |entry| {
+            let name = entry.file_name();
+            let pid = name.to_str()?.strip_prefix(prefix)?.strip_suffix(suffix)?.parse().ok()?;
+            Some((pid, entry.path()))
+        })
+}
+
+fn runs_bar(pid: u32, bar: &str) -> bool {
+    match fs::read(format!("/proc/{pid}/cmdline")) {
+        Ok(cmdline) => cmdline
+            .split(|byte| *byte == 0)
+            .skip(1)
+            .any(|argument| argument == bar.as_bytes()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+        Err(error) => {
+            tracing::debug!(pid, "could not read command line of polybar: {error}");
+            false
+        }
+    }
+}
+
+/// Sends an action by spawning `polybar-msg`, to the process `pid` or every bar if it's `None`. It isn't waited for
+/// here, as it can take a while to time out and the event loop would be stuck meanwhile.
+fn polybar_msg(pid: Option<u32>, action: &str) -> anyhow::Result<()> {
+    let mut command = Command::new("polybar-msg");
+
+    if let Some(pid) = pid {
+        command.arg("-p").arg(pid.to_string());
+    }
+
+    let mut child = command
+        .args([OsStr::new("action"), OsStr::new(action)])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("failed to run polybar-msg")?;
+
+    thread::spawn(move || match child.wait() {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!(?pid, "polybar-msg failed with {status}"),
+        Err(error) => tracing::warn!(?pid, "failed to wait for polybar-msg: {error}"),
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc;
+    use std::time::Instant;
+    use super::*;
+    use crate::application::AppInfo;
+    use crate::testing::TempDir;
+
+    fn nautilus() -> ResolvedWindow {
+        ResolvedWindow {
+            wm_class: "org.gnome.Nautilus".to_owned(),
+            wm_name: "Home <user>".to_owned(),
+            app: Some(AppInfo { id: "files".to_owned(), short_name: None, icon: None, color: None }),
+            ..ResolvedWindow::without_properties(1, "Files".to_owned())
+        }
+    }
+
+    fn waybar_line(window: WmState, styling: bool) -> String {
+        let waybar = Waybar { extra_classes: vec![StateClass::Fullscreen, StateClass::Urgent], tooltip: true };
+        let state = DisplayState { window, ..DisplayState::default() };
+        waybar.line("Files", &nautilus(), &state, styling).unwrap()
+    }
+
+    #[test]
+    fn waybar_lines() {
+        let fullscreen = WmState { fullscreen: true, ..WmState::default() };
+        let everything = WmState { fullscreen: true, maximized: true, urgent: true, skip_taskbar: true };
+
+        assert_eq!(
+            waybar_line(WmState::default(), true),
+            r#"{"text":"Files","alt":"files","tooltip":"Files\nHome &lt;user&gt;","class":["org-gnome-nautilus","files"]}"#,
+        );
+        assert_eq!(
+            waybar_line(fullscreen, true),
+            r#"{"text":"Files","alt":"files","tooltip":"Files\nHome &lt;user&gt;","class":["org-gnome-nautilus","files","fullscreen"]}"#,
+        );
+        // maximized isn't one of the extra classes
+        assert_eq!(
+            waybar_line(everything, true),
+            r#"{"text":"Files","alt":"files","tooltip":"Files\nHome &lt;user&gt;","class":["org-gnome-nautilus","files","fullscreen","urgent"]}"#,
+        );
+        assert_eq!(
+            waybar_line(everything, false),
+            r#"{"text":"Files","alt":"files","tooltip":"Files\nHome &lt;user&gt;","class":[]}"#,
+        );
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("org.gnome.Nautilus"), "org-gnome-nautilus");
+        assert_eq!(slug("Google Chrome"), "google-chrome");
+        assert_eq!(slug("  jetbrains--idea  "), "jetbrains-idea");
+        assert_eq!(slug("2048"), "_2048");
+        assert_eq!(slug("Ünïcödé"), "ünïcödé");
+        assert_eq!(slug("..."), "");
+    }
+
+    #[test]
+    fn legacy_pipe_without_reader_is_skipped() {
+        let path = env::temp_dir().join(format!("polybar_mqueue.test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let status = Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+
+        let socket = Socket { pid: 0, path: path.clone(), legacy: true };
+        let result = socket.send("#title.send.Firefox");
+        fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+    }
+
+    #[test]
+    fn unanswered_socket_does_not_block() {
+        let dir = TempDir::new("ipc");
+        let path = dir.path().join("ipc.0.sock");
+        let _listener = UnixListener::bind(&path).unwrap();
+        let socket = Socket { pid: 0, path, legacy: false };
+        let (sender, receiver) = mpsc::channel();
+        let courier = Courier::spawn();
+
+        let started = Instant::now();
+        courier.queue(move || sender.send(socket.send("#title.send.Firefox")).unwrap());
+        assert!(started.elapsed() < IPC_TIMEOUT / 10);
+
+        let result = receiver.recv_timeout(IPC_TIMEOUT * 5).unwrap();
+        assert!(result.unwrap_err().to_string().contains("failed to read response"));
+        courier.flush();
+    }
+
+    #[test]
+    fn only_the_latest_title_waits() {
+        let (sender, receiver) = mpsc::channel();
+        let courier = Courier::spawn();
+        let (started, busy) = mpsc::channel();
+        let (block, blocked) = mpsc::channel::<()>();
+
+        courier.queue(move || {
+            started.send(()).unwrap();
+            blocked.recv().unwrap()
+        });
+        busy.recv().unwrap();
+
+        for title in ["Firefox", "Files", "Terminal"] {
+            let sender = sender.clone();
+            courier.queue(move || sender.send(title).unwrap());
+        }
+
+        block.send(()).unwrap();
+        courier.flush();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["Terminal"]);
+    }
+}
@@ -0,0 +1,424 @@
+//! Additional outputs (`[[outputs]]`), each rendering the same window with its own template and truncation, e.g. a
+//! terse title for a vertical bar next to the full one.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use crate::app::{registry, render_truncated, DisplayState, RenderContext};
+use crate::output::{self, Waybar};
+use crate::{templating, Config, ResolvedWindow};
+
+const FIFO_RETRY: Duration = Duration::from_millis(100);
+
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ExtraOutput {
+    pub target: Target,
+
+    /// The name of an entry in `templates` to render instead of `template`. The changed template is only used for
+    /// outputs without one.
+    pub template: Option<String>,
+
+    #[serde(default)]
+    pub format: Format,
+    pub max_output_length: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Stdout,
+    Fifo(PathBuf),
+    File(PathBuf),
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Plain,
+    Waybar,
+}
+
+pub struct ExtraOutputs {
+    outputs: Vec<Compiled>,
+}
+
+struct Compiled {
+    config: ExtraOutput,
+    handlebars: Handlebars<'static>,
+    last: Option<String>,
+    fifo: Fifo,
+}
+
+impl ExtraOutputs {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let outputs = config.outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| {
+                let template = match &output.template {
+                    Some(name) => config.templates
+                        .get(name)
+                        .with_context(|| format!("outputs[{index}] refers to template {name:?}, which isn't in templates"))?,
+                    None => &config.template,
+                };
+                let escape = match output.format {
+                    Format::Plain => templating::escape_polybar,
+                    Format::Waybar => handlebars::html_escape,
+                };
+                let handlebars = registry(config, template, escape, output.template.is_none())
+                    .with_context(|| format!("failed to set up templates of outputs[{index}]"))?;
+
+                Ok(Compiled { config: output.clone(), handlebars, last: None, fifo: Fifo::default() })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { outputs })
+    }
+
+    /// Renders and writes the window to every output whose line changed. Outputs which can't be written to are
+    /// logged and skipped, so that one of them failing doesn't stop the others.
+    pub fn write(&mut self, config: &Config, context: &RenderContext, state: &DisplayState, resolved: &ResolvedWindow) {
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            if let Err(error) = output.write(config, context, state, resolved) {
+                tracing::warn!(index, "failed to write to additional output: {error:#}");
+            }
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.outputs.iter().filter_map(|output| output.fifo.retry).min()
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) {
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            let (Target::Fifo(path), Some(retry)) = (&output.config.target, output.fifo.retry) else {
+                continue
+            };
+
+            if now >= retry {
+                if let Err(error) = output.fifo.flush(path, now) {
+                    tracing::warn!(index, "failed to write to additional output: {error:#}");
+                }
+            }
+        }
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = String> + '_ {
+        self.outputs
+            .iter()
+            .flat_map(|output| output.handlebars.get_templates().values())
+            .flat_map(templating::variables)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.outputs.iter().map(|output| output.fifo.dropped).sum()
+    }
+}
+
+impl Compiled {
+    fn write(&mut self, config: &Config, context: &RenderContext, state: &DisplayState, resolved: &ResolvedWindow) -> anyhow::Result<()> {
+        let max_output_length = self.config.max_output_length.or(config.max_output_length);
+        let rendered = render_truncated(&self.handlebars, config, context, state, max_output_length)?;
+        let styling = output::styling(config);
+        let rendered = output::decorate(&rendered, state, styling);
+        let line = match self.config.format {
+            Format::Plain => rendered.into_owned(),
+            Format::Waybar => Waybar::default().line(&rendered, resolved, state, styling)?,
+        };
+
+        if self.last.as_ref() == Some(&line) {
+            return Ok(())
+        }
+
+        match &self.config.target {
+            Target::Stdout => output::print(&line)?,
+            Target::File(path) => fs::write(path, format!("{line}\n")).with_context(|| format!("failed to write {}", path.display()))?,
+            Target::Fifo(path) => {
+                self.fifo.push(&line);
+                self.fifo.flush(path, Instant::now())?;
+            }
+        }
+
+        self.last = Some(line);
+        Ok(())
+    }
+}
+
+/// A fifo which is written to without ever blocking, so that a stalled reader can't freeze the module.
+///
+/// Only the latest line is kept while the reader can't keep up, as outdated titles aren't worth showing: when it
+/// catches up, it gets the current title right away rather than a backlog.
+#[derive(Default)]
+struct Fifo {
+    file: Option<File>,
+
+    /// What's left of a line the reader only took part of, which has to be written before anything else to keep
+    /// lines intact.
+    partial: Vec<u8>,
+    pending: Option<String>,
+    retry: Option<Instant>,
+    dropped: u64,
+    unreported: u64,
+    warned_at: Option<Instant>,
+}
+
+impl Fifo {
+    fn push(&mut self, line: &str) {
+        if self.pending.replace(format!("{line}\n")).is_some() {
+            self.dropped += 1;
+            self.unreported += 1;
+        }
+    }
+
+    fn flush(&mut self, path: &Path, now: Instant) -> anyhow::Result<()> {
+        self.retry = None;
+
+        if self.file.is_none() {
+            // without a reader, opening fails right away instead of blocking
+            match OpenOptions::new().write(true).custom_flags(nix::libc::O_NONBLOCK).open(path) {
+                Ok(file) => self.file = Some(file),
+                Err(error) if error.raw_os_error() == Some(nix::libc::ENXIO) => {
+                    tracing::debug!(path = %path.display(), "nothing is reading the fifo, skipping it");
+                    self.pending = None;
+                    return Ok(())
+                }
+                Err(error) => return Err(error).with_context(|| format!("failed to open {}", path.display())),
+            }
+        }
+
+        loop {
+            if self.partial.is_empty() {
+                match self.pending.take() {
+                    Some(line) => self.partial = line.into_bytes(),
+                    None => return Ok(()),
+                }
+            }
+
+            let file = self.file.as_mut().expect("fifo was opened above");
+
+            match file.write(&self.partial) {
+                Ok(written) => {
+                    self.partial.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    self.retry = Some(now + FIFO_RETRY);
+                    self.warn_about_drops(path, now);
+                    return Ok(())
+                }
+                Err(error) if error.kind() == ErrorKind::BrokenPipe => {
+                    tracing::debug!(path = %path.display(), "the reader of the fifo went away");
+                    self.file = None;
+                    self.partial.clear();
+                    self.pending = None;
+                    return Ok(())
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error).with_context(|| format!("failed to write to {}", path.display())),
+            }
+        }
+    }
+
+    fn warn_about_drops(&mut self, path: &Path, now: Instant) {
+        if self.unreported == 0 || self.warned_at.is_some_and(|warned_at| now < warned_at + DROP_WARNING_INTERVAL) {
+            return
+        }
+
+        tracing::warn!(path = %path.display(), dropped = self.unreported, "the reader of the fifo is falling behind, dropped outdated lines");
+        self.unreported = 0;
+        self.warned_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn context(config: &Config, resolved: &ResolvedWindow) -> RenderContext {
+        let mut context = RenderContext::default();
+        context.update(resolved, &DisplayState::default(), &config.resolver);
+        context
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn outputs_are_rendered_and_truncated_independently() {
+        let dir = TempDir::new("outputs");
+        let full = dir.path().join("full");
+        let terse = dir.path().join("terse");
+        let config = Config {
+            template: "{{ name }}".to_owned(),
+            templates: BTreeMap::from([("terse".to_owned(), "{{ wm_class }}: {{ name }}".to_owned())]),
+            styling: false,
+            max_output_length: Some(40),
+            outputs: vec![
+                ExtraOutput { target: Target::File(full.clone()), template: None, format: Format::Plain, max_output_length: None },
+                ExtraOutput { target: Target::File(terse.clone()), template: Some("terse".to_owned()), format: Format::Waybar, max_output_length: Some(12) },
+            ],
+            ..Config::default()
+        };
+        let mut outputs = ExtraOutputs::new(&config).unwrap();
+        let resolved = ResolvedWindow {
+            wm_class: "firefox".to_owned(),
+            ..ResolvedWindow::without_properties(1, "Rust Programming Language - Mozilla Firefox".to_owned())
+        };
+
+        outputs.write(&config, &context(&config, &resolved), &DisplayState::default(), &resolved);
+
+        assert_eq!(read(&full), "Rust Programming Language - Mozilla Fire\n");
+        let terse_line = serde_json::from_str::<serde_json::Value>(read(&terse).trim_end()).unwrap();
+        assert_eq!(terse_line["text"], "firefox: Rus");
+    }
+
+    #[test]
+    fn outputs_are_deduplicated_independently() {
+        let dir = TempDir::new("outputs-dedup");
+        let name = dir.path().join("name");
+        let class = dir.path().join("class");
+        let config = Config {
+            template: "{{ name }}".to_owned(),
+            templates: BTreeMap::from([("class".to_owned(), "{{ wm_class }}".to_owned())]),
+            styling: false,
+            outputs: vec![
+                ExtraOutput { target: Target::File(name.clone()), template: None, format: Format::Plain, max_output_length: None },
+                ExtraOutput { target: Target::File(class.clone()), template: Some("class".to_owned()), format: Format::Plain, max_output_length: None },
+            ],
+            ..Config::default()
+        };
+        let mut outputs = ExtraOutputs::new(&config).unwrap();
+        let mut write = |title: &str| {
+            let resolved = ResolvedWindow { wm_class: "firefox".to_owned(), ..ResolvedWindow::without_properties(1, title.to_owned()) };
+            outputs.write(&config, &context(&config, &resolved), &DisplayState::default(), &resolved);
+        };
+
+        write("GitHub");
+        fs::remove_file(&class).unwrap();
+        write("YouTube");
+
+        // the unchanged class isn't written again, but doesn't hold back the changed name either
+        assert_eq!(read(&name), "YouTube\n");
+        assert!(!class.exists());
+    }
+
+    #[test]
+    fn unknown_template_is_an_error() {
+        let config = Config {
+            outputs: vec![ExtraOutput { target: Target::Stdout, template: Some("missing".to_owned()), format: Format::Plain, max_output_length: None }],
+            ..Config::default()
+        };
+
+        let error = ExtraOutputs::new(&config).err().unwrap();
+
+        assert!(error.to_string().contains("outputs[0] refers to template \"missing\""), "{error:#}");
+    }
+
+    fn fifo(dir: &TempDir) -> (PathBuf, File) {
+        let path = dir.path().join("fifo");
+        assert!(std::process::Command::new("mkfifo").arg(&path).status().unwrap().success());
+        let reader = OpenOptions::new().read(true).custom_flags(nix::libc::O_NONBLOCK).open(&path).unwrap();
+
+        (path, reader)
+    }
+
+    fn drain(reader: &mut File) -> String {
+        let mut read = Vec::new();
+        let mut buffer = [0; 4096];
+
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => read.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => panic!("failed to read fifo: {error}"),
+            }
+        }
+
+        String::from_utf8(read).unwrap()
+    }
+
+    fn line(index: usize) -> String {
+        format!("{index:05} {}", "x".repeat(1000))
+    }
+
+    #[test]
+    fn slow_fifo_reader_gets_only_the_latest_line() {
+        let dir = TempDir::new("outputs-fifo");
+        let (path, mut reader) = fifo(&dir);
+        let start = Instant::now();
+        let mut fifo = Fifo::default();
+
+        // the reader stalls until the pipe is full
+        let mut index = 0;
+        while fifo.retry.is_none() {
+            fifo.push(&line(index));
+            fifo.flush(&path, start).unwrap();
+            index += 1;
+        }
+        let blocked = index;
+        for _ in 0..50 {
+            fifo.push(&line(index));
+            fifo.flush(&path, start).unwrap();
+            index += 1;
+        }
+
+        assert_eq!(fifo.retry, Some(start + FIFO_RETRY));
+        assert_eq!(fifo.dropped, 49);
+
+        // then catches up
+        let mut read = drain(&mut reader);
+        while fifo.retry.is_some() {
+            fifo.flush(&path, start + FIFO_RETRY).unwrap();
+            read += &drain(&mut reader);
+        }
+
+        let lines = read.lines().collect::<Vec<_>>();
+        let expected = (0..blocked).chain([index - 1]).map(line).collect::<Vec<_>>();
+        assert_eq!(lines.len(), expected.len());
+        assert!(lines.iter().zip(&expected).all(|(line, expected)| line == expected), "lines were mangled or reordered");
+        assert_eq!(fifo.pending, None);
+        assert!(fifo.partial.is_empty());
+    }
+
+    #[test]
+    fn fifo_without_reader_is_skipped() {
+        let dir = TempDir::new("outputs-fifo-none");
+        let (path, reader) = fifo(&dir);
+        drop(reader);
+        let mut fifo = Fifo::default();
+
+        fifo.push("Firefox");
+        fifo.flush(&path, Instant::now()).unwrap();
+
+        assert!(fifo.file.is_none());
+        assert_eq!((fifo.pending, fifo.retry, fifo.dropped), (None, None, 0));
+    }
+
+    #[test]
+    fn fifo_reader_going_away() {
+        let dir = TempDir::new("outputs-fifo-gone");
+        let (path, reader) = fifo(&dir);
+        let mut fifo = Fifo::default();
+
+        fifo.push("Firefox");
+        fifo.flush(&path, Instant::now()).unwrap();
+        drop(reader);
+        fifo.push("Alacritty");
+        fifo.flush(&path, Instant::now()).unwrap();
+
+        assert!(fifo.file.is_none());
+        assert_eq!(fifo.pending, None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::{self, Formatter};
+use std::str::FromStr;
+use regex::Regex;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// A regular expression in the config, compiled when the config is read so that an invalid one is reported right
+/// away rather than when it's first matched.
+#[derive(DeserializeFromStr, SerializeDisplay, Clone, Debug)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Matches the whole value against `glob`, in which `*` stands for any number of characters and `?` for exactly
+    /// one. Every other character is taken literally.
+    pub fn wildcard(glob: &str) -> Self {
+        let mut regex = String::from("(?s)^");
+        let mut literal = String::new();
+
+        for c in glob.chars() {
+            let wildcard = match c {
+                '*' => ".*",
+                '?' => ".",
+                _ => {
+                    literal.push(c);
+                    continue
+                }
+            };
+
+            regex.push_str(&regex::escape(&literal));
+            regex.push_str(wildcard);
+            literal.clear();
+        }
+
+        regex.push_str(&regex::escape(&literal));
+        regex.push('$');
+        Self(Regex::new(&regex).expect("a glob with its literals escaped is a valid regex"))
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Regex::new(s).map(Self)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, value: &str) -> bool {
+        Pattern::wildcard(glob).is_match(value)
+    }
+
+    #[test]
+    fn star_matches_any_number_of_characters() {
+        assert!(matches("gimp*", "gimp"));
+        assert!(matches("gimp*", "gimp-2.10"));
+        assert!(matches("* - mpv", "video.mkv - mpv"));
+        assert!(matches("*", ""));
+        assert!(matches("*", "line\nbreak"));
+        assert!(!matches("gimp*", "Gimp"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("term?", "term1"));
+        assert!(!matches("term?", "term"));
+        assert!(!matches("term?", "term12"));
+        assert!(matches("??", "\n\t"));
+    }
+
+    #[test]
+    fn whole_value_has_to_match() {
+        assert!(!matches("fire", "firefox"));
+        assert!(!matches("fox", "firefox"));
+        assert!(!matches("*fox", "firefox - nightly"));
+        assert!(matches("*fox*", "firefox - nightly"));
+    }
+
+    #[test]
+    fn everything_else_is_literal() {
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+        assert!(matches("(1+1)^2 [$]|{x}", "(1+1)^2 [$]|{x}"));
+        assert!(matches(r"C:\*", r"C:\Users"));
+        assert!(!matches(r"\*", "*x"));
+        // a wildcard matches the literal character as well
+        assert!(matches("*", "*"));
+        assert!(matches("why?", "why?"));
+    }
+
+    #[test]
+    fn wildcards_match_characters_not_bytes() {
+        assert!(matches("caf?", "café"));
+        assert!(matches("?", "🦀"));
+        assert!(!matches("?", "e\u{301}"));
+        assert!(matches("Ünï*", "Ünïcödé"));
+        assert!(!matches("ünï*", "Ünïcödé"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Overrides made at runtime through the control socket (renames and pausing), persisted with `persist_state` so
+//! that they survive the module being restarted, e.g. by polybar on every monitor hotplug. The usage statistics are
+//! kept in the same file with `usage_tracking`.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::Context;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use crate::usage::ClassUsage;
+use crate::ResolvedWindow;
+
+/// How many characters of the title go into a fingerprint. Titles tend to change at their end (the document, the
+/// page), so a prefix still matches after a change like that.
+const TITLE_PREFIX_CHARS: usize = 16;
+
+/// Identifies a window across restarts, as its id changes whenever it's recreated.
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq, Debug)]
+pub struct Fingerprint {
+    pub class: String,
+    pub instance: String,
+    pub title_prefix: String,
+}
+
+impl Fingerprint {
+    pub fn of(resolved: &ResolvedWindow) -> Self {
+        Self {
+            class: resolved.wm_class.clone(),
+            instance: resolved.instance.clone(),
+            title_prefix: resolved.wm_name.chars().take(TITLE_PREFIX_CHARS).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PersistedRename {
+    #[serde(flatten)]
+    pub fingerprint: Fingerprint,
+    pub name: String,
+    pub saved_at: u64,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct State {
+    #[serde(default)]
+    pub paused: bool,
+
+    #[serde(default)]
+    pub renames: Vec<PersistedRename>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<ClassUsage>,
+}
+
+#[derive(Debug)]
+pub struct PersistedState {
+    path: PathBuf,
+    state: State,
+}
+
+impl PersistedState {
+    pub fn path() -> Option<PathBuf> {
+        ProjectDirs::from("", "ALinuxPerson", "polybar-title-module")
+            .and_then(|dirs| dirs.state_dir().map(|dir| dir.join("state.json")))
+    }
+
+    /// Loads the state file, leaving out renames older than `max_age`. A state file which is missing or can't be
+    /// read starts out empty; the latter is warned about.
+    pub fn load(path: PathBuf, max_age: Duration) -> Self {
+        let state = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|error| tracing::warn!(path = %path.display(), "ignoring corrupt state file: {error}"))
+                .unwrap_or_default(),
+            Err(error) if error.kind() == ErrorKind::NotFound => State::default(),
+            Err(error) => {
+                tracing::warn!(path = %path.display(), "ignoring state file which can't be read: {error}");
+                State::default()
+            }
+        };
+
+        let mut persisted = Self { path, state };
+        let oldest = now().saturating_sub(max_age.as_secs());
+        persisted.state.renames.retain(|rename| rename.saved_at >= oldest);
+        tracing::debug!(renames = persisted.state.renames.len(), paused = persisted.state.paused, "loaded state file");
+
+        persisted
+    }
+
+    pub fn paused(&self) -> bool {
+        self.state.paused
+    }
+
+    pub fn rename(&self, fingerprint: &Fingerprint) -> Option<&str> {
+        self.state.renames
+            .iter()
+            .find(|rename| rename.fingerprint == *fingerprint)
+            .map(|rename| rename.name.as_str())
+    }
+
+    pub fn usage(&self) -> &[ClassUsage] {
+        &self.state.usage
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.state.paused = paused;
+        self.save();
+    }
+
+    pub fn set_rename(&mut self, fingerprint: Fingerprint, name: Option<String>) {
+        self.state.renames.retain(|rename| rename.fingerprint != fingerprint);
+
+        if let Some(name) = name {
+            self.state.renames.push(PersistedRename { fingerprint, name, saved_at: now() });
+        }
+
+        self.save();
+    }
+
+    pub fn set_usage(&mut self, usage: Vec<ClassUsage>) {
+        self.state.usage = usage;
+        self.save();
+    }
+
+    /// Writes the state file, replacing it at once so that it's never left half written. Failing to is only warned
+    /// about, as the overrides still apply until the module exits.
+    fn save(&self) {
+        let save = || -> anyhow::Result<()> {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+            }
+
+            let contents = serde_json::to_string_pretty(&self.state).context("failed to serialize state")?;
+            let temporary = self.path.with_extension("json.tmp");
+            fs::write(&temporary, contents).with_context(|| format!("failed to write {}", temporary.display()))?;
+            fs::rename(&temporary, &self.path).with_context(|| format!("failed to replace {}", self.path.display()))
+        };
+
+        if let Err(error) = save() {
+            tracing::warn!("could not persist state: {error:#}");
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn window(window: u32, class: &str, title: &str) -> ResolvedWindow {
+        ResolvedWindow {
+            wm_class: class.to_owned(),
+            instance: class.to_lowercase(),
+            wm_name: title.to_owned(),
+            ..ResolvedWindow::without_properties(window, title.to_owned())
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new("persist");
+        let path = dir.path().join("state/state.json");
+        let firefox = Fingerprint::of(&window(1, "Firefox", "GitHub"));
+        let terminal = Fingerprint::of(&window(2, "Alacritty", "vim"));
+
+        let mut state = PersistedState::load(path.clone(), DAY);
+        state.set_paused(true);
+        state.set_rename(firefox.clone(), Some("Work".to_owned()));
+        state.set_rename(terminal.clone(), Some("Editor".to_owned()));
+        state.set_rename(terminal.clone(), None);
+        state.set_usage(vec![ClassUsage { class: "Firefox".to_owned(), total_seconds: 60, sessions: 2 }]);
+
+        let loaded = PersistedState::load(path.clone(), DAY);
+        assert!(loaded.paused());
+        assert_eq!(loaded.rename(&firefox), Some("Work"));
+        assert_eq!(loaded.rename(&terminal), None);
+        assert_eq!(loaded.usage().len(), 1);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn fingerprint_matches_a_recreated_window() {
+        let dir = TempDir::new("persist-fingerprint");
+        let path = dir.path().join("state.json");
+        let mut state = PersistedState::load(path.clone(), DAY);
+        state.set_rename(Fingerprint::of(&window(0x1400003, "Alacritty", "vim ~/notes/todo.md")), Some("Notes".to_owned()));
+
+        let loaded = PersistedState::load(path, DAY);
+
+        // the id changes and the title changes past its prefix
+        assert_eq!(loaded.rename(&Fingerprint::of(&window(0x2600007, "Alacritty", "vim ~/notes/todo.md [+]"))), Some("Notes"));
+        assert_eq!(loaded.rename(&Fingerprint::of(&window(0x1400003, "Alacritty", "htop"))), None);
+        assert_eq!(loaded.rename(&Fingerprint::of(&window(0x1400003, "kitty", "vim ~/notes/todo.md"))), None);
+    }
+
+    #[test]
+    fn old_renames_expire() {
+        let dir = TempDir::new("persist-expire");
+        let old = now() - 2 * DAY.as_secs();
+        let path = dir.write(
+            "state.json",
+            format!(r#"{{"renames": [{{"class": "a", "instance": "a", "title_prefix": "a", "name": "old", "saved_at": {old}}}]}}"#),
+        );
+        let fingerprint = Fingerprint { class: "a".to_owned(), instance: "a".to_owned(), title_prefix: "a".to_owned() };
+
+        assert_eq!(PersistedState::load(path.clone(), 3 * DAY).rename(&fingerprint), Some("old"));
+        assert_eq!(PersistedState::load(path, DAY).rename(&fingerprint), None);
+    }
+
+    #[test]
+    fn corrupt_state_is_ignored() {
+        let dir = TempDir::new("persist-corrupt");
+        let path = dir.write("state.json", "{\"paused\": tru");
+
+        let mut state = PersistedState::load(path.clone(), DAY);
+        assert!(!state.paused());
+
+        // and replaced with the next change
+        state.set_paused(true);
+        assert!(PersistedState::load(path, DAY).paused());
+    }
+}
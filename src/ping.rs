@@ -0,0 +1,256 @@
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::intern_atom;
+
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+const PONG_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Detects whether the focused window stopped responding by periodically sending it `_NET_WM_PING` messages.
+///
+/// Only windows which list `_NET_WM_PING` in their `WM_PROTOCOLS` are pinged, so windows which don't support the
+/// protocol are never considered stale.
+pub struct PingTracker {
+    root: Window,
+    wm_protocols: Atom,
+    net_wm_ping: Atom,
+    window: Option<Window>,
+    serial: u32,
+    next_ping: Instant,
+    awaiting_pong_since: Option<Instant>,
+    stale: bool,
+}
+
+impl PingTracker {
+    pub fn new(connection: &RustConnection, root: Window) -> anyhow::Result<Self> {
+        Ok(Self {
+            root,
+            wm_protocols: intern_atom(connection, "WM_PROTOCOLS")?,
+            net_wm_ping: intern_atom(connection, "_NET_WM_PING")?,
+            window: None,
+            serial: 0,
+            next_ping: Instant::now(),
+            awaiting_pong_since: None,
+            stale: false,
+        })
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn focus(&mut self, connection: &impl RequestConnection, window: Window) -> anyhow::Result<()> {
+        self.stale = false;
+        self.awaiting_pong_since = None;
+        self.next_ping = Instant::now() + PING_INTERVAL;
+        self.window = None;
+
+        if window == 0 {
+            return Ok(())
+        }
+
+        let protocols = connection
+            .get_property(false, window, self.wm_protocols, AtomEnum::ATOM, 0, 64)
+            .context("failed to make GetProperty reply for retrieving WM_PROTOCOLS")?
+            .reply()
+            .context("GetProperty response for retrieving WM_PROTOCOLS failed")?;
+        let supports_ping = protocols
+            .value32()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == self.net_wm_ping));
+
+        if supports_ping {
+            self.window = Some(window)
+        } else {
+            tracing::debug!(%window, "window does not support _NET_WM_PING");
+        }
+
+        Ok(())
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.window?;
+
+        Some(match self.awaiting_pong_since {
+            Some(since) if !self.stale => since + PONG_TIMEOUT,
+            _ => self.next_ping,
+        })
+    }
+
+    /// Sends the next ping or notices that the last one wasn't answered. Returns whether the stale state changed.
+    pub fn on_deadline(&mut self, connection: &impl Connection, now: Instant) -> anyhow::Result<bool> {
+        let Some(window) = self.window else { return Ok(false) };
+
+        if let Some(since) = self.awaiting_pong_since {
+            if !self.stale && now >= since + PONG_TIMEOUT {
+                tracing::debug!(%window, "window did not answer ping in time, marking as stale");
+                self.stale = true;
+                return Ok(true)
+            }
+        }
+
+        if now >= self.next_ping {
+            self.serial = self.serial.wrapping_add(1);
+            self.next_ping = now + PING_INTERVAL;
+            self.awaiting_pong_since.get_or_insert(now);
+
+            tracing::debug!(%window, serial = %self.serial, "sending ping");
+            let event = ClientMessageEvent::new(32, window, self.wm_protocols, [self.net_wm_ping, self.serial, window, 0, 0]);
+            connection
+                .send_event(false, window, EventMask::NO_EVENT, event)
+                .context("failed to send _NET_WM_PING")?;
+            connection.flush().context("failed to flush connection")?;
+        }
+
+        Ok(false)
+    }
+
+    /// Handles a pong sent back by a client. Returns whether the stale state changed.
+    pub fn on_client_message(&mut self, event: &ClientMessageEvent) -> bool {
+        let [protocol, _, window, ..] = event.data.as_data32();
+
+        if event.window != self.root || event.type_ != self.wm_protocols || protocol != self.net_wm_ping || Some(window) != self.window {
+            return false
+        }
+
+        tracing::debug!(%window, "received pong");
+        self.awaiting_pong_since = None;
+        std::mem::replace(&mut self.stale, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{u32_at, Logged, MockConnection};
+
+    const ROOT: Window = 1;
+    const WM_PROTOCOLS: Atom = 100;
+    const NET_WM_PING: Atom = 101;
+    const WINDOW: Window = 10;
+
+    fn tracker() -> PingTracker {
+        PingTracker {
+            root: ROOT,
+            wm_protocols: WM_PROTOCOLS,
+            net_wm_ping: NET_WM_PING,
+            window: None,
+            serial: 0,
+            next_ping: Instant::now(),
+            awaiting_pong_since: None,
+            stale: false,
+        }
+    }
+
+    /// The reply to reading `WM_PROTOCOLS`.
+    fn protocols(protocols: &[Atom]) -> Vec<u8> {
+        let mut reply = vec![1, 32, 0, 0];
+        reply.extend_from_slice(&(protocols.len() as u32).to_ne_bytes());
+        reply.extend_from_slice(&u32::from(AtomEnum::ATOM).to_ne_bytes());
+        reply.extend_from_slice(&0u32.to_ne_bytes());
+        reply.extend_from_slice(&(protocols.len() as u32).to_ne_bytes());
+        reply.resize(32, 0);
+        reply.extend(protocols.iter().flat_map(|protocol| protocol.to_ne_bytes()));
+        reply
+    }
+
+    fn focused(supports_ping: bool) -> (PingTracker, MockConnection, Instant) {
+        let connection = MockConnection::default();
+        let mut tracker = tracker();
+        connection.reply(protocols(if supports_ping { &[1, NET_WM_PING] } else { &[1] }));
+        tracker.focus(&connection, WINDOW).unwrap();
+        connection.take();
+
+        let now = Instant::now();
+        tracker.next_ping = now + PING_INTERVAL;
+        (tracker, connection, now)
+    }
+
+    /// The serials of the pings sent through `connection`, which are 28 bytes into the SendEvent requests.
+    fn pings(connection: &MockConnection) -> Vec<u32> {
+        connection.take().iter().filter_map(|logged| match logged {
+            Logged::Sent(request) => Some(u32_at(request, 28)),
+            Logged::Answered => None,
+        }).collect()
+    }
+
+    fn pong(serial: u32, window: Window) -> ClientMessageEvent {
+        ClientMessageEvent::new(32, ROOT, WM_PROTOCOLS, [NET_WM_PING, serial, window, 0, 0])
+    }
+
+    #[test]
+    fn windows_without_ping_support_are_never_pinged() {
+        let (mut tracker, connection, now) = focused(false);
+
+        assert_eq!(tracker.deadline(), None);
+        assert!(!tracker.on_deadline(&connection, now + PING_INTERVAL * 10).unwrap());
+        assert!(pings(&connection).is_empty());
+        assert!(!tracker.is_stale());
+    }
+
+    #[test]
+    fn unanswered_ping_times_out() {
+        let (mut tracker, connection, now) = focused(true);
+        assert_eq!(tracker.deadline(), Some(now + PING_INTERVAL));
+
+        let sent = now + PING_INTERVAL;
+        assert!(!tracker.on_deadline(&connection, sent).unwrap());
+        assert_eq!(pings(&connection), [1]);
+        assert_eq!(tracker.deadline(), Some(sent + PONG_TIMEOUT));
+
+        assert!(!tracker.on_deadline(&connection, sent + PONG_TIMEOUT / 2).unwrap());
+        assert!(!tracker.is_stale());
+
+        assert!(tracker.on_deadline(&connection, sent + PONG_TIMEOUT).unwrap());
+        assert!(tracker.is_stale());
+        // a stale window keeps being pinged at the usual interval, to notice when it answers again
+        assert_eq!(tracker.deadline(), Some(sent + PING_INTERVAL));
+        assert!(pings(&connection).is_empty());
+    }
+
+    #[test]
+    fn pong_clears_stale() {
+        let (mut tracker, connection, now) = focused(true);
+        let sent = now + PING_INTERVAL;
+        tracker.on_deadline(&connection, sent).unwrap();
+        tracker.on_deadline(&connection, sent + PONG_TIMEOUT).unwrap();
+
+        assert!(!tracker.on_client_message(&pong(1, WINDOW + 1)));
+        assert!(tracker.is_stale());
+        assert!(tracker.on_client_message(&pong(1, WINDOW)));
+        assert!(!tracker.is_stale());
+        assert_eq!(tracker.deadline(), Some(sent + PING_INTERVAL));
+        assert!(!tracker.on_client_message(&pong(1, WINDOW)));
+    }
+
+    #[test]
+    fn answered_pings_keep_the_interval() {
+        let (mut tracker, connection, now) = focused(true);
+        let mut sent = now;
+
+        for _ in 0..3 {
+            sent += PING_INTERVAL;
+            tracker.on_deadline(&connection, sent).unwrap();
+            tracker.on_client_message(&pong(tracker.serial, WINDOW));
+            assert_eq!(tracker.deadline(), Some(sent + PING_INTERVAL));
+        }
+
+        assert_eq!(pings(&connection), [1, 2, 3]);
+        assert!(!tracker.is_stale());
+    }
+
+    #[test]
+    fn focusing_another_window_starts_over() {
+        let (mut tracker, connection, now) = focused(true);
+        tracker.on_deadline(&connection, now + PING_INTERVAL).unwrap();
+        tracker.on_deadline(&connection, now + PING_INTERVAL + PONG_TIMEOUT).unwrap();
+        assert!(tracker.is_stale());
+
+        tracker.focus(&connection, 0).unwrap();
+
+        assert!(!tracker.is_stale());
+        assert_eq!(tracker.deadline(), None);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::{intern_atom, is_window_gone};
+
+const MAX_CLIENT_DEPTH: usize = 3;
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Track {
+    #[default]
+    Focus,
+    Pointer,
+}
+
+/// Follows the window under the pointer by querying it periodically.
+///
+/// A window only gets displayed once the pointer stayed over it for two consecutive polls, so that quickly moving
+/// the pointer across several windows doesn't resolve every one of them.
+pub struct PointerTracker {
+    root: Window,
+    wm_state: Atom,
+    interval: Duration,
+    next_poll: Instant,
+    candidate: Option<Window>,
+}
+
+impl PointerTracker {
+    pub fn new(connection: &RustConnection, root: Window, interval: Duration) -> anyhow::Result<Self> {
+        Ok(Self {
+            root,
+            wm_state: intern_atom(connection, "WM_STATE")?,
+            interval,
+            next_poll: Instant::now(),
+            candidate: None,
+        })
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.next_poll
+    }
+
+    /// Polls the pointer if it's due. Returns the client window which should be displayed, if it differs from
+    /// `displayed`.
+    pub fn on_deadline(&mut self, connection: &impl RequestConnection, now: Instant, displayed: Option<Window>) -> anyhow::Result<Option<Window>> {
+        if now < self.next_poll {
+            return Ok(None)
+        }
+
+        self.next_poll = now + self.interval;
+
+        let top_level = connection
+            .query_pointer(self.root)
+            .context("failed to make QueryPointer reply")?
+            .reply()
+            .context("QueryPointer response failed")?
+            .child;
+        let window = if top_level == 0 {
+            0
+        } else {
+            match client_window(connection, self.wm_state, top_level) {
+                Ok(window) => window,
+                Err(error) if is_window_gone(&error) => {
+                    // the window under the pointer was destroyed, whatever is under it now is seen with the next poll
+                    tracing::debug!(%top_level, "window under pointer is gone already");
+                    return Ok(None)
+                }
+                Err(error) => return Err(error.context("failed to find client window under pointer")),
+            }
+        };
+
+        if Some(window) == displayed {
+            self.candidate = None;
+            return Ok(None)
+        }
+
+        if self.candidate == Some(window) {
+            tracing::debug!(%window, "pointer settled on window");
+            self.candidate = None;
+            Ok(Some(window))
+        } else {
+            self.candidate = Some(window);
+            Ok(None)
+        }
+    }
+}
+
+/// Translates a (possibly reparented) top level window into the client window carrying the actual properties,
+/// which is the first window at or below it with a `WM_STATE` property. Falls back to the window itself.
+pub fn client_window(connection: &impl RequestConnection, wm_state: Atom, window: Window) -> anyhow::Result<Window> {
+    let mut queue = VecDeque::from([(window, 0)]);
+
+    while let Some((current, depth)) = queue.pop_front() {
+        let has_wm_state = connection
+            .get_property(false, current, wm_state, AtomEnum::ANY, 0, 0)
+            .context("failed to make GetProperty reply for retrieving WM_STATE")?
+            .reply()
+            .context("GetProperty response for retrieving WM_STATE failed")?
+            .type_ != u32::from(AtomEnum::NONE);
+
+        if has_wm_state {
+            return Ok(current)
+        }
+
+        if depth < MAX_CLIENT_DEPTH {
+            let children = connection
+                .query_tree(current)
+                .context("failed to make QueryTree reply")?
+                .reply()
+                .context("QueryTree response failed")?
+                .children;
+            queue.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+    }
+
+    Ok(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{u32_at, Logged, MockConnection};
+
+    const ROOT: Window = 1;
+    const WM_STATE: Atom = 100;
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn tracker(now: Instant) -> PointerTracker {
+        PointerTracker { root: ROOT, wm_state: WM_STATE, interval: INTERVAL, next_poll: now, candidate: None }
+    }
+
+    fn query_pointer(child: Window) -> Vec<u8> {
+        let mut reply = vec![1, 1, 0, 0, 0, 0, 0, 0];
+        reply.extend_from_slice(&ROOT.to_ne_bytes());
+        reply.extend_from_slice(&child.to_ne_bytes());
+        reply.resize(32, 0);
+        reply
+    }
+
+    /// The reply to reading no bytes of `WM_STATE`, which tells whether the window has it by its type.
+    fn wm_state(has: bool) -> Vec<u8> {
+        let mut reply = vec![1, 8, 0, 0, 0, 0, 0, 0];
+        reply.extend_from_slice(&(if has { WM_STATE } else { u32::from(AtomEnum::NONE) }).to_ne_bytes());
+        reply.resize(32, 0);
+        reply
+    }
+
+    /// Queues the replies for the pointer being over the client window `window`, which isn't reparented.
+    fn over(connection: &MockConnection, window: Window) {
+        connection.reply(query_pointer(window));
+        connection.reply(wm_state(true));
+    }
+
+    fn requests(connection: &MockConnection) -> usize {
+        connection.take().iter().filter(|logged| matches!(logged, Logged::Sent(_))).count()
+    }
+
+    #[test]
+    fn polls_once_per_interval() {
+        let start = Instant::now();
+        let connection = MockConnection::default();
+        let mut tracker = tracker(start);
+        assert_eq!(tracker.deadline(), start);
+
+        over(&connection, 10);
+        assert_eq!(tracker.on_deadline(&connection, start, None).unwrap(), None);
+        assert_eq!(tracker.deadline(), start + INTERVAL);
+        assert_eq!(requests(&connection), 2);
+
+        assert_eq!(tracker.on_deadline(&connection, start + INTERVAL / 2, None).unwrap(), None);
+        assert_eq!(requests(&connection), 0);
+
+        // a late poll schedules the next one from when it happened
+        let late = start + INTERVAL * 3;
+        over(&connection, 10);
+        assert_eq!(tracker.on_deadline(&connection, late, None).unwrap(), Some(10));
+        assert_eq!(tracker.deadline(), late + INTERVAL);
+    }
+
+    #[test]
+    fn window_is_displayed_once_the_pointer_stays() {
+        let start = Instant::now();
+        let connection = MockConnection::default();
+        let mut tracker = tracker(start);
+        let mut poll = |window: Window, polls: u32, displayed: Option<Window>| {
+            over(&connection, window);
+            tracker.on_deadline(&connection, start + INTERVAL * polls, displayed).unwrap()
+        };
+
+        assert_eq!(poll(10, 0, None), None);
+        assert_eq!(poll(11, 1, None), None);
+        assert_eq!(poll(11, 2, None), Some(11));
+        // the displayed window isn't displayed again, and moving back to it in between resets the candidate
+        assert_eq!(poll(11, 3, Some(11)), None);
+        assert_eq!(poll(12, 4, Some(11)), None);
+        assert_eq!(poll(11, 5, Some(11)), None);
+        assert_eq!(poll(12, 6, Some(11)), None);
+        assert_eq!(poll(12, 7, Some(11)), Some(12));
+    }
+
+    #[test]
+    fn client_window_is_found_below_the_frame() {
+        let connection = MockConnection::default();
+        let mut tracker = tracker(Instant::now());
+        let mut query_tree = vec![1, 0, 0, 0, 1, 0, 0, 0];
+        query_tree.extend_from_slice(&ROOT.to_ne_bytes());
+        query_tree.extend_from_slice(&ROOT.to_ne_bytes());
+        query_tree.extend_from_slice(&1u16.to_ne_bytes());
+        query_tree.resize(32, 0);
+        query_tree.extend_from_slice(&11u32.to_ne_bytes());
+
+        for _ in 0..2 {
+            connection.reply(query_pointer(10));
+            connection.reply(wm_state(false));
+            connection.reply(query_tree.clone());
+            connection.reply(wm_state(true));
+        }
+
+        tracker.on_deadline(&connection, tracker.deadline(), None).unwrap();
+        assert_eq!(tracker.on_deadline(&connection, tracker.deadline(), None).unwrap(), Some(11));
+    }
+
+    #[test]
+    fn gone_window_is_skipped() {
+        let connection = MockConnection::with_gone([10]);
+        let mut tracker = tracker(Instant::now());
+        connection.reply(query_pointer(10));
+
+        assert_eq!(tracker.on_deadline(&connection, tracker.deadline(), None).unwrap(), None);
+        assert_eq!(tracker.candidate, None);
+
+        let requested = connection.take().iter().filter_map(|logged| match logged {
+            Logged::Sent(request) => Some(u32_at(request, 4)),
+            Logged::Answered => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(requested, [ROOT, 10]);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
+use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use x11rb::protocol::xproto::Window;
@@ -89,6 +90,11 @@ impl ProcessInspector {
     }
 
     fn find_foreground_child(&self, pid: u32) -> Option<String> {
+        if !self.in_our_pid_namespace(pid) {
+            tracing::debug!(%pid, "process isn't in our pid namespace, so its pid can't be looked up");
+            return None
+        }
+
         let mut children = HashMap::<u32, Vec<Stat>>::new();
 
         for stat in self.scan() {
@@ -128,6 +134,19 @@ impl ProcessInspector {
         best.map(|(_, _, stat)| stat.comm)
     }
 
+    /// Whether `pid` refers to a process in the same pid namespace as us. Sandboxed applications (e.g. Flatpak) set
+    /// `_NET_WM_PID` to their pid within the sandbox, which either doesn't exist here or is some other process.
+    /// Processes whose namespace can't be read (e.g. of other users) are assumed to be in ours.
+    fn in_our_pid_namespace(&self, pid: u32) -> bool {
+        let namespace = |process: &str| fs::read_link(self.proc_root.join(process).join("ns/pid"));
+
+        match (namespace(&pid.to_string()), namespace("self")) {
+            (Ok(theirs), Ok(ours)) => theirs == ours,
+            (Err(error), _) if error.kind() == ErrorKind::NotFound => false,
+            _ => true,
+        }
+    }
+
     fn scan(&self) -> Vec<Stat> {
         let entries = match fs::read_dir(&self.proc_root) {
             Ok(entries) => entries,
@@ -150,3 +169,143 @@ impl ProcessInspector {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// A fake `/proc` whose processes are all in the same pid namespace.
+    struct Proc(TempDir);
+
+    impl Proc {
+        fn new() -> Self {
+            let proc = Self(TempDir::new("proc"));
+            proc.namespace("self");
+            proc
+        }
+
+        fn namespace(&self, process: &str) {
+            fs::create_dir_all(self.0.path().join(process).join("ns")).unwrap();
+            symlink("pid:[4026531836]", self.0.path().join(process).join("ns/pid")).unwrap();
+        }
+
+        /// Adds a process with `comm` as its name, whose terminal's foreground process group is `tpgid`.
+        fn process(&self, pid: u32, comm: &str, ppid: u32, tty_nr: i32, tpgid: i32) {
+            self.0.write(format!("{pid}/stat"), format!("{pid} ({comm}) S {ppid} {pid} {pid} {tty_nr} {tpgid} 4194304 0 0"));
+            self.namespace(&pid.to_string());
+        }
+
+        fn foreground_child(&self, pid: u32) -> Option<String> {
+            ProcessInspector::new(self.0.path(), true).foreground_child(1, pid)
+        }
+    }
+
+    #[test]
+    fn foreground_child_several_levels_down() {
+        let proc = Proc::new();
+        proc.process(100, "alacritty", 1, 0, -1);
+        proc.process(101, "zsh", 100, 34816, 103);
+        proc.process(102, "tmux: client", 101, 34816, 103);
+        proc.process(103, "nvim", 101, 34816, 103);
+        // rust-analyzer is in its own process group, so it isn't in the foreground
+        proc.process(104, "rust-analyzer", 103, 34816, 103);
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("nvim"));
+    }
+
+    #[test]
+    fn foreground_process_group_wins_over_depth() {
+        let proc = Proc::new();
+        proc.process(100, "kitty", 1, 0, -1);
+        proc.process(101, "bash", 100, 34816, 102);
+        proc.process(102, "htop", 101, 34816, 102);
+        proc.process(103, "sleep", 101, 34816, 102);
+        proc.process(104, "daemon", 103, 34816, 102);
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("htop"));
+    }
+
+    #[test]
+    fn command_names_with_parentheses() {
+        let proc = Proc::new();
+        proc.process(100, "xterm", 1, 0, -1);
+        proc.process(101, "weird) (name", 100, 34816, 101);
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("weird) (name"));
+    }
+
+    #[test]
+    fn unreadable_processes_are_skipped() {
+        let proc = Proc::new();
+        proc.process(100, "xterm", 1, 0, -1);
+        proc.process(101, "bash", 100, 34816, 101);
+        // reading a stat file which is a directory fails like one without permission to read it
+        fs::create_dir_all(proc.0.path().join("102/stat")).unwrap();
+        proc.0.write("103/stat", "garbage");
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("bash"));
+    }
+
+    #[test]
+    fn no_child_without_a_terminal() {
+        let proc = Proc::new();
+        proc.process(100, "firefox", 1, 0, -1);
+        proc.process(101, "firefox-bin", 100, 0, -1);
+
+        assert_eq!(proc.foreground_child(100), None);
+    }
+
+    #[test]
+    fn process_in_another_pid_namespace_is_skipped() {
+        let proc = Proc::new();
+        proc.0.write("100/stat", "100 (bwrap) S 1 100 100 0 -1 4194304 0 0");
+        proc.process(101, "bash", 100, 34816, 101);
+
+        // a sandboxed pid which doesn't exist here
+        assert_eq!(proc.foreground_child(200), None);
+    }
+
+    #[test]
+    fn process_in_a_different_pid_namespace_is_skipped() {
+        let proc = Proc::new();
+        proc.process(100, "bash", 1, 34816, 100);
+        proc.process(101, "vim", 100, 34816, 101);
+
+        // pid 100 exists here, but the sandboxed window's pid 100 is some other process
+        fs::remove_file(proc.0.path().join("100/ns/pid")).unwrap();
+        symlink("pid:[4026532201]", proc.0.path().join("100/ns/pid")).unwrap();
+
+        assert_eq!(proc.foreground_child(100), None);
+    }
+
+    #[test]
+    fn unreadable_namespace_is_assumed_to_be_ours() {
+        let proc = Proc::new();
+        proc.process(100, "bash", 1, 34816, 101);
+        proc.process(101, "vim", 100, 34816, 101);
+
+        // reading a link which is a plain file fails like one of another user's process
+        fs::remove_file(proc.0.path().join("100/ns/pid")).unwrap();
+        proc.0.write("100/ns/pid", "");
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("vim"));
+    }
+
+    #[test]
+    fn unreadable_own_namespace() {
+        let proc = Proc::new();
+        proc.process(100, "bash", 1, 34816, 101);
+        proc.process(101, "vim", 100, 34816, 101);
+        fs::remove_file(proc.0.path().join("self/ns/pid")).unwrap();
+
+        assert_eq!(proc.foreground_child(100).as_deref(), Some("vim"));
+    }
+
+    #[test]
+    fn missing_proc_root() {
+        let inspector = ProcessInspector::new("/nonexistent/proc", true);
+        assert_eq!(inspector.find_foreground_child(100), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Workarounds for bugs of particular window managers, each of which hooks into one point of resolving the active
+//! window. They're enabled by the name the window manager gives itself (`_NET_WM_NAME` of the window in
+//! `_NET_SUPPORTING_WM_CHECK`), and `quirks` and `disable_quirks` in the config turn them on and off regardless.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, MapState, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::atoms::Atoms;
+use crate::Config;
+
+/// The window managers known to need a quirk, by the start of their name, compared case-insensitively.
+const KNOWN: &[(&str, Quirk)] = &[
+    ("Openbox", Quirk::NoStaleActiveWindow),
+    ("FVWM", Quirk::FrameWindows),
+    ("Mutter", Quirk::RepeatedActiveWindow),
+    ("GNOME Shell", Quirk::RepeatedActiveWindow),
+];
+
+#[derive(Deserialize, Serialize, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Quirk {
+    /// `_NET_ACTIVE_WINDOW` can keep pointing at a window after it was unmapped (Openbox), so an active window which
+    /// isn't viewable counts as no window being active.
+    NoStaleActiveWindow,
+
+    /// The active window can be the frame around the client (FVWM), so the client is looked for next to the active
+    /// window even if it has properties of its own, or `search_client_window` is off.
+    FrameWindows,
+
+    /// `_NET_ACTIVE_WINDOW` is set again to the window which is active already (Mutter), so changes to it which
+    /// don't change the window are ignored rather than resolving the window again.
+    RepeatedActiveWindow,
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoStaleActiveWindow => "no_stale_active_window",
+            Self::FrameWindows => "frame_windows",
+            Self::RepeatedActiveWindow => "repeated_active_window",
+        })
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Quirks(BTreeSet<Quirk>);
+
+impl Quirks {
+    pub fn new(config: &Config, wm_name: Option<&str>) -> Self {
+        let quirks = detect(wm_name)
+            .chain(config.quirks.iter().copied())
+            .filter(|quirk| !config.disable_quirks.contains(quirk))
+            .collect();
+
+        Self(quirks)
+    }
+
+    pub fn contains(&self, quirk: Quirk) -> bool {
+        self.0.contains(&quirk)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Quirk> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Window-id translation: with [`Quirk::NoStaleActiveWindow`], the active window `window`, or 0 if it isn't
+    /// viewable.
+    pub fn active_window(&self, connection: &impl RequestConnection, window: Window) -> anyhow::Result<Window> {
+        if window == 0 || !self.contains(Quirk::NoStaleActiveWindow) {
+            return Ok(window)
+        }
+
+        let attributes = connection
+            .get_window_attributes(window)
+            .context("failed to make GetWindowAttributes reply")?
+            .reply();
+
+        // a window which is gone is left to the usual handling
+        match attributes {
+            Ok(attributes) if attributes.map_state != MapState::VIEWABLE => {
+                tracing::debug!(%window, "active window isn't viewable, no window is active");
+                Ok(0)
+            }
+            _ => Ok(window),
+        }
+    }
+}
+
+pub fn detect(wm_name: Option<&str>) -> impl Iterator<Item = Quirk> + '_ {
+    KNOWN
+        .iter()
+        .filter(move |(name, _)| {
+            wm_name.is_some_and(|wm_name| wm_name.get(..name.len()).is_some_and(|start| start.eq_ignore_ascii_case(name)))
+        })
+        .map(|(_, quirk)| *quirk)
+}
+
+pub fn wm_name(connection: &RustConnection, atoms: &Atoms, root: Window) -> anyhow::Result<Option<String>> {
+    let check = connection
+        .get_property(false, root, atoms.net_supporting_wm_check, AtomEnum::WINDOW, 0, 1)
+        .context("failed to make GetProperty reply for retrieving _NET_SUPPORTING_WM_CHECK")?
+        .reply()
+        .context("GetProperty response for _NET_SUPPORTING_WM_CHECK failed")?;
+    let Some(window) = check.value32().and_then(|mut value| value.next()).filter(|window| *window != 0) else {
+        return Ok(None)
+    };
+
+    let name = connection
+        .get_property(false, window, atoms.net_wm_name, atoms.utf8_string, 0, 64)
+        .context("failed to make GetProperty reply for retrieving _NET_WM_NAME of window manager")?
+        .reply()
+        .context("GetProperty response for _NET_WM_NAME of window manager failed")?;
+
+    Ok(Some(String::from_utf8_lossy(&name.value).into_owned()).filter(|name| !name.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Logged, MockConnection};
+
+    fn quirks(config: &str, wm_name: Option<&str>) -> Vec<Quirk> {
+        Quirks::new(&Config::from_sources([config, "resolver.filters = {}"]).unwrap(), wm_name).iter().collect()
+    }
+
+    #[test]
+    fn detection() {
+        assert_eq!(detect(Some("Openbox")).collect::<Vec<_>>(), [Quirk::NoStaleActiveWindow]);
+        assert_eq!(detect(Some("fvwm3")).collect::<Vec<_>>(), [Quirk::FrameWindows]);
+        assert_eq!(detect(Some("GNOME Shell")).collect::<Vec<_>>(), [Quirk::RepeatedActiveWindow]);
+        assert_eq!(detect(Some("i3")).count(), 0);
+        assert_eq!(detect(Some("Open")).count(), 0);
+        assert_eq!(detect(None).count(), 0);
+    }
+
+    #[test]
+    fn config_overrides_detection() {
+        assert_eq!(quirks("", Some("Openbox 3.6")), [Quirk::NoStaleActiveWindow]);
+        assert_eq!(quirks(r#"disable_quirks = ["no_stale_active_window"]"#, Some("Openbox 3.6")), []);
+        assert_eq!(quirks(r#"quirks = ["frame_windows"]"#, Some("i3")), [Quirk::FrameWindows]);
+        assert_eq!(
+            quirks(r#"quirks = ["frame_windows"]"#, Some("Mutter")),
+            [Quirk::FrameWindows, Quirk::RepeatedActiveWindow],
+        );
+        // disabling wins over enabling
+        assert_eq!(quirks("quirks = [\"frame_windows\"]\ndisable_quirks = [\"frame_windows\"]", None), []);
+    }
+
+    #[test]
+    fn names_match_the_config() {
+        for quirk in [Quirk::NoStaleActiveWindow, Quirk::FrameWindows, Quirk::RepeatedActiveWindow] {
+            assert_eq!(serde_json::to_string(&quirk).unwrap(), format!("\"{quirk}\""));
+        }
+    }
+
+    #[test]
+    fn active_window_is_only_checked_with_the_quirk() {
+        let connection = MockConnection::default();
+
+        assert_eq!(Quirks::default().active_window(&connection, 5).unwrap(), 5);
+        assert_eq!(Quirks([Quirk::NoStaleActiveWindow].into()).active_window(&connection, 0).unwrap(), 0);
+        assert!(connection.take().is_empty());
+    }
+
+    #[test]
+    fn gone_active_window_is_left_to_the_usual_handling() {
+        let connection = MockConnection::with_gone([5]);
+        let quirks = Quirks([Quirk::NoStaleActiveWindow].into());
+
+        assert_eq!(quirks.active_window(&connection, 5).unwrap(), 5);
+        assert!(matches!(connection.take()[..], [Logged::Sent(_), Logged::Answered]));
+    }
+}
@@ -0,0 +1,108 @@
+//! Connecting to the X server again after the connection was lost, e.g. because it restarted, backing off
+//! exponentially between attempts.
+
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::units::Millis;
+
+const FIRST_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Reconnect {
+    /// How many attempts are made before giving up and exiting, e.g. `0` to leave restarting to systemd. Unlimited
+    /// if it isn't set.
+    pub max_retries: Option<u32>,
+
+    /// The wait between attempts doubles up to this.
+    #[serde(default = "max_interval")]
+    pub max_interval: Millis,
+
+    /// Written while disconnected, so that the bar doesn't keep showing the title from before.
+    #[serde(default)]
+    pub text: String,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            max_interval: max_interval(),
+            text: String::new(),
+        }
+    }
+}
+
+fn max_interval() -> Millis {
+    Millis::from_secs(30)
+}
+
+#[derive(Debug)]
+pub struct Backoff {
+    interval: Duration,
+    max_interval: Duration,
+    retries_left: Option<u32>,
+}
+
+impl Backoff {
+    pub fn new(reconnect: &Reconnect) -> Self {
+        Self {
+            interval: FIRST_INTERVAL.min(reconnect.max_interval.get()),
+            max_interval: reconnect.max_interval.get(),
+            retries_left: reconnect.max_retries,
+        }
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    /// How long to wait before the next attempt, or `None` once there are no retries left.
+    fn next(&mut self) -> Option<Duration> {
+        if let Some(retries_left) = &mut self.retries_left {
+            *retries_left = retries_left.checked_sub(1)?;
+        }
+
+        let interval = self.interval;
+        self.interval = (interval * 2).min(self.max_interval);
+        Some(interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnect(max_retries: Option<u32>, max_interval: u64) -> Reconnect {
+        Reconnect { max_retries, max_interval: Millis::from_millis(max_interval), ..Reconnect::default() }
+    }
+
+    fn millis(backoff: Backoff) -> Vec<u128> {
+        backoff.map(|interval| interval.as_millis()).collect()
+    }
+
+    #[test]
+    fn interval_doubles_up_to_the_cap() {
+        let intervals = Backoff::new(&Reconnect::default()).take(10).map(|interval| interval.as_millis()).collect::<Vec<_>>();
+        assert_eq!(intervals, [250, 500, 1_000, 2_000, 4_000, 8_000, 16_000, 30_000, 30_000, 30_000]);
+
+        assert_eq!(millis(Backoff::new(&reconnect(Some(4), 1_000))), [250, 500, 1_000, 1_000]);
+        assert_eq!(millis(Backoff::new(&reconnect(Some(3), 100))), [100, 100, 100]);
+    }
+
+    #[test]
+    fn retries_run_out() {
+        assert_eq!(millis(Backoff::new(&reconnect(Some(0), 30_000))), []);
+        assert_eq!(millis(Backoff::new(&reconnect(Some(2), 30_000))), [250, 500]);
+    }
+
+    #[test]
+    fn every_disconnect_starts_over() {
+        // a backoff lasts for one disconnect, so after connecting again the next one waits as little as the first
+        let reconnect = reconnect(Some(3), 30_000);
+        let mut first = Backoff::new(&reconnect);
+        first.nth(1);
+
+        assert_eq!(millis(first), [1_000]);
+        assert_eq!(millis(Backoff::new(&reconnect)), [250, 500, 1_000]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Recording the inputs of every resolution to a file, and replaying them through the current config later. This
+//! makes resolution bugs reproducible without access to the window manager they happened with.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::protocol::xproto::Window;
+use crate::{Config, ResolvedWindow, WindowProperties};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Record {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub trigger: String,
+    pub window: Window,
+    pub class: String,
+    pub instance: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub role: Option<String>,
+    pub child_process: Option<String>,
+
+    #[serde(default)]
+    pub flatpak_id: Option<String>,
+
+    #[serde(default)]
+    pub client_machine: Option<String>,
+
+    #[serde(default)]
+    pub remote: bool,
+
+    #[serde(default)]
+    pub xwayland: bool,
+    pub output: String,
+}
+
+impl Record {
+    pub fn new(trigger: &str, resolved: &ResolvedWindow) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| u64::try_from(since.as_millis()).unwrap_or(u64::MAX));
+
+        Self {
+            timestamp,
+            trigger: trigger.to_owned(),
+            window: resolved.window,
+            class: resolved.wm_class.clone(),
+            instance: resolved.instance.clone(),
+            name: resolved.wm_name.clone(),
+            role: resolved.role.clone(),
+            child_process: resolved.child_process.clone(),
+            flatpak_id: resolved.flatpak_id.clone(),
+            client_machine: resolved.client_machine.clone(),
+            remote: resolved.remote,
+            xwayland: resolved.xwayland,
+            output: resolved.name.clone(),
+        }
+    }
+
+    pub fn resolve(&self, config: &Config) -> ResolvedWindow {
+        if self.window == 0 {
+            return config.resolver.desktop()
+        }
+
+        config.resolver.resolve_properties(self.window, WindowProperties {
+            class: &self.class,
+            instance: &self.instance,
+            name: &self.name,
+            role: self.role.as_deref(),
+            child_process: self.child_process.as_deref(),
+            flatpak_id: self.flatpak_id.as_deref(),
+            client_machine: self.client_machine.as_deref(),
+            remote: self.client_machine.is_some().then_some(self.remote),
+            xwayland: Some(self.xwayland),
+        })
+    }
+}
+
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open {} for recording", path.display()))?;
+
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, record: &Record) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record).context("failed to serialize record")?;
+        line.push(b'\n');
+        self.file.write_all(&line).context("failed to write record")
+    }
+}
+
+/// Resolves every record in `path` with `config` and prints the results. With `diff`, only the records which resolve
+/// differently than at the time of recording are printed, along with what they resolved to back then.
+pub fn replay(path: &Path, config: &Config, diff: bool) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let (mut total, mut changed) = (0, 0);
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("failed to read record")?;
+
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let record: Record = serde_json::from_str(&line).with_context(|| format!("invalid record on line {}", index + 1))?;
+        let resolved = record.resolve(config);
+        total += 1;
+
+        if !diff {
+            println!("{}", resolved.name);
+        } else if resolved.name != record.output {
+            changed += 1;
+            println!("{:#x} ({}): {:?} -> {:?}", record.window, record.trigger, record.output, resolved.name);
+        }
+    }
+
+    if diff {
+        println!("{changed} of {total} records resolve differently");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Records as an older version wrote them, without the properties which were added later.
+    const FIXTURE: &str = r#"
+{"timestamp":1700000000000,"trigger":"_NET_ACTIVE_WINDOW","window":4194311,"class":"firefox","instance":"Navigator","name":"Mozilla Firefox","child_process":null,"output":"Firefox"}
+
+{"timestamp":1700000000500,"trigger":"_NET_WM_NAME","window":8388621,"class":"Alacritty","instance":"Alacritty","name":"vim","role":"terminal","child_process":"nvim","flatpak_id":null,"client_machine":"laptop","remote":false,"xwayland":true,"output":"Alacritty"}
+{"timestamp":1700000001000,"trigger":"_NET_ACTIVE_WINDOW","window":0,"class":"","instance":"","name":"","child_process":null,"output":"Desktop"}
+"#;
+
+    fn config() -> Config {
+        Config::from_sources([r#"
+            [resolver]
+            desktop_name = "Desktop"
+
+            [resolver.filters]
+            "wm_class=firefox" = { filter = "new_name", value = "Firefox" }
+            "child_process=nvim" = { filter = "new_name", value = "Neovim" }
+        "#]).unwrap()
+    }
+
+    fn records(contents: &str) -> Vec<Record> {
+        contents.lines().filter(|line| !line.trim().is_empty()).map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn fixture_resolves_with_the_current_config() {
+        let config = config();
+        let outputs = records(FIXTURE).iter().map(|record| record.resolve(&config).name).collect::<Vec<_>>();
+
+        assert_eq!(outputs, ["Firefox", "Neovim", "Desktop"]);
+    }
+
+    #[test]
+    fn recorded_windows_replay_the_same() {
+        let config = config();
+        let dir = TempDir::new("record");
+        let path = dir.path().join("records.jsonl");
+        let mut recorder = Recorder::open(&path).unwrap();
+
+        for record in records(FIXTURE) {
+            let resolved = record.resolve(&config);
+            recorder.record(&Record::new(&record.trigger, &resolved)).unwrap();
+        }
+
+        let recorded = records(&fs::read_to_string(&path).unwrap());
+        let originals = records(FIXTURE);
+        assert_eq!(recorded.len(), originals.len());
+
+        for (recorded, original) in recorded.iter().zip(&originals) {
+            assert_eq!(recorded.trigger, original.trigger);
+            assert_eq!(recorded.window, original.window);
+            assert_eq!(recorded.child_process, original.child_process);
+            assert_eq!(recorded.xwayland, original.xwayland);
+            assert_eq!(recorded.output, recorded.resolve(&config).name);
+        }
+
+        // a second recording is appended rather than replacing the first
+        drop(recorder);
+        Recorder::open(&path).unwrap().record(&recorded[0]).unwrap();
+        assert_eq!(records(&fs::read_to_string(&path).unwrap()).len(), originals.len() + 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Remembers what the templates rendered to for the last few inputs, so that displaying the same window in the
+//! same state again (e.g. when focus bounces back and forth between two windows) doesn't render them again.
+//!
+//! An entry is looked up by a hash of everything rendering depends on: the render context, which holds every
+//! variable, and the state picking the template and the workspace prefix. Anything else comes from the config,
+//! and the cache is cleared whenever that changes.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use crate::app::{DisplayState, RenderContext};
+
+const CAPACITY: usize = 16;
+
+/// Variables which change over time on their own, so that entries rendered with them would hardly ever be used
+/// again. Templates referring to any of them aren't cached at all.
+const TIME_VARYING: &[&str] = &["focus_color"];
+
+#[derive(Debug)]
+pub struct RenderCache {
+    enabled: bool,
+
+    /// The most recently used entry first.
+    entries: VecDeque<(u64, String)>,
+}
+
+impl RenderCache {
+    pub fn new(variables: &HashSet<String>) -> Self {
+        let enabled = !TIME_VARYING.iter().any(|variable| variables.contains(*variable));
+
+        if !enabled {
+            tracing::debug!("templates refer to variables which vary over time, not caching outputs");
+        }
+
+        Self { enabled, entries: VecDeque::new() }
+    }
+
+    pub fn get_or_render(
+        &mut self,
+        context: &RenderContext,
+        state: &DisplayState,
+        render: impl FnOnce() -> anyhow::Result<String>,
+    ) -> anyhow::Result<String> {
+        if !self.enabled {
+            return render()
+        }
+
+        let key = key(context, state);
+
+        if let Some(position) = self.entries.iter().position(|(entry, _)| *entry == key) {
+            tracing::debug!("output is cached, not rendering it again");
+            let entry = self.entries.remove(position).expect("position is within the entries");
+            let rendered = entry.1.clone();
+            self.entries.push_front(entry);
+
+            return Ok(rendered)
+        }
+
+        let rendered = render()?;
+        self.entries.truncate(CAPACITY - 1);
+        self.entries.push_front((key, rendered.clone()));
+
+        Ok(rendered)
+    }
+}
+
+fn key(context: &RenderContext, state: &DisplayState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    context.hash(&mut hasher);
+    state.highlighted.hash(&mut hasher);
+
+    // whether the workspace prefix applies depends on the raw name of the desktop, which the context may not have
+    if let Some(desktop) = &state.desktop {
+        desktop.index.hash(&mut hasher);
+        desktop.name.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use handlebars::Template;
+    use crate::{ResolvedWindow, Resolver};
+    use crate::desktop::Desktop;
+    use crate::templating;
+    use super::*;
+
+    fn context(window: u32, title: &str) -> RenderContext {
+        let mut context = RenderContext::default();
+        context.update(&ResolvedWindow::without_properties(window, title.to_owned()), &DisplayState::default(), &Resolver::default());
+        context
+    }
+
+    fn variables(template: &str) -> HashSet<String> {
+        templating::variables(&Template::compile(template).unwrap())
+    }
+
+    fn render(cache: &mut RenderCache, context: &RenderContext, state: &DisplayState, renders: &Cell<usize>) -> String {
+        cache.get_or_render(context, state, || {
+            renders.set(renders.get() + 1);
+            Ok(format!("rendered {}", renders.get()))
+        }).unwrap()
+    }
+
+    #[test]
+    fn focus_bouncing_back_hits_the_cache() {
+        let mut cache = RenderCache::new(&variables("{{ name }}"));
+        let (firefox, terminal) = (context(1, "Firefox"), context(2, "Terminal"));
+        let state = DisplayState::default();
+        let renders = Cell::new(0);
+
+        let first = render(&mut cache, &firefox, &state, &renders);
+        render(&mut cache, &terminal, &state, &renders);
+
+        assert_eq!(render(&mut cache, &firefox, &state, &renders), first);
+        assert_eq!(renders.get(), 2);
+    }
+
+    #[test]
+    fn changes_miss_the_cache() {
+        let mut cache = RenderCache::new(&variables("{{ name }}"));
+        let state = DisplayState::default();
+        let renders = Cell::new(0);
+
+        render(&mut cache, &context(1, "Firefox"), &state, &renders);
+        render(&mut cache, &context(1, "Firefox — Private"), &state, &renders);
+        render(&mut cache, &context(2, "Firefox"), &state, &renders);
+        render(&mut cache, &context(1, "Firefox"), &DisplayState { highlighted: true, ..DisplayState::default() }, &renders);
+        render(&mut cache, &context(1, "Firefox"), &DisplayState {
+            desktop: Some(Desktop { index: 1, name: None }),
+            ..DisplayState::default()
+        }, &renders);
+
+        assert_eq!(renders.get(), 5);
+    }
+
+    #[test]
+    fn least_recently_used_is_forgotten() {
+        let mut cache = RenderCache::new(&variables("{{ name }}"));
+        let state = DisplayState::default();
+        let renders = Cell::new(0);
+
+        for window in 0..=CAPACITY as u32 {
+            render(&mut cache, &context(window, "Firefox"), &state, &renders);
+        }
+
+        render(&mut cache, &context(CAPACITY as u32, "Firefox"), &state, &renders);
+        assert_eq!(renders.get(), CAPACITY + 1);
+
+        render(&mut cache, &context(0, "Firefox"), &state, &renders);
+        assert_eq!(renders.get(), CAPACITY + 2);
+    }
+
+    #[test]
+    fn time_varying_variables_bypass_the_cache() {
+        let mut cache = RenderCache::new(&variables("{{ #if focus_color }}%{F{{ focus_color }}}{{ /if }}{{ name }}"));
+        let firefox = context(1, "Firefox");
+        let state = DisplayState::default();
+        let renders = Cell::new(0);
+
+        render(&mut cache, &firefox, &state, &renders);
+        render(&mut cache, &firefox, &state, &renders);
+
+        assert_eq!(renders.get(), 2);
+    }
+
+    #[test]
+    fn render_errors_arent_cached() {
+        let mut cache = RenderCache::new(&variables("{{ name }}"));
+        let firefox = context(1, "Firefox");
+        let state = DisplayState::default();
+
+        assert!(cache.get_or_render(&firefox, &state, || Err(anyhow::anyhow!("broken"))).is_err());
+        assert_eq!(cache.get_or_render(&firefox, &state, || Ok("Firefox".to_owned())).unwrap(), "Firefox");
+    }
+}
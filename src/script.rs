@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Hiragana,
+    Katakana,
+    Han,
+
+    /// Anything else, which includes digits, punctuation and whitespace. These never start a run of their own and
+    /// are attached to the surrounding run instead.
+    Common,
+}
+
+impl Script {
+    /// Buckets a character into a script by its code point range. This is a simplification of the Unicode script
+    /// property which is good enough to tell apart the scripts common in window titles.
+    pub fn of(c: char) -> Self {
+        match c {
+            'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => Self::Latin,
+            '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Self::Greek,
+            '\u{0400}'..='\u{052F}' => Self::Cyrillic,
+            '\u{0530}'..='\u{058F}' => Self::Armenian,
+            '\u{0590}'..='\u{05FF}' => Self::Hebrew,
+            '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' => Self::Arabic,
+            '\u{0900}'..='\u{097F}' => Self::Devanagari,
+            '\u{0E00}'..='\u{0E7F}' => Self::Thai,
+            '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => Self::Hangul,
+            '\u{3040}'..='\u{309F}' => Self::Hiragana,
+            '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => Self::Katakana,
+            '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2FA1F}' => Self::Han,
+            _ => Self::Common,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Transliterate {
+    Ascii,
+    Strip,
+}
+
+impl Transliterate {
+    /// Transliterates `value`. If `scripts` is given, only runs of characters in one of those scripts are transformed
+    /// and everything else (e.g. Latin text within a Japanese title) is left intact.
+    pub fn apply<'v>(&self, value: &'v str, scripts: Option<&[Script]>) -> Cow<'v, str> {
+        let Some(scripts) = scripts else {
+            return self.transform(value)
+        };
+
+        if !value.chars().any(|c| scripts.contains(&Script::of(c))) {
+            return Cow::Borrowed(value)
+        }
+
+        let mut result = String::with_capacity(value.len());
+
+        for (script, run) in runs(value) {
+            if scripts.contains(&script) {
+                result.push_str(&self.transform(run))
+            } else {
+                result.push_str(run)
+            }
+        }
+
+        Cow::Owned(result)
+    }
+
+    fn transform<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        match self {
+            Self::Ascii => deunicode::deunicode_with_tofu_cow(value, ""),
+            Self::Strip if value.is_ascii() => Cow::Borrowed(value),
+            Self::Strip => Cow::Owned(value.chars().filter(char::is_ascii).collect()),
+        }
+    }
+}
+
+/// Splits a value into runs of the same script. Common characters are attached to the run before them, or to the
+/// first run if the value starts with them, so that e.g. spaces between two Japanese words stay part of that run.
+fn runs(value: &str) -> Vec<(Script, &str)> {
+    let mut runs = Vec::<(Script, usize)>::new();
+
+    for (index, c) in value.char_indices() {
+        let script = Script::of(c);
+
+        match runs.last_mut() {
+            Some((run_script, _)) if *run_script == script || script == Script::Common => {}
+            Some((run_script @ Script::Common, _)) => *run_script = script,
+            _ => runs.push((script, index)),
+        }
+    }
+
+    runs.iter()
+        .enumerate()
+        .map(|(i, (script, start))| {
+            let end = runs.get(i + 1).map_or(value.len(), |(_, end)| *end);
+            (*script, &value[*start..end])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAPANESE: &[Script] = &[Script::Han, Script::Hiragana, Script::Katakana];
+
+    fn strip(value: &str, scripts: &[Script]) -> String {
+        Transliterate::Strip.apply(value, Some(scripts)).into_owned()
+    }
+
+    #[test]
+    fn scripts_of_characters() {
+        assert_eq!(Script::of('a'), Script::Latin);
+        assert_eq!(Script::of('é'), Script::Latin);
+        assert_eq!(Script::of('東'), Script::Han);
+        assert_eq!(Script::of('の'), Script::Hiragana);
+        assert_eq!(Script::of('カ'), Script::Katakana);
+        assert_eq!(Script::of('ж'), Script::Cyrillic);
+        assert_eq!(Script::of(' '), Script::Common);
+        assert_eq!(Script::of('7'), Script::Common);
+        assert_eq!(Script::of('—'), Script::Common);
+    }
+
+    #[test]
+    fn runs_of_a_mixed_title() {
+        assert_eq!(runs("東京の天気 - Google Chrome"), [(Script::Han, "東京"), (Script::Hiragana, "の"), (Script::Han, "天気 - "), (Script::Latin, "Google Chrome")]);
+        assert_eq!(runs("- YouTube"), [(Script::Latin, "- YouTube")]);
+        assert_eq!(runs("123"), [(Script::Common, "123")]);
+        assert_eq!(runs(""), []);
+    }
+
+    #[test]
+    fn only_matching_runs_are_transformed() {
+        assert_eq!(strip("東京の天気 - Google Chrome", JAPANESE), " - Google Chrome");
+        assert_eq!(strip("Café — カフェ", JAPANESE), "Café — ");
+        assert_eq!(strip("Café — カフェ", &[Script::Latin]), "Caf  カフェ");
+        assert_eq!(Transliterate::Ascii.apply("カフェ: Café", Some(JAPANESE)), "kahue: Café");
+    }
+
+    #[test]
+    fn titles_without_matching_scripts_are_left_alone() {
+        assert!(matches!(Transliterate::Strip.apply("Crème brûlée", Some(JAPANESE)), Cow::Borrowed("Crème brûlée")));
+        assert!(matches!(Transliterate::Ascii.apply("Привет", Some(JAPANESE)), Cow::Borrowed("Привет")));
+    }
+
+    #[test]
+    fn every_script_without_a_list() {
+        assert_eq!(Transliterate::Strip.apply("Café カフェ", None), "Caf ");
+        assert_eq!(Transliterate::Ascii.apply("Café", None), "Cafe");
+    }
+}
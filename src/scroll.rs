@@ -0,0 +1,182 @@
+//! Scrolling outputs which are too wide through a window of fixed width, like zscroll, rather than truncating them.
+
+use std::num::NonZeroUsize;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::truncate;
+use crate::units::Millis;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Scroll {
+    /// How many characters are shown at once. Outputs which fit are shown as they are.
+    pub width: NonZeroUsize,
+
+    #[serde(default = "interval", alias = "interval_ms")]
+    pub interval: Millis,
+
+    #[serde(default = "separator")]
+    pub separator: String,
+}
+
+fn interval() -> Millis {
+    Millis::from_millis(250)
+}
+
+fn separator() -> String {
+    " | ".to_owned()
+}
+
+/// Keeps track of how far the output scrolled.
+///
+/// Polybar tags in a scrolling output are dropped, as a cut could break them. Colors and click actions from the
+/// config are added around the scrolled text, so they still apply.
+#[derive(Debug)]
+pub struct Marquee {
+    config: Scroll,
+    separator: Vec<String>,
+    text: Option<String>,
+    offset: usize,
+    next: Option<Instant>,
+}
+
+impl Marquee {
+    /// `escape` is the one the output is rendered with, so that the separator is shown as it's written in the config.
+    pub fn new(config: Scroll, escape: impl Fn(&str) -> String) -> Self {
+        let separator = truncate::visible_graphemes(&escape(&config.separator));
+        Self { config, separator, text: None, offset: 0, next: None }
+    }
+
+    /// The part of `rendered` to show now. A different output than before starts over from its beginning.
+    pub fn show(&mut self, rendered: String, now: Instant) -> String {
+        let width = self.config.width.get();
+
+        if self.text.as_ref() != Some(&rendered) {
+            self.offset = 0;
+            self.next = (truncate::visible_graphemes(&rendered).len() > width).then(|| now + self.config.interval.get());
+            self.text = Some(rendered.clone());
+        }
+
+        if self.next.is_none() {
+            return rendered
+        }
+
+        let separator = self.separator.iter().cloned();
+        let cycle = truncate::visible_graphemes(&rendered).into_iter().chain(separator).collect::<Vec<_>>();
+
+        cycle.iter().cycle().skip(self.offset % cycle.len()).take(width).map(String::as_str).collect()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// Scrolls on by one character if it's due. Returns whether it did, in which case the output needs to be
+    /// written again.
+    pub fn on_deadline(&mut self, now: Instant) -> bool {
+        match self.next {
+            Some(next) if now >= next => {
+                self.offset = self.offset.wrapping_add(1);
+                self.next = Some(now + self.config.interval.get());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::templating;
+
+    fn scrolling(width: usize, separator: &str) -> Marquee {
+        let config = Scroll {
+            width: NonZeroUsize::new(width).unwrap(),
+            interval: Millis::from_millis(100),
+            separator: separator.to_owned(),
+        };
+
+        Marquee::new(config, templating::escape_polybar)
+    }
+
+    /// Scrolls `marquee` on by `steps` characters, returning when it's due next.
+    fn scroll(marquee: &mut Marquee, steps: usize) -> Instant {
+        for _ in 0..steps {
+            let next = marquee.deadline().unwrap();
+            assert!(marquee.on_deadline(next));
+        }
+
+        marquee.deadline().unwrap()
+    }
+
+    #[test]
+    fn short_outputs_dont_scroll() {
+        let mut marquee = scrolling(10, " | ");
+        assert_eq!(marquee.show("Firefox".to_owned(), Instant::now()), "Firefox");
+        assert_eq!(marquee.deadline(), None);
+    }
+
+    #[test]
+    fn scrolls_through_the_separator() {
+        let mut marquee = scrolling(4, " | ");
+        let now = Instant::now();
+        assert_eq!(marquee.show("Firefox".to_owned(), now), "Fire");
+        assert_eq!(marquee.deadline(), Some(now + Duration::from_millis(100)));
+
+        let now = scroll(&mut marquee, 5);
+        assert_eq!(marquee.show("Firefox".to_owned(), now), "ox |");
+
+        let now = scroll(&mut marquee, 5);
+        assert_eq!(marquee.show("Firefox".to_owned(), now), "Fire");
+    }
+
+    #[test]
+    fn different_output_starts_over() {
+        let mut marquee = scrolling(4, " | ");
+        marquee.show("Firefox".to_owned(), Instant::now());
+        let now = scroll(&mut marquee, 3);
+        assert_eq!(marquee.show("Firefox".to_owned(), now), "efox");
+
+        assert_eq!(marquee.show("Thunderbird".to_owned(), now), "Thun");
+        assert_eq!(marquee.deadline(), Some(now + Duration::from_millis(100)));
+
+        // and one which fits stops scrolling
+        assert_eq!(marquee.show("Mail".to_owned(), now), "Mail");
+        assert_eq!(marquee.deadline(), None);
+    }
+
+    #[test]
+    fn graphemes_are_never_cut() {
+        let mut marquee = scrolling(2, "|");
+        let rendered = "e\u{301}👩‍👩‍👧x";
+        let mut now = Instant::now();
+        let mut shown = Vec::new();
+
+        for _ in 0..4 {
+            shown.push(marquee.show(rendered.to_owned(), now));
+            now = scroll(&mut marquee, 1);
+        }
+
+        assert_eq!(shown, ["e\u{301}👩‍👩‍👧", "👩‍👩‍👧x", "x|", "|e\u{301}"]);
+    }
+
+    #[test]
+    fn separator_is_escaped_and_kept_whole() {
+        let mut marquee = scrolling(3, "%");
+        marquee.show("100%% done".to_owned(), Instant::now());
+
+        // the `%` of the separator is escaped like the title, and neither `%%` is ever cut in half
+        let now = scroll(&mut marquee, 7);
+        assert_eq!(marquee.show("100%% done".to_owned(), now), "ne%%");
+        let now = scroll(&mut marquee, 1);
+        assert_eq!(marquee.show("100%% done".to_owned(), now), "e%%1");
+        let now = scroll(&mut marquee, 1);
+        assert_eq!(marquee.show("100%% done".to_owned(), now), "%%10");
+
+        let mut marquee = scrolling(2, "%%");
+        marquee.show("abc".to_owned(), Instant::now());
+        let now = scroll(&mut marquee, 3);
+        assert_eq!(marquee.show("abc".to_owned(), now), "%%%%");
+    }
+}
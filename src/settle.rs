@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+/// How long to wait for related property changes after the active window or desktop changed before rendering.
+/// Window managers change both when switching desktops, in no particular order.
+const SETTLE_DELAY: Duration = Duration::from_millis(10);
+
+/// Waits for changes of the active window and the current desktop to settle, so that both are read together once
+/// they did. The wait starts with the first change; later ones while it's pending don't extend it.
+#[derive(Default, Debug)]
+pub struct Settle {
+    until: Option<Instant>,
+}
+
+impl Settle {
+    pub fn changed(&mut self, now: Instant) {
+        self.until.get_or_insert(now + SETTLE_DELAY);
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.until.is_some()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.until
+    }
+
+    pub fn cancel(&mut self) {
+        self.until = None
+    }
+
+    /// Whether the changes settled, in which case the active window and desktop need to be read again.
+    pub fn on_deadline(&mut self, now: Instant) -> bool {
+        match self.until {
+            Some(until) if now >= until => {
+                self.until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the window manager reports, which is only read once the changes settled.
+    #[derive(Default)]
+    struct Server {
+        desktop: &'static str,
+        window: &'static str,
+    }
+
+    enum Change {
+        Desktop(&'static str),
+        Window(&'static str),
+    }
+
+    fn run(changes: [Change; 2]) -> Vec<String> {
+        let start = Instant::now();
+        let mut server = Server { desktop: "1", window: "firefox" };
+        let mut settle = Settle::default();
+        let mut outputs = Vec::new();
+
+        for (elapsed, change) in changes.into_iter().enumerate() {
+            let now = start + Duration::from_millis(elapsed as u64);
+
+            match change {
+                Change::Desktop(desktop) => server.desktop = desktop,
+                Change::Window(window) => server.window = window,
+            }
+
+            settle.changed(now);
+            assert!(!settle.on_deadline(now));
+        }
+
+        let mut now = start;
+
+        while let Some(deadline) = settle.deadline() {
+            now = now.max(deadline);
+
+            if settle.on_deadline(now) {
+                outputs.push(format!("{}: {}", server.desktop, server.window));
+            }
+        }
+
+        outputs
+    }
+
+    #[test]
+    fn both_orders_render_the_same() {
+        let desktop_first = run([Change::Desktop("2"), Change::Window("alacritty")]);
+        let window_first = run([Change::Window("alacritty"), Change::Desktop("2")]);
+
+        assert_eq!(desktop_first, ["2: alacritty"]);
+        assert_eq!(window_first, desktop_first);
+    }
+
+    #[test]
+    fn later_changes_dont_extend_the_wait() {
+        let start = Instant::now();
+        let mut settle = Settle::default();
+
+        settle.changed(start);
+        settle.changed(start + Duration::from_millis(5));
+        assert_eq!(settle.deadline(), Some(start + SETTLE_DELAY));
+
+        assert!(settle.on_deadline(start + SETTLE_DELAY));
+        assert!(!settle.is_pending());
+        assert!(!settle.on_deadline(start + SETTLE_DELAY * 2));
+    }
+
+    #[test]
+    fn cancelled_changes_never_settle() {
+        let start = Instant::now();
+        let mut settle = Settle::default();
+
+        settle.changed(start);
+        settle.cancel();
+
+        assert_eq!(settle.deadline(), None);
+        assert!(!settle.on_deadline(start + SETTLE_DELAY));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use x11rb::protocol::xproto::Window;
+use crate::units::Millis;
+
+/// Only this many characters of a title are compared, so that comparing long titles stays cheap.
+const MAX_COMPARED_CHARS: usize = 256;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SuppressSimilar {
+    #[serde(default = "threshold")]
+    pub threshold: f64,
+
+    /// How long after an output was written similar ones are suppressed. Afterwards the latest one is written even if
+    /// it's similar, so the output never falls behind for longer than this.
+    #[serde(default = "window", alias = "window_ms")]
+    pub window: Millis,
+}
+
+fn threshold() -> f64 {
+    0.9
+}
+
+fn window() -> Millis {
+    Millis::from_millis(2000)
+}
+
+#[derive(Debug)]
+pub struct SimilarOutputs {
+    config: SuppressSimilar,
+    written: Option<(Window, String, Instant)>,
+    suppressed: bool,
+}
+
+impl SimilarOutputs {
+    pub fn new(config: SuppressSimilar) -> Self {
+        Self {
+            config,
+            written: None,
+            suppressed: false,
+        }
+    }
+
+    /// Whether `output` for `window` should be written, which it is unless it's similar to the last written output of
+    /// the same window, and that was written recently. Outputs for other windows are always written.
+    pub fn should_write(&mut self, window: Window, output: &str, now: Instant) -> bool {
+        if let Some((written_window, written, at)) = &self.written {
+            let recent = now.saturating_duration_since(*at) < self.config.window.get();
+
+            if *written_window == window && recent && similarity(written, output) >= self.config.threshold {
+                tracing::debug!(%output, "output is similar to the written one, suppressing it");
+                self.suppressed = true;
+                return false
+            }
+        }
+
+        self.written = Some((window, output.to_owned(), now));
+        self.suppressed = false;
+        true
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        match &self.written {
+            Some((_, _, at)) if self.suppressed => Some(*at + self.config.window.get()),
+            _ => None,
+        }
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) -> bool {
+        if self.deadline().is_some_and(|deadline| now >= deadline) {
+            self.suppressed = false;
+            return true
+        }
+
+        false
+    }
+}
+
+/// How similar two strings are, from 0 (nothing in common) to 1 (equal ignoring case and which digits they contain),
+/// as the Dice coefficient of their character trigrams. Only the first [`MAX_COMPARED_CHARS`] characters are
+/// compared.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0
+    }
+
+    let (a, b) = (trigrams(a), trigrams(b));
+    let total = a.values().sum::<usize>() + b.values().sum::<usize>();
+
+    if total == 0 {
+        return 0.0
+    }
+
+    let shared = a.iter().map(|(trigram, count)| (*count).min(b.get(trigram).copied().unwrap_or(0))).sum::<usize>();
+    (2 * shared) as f64 / total as f64
+}
+
+fn trigrams(value: &str) -> HashMap<[char; 3], usize> {
+    // padded, so that short strings and the ends of strings still count
+    let chars = [' ', ' ']
+        .into_iter()
+        .chain(value.chars().take(MAX_COMPARED_CHARS).flat_map(normalize))
+        .chain([' '])
+        .collect::<Vec<_>>();
+    let mut trigrams = HashMap::new();
+
+    for window in chars.windows(3) {
+        *trigrams.entry([window[0], window[1], window[2]]).or_default() += 1;
+    }
+
+    trigrams
+}
+
+/// Lowercases `c`, and makes every digit the same, so that counters changing don't count as a difference.
+fn normalize(c: char) -> impl Iterator<Item = char> {
+    let c = if c.is_ascii_digit() { '0' } else { c };
+    c.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn outputs() -> SimilarOutputs {
+        SimilarOutputs::new(SuppressSimilar { threshold: threshold(), window: window() })
+    }
+
+    #[test]
+    fn similarity_of_titles() {
+        assert_eq!(similarity("cargo build", "cargo build"), 1.0);
+        assert_eq!(similarity("building… 42%", "building… 43%"), 1.0);
+        assert!(similarity("building… 42% [foo]", "building… 99% [foo]") >= 0.9);
+        assert!(similarity("vim ~/notes.md", "Mozilla Firefox") < 0.2);
+        assert_eq!(similarity("", "abc"), similarity("abc", ""));
+    }
+
+    #[test]
+    fn similarity_of_long_titles_is_bounded() {
+        let long = "x".repeat(100_000);
+        let started = Instant::now();
+
+        assert_eq!(similarity(&format!("{long}a"), &format!("{long}b")), 1.0);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn counter_is_suppressed() {
+        let start = Instant::now();
+        let mut outputs = outputs();
+
+        assert!(outputs.should_write(1, "cargo build: 1/250", start));
+        assert!(!outputs.should_write(1, "cargo build: 2/250", start + Duration::from_millis(100)));
+        assert!(!outputs.should_write(1, "cargo build: 3/250", start + Duration::from_millis(200)));
+
+        // the latest one is written once the time window is over
+        assert_eq!(outputs.deadline(), Some(start + Duration::from_secs(2)));
+        assert!(!outputs.on_deadline(start + Duration::from_millis(1999)));
+        assert!(outputs.on_deadline(start + Duration::from_secs(2)));
+        assert!(outputs.should_write(1, "cargo build: 3/250", start + Duration::from_secs(2)));
+        assert_eq!(outputs.deadline(), None);
+    }
+
+    #[test]
+    fn tab_switch_is_written() {
+        let start = Instant::now();
+        let mut outputs = outputs();
+
+        assert!(outputs.should_write(1, "GitHub - Mozilla Firefox", start));
+        assert!(outputs.should_write(1, "YouTube - Mozilla Firefox", start + Duration::from_millis(100)));
+        assert_eq!(outputs.deadline(), None);
+    }
+
+    #[test]
+    fn other_window_is_written() {
+        let start = Instant::now();
+        let mut outputs = outputs();
+
+        assert!(outputs.should_write(1, "htop", start));
+        assert!(outputs.should_write(2, "htop", start + Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,152 @@
+//! `backend = "stdin"`, which reads the windows to display as lines of JSON from stdin instead of asking the X
+//! server, e.g. from a daemon for a window manager the module doesn't support. Every line goes through the same
+//! filters, templates and outputs as a window from the X server would, which also makes it a way to check the whole
+//! pipeline against fixed input.
+
+use std::io::{self, BufRead};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use x11rb::protocol::xproto::Window;
+use crate::app::{render, templates, DisplayState, RenderContext};
+use crate::outputs::ExtraOutputs;
+use crate::{output, sanitize, Config, DesktopBehavior, ResolvedWindow, WindowProperties};
+
+#[derive(Deserialize, Serialize, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    X11,
+
+    /// Lines like `{"class": "firefox", "title": "Mozilla Firefox", "instance": "Navigator"}` on stdin.
+    Stdin,
+}
+
+impl Backend {
+    /// The template variables this backend has no values for, which are always empty (or `false`) with it.
+    pub fn unavailable_variables(self) -> &'static [&'static str] {
+        match self {
+            Self::X11 => &[],
+            Self::Stdin => &[
+                "stale", "child_process", "flatpak_id", "desktop_index", "desktop", "workspace_index",
+                "workspace", "focus_color", "client_machine", "remote",
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StdinWindow {
+    #[serde(default)]
+    class: String,
+
+    #[serde(default)]
+    title: String,
+
+    #[serde(default)]
+    instance: String,
+
+    /// Whether the window is an XWayland client, for compositors which tell. Filters on `xwayland` don't match
+    /// windows without it.
+    #[serde(default)]
+    xwayland: Option<bool>,
+
+    /// Whether the window has `_NET_WM_STATE_SKIP_TASKBAR`, which keeps the previous output if
+    /// `respect_skip_taskbar` is set.
+    #[serde(default)]
+    skip_taskbar: bool,
+
+    /// The id of the window, which only matters to templates. 0 displays the desktop.
+    #[serde(default = "window")]
+    window: Window,
+}
+
+fn window() -> Window {
+    1
+}
+
+impl StdinWindow {
+    fn resolve(&self, config: &Config) -> ResolvedWindow {
+        if self.window == 0 {
+            return config.resolver.desktop()
+        }
+
+        config.resolver.resolve_properties(self.window, WindowProperties {
+            class: &sanitize(&self.class),
+            instance: &sanitize(&self.instance),
+            name: &sanitize(&self.title),
+            role: None,
+            child_process: None,
+            flatpak_id: None,
+            client_machine: None,
+            remote: None,
+            xwayland: self.xwayland,
+        })
+    }
+}
+
+/// Displays every window read from stdin until it's closed. Lines which aren't a window are skipped with a warning.
+pub fn run(config: &Config) -> anyhow::Result<()> {
+    let handlebars = templates(config)?;
+    let mut outputs = ExtraOutputs::new(config)?;
+    let state = DisplayState::default();
+    let mut context = RenderContext::default();
+    let styling = output::styling(config);
+    let mut written = None;
+
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line = line.context("failed to read from stdin")?;
+
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let window = match serde_json::from_str::<StdinWindow>(&line) {
+            Ok(window) => window,
+            Err(error) => {
+                tracing::warn!(line = index + 1, "skipping line which isn't a window: {error}");
+                continue
+            }
+        };
+
+        if config.respect_skip_taskbar && window.skip_taskbar {
+            tracing::debug!(line = index + 1, "window skips the taskbar, keeping previous output");
+            continue
+        }
+
+        if window.window == 0 && config.resolver.desktop_behavior == DesktopBehavior::KeepLast && written.is_some() {
+            tracing::debug!(line = index + 1, "no window is active, keeping previous output");
+            continue
+        }
+
+        let resolved = window.resolve(config);
+
+        let Some(resolved) = config.resolver.apply_ignore(resolved, written.is_some(), state.desktop.as_ref()) else {
+            tracing::debug!(line = index + 1, "window is ignored, keeping previous output");
+            continue
+        };
+
+        context.update(&resolved, &state, &config.resolver);
+        let rendered = render(&handlebars, config, &context, &state)?;
+
+        let line = config.output.line(&rendered, &resolved, &state, config.output_format, styling)?;
+
+        if !config.always_print && written.as_ref() == Some(&line) {
+            continue
+        }
+
+        match config.output.send(&line) {
+            Err(error) if output::is_stdout_closed(&error) => {
+                tracing::info!("stdout was closed, exiting");
+                return Ok(())
+            }
+            result => result.context("failed to write title")?,
+        }
+
+        written = Some(line);
+        outputs.write(config, &context, &state, &resolved);
+    }
+
+    tracing::debug!("stdin was closed");
+    output::flush();
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use anyhow::Context;
+use x11rb::connection::RequestConnection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ChangeWindowAttributesAux, ConnectionExt, EventMask, Window};
+
+/// Keeps a set of windows subscribed to events, e.g. every client window to property changes.
+///
+/// Only the windows which were added or removed since the last update are (un)subscribed, and every request of an
+/// update is sent before any of them is checked, so that they share a single round trip even for many windows.
+#[derive(Clone, Debug)]
+pub struct SubscriptionManager {
+    mask: EventMask,
+    subscribed: HashSet<Window>,
+}
+
+impl SubscriptionManager {
+    pub fn new(mask: EventMask) -> Self {
+        Self {
+            mask,
+            subscribed: HashSet::new(),
+        }
+    }
+
+    pub fn contains(&self, window: Window) -> bool {
+        self.subscribed.contains(&window)
+    }
+
+    pub fn windows(&self) -> &HashSet<Window> {
+        &self.subscribed
+    }
+
+    /// Subscribes to the `windows` which aren't subscribed yet, and unsubscribes from those which aren't in `windows`
+    /// anymore. Returns the windows which were added.
+    pub fn update(&mut self, connection: &impl RequestConnection, windows: HashSet<Window>) -> anyhow::Result<Vec<Window>> {
+        let added = windows.difference(&self.subscribed).copied().collect::<Vec<_>>();
+        let removed = self.subscribed.difference(&windows).copied().collect::<Vec<_>>();
+
+        if added.is_empty() && removed.is_empty() {
+            return Ok(added)
+        }
+
+        tracing::debug!(added = added.len(), removed = removed.len(), "updating event subscriptions");
+        let subscribe = ChangeWindowAttributesAux::new().event_mask(self.mask);
+        let unsubscribe = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+
+        let cookies = added
+            .iter()
+            .map(|window| (*window, &subscribe))
+            .chain(removed.iter().map(|window| (*window, &unsubscribe)))
+            .map(|(window, attributes)| {
+                let cookie = connection
+                    .change_window_attributes(window, attributes)
+                    .context("failed to make ChangeWindowAttributes reply")?;
+
+                Ok((window, cookie))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        for (window, cookie) in cookies {
+            match cookie.check() {
+                // windows are often gone by the time they're (un)subscribed, which is fine
+                Err(ReplyError::X11Error(error)) => {
+                    tracing::debug!(%window, "failed to change event subscription of window: {error:?}");
+                }
+                result => result.context("ChangeWindowAttributes response failed")?,
+            }
+        }
+
+        self.subscribed = windows;
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::xproto::{CHANGE_WINDOW_ATTRIBUTES_REQUEST, CW};
+    use super::*;
+    use crate::testing::{u32_at, Logged, MockConnection};
+
+    /// The windows whose event mask was changed, sorted, after checking that every request was sent before any was
+    /// checked.
+    fn changes(log: Vec<Logged>) -> Vec<(Window, u32)> {
+        let sent = log.iter().take_while(|logged| **logged != Logged::Answered).count();
+        assert!(log[sent..].iter().all(|logged| *logged == Logged::Answered), "requests weren't batched: {log:?}");
+        assert_eq!(log.len(), sent * 2, "not every request was checked: {log:?}");
+
+        let mut changes = log
+            .into_iter()
+            .filter_map(|logged| match logged {
+                Logged::Sent(request) => {
+                    assert_eq!(request[0], CHANGE_WINDOW_ATTRIBUTES_REQUEST);
+                    assert_eq!(u32_at(&request, 8), u32::from(CW::EVENT_MASK));
+                    Some((u32_at(&request, 4), u32_at(&request, 12)))
+                }
+                Logged::Answered => None,
+            })
+            .collect::<Vec<_>>();
+        changes.sort_unstable();
+
+        changes
+    }
+
+    fn sorted(mut windows: Vec<Window>) -> Vec<Window> {
+        windows.sort_unstable();
+        windows
+    }
+
+    #[test]
+    fn only_changes_are_subscribed() {
+        let (subscribed, unsubscribed) = (u32::from(EventMask::PROPERTY_CHANGE), u32::from(EventMask::NO_EVENT));
+        let connection = MockConnection::default();
+        let mut subscriptions = SubscriptionManager::new(EventMask::PROPERTY_CHANGE);
+
+        let added = subscriptions.update(&connection, HashSet::from([1, 2, 3])).unwrap();
+        assert_eq!(sorted(added), [1, 2, 3]);
+        assert_eq!(changes(connection.take()), [(1, subscribed), (2, subscribed), (3, subscribed)]);
+
+        // a window opens and another one closes
+        let added = subscriptions.update(&connection, HashSet::from([2, 3, 4])).unwrap();
+        assert_eq!(added, [4]);
+        assert_eq!(changes(connection.take()), [(1, unsubscribed), (4, subscribed)]);
+
+        // nothing changed
+        assert!(subscriptions.update(&connection, HashSet::from([4, 3, 2])).unwrap().is_empty());
+        assert_eq!(connection.take(), []);
+
+        // the last windows close
+        assert!(subscriptions.update(&connection, HashSet::new()).unwrap().is_empty());
+        assert_eq!(changes(connection.take()), [(2, unsubscribed), (3, unsubscribed), (4, unsubscribed)]);
+        assert!(subscriptions.windows().is_empty());
+    }
+
+    #[test]
+    fn many_windows_share_a_round_trip() {
+        let connection = MockConnection::default();
+        let mut subscriptions = SubscriptionManager::new(EventMask::PROPERTY_CHANGE);
+
+        subscriptions.update(&connection, (1..=80).collect()).unwrap();
+        assert_eq!(changes(connection.take()).len(), 80);
+
+        subscriptions.update(&connection, (5..=85).collect()).unwrap();
+        assert_eq!(changes(connection.take()).len(), 4 + 5);
+    }
+
+    #[test]
+    fn windows_which_are_gone_are_fine() {
+        let subscribed = u32::from(EventMask::PROPERTY_CHANGE);
+        let connection = MockConnection::with_gone([2]);
+        let mut subscriptions = SubscriptionManager::new(EventMask::PROPERTY_CHANGE);
+
+        subscriptions.update(&connection, HashSet::from([1, 2])).unwrap();
+
+        assert_eq!(changes(connection.take()), [(1, subscribed), (2, subscribed)]);
+        assert!(subscriptions.contains(1));
+    }
+}
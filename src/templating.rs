@@ -1,6 +1,228 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::Instant;
+use anyhow::Context as _;
 use handlebars::template::{BlockParam, Parameter, TemplateElement};
-use handlebars::Template;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, JsonRender, JsonValue, Output, RenderContext,
+    RenderError, Renderable, ScopedJson, Template,
+};
+
+/// Escapes values inserted into templates for polybar, which would otherwise interpret formatting tags such as
+/// `%{A1:command:}` in window titles. Literal text in templates is left as is, so it can still contain tags.
+pub fn escape_polybar(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Registers the helpers comparing strings: `contains`, `eq`, `ne`, `starts_with` and `ends_with`. `eq` and `ne`
+/// replace the built-in helpers of the same name.
+pub fn register_helpers(handlebars: &mut Handlebars) {
+    handlebars.register_helper("contains", Box::new(StringTest { name: "contains", test: |a, b| a.contains(b) }));
+    handlebars.register_helper("eq", Box::new(StringTest { name: "eq", test: |a, b| a == b }));
+    handlebars.register_helper("ne", Box::new(StringTest { name: "ne", test: |a, b| a != b }));
+    handlebars.register_helper("starts_with", Box::new(StringTest { name: "starts_with", test: |a, b| a.starts_with(b) }));
+    handlebars.register_helper("ends_with", Box::new(StringTest { name: "ends_with", test: |a, b| a.ends_with(b) }));
+}
+
+/// A helper testing two strings, e.g. `(contains name "YouTube")`. It evaluates to `"true"` or `""`, so it works with
+/// `#if`, and can be used as a block itself: `{{#contains name "YouTube"}}...{{else}}...{{/contains}}`. A third parameter
+/// of `true` makes the test case insensitive.
+struct StringTest {
+    name: &'static str,
+    test: fn(&str, &str) -> bool,
+}
+
+impl StringTest {
+    fn test(&self, h: &Helper) -> Result<bool, RenderError> {
+        let param = |index| {
+            h.param(index)
+                .map(|param| param.value().render())
+                .ok_or_else(|| RenderError::new(format!("{} expects two parameters", self.name)))
+        };
+        let (a, b) = (param(0)?, param(1)?);
+
+        if h.param(2).is_some_and(|param| param.value().as_bool() == Some(true)) {
+            Ok((self.test)(&a.to_lowercase(), &b.to_lowercase()))
+        } else {
+            Ok((self.test)(&a, &b))
+        }
+    }
+}
+
+impl HelperDef for StringTest {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = if self.test(h)? { "true" } else { "" };
+        Ok(ScopedJson::Derived(JsonValue::from(value)))
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let result = self.test(h)?;
+
+        if !h.is_block() {
+            out.write(if result { "true" } else { "" })?;
+            return Ok(())
+        }
+
+        match if result { h.template() } else { h.inverse() } {
+            Some(template) => template.render(r, ctx, rc, out),
+            None => Ok(()),
+        }
+    }
+}
+
+const HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte", "and", "or", "not",
+    "len", "contains", "starts_with", "ends_with",
+];
+
+pub const MAX_PARTIAL_DEPTH: usize = 8;
+
+/// Checks that the partials (`{{> name}}`) the templates in `handlebars` include, registered or inline, aren't nested
+/// more than [`MAX_PARTIAL_DEPTH`] deep. Handlebars has no limit of its own, so a template including itself would be
+/// rendered until the stack overflows.
+pub fn check_partials(handlebars: &Handlebars) -> anyhow::Result<()> {
+    let mut partials = HashMap::new();
+
+    for (name, template) in handlebars.get_templates() {
+        collect_partials(name, template, &mut partials)?;
+    }
+
+    for name in handlebars.get_templates().keys() {
+        check_depth(name, &partials, &mut Vec::new())?;
+    }
+
+    Ok(())
+}
+
+fn collect_partials<'t>(name: &'t str, template: &'t Template, partials: &mut HashMap<&'t str, Vec<&'t str>>) -> anyhow::Result<()> {
+    let mut included = Vec::new();
+    let mut pending = vec![template];
+
+    while let Some(template) = pending.pop() {
+        for element in &template.elements {
+            match element {
+                TemplateElement::HelperBlock(helper) => pending.extend(helper.template.iter().chain(&helper.inverse)),
+                TemplateElement::DecoratorBlock(decorator) if decorator.name.as_name() == Some("inline") => {
+                    if let (Some(inline), Some(body)) = (decorator.params.first().and_then(literal_name), &decorator.template) {
+                        collect_partials(inline, body, partials)?;
+                    }
+                }
+                TemplateElement::DecoratorBlock(decorator) => pending.extend(&decorator.template),
+                TemplateElement::PartialExpression(partial) | TemplateElement::PartialBlock(partial) => {
+                    let partial_name = partial.name
+                        .as_name()
+                        .or_else(|| literal_name(&partial.name))
+                        .with_context(|| format!("{name} includes a partial by a computed name, which can't be checked for recursion"))?;
+
+                    // the block passed to the partial, rather than another template
+                    if !partial_name.starts_with('@') {
+                        included.push(partial_name);
+                    }
+
+                    pending.extend(&partial.template);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    partials.entry(name).or_default().extend(included);
+    Ok(())
+}
+
+fn literal_name(parameter: &Parameter) -> Option<&str> {
+    match parameter {
+        Parameter::Literal(JsonValue::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn check_depth<'t>(name: &'t str, partials: &HashMap<&'t str, Vec<&'t str>>, stack: &mut Vec<&'t str>) -> anyhow::Result<()> {
+    anyhow::ensure!(!stack.contains(&name), "partial {name} includes itself: {} > {name}", stack.join(" > "));
+    stack.push(name);
+    anyhow::ensure!(stack.len() <= MAX_PARTIAL_DEPTH, "partials are nested more than {MAX_PARTIAL_DEPTH} deep: {}", stack.join(" > "));
+
+    for partial in partials.get(name).into_iter().flatten() {
+        check_depth(partial, partials, stack)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+pub const MAX_RENDERED_SIZE: usize = 64 * 1024;
+
+/// Renders the template `name`, cutting it short once it writes past `deadline` or [`MAX_RENDERED_SIZE`]. Returns
+/// `None` if it was, so that a runaway template doesn't stall the event loop until it's done.
+///
+/// The limits are checked whenever the template writes something, which is all the time for an `{{#each}}` over a
+/// huge value.
+pub fn render_before<T: serde::Serialize>(handlebars: &Handlebars, name: &str, data: &T, deadline: Instant) -> anyhow::Result<Option<String>> {
+    let mut output = Bounded { output: Vec::new(), deadline, exceeded: false };
+
+    match handlebars.render_to_write(name, data, &mut output) {
+        Err(_) if output.exceeded => Ok(None),
+        Err(error) => Err(error.into()),
+        Ok(()) => String::from_utf8(output.output).map(Some).context("template rendered invalid utf-8"),
+    }
+}
+
+struct Bounded {
+    output: Vec<u8>,
+    deadline: Instant,
+    exceeded: bool,
+}
+
+impl Write for Bounded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.output.len() + buf.len() > MAX_RENDERED_SIZE || Instant::now() > self.deadline {
+            self.exceeded = true;
+            return Err(io::Error::other("render went over budget"))
+        }
+
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Checks that `template` only refers to variables in `known`, so that a typo is an error when the config is loaded
+/// instead of rendering as nothing. Variables in `unavailable`, which the backend has no values for, are only warned
+/// about, as the same template may be used with another backend.
+pub fn validate(name: &str, template: &Template, known: &[&str], unavailable: &[&str]) -> anyhow::Result<()> {
+    let mut referenced = variables(template)
+        .into_iter()
+        .filter(|variable| !variable.starts_with('@') && !HELPERS.contains(&variable.as_str()))
+        .collect::<Vec<_>>();
+    referenced.sort();
+
+    let unknown = referenced.iter().filter(|variable| !known.contains(&variable.as_str())).map(String::as_str).collect::<Vec<_>>();
+    anyhow::ensure!(unknown.is_empty(), "{name} refers to unknown variables: {}", unknown.join(", "));
+
+    let missing = referenced.iter().filter(|variable| unavailable.contains(&variable.as_str())).map(String::as_str).collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        tracing::warn!("{name} refers to variables the backend doesn't provide, which are always empty: {}", missing.join(", "));
+    }
+
+    Ok(())
+}
 
 /// Collects the names of the top level variables a template refers to, e.g. `name` for both `{{ name }}` and
 /// `{{ name.length }}`. This is used to decide which (potentially expensive) values need to be computed at all.
@@ -66,3 +288,113 @@ fn insert_path(raw: &str, variables: &mut HashSet<String>) {
         variables.insert(variable.to_owned());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::stdin::Backend;
+
+    const KNOWN: &[&str] = &["name", "child_process"];
+
+    fn slow() -> (Handlebars<'static>, serde_json::Value) {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_template_string("slow", "{{#each items}}{{#each ../items}}{{#each ../../items}}x{{/each}}{{/each}}{{/each}}").unwrap();
+        (handlebars, serde_json::json!({ "items": vec![0; 1000] }))
+    }
+
+    #[test]
+    fn slow_render_is_cut_short() {
+        let (handlebars, data) = slow();
+        let started = Instant::now();
+
+        let rendered = render_before(&handlebars, "slow", &data, started + Duration::from_millis(10)).unwrap();
+
+        assert_eq!(rendered, None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn huge_render_is_cut_short() {
+        let (handlebars, data) = slow();
+
+        let rendered = render_before(&handlebars, "slow", &data, Instant::now() + Duration::from_secs(60)).unwrap();
+
+        assert_eq!(rendered, None);
+    }
+
+    #[test]
+    fn render_within_budget_is_kept() {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_template_string("name", "{{ name }}").unwrap();
+
+        let rendered = render_before(&handlebars, "name", &serde_json::json!({ "name": "Firefox" }), Instant::now() + Duration::from_secs(60));
+
+        assert_eq!(rendered.unwrap().as_deref(), Some("Firefox"));
+    }
+
+    fn render_helper(template: &str) -> String {
+        let mut handlebars = Handlebars::new();
+        register_helpers(&mut handlebars);
+        handlebars.render_template(template, &serde_json::json!({ "name": "Lo-fi beats - YouTube", "class": "firefox" })).unwrap()
+    }
+
+    #[test]
+    fn string_tests_inline() {
+        assert_eq!(render_helper(r#"{{ contains name "YouTube" }}"#), "true");
+        assert_eq!(render_helper(r#"{{ contains name "youtube" }}"#), "");
+        assert_eq!(render_helper(r#"{{ contains name "youtube" true }}"#), "true");
+        assert_eq!(render_helper(r#"{{ eq class "firefox" }}"#), "true");
+        assert_eq!(render_helper(r#"{{ eq class "Firefox" }}"#), "");
+        assert_eq!(render_helper(r#"{{ eq class "Firefox" true }}"#), "true");
+        assert_eq!(render_helper(r#"{{ ne class "firefox" }}"#), "");
+        assert_eq!(render_helper(r#"{{ ne class "chromium" }}"#), "true");
+        assert_eq!(render_helper(r#"{{ starts_with name "Lo-fi" }}"#), "true");
+        assert_eq!(render_helper(r#"{{ starts_with name "YouTube" }}"#), "");
+        assert_eq!(render_helper(r#"{{ ends_with name "YouTube" }}"#), "true");
+        assert_eq!(render_helper(r#"{{ ends_with name "YOUTUBE" true }}"#), "true");
+        assert_eq!(render_helper(r#"{{ ends_with name "YOUTUBE" false }}"#), "");
+    }
+
+    #[test]
+    fn string_tests_in_if() {
+        assert_eq!(render_helper(r#"{{#if (contains name "YouTube")}}yt{{else}}other{{/if}}"#), "yt");
+        assert_eq!(render_helper(r#"{{#if (eq class "chromium")}}chromium{{else}}other{{/if}}"#), "other");
+        assert_eq!(render_helper(r#"{{#unless (ne class "firefox")}}firefox{{/unless}}"#), "firefox");
+    }
+
+    #[test]
+    fn string_tests_as_blocks() {
+        assert_eq!(render_helper(r#"{{#contains name "YouTube"}}yt{{else}}other{{/contains}}"#), "yt");
+        assert_eq!(render_helper(r#"{{#starts_with class "chrom"}}chromium{{else}}{{ class }}{{/starts_with}}"#), "firefox");
+        assert_eq!(render_helper(r#"{{#eq class "firefox"}}ff{{/eq}}"#), "ff");
+        assert_eq!(render_helper(r#"{{#eq class "chromium"}}ff{{/eq}}"#), "");
+    }
+
+    #[test]
+    fn string_tests_need_two_parameters() {
+        let mut handlebars = Handlebars::new();
+        register_helpers(&mut handlebars);
+
+        let error = handlebars.render_template(r#"{{ contains name }}"#, &serde_json::json!({ "name": "a" })).unwrap_err();
+        assert!(error.to_string().contains("contains expects two parameters"), "{error}");
+    }
+
+    #[test]
+    fn variables_the_backend_lacks_are_only_warned_about() {
+        let template = Template::compile("{{ name }} {{ child_process }}").unwrap();
+
+        for backend in [Backend::X11, Backend::Stdin] {
+            validate("template", &template, KNOWN, backend.unavailable_variables()).unwrap();
+        }
+    }
+
+    #[test]
+    fn variables_no_backend_provides_are_errors() {
+        let template = Template::compile("{{ nmae }}").unwrap();
+
+        for backend in [Backend::X11, Backend::Stdin] {
+            assert!(validate("template", &template, KNOWN, backend.unavailable_variables()).is_err());
+        }
+    }
+}
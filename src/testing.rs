@@ -0,0 +1,232 @@
+//! Helpers shared by the tests of several modules.
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::io::IoSlice;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, process};
+use x11rb::connection::{BufWithFds, Connection, ReplyOrError, RequestConnection, RequestKind};
+use x11rb::cookie::{Cookie, CookieWithFds, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError, ReplyOrIdError};
+use x11rb::extension_manager::ExtensionManager;
+use x11rb::protocol::xproto::{Setup, Window};
+use x11rb::protocol::Event;
+use x11rb::reexports::x11rb_protocol::{DiscardMode, RawEventAndSeqNumber, SequenceNumber};
+use x11rb::utils::RawFdContainer;
+use x11rb::x11_utils::{ExtensionInformation, TryParse, TryParseFd, X11Error};
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("polybar-title-module-{name}-{}-{count}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A xorshift generator for property tests, seeded so that failures reproduce.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    pub fn string(&mut self, alphabet: &[char], max_len: usize) -> String {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| *self.pick(alphabet)).collect()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Logged {
+    Sent(Vec<u8>),
+    Answered,
+}
+
+/// A connection which records the requests sent through it, and fails those about `gone` windows like the X server
+/// does for windows which were destroyed. Only requests about a window (which is their first field) are supported.
+/// The other requests with a reply get the ones queued with [`MockConnection::reply`], in order. Extensions are only
+/// supported by the ones made with [`MockConnection::with_extensions`].
+#[derive(Default)]
+pub struct MockConnection {
+    extensions: bool,
+    gone: HashSet<Window>,
+    log: RefCell<Vec<Logged>>,
+    windows: RefCell<Vec<Window>>,
+    replies: RefCell<VecDeque<Vec<u8>>>,
+    setup: Setup,
+}
+
+impl MockConnection {
+    pub fn with_gone(gone: impl IntoIterator<Item = Window>) -> Self {
+        Self { gone: gone.into_iter().collect(), ..Self::default() }
+    }
+
+    pub fn with_extensions() -> Self {
+        Self { extensions: true, ..Self::default() }
+    }
+
+    pub fn reply(&self, reply: Vec<u8>) {
+        self.replies.borrow_mut().push_back(reply);
+    }
+
+    pub fn take(&self) -> Vec<Logged> {
+        self.log.take()
+    }
+
+    fn send(&self, bufs: &[IoSlice<'_>]) -> SequenceNumber {
+        let request = bufs.iter().flat_map(|buf| buf.iter().copied()).collect::<Vec<_>>();
+        let mut windows = self.windows.borrow_mut();
+        windows.push(u32_at(&request, 4));
+        self.log.borrow_mut().push(Logged::Sent(request));
+
+        windows.len() as SequenceNumber - 1
+    }
+
+    fn error(&self, sequence: SequenceNumber) -> Option<Vec<u8>> {
+        self.log.borrow_mut().push(Logged::Answered);
+        let window = self.windows.borrow()[sequence as usize];
+
+        if !self.gone.contains(&window) {
+            return None
+        }
+
+        let mut error = vec![0, 3];
+        error.extend_from_slice(&(sequence as u16).to_ne_bytes());
+        error.extend_from_slice(&window.to_ne_bytes());
+        error.resize(32, 0);
+
+        Some(error)
+    }
+}
+
+pub fn u32_at(request: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(request[offset..offset + 4].try_into().unwrap())
+}
+
+impl RequestConnection for MockConnection {
+    type Buf = Vec<u8>;
+
+    fn send_request_with_reply<R: TryParse>(&self, bufs: &[IoSlice<'_>], _: Vec<RawFdContainer>) -> Result<Cookie<'_, Self, R>, ConnectionError> {
+        Ok(Cookie::new(self, self.send(bufs)))
+    }
+
+    fn send_request_with_reply_with_fds<R: TryParseFd>(&self, bufs: &[IoSlice<'_>], _: Vec<RawFdContainer>) -> Result<CookieWithFds<'_, Self, R>, ConnectionError> {
+        Ok(CookieWithFds::new(self, self.send(bufs)))
+    }
+
+    fn send_request_without_reply(&self, bufs: &[IoSlice<'_>], _: Vec<RawFdContainer>) -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        Ok(VoidCookie::new(self, self.send(bufs)))
+    }
+
+    fn discard_reply(&self, _: SequenceNumber, _: RequestKind, _: DiscardMode) {}
+
+    fn prefetch_extension_information(&self, _: &'static str) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn extension_information(&self, _: &'static str) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        Ok(self.extensions.then_some(ExtensionInformation { major_opcode: 128, first_event: 64, first_error: 128 }))
+    }
+
+    fn wait_for_reply_or_raw_error(&self, sequence: SequenceNumber) -> Result<ReplyOrError<Vec<u8>>, ConnectionError> {
+        match self.error(sequence) {
+            Some(error) => Ok(ReplyOrError::Error(error)),
+            None => Ok(ReplyOrError::Reply(self.replies.borrow_mut().pop_front().expect("no reply is queued"))),
+        }
+    }
+
+    fn wait_for_reply(&self, sequence: SequenceNumber) -> Result<Option<Vec<u8>>, ConnectionError> {
+        match self.error(sequence) {
+            Some(_) => Ok(None),
+            None => Ok(Some(self.replies.borrow_mut().pop_front().expect("no reply is queued"))),
+        }
+    }
+
+    fn wait_for_reply_with_fds_raw(&self, sequence: SequenceNumber) -> Result<ReplyOrError<BufWithFds<Vec<u8>>, Vec<u8>>, ConnectionError> {
+        Ok(ReplyOrError::Error(self.error(sequence).expect("replies are only mocked for windows which are gone")))
+    }
+
+    fn check_for_raw_error(&self, sequence: SequenceNumber) -> Result<Option<Vec<u8>>, ConnectionError> {
+        Ok(self.error(sequence))
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {}
+
+    fn maximum_request_bytes(&self) -> usize {
+        1 << 18
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        X11Error::try_parse(error, &ExtensionManager::default())
+    }
+
+    fn parse_event(&self, _: &[u8]) -> Result<Event, ParseError> {
+        unimplemented!("events aren't mocked")
+    }
+}
+
+/// Has no events, and nothing to flush since requests are handled as soon as they're sent.
+impl Connection for MockConnection {
+    fn wait_for_raw_event_with_sequence(&self) -> Result<RawEventAndSeqNumber<Vec<u8>>, ConnectionError> {
+        unimplemented!("events aren't mocked")
+    }
+
+    fn poll_for_raw_event_with_sequence(&self) -> Result<Option<RawEventAndSeqNumber<Vec<u8>>>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        unimplemented!("ids aren't mocked")
+    }
+}
@@ -0,0 +1,286 @@
+//! Measuring and truncating output which contains polybar formatting tags (`%{F#f00}`, `%{A1:command:}`, ...).
+//! Tags take no space on the bar, and cutting into one would break the whole line, so they're skipped when counting
+//! and tags left open by a cut are closed again.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Copy, Clone, Debug)]
+enum Token<'v> {
+    /// Something taking space on the bar, which is either one grapheme cluster or an escaped `%%`.
+    Visible(&'v str),
+    Tag(&'v str),
+
+    /// A `%{` which is never closed. It's dropped when truncating, as it can't be cut safely.
+    Unterminated,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Open {
+    Foreground,
+    Background,
+    UnderlineColor,
+    Font,
+    Action,
+    Underline,
+    Overline,
+}
+
+impl Open {
+    fn close(self) -> &'static str {
+        match self {
+            Self::Foreground => "%{F-}",
+            Self::Background => "%{B-}",
+            Self::UnderlineColor => "%{U-}",
+            Self::Font => "%{T-}",
+            Self::Action => "%{A}",
+            Self::Underline => "%{-u}",
+            Self::Overline => "%{-o}",
+        }
+    }
+}
+
+pub fn visible_len(value: &str) -> usize {
+    tokens(value).filter(|token| matches!(token, Token::Visible(_))).count()
+}
+
+/// Cuts `value` down to at most `max` visible grapheme clusters. Tags are never cut into, and every tag still open at the
+/// cut is closed, innermost first. A `%{` which is never closed is dropped along with everything after it, even if
+/// the value is short enough, as it would swallow whatever the bar shows next to it.
+pub fn truncate(value: &str, max: usize) -> String {
+    if visible_len(value) <= max && !tokens(value).any(|token| matches!(token, Token::Unterminated)) {
+        return value.to_owned()
+    }
+
+    let mut truncated = String::with_capacity(value.len());
+    let mut open = Vec::new();
+    let mut visible = 0;
+
+    for token in tokens(value) {
+        match token {
+            Token::Visible(_) if visible == max => break,
+            Token::Visible(text) => {
+                visible += 1;
+                truncated.push_str(text);
+            }
+            Token::Tag(contents) => {
+                track(contents, &mut open);
+                truncated.push_str("%{");
+                truncated.push_str(contents);
+                truncated.push('}');
+            }
+            Token::Unterminated => {}
+        }
+    }
+
+    for tag in open.iter().rev() {
+        truncated.push_str(tag.close())
+    }
+
+    truncated
+}
+
+/// The pieces of `value` which take space on the bar, in order: whole grapheme clusters and escaped `%%`. Tags are
+/// left out.
+pub fn visible_graphemes(value: &str) -> Vec<String> {
+    tokens(value)
+        .filter_map(|token| match token {
+            Token::Visible(text) => Some(text.to_owned()),
+            Token::Tag(_) | Token::Unterminated => None,
+        })
+        .collect()
+}
+
+fn tokens(value: &str) -> impl Iterator<Item = Token<'_>> {
+    let mut rest = value;
+
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None
+        }
+
+        let (token, len) = if rest.starts_with("%%") {
+            (Token::Visible(&rest[..2]), 2)
+        } else if let Some(tag) = rest.strip_prefix("%{") {
+            match tag_end(tag) {
+                Some(end) => (Token::Tag(&tag[..end]), end + 3),
+                None => (Token::Unterminated, rest.len()),
+            }
+        } else {
+            // a cluster never reaches into a following tag or escape, even after a prepended character
+            let grapheme = rest.graphemes(true).next().unwrap_or(rest);
+            let len = grapheme.char_indices().skip(1).find(|(_, c)| *c == '%').map_or(grapheme.len(), |(index, _)| index);
+            (Token::Visible(&rest[..len]), len)
+        };
+
+        rest = &rest[len..];
+        Some(token)
+    })
+}
+
+/// Finds the `}` closing a tag, skipping over the commands of actions which may contain braces themselves.
+pub(crate) fn tag_end(tag: &str) -> Option<usize> {
+    let mut in_command = false;
+    let mut escaped = false;
+    let mut previous = None;
+
+    for (index, c) in tag.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_command => escaped = true,
+            ':' if in_command => in_command = false,
+            ':' if matches!(previous, Some('A' | '0'..='9')) && action_starts_before(&tag[..index]) => in_command = true,
+            '}' if !in_command => return Some(index),
+            _ => {}
+        }
+
+        previous = Some(c);
+    }
+
+    None
+}
+
+fn action_starts_before(before: &str) -> bool {
+    let word = before.rsplit(' ').next().unwrap_or(before);
+    word.strip_prefix('A').is_some_and(|button| button.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Updates the tags in effect with the contents of one `%{...}` tag, which may hold several space separated ones.
+/// Actions nest, while colors, fonts and lines are simply on or off (`%{F-}` resets the color, whatever was set
+/// before).
+fn track(contents: &str, open: &mut Vec<Open>) {
+    let mut rest = contents.trim_start();
+
+    while !rest.is_empty() {
+        let item_len = rest.find(' ').unwrap_or(rest.len());
+        let item = &rest[..item_len];
+        let mut next = &rest[item_len..];
+
+        if let Some(action) = item.strip_prefix('A') {
+            let button = action.trim_start_matches(|c: char| c.is_ascii_digit());
+
+            if button.starts_with(':') {
+                open.push(Open::Action);
+
+                // the command may contain spaces, so the item only ends after it
+                let colon = item.len() - button.len();
+                let command = &rest[colon + 1..];
+                next = command_end(command).map_or("", |end| &command[end + 1..]);
+            } else {
+                close(open, Open::Action);
+            }
+        } else {
+            let kind = match item.as_bytes() {
+                [b'F', ..] => Some(Open::Foreground),
+                [b'B', ..] => Some(Open::Background),
+                [b'U', ..] => Some(Open::UnderlineColor),
+                [b'T', ..] => Some(Open::Font),
+                [b'+' | b'-', b'u'] => Some(Open::Underline),
+                [b'+' | b'-', b'o'] => Some(Open::Overline),
+                _ => None,
+            };
+
+            match kind {
+                Some(kind) if item.ends_with('-') || item.starts_with('-') => close(open, kind),
+                Some(kind) if !open.contains(&kind) => open.push(kind),
+                _ => {}
+            }
+        }
+
+        rest = next.trim_start();
+    }
+}
+
+fn command_end(command: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, c) in command.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ':' => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn close(open: &mut Vec<Open>, kind: Open) {
+    if let Some(index) = open.iter().rposition(|tag| *tag == kind) {
+        open.remove(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_length_skips_tags() {
+        assert_eq!(visible_len("Firefox"), 7);
+        assert_eq!(visible_len("%{F#f00}Fire%{F-}fox"), 7);
+        assert_eq!(visible_len("100%% done"), 9);
+        assert_eq!(visible_len("%{A1:notify-send {a}:}x%{A}"), 1);
+        assert_eq!(visible_len("%{F#f00"), 0);
+    }
+
+    #[test]
+    fn short_values_are_kept() {
+        assert_eq!(truncate("%{F#f00}Firefox", 7), "%{F#f00}Firefox");
+    }
+
+    #[test]
+    fn cut_inside_a_tag() {
+        // the cut would land in the middle of `%{F#f00}`, which is kept whole and closed again
+        assert_eq!(truncate("Fi%{F#f00}refox", 2), "Fi%{F#f00}%{F-}");
+        assert_eq!(truncate("Fi%{F#f00}refox%{F-}", 3), "Fi%{F#f00}r%{F-}");
+    }
+
+    #[test]
+    fn nested_tags_close_innermost_first() {
+        assert_eq!(
+            truncate("%{A1:xdotool key super:}%{F#f00}%{+u}Firefox%{-u}%{F-}%{A}", 4),
+            "%{A1:xdotool key super:}%{F#f00}%{+u}Fire%{-u}%{F-}%{A}",
+        );
+        assert_eq!(truncate("%{A1:a:}%{A3:b:}Firefox%{A}%{A}", 1), "%{A1:a:}%{A3:b:}F%{A}%{A}");
+    }
+
+    #[test]
+    fn tags_closed_before_the_cut_stay_closed() {
+        assert_eq!(truncate("%{F#f00}Fire%{F-} fox", 5), "%{F#f00}Fire%{F-} ");
+        assert_eq!(truncate("%{B#000 F#fff}Firefox", 4), "%{B#000 F#fff}Fire%{F-}%{B-}");
+    }
+
+    #[test]
+    fn action_commands_with_braces_and_colons() {
+        let value = r"%{A1:i3-msg '[class=\:x] focus' {}:}Firefox%{A}";
+        assert_eq!(truncate(value, 4), r"%{A1:i3-msg '[class=\:x] focus' {}:}Fire%{A}");
+    }
+
+    #[test]
+    fn unclosed_tag_in_the_input_is_dropped() {
+        assert_eq!(truncate("Firefox%{F#f00", 4), "Fire");
+        assert_eq!(truncate("Fire%{F#f00 fox and more", 4), "Fire");
+        assert_eq!(truncate("Fire%{F#f00", 10), "Fire");
+    }
+
+    #[test]
+    fn escaped_percent_is_never_split() {
+        assert_eq!(truncate("100%% done", 4), "100%%");
+        assert_eq!(truncate("%%%{F#f00}%%", 1), "%%%{F#f00}%{F-}");
+    }
+
+    #[test]
+    fn cut_between_grapheme_clusters() {
+        let value = "cafe\u{301}👩‍👩‍👧!";
+        assert_eq!(visible_len(value), 6);
+        assert_eq!(truncate(value, 4), "cafe\u{301}");
+        assert_eq!(truncate(value, 5), "cafe\u{301}👩‍👩‍👧");
+        assert_eq!(truncate("%{F#f00}e\u{301}%{F-}👩‍👩‍👧", 1), "%{F#f00}e\u{301}%{F-}");
+    }
+
+    #[test]
+    fn graphemes_are_whole() {
+        assert_eq!(visible_graphemes("%{F#f00}e\u{301}👩‍👩‍👧%%x"), ["e\u{301}", "👩‍👩‍👧", "%%", "x"]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Durations and sizes in the config, which can be written the humane way (`"250ms"`, `"2s"`, `"4KiB"`) as well as
+//! bare integers in the unit the option always used, so that configs from before keep working.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub type Millis = Duration<1>;
+
+pub type Seconds = Duration<1000>;
+
+/// A duration like `"250ms"`, `"2s"` or `"1h 30m"`. A bare integer counts `BARE_UNIT_MS` milliseconds each. It's
+/// serialized the humane way.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Duration<const BARE_UNIT_MS: u64>(time::Duration);
+
+impl<const BARE_UNIT_MS: u64> Duration<BARE_UNIT_MS> {
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(time::Duration::from_millis(millis))
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(time::Duration::from_secs(secs))
+    }
+
+    pub const fn get(self) -> time::Duration {
+        self.0
+    }
+
+    fn from_bare(value: u64) -> Result<Self, String> {
+        value
+            .checked_mul(BARE_UNIT_MS)
+            .map(Self::from_millis)
+            .ok_or_else(|| format!("{value} is too long a duration"))
+    }
+}
+
+impl<const BARE_UNIT_MS: u64> From<Duration<BARE_UNIT_MS>> for time::Duration {
+    fn from(duration: Duration<BARE_UNIT_MS>) -> Self {
+        duration.0
+    }
+}
+
+impl<const BARE_UNIT_MS: u64> FromStr for Duration<BARE_UNIT_MS> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(value) = s.parse() {
+            return Self::from_bare(value)
+        }
+
+        humantime::parse_duration(s)
+            .map(Self)
+            .map_err(|error| format!("invalid duration {s:?} ({error}), expected something like \"250ms\", \"2s\" or \"1m\""))
+    }
+}
+
+impl<const BARE_UNIT_MS: u64> fmt::Display for Duration<BARE_UNIT_MS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
+impl<'de, const BARE_UNIT_MS: u64> Deserialize<'de> for Duration<BARE_UNIT_MS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(BareOrHumane::<Self>::new("a duration like \"250ms\" or an integer"))
+    }
+}
+
+impl<const BARE_UNIT_MS: u64> Serialize for Duration<BARE_UNIT_MS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+const BYTE_UNITS: [(&str, u64); 9] = [
+    ("b", 1),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("k", 1 << 10),
+    ("m", 1 << 20),
+];
+
+/// A size in bytes like `"512B"`, `"4KiB"` or `"1MB"`, where units are case insensitive and `K` and `M` are binary.
+/// A bare integer is in bytes. It's serialized with the largest binary unit it's a multiple of.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ByteSize(u64);
+
+impl ByteSize {
+    pub const fn from_bytes(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn bytes(self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let invalid = || format!("invalid size {s:?}, expected something like \"512B\", \"4KiB\" or \"1MB\"");
+
+        let number = number.parse::<u64>().map_err(|_| invalid())?;
+        let unit = unit.trim().to_ascii_lowercase();
+        let multiplier = match unit.as_str() {
+            "" => 1,
+            unit => BYTE_UNITS.iter().find(|(name, _)| *name == unit).map(|(_, bytes)| *bytes).ok_or_else(invalid)?,
+        };
+
+        number
+            .checked_mul(multiplier)
+            .map(Self)
+            .ok_or_else(|| format!("{s:?} is too large a size"))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, bytes) = [("GiB", 1 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)]
+            .into_iter()
+            .find(|(_, bytes)| self.0 != 0 && self.0.is_multiple_of(*bytes))
+            .unwrap_or(("B", 1));
+
+        write!(f, "{}{unit}", self.0 / bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(BareOrHumane::<Self>::new("a size like \"4KiB\" or an integer"))
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct BareOrHumane<T> {
+    expecting: &'static str,
+    value: std::marker::PhantomData<T>,
+}
+
+impl<T> BareOrHumane<T> {
+    fn new(expecting: &'static str) -> Self {
+        Self { expecting, value: std::marker::PhantomData }
+    }
+}
+
+impl<T: FromStr<Err = String>> Visitor<'_> for BareOrHumane<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        value.to_string().parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        match u64::try_from(value) {
+            Ok(value) => self.visit_u64(value),
+            Err(_) => Err(E::custom(format!("{value} is negative"))),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        value.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use super::*;
+
+    #[derive(Deserialize, Serialize)]
+    struct Value<T> {
+        value: T,
+    }
+
+    fn parse<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+        toml::from_str::<Value<T>>(&format!("value = {value}")).map(|value| value.value).map_err(|error| error.to_string())
+    }
+
+    fn serialize<T: Serialize>(value: T) -> String {
+        toml::to_string(&Value { value }).unwrap().trim_end().trim_start_matches("value = ").to_owned()
+    }
+
+    #[test]
+    fn duration_units() {
+        for (value, millis) in [
+            ("\"250ms\"", 250),
+            ("\"2s\"", 2_000),
+            ("\"1m\"", 60_000),
+            ("\"1h 30m\"", 5_400_000),
+            ("\"1s 500ms\"", 1_500),
+            ("\"0s\"", 0),
+        ] {
+            assert_eq!(parse::<Millis>(value).unwrap().get(), time::Duration::from_millis(millis), "{value}");
+        }
+    }
+
+    #[test]
+    fn bare_durations_are_in_the_unit_of_the_option() {
+        assert_eq!(parse::<Millis>("250").unwrap(), Millis::from_millis(250));
+        assert_eq!(parse::<Seconds>("2").unwrap(), Seconds::from_secs(2));
+        assert_eq!(parse::<Seconds>("\"2\"").unwrap(), Seconds::from_secs(2));
+        assert_eq!(parse::<Seconds>("\"2s\"").unwrap(), Seconds::from_secs(2));
+        assert!(parse::<Seconds>(&u64::MAX.to_string()).is_err());
+    }
+
+    #[test]
+    fn size_units() {
+        for (value, bytes) in [
+            ("512", 512),
+            ("\"512\"", 512),
+            ("\"512B\"", 512),
+            ("\"4KiB\"", 4096),
+            ("\"4kib\"", 4096),
+            ("\"4K\"", 4096),
+            ("\"4KB\"", 4000),
+            ("\"1MiB\"", 1 << 20),
+            ("\"1M\"", 1 << 20),
+            ("\"1MB\"", 1_000_000),
+            ("\"2GiB\"", 2 << 30),
+            ("\"1GB\"", 1_000_000_000),
+        ] {
+            assert_eq!(parse::<ByteSize>(value).unwrap().bytes(), bytes, "{value}");
+        }
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(parse::<Millis>("\" 250ms \"").unwrap(), Millis::from_millis(250));
+        assert_eq!(parse::<Millis>("\"\\t250\\n\"").unwrap(), Millis::from_millis(250));
+        assert_eq!(parse::<ByteSize>("\" 4 KiB \"").unwrap(), ByteSize::from_bytes(4096));
+    }
+
+    #[test]
+    fn nonsense_is_rejected() {
+        for value in ["-1", "\"-1s\"", "\"\"", "\"fast\"", "\"2 fortnights\"", "\"1.5\"", "1.5", "true", "[]"] {
+            assert!(parse::<Millis>(value).is_err(), "{value}");
+        }
+
+        for value in ["-1", "\"-1KiB\"", "\"\"", "\"KiB\"", "\"4 XB\"", "\"1.5MiB\"", "1.5", "\"99999999999GiB\""] {
+            assert!(parse::<ByteSize>(value).is_err(), "{value}");
+        }
+
+        assert!(parse::<Millis>("-1").unwrap_err().contains("-1 is negative"));
+        assert!(parse::<ByteSize>("\"4 XB\"").unwrap_err().contains("expected something like"));
+    }
+
+    #[test]
+    fn serialized_the_humane_way() {
+        assert_eq!(serialize(Millis::from_millis(250)), "\"250ms\"");
+        assert_eq!(serialize(Millis::from_millis(5_400_000)), "\"1h 30m\"");
+        assert_eq!(serialize(Seconds::from_secs(2)), "\"2s\"");
+        assert_eq!(serialize(ByteSize::from_bytes(4096)), "\"4KiB\"");
+        assert_eq!(serialize(ByteSize::from_bytes(3 << 20)), "\"3MiB\"");
+        assert_eq!(serialize(ByteSize::from_bytes(1000)), "\"1000B\"");
+        assert_eq!(serialize(ByteSize::from_bytes(0)), "\"0B\"");
+
+        // and read back as the same
+        for millis in [0, 1, 999, 1_000, 61_001, 86_400_000] {
+            assert_eq!(parse::<Millis>(&serialize(Millis::from_millis(millis))).unwrap(), Millis::from_millis(millis));
+        }
+    }
+}
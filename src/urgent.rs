@@ -0,0 +1,289 @@
+//! Alternating between the focused window and background windows which demand attention (`alternate_interval`).
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, EventMask, PropertyNotifyEvent, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::intern_atom;
+use crate::subscriptions::SubscriptionManager;
+use crate::wm_state::WmStateReader;
+
+/// How long to wait for further changes of the client list before reading it, so that windows opening or closing in
+/// quick succession are handled in one go.
+const CLIENT_LIST_DELAY: Duration = Duration::from_millis(250);
+
+/// Keeps track of which client windows are urgent, and which of the display items (the focused window, then every
+/// other urgent window) is currently displayed.
+pub struct UrgentWindows {
+    reader: WmStateReader,
+    net_client_list: Atom,
+    clients: SubscriptionManager,
+    clients_changed: Option<Instant>,
+    rotation: Rotation,
+}
+
+impl UrgentWindows {
+    /// Starts tracking the client windows, taking over the subscriptions of `previous`, e.g. from before the config
+    /// was reloaded, so that windows which are subscribed already aren't subscribed again.
+    pub fn new(connection: &RustConnection, root: Window, interval: Duration, previous: Option<&Self>) -> anyhow::Result<Self> {
+        let mut urgent = Self {
+            reader: WmStateReader::new(connection)?,
+            net_client_list: intern_atom(connection, "_NET_CLIENT_LIST")?,
+            clients: previous.map_or_else(|| SubscriptionManager::new(EventMask::PROPERTY_CHANGE), |previous| previous.clients.clone()),
+            clients_changed: None,
+            rotation: Rotation::new(interval),
+        };
+        urgent.update_clients(connection, root)?;
+
+        for window in urgent.clients.windows().clone() {
+            urgent.update_window(connection, window);
+        }
+
+        Ok(urgent)
+    }
+
+    pub fn stop(mut self, connection: &RustConnection) -> anyhow::Result<()> {
+        self.clients.update(connection, HashSet::new())?;
+        Ok(())
+    }
+
+    pub fn is_client_list_atom(&self, atom: Atom) -> bool {
+        atom == self.net_client_list
+    }
+
+    pub fn is_client(&self, window: Window) -> bool {
+        self.clients.contains(window)
+    }
+
+    pub fn on_client_list_change(&mut self, now: Instant) {
+        self.clients_changed.get_or_insert(now + CLIENT_LIST_DELAY);
+    }
+
+    pub fn clients_due(&self, now: Instant) -> bool {
+        self.clients_changed.is_some_and(|changed| now >= changed)
+    }
+
+    pub fn update_clients(&mut self, connection: &RustConnection, root: Window) -> anyhow::Result<()> {
+        self.clients_changed = None;
+
+        let clients = connection
+            .get_property(false, root, self.net_client_list, AtomEnum::WINDOW, 0, u32::MAX)
+            .context("failed to make GetProperty reply for retrieving _NET_CLIENT_LIST")?
+            .reply()
+            .context("GetProperty response for retrieving _NET_CLIENT_LIST failed")?
+            .value32()
+            .map(Iterator::collect::<HashSet<_>>)
+            .unwrap_or_default();
+        tracing::debug!(clients = clients.len(), "read client list");
+
+        self.rotation.urgent.retain(|window| clients.contains(window));
+        let added = self.clients.update(connection, clients).context("failed to subscribe to client windows")?;
+
+        for window in added {
+            self.update_window(connection, window);
+        }
+
+        Ok(())
+    }
+
+    pub fn on_property_notify(&mut self, connection: &RustConnection, event: &PropertyNotifyEvent) -> bool {
+        if !self.clients.contains(event.window) || !self.reader.is_state_atom(event.atom) {
+            return false
+        }
+
+        self.update_window(connection, event.window)
+    }
+
+    fn update_window(&mut self, connection: &RustConnection, window: Window) -> bool {
+        let urgent = match self.reader.get(connection, window) {
+            Ok(state) => state.urgent,
+            Err(error) => {
+                tracing::debug!(%window, "failed to get state of client window, treating it as not urgent: {error:#}");
+                false
+            }
+        };
+
+        self.rotation.set_urgent(window, urgent)
+    }
+
+    pub fn reschedule(&mut self, focused: Option<Window>, now: Instant) {
+        self.rotation.reschedule(focused, now)
+    }
+
+    pub fn reset(&mut self, focused: Option<Window>, now: Instant) {
+        self.rotation.reset(focused, now)
+    }
+
+    pub fn current(&self, focused: Option<Window>) -> Option<Window> {
+        self.rotation.current(focused)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        [self.rotation.next, self.clients_changed].into_iter().flatten().min()
+    }
+
+    pub fn on_deadline(&mut self, focused: Option<Window>, now: Instant) -> bool {
+        self.rotation.on_deadline(focused, now)
+    }
+}
+
+/// The urgent windows in the order they became urgent, and which display item is shown.
+#[derive(Debug)]
+struct Rotation {
+    interval: Duration,
+    urgent: Vec<Window>,
+
+    /// The index of the displayed item, where 0 is the focused window.
+    current: usize,
+    next: Option<Instant>,
+}
+
+impl Rotation {
+    fn new(interval: Duration) -> Self {
+        Self { interval, urgent: Vec::new(), current: 0, next: None }
+    }
+
+    /// Returns whether `window` changed from or to being urgent.
+    fn set_urgent(&mut self, window: Window, urgent: bool) -> bool {
+        let position = self.urgent.iter().position(|urgent| *urgent == window);
+
+        match (urgent, position) {
+            (true, None) => {
+                tracing::debug!(%window, "window became urgent");
+                self.urgent.push(window)
+            }
+            (false, Some(position)) => {
+                tracing::debug!(%window, "window is no longer urgent");
+                self.urgent.remove(position);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn others(&self, focused: Option<Window>) -> impl Iterator<Item = Window> + '_ {
+        self.urgent.iter().copied().filter(move |window| Some(*window) != focused)
+    }
+
+    /// Starts or stops alternating depending on whether windows other than the `focused` one are urgent, going back
+    /// to the focused window if the displayed item is gone.
+    fn reschedule(&mut self, focused: Option<Window>, now: Instant) {
+        let others = self.others(focused).count();
+
+        if others == 0 {
+            self.current = 0;
+            self.next = None;
+            return
+        }
+
+        if self.current > others {
+            self.current = 0;
+        }
+
+        self.next.get_or_insert(now + self.interval);
+    }
+
+    fn reset(&mut self, focused: Option<Window>, now: Instant) {
+        self.current = 0;
+        self.next = None;
+        self.reschedule(focused, now);
+    }
+
+    fn current(&self, focused: Option<Window>) -> Option<Window> {
+        self.current.checked_sub(1).and_then(|index| self.others(focused).nth(index))
+    }
+
+    fn on_deadline(&mut self, focused: Option<Window>, now: Instant) -> bool {
+        if self.next.is_none_or(|next| now < next) {
+            return false
+        }
+
+        let items = self.others(focused).count() + 1;
+        self.current = (self.current + 1) % items;
+        self.next = None;
+        self.reschedule(focused, now);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(3);
+    const FOCUSED: Option<Window> = Some(1);
+
+    #[test]
+    fn alternates_every_interval() {
+        let start = Instant::now();
+        let mut rotation = Rotation::new(INTERVAL);
+        assert!(rotation.set_urgent(2, true));
+        assert!(rotation.set_urgent(3, true));
+        assert!(!rotation.set_urgent(3, true));
+        rotation.reschedule(FOCUSED, start);
+
+        assert_eq!(rotation.next, Some(start + INTERVAL));
+        assert!(!rotation.on_deadline(FOCUSED, start + INTERVAL / 2));
+        assert_eq!(rotation.current(FOCUSED), None);
+
+        let mut displayed = Vec::new();
+        let mut now = start;
+
+        for _ in 0..4 {
+            now = rotation.next.unwrap();
+            assert!(rotation.on_deadline(FOCUSED, now));
+            displayed.push(rotation.current(FOCUSED));
+        }
+
+        assert_eq!(displayed, [Some(2), Some(3), None, Some(2)]);
+        assert_eq!(rotation.next, Some(now + INTERVAL));
+    }
+
+    #[test]
+    fn focused_window_isnt_alternated_with() {
+        let mut rotation = Rotation::new(INTERVAL);
+        rotation.set_urgent(1, true);
+        rotation.reschedule(FOCUSED, Instant::now());
+
+        assert_eq!(rotation.next, None);
+    }
+
+    #[test]
+    fn losing_urgency_goes_back_to_the_focused_window() {
+        let start = Instant::now();
+        let mut rotation = Rotation::new(INTERVAL);
+        rotation.set_urgent(2, true);
+        rotation.reschedule(FOCUSED, start);
+        rotation.on_deadline(FOCUSED, start + INTERVAL);
+        assert_eq!(rotation.current(FOCUSED), Some(2));
+
+        assert!(rotation.set_urgent(2, false));
+        assert!(!rotation.set_urgent(2, false));
+        rotation.reschedule(FOCUSED, start + INTERVAL);
+
+        assert_eq!(rotation.current(FOCUSED), None);
+        assert_eq!((rotation.current, rotation.next), (0, None));
+        assert!(!rotation.on_deadline(FOCUSED, start + INTERVAL * 10));
+    }
+
+    #[test]
+    fn displayed_window_losing_urgency_starts_over() {
+        let start = Instant::now();
+        let mut rotation = Rotation::new(INTERVAL);
+        rotation.set_urgent(2, true);
+        rotation.set_urgent(3, true);
+        rotation.reschedule(FOCUSED, start);
+        rotation.on_deadline(FOCUSED, start + INTERVAL);
+        rotation.on_deadline(FOCUSED, start + INTERVAL * 2);
+        assert_eq!(rotation.current(FOCUSED), Some(3));
+
+        rotation.set_urgent(3, false);
+        rotation.reschedule(FOCUSED, start + INTERVAL * 2);
+
+        assert_eq!(rotation.current(FOCUSED), None);
+        assert_eq!(rotation.next, Some(start + INTERVAL * 3));
+    }
+}
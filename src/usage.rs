@@ -0,0 +1,201 @@
+//! How long windows of each class were focused since the module started, replied to `stats usage` on the control
+//! socket and, with `usage_tracking`, kept in the state file across restarts.
+//!
+//! This is wall-clock time: a window counts as focused for as long as it's displayed, whether or not anyone is
+//! looking at it. Nothing is counted while no window is active or writing is paused.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// How often the usage is written to the state file when it's persisted. What accumulated since is lost when the
+/// module exits.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq, Debug)]
+pub struct ClassUsage {
+    pub class: String,
+    pub total_seconds: u64,
+
+    /// How often the class was focused, where switching between windows of the same class doesn't count.
+    pub sessions: u64,
+}
+
+#[derive(Default, Debug)]
+struct Accumulated {
+    total: Duration,
+    sessions: u64,
+}
+
+#[derive(Default, Debug)]
+pub struct UsageTracker {
+    classes: BTreeMap<String, Accumulated>,
+    current: Option<(String, Instant)>,
+    next_save: Option<Instant>,
+    restored: bool,
+}
+
+impl UsageTracker {
+    /// Persists the usage every [`SAVE_INTERVAL`] from `now` on. The usage which was `persisted` before is added to
+    /// the one accumulated so far, the first time only, as it's in there from then on.
+    pub fn start_saving(&mut self, persisted: &[ClassUsage], now: Instant) {
+        if !self.restored {
+            for usage in persisted {
+                let accumulated = self.classes.entry(usage.class.clone()).or_default();
+                accumulated.total += Duration::from_secs(usage.total_seconds);
+                accumulated.sessions += usage.sessions;
+            }
+
+            self.restored = true;
+        }
+
+        self.next_save.get_or_insert(now + SAVE_INTERVAL);
+    }
+
+    pub fn stop_saving(&mut self) {
+        self.next_save = None;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.next_save
+    }
+
+    pub fn on_deadline(&mut self, now: Instant) -> Option<Vec<ClassUsage>> {
+        self.next_save.filter(|next_save| now >= *next_save)?;
+        self.next_save = Some(now + SAVE_INTERVAL);
+
+        Some(self.usage(now))
+    }
+
+    /// Counts the time from now on towards `class`, or nothing if it's `None` (no window is active, or writing is
+    /// paused). Focusing the class which is focused already changes nothing.
+    pub fn focus(&mut self, class: Option<&str>, now: Instant) {
+        let class = class.filter(|class| !class.is_empty());
+
+        if self.current.as_ref().map(|(current, _)| current.as_str()) == class {
+            return
+        }
+
+        self.end_session(now);
+
+        if let Some(class) = class {
+            self.classes.entry(class.to_owned()).or_default().sessions += 1;
+            self.current = Some((class.to_owned(), now));
+        }
+    }
+
+    /// The usage of every class so far, including the time the current class has been focused for, the most used
+    /// class first.
+    pub fn usage(&self, now: Instant) -> Vec<ClassUsage> {
+        let mut usage = self.classes
+            .iter()
+            .map(|(class, accumulated)| {
+                let running = match &self.current {
+                    Some((current, since)) if current == class => now.saturating_duration_since(*since),
+                    _ => Duration::ZERO,
+                };
+
+                ClassUsage {
+                    class: class.clone(),
+                    total_seconds: (accumulated.total + running).as_secs(),
+                    sessions: accumulated.sessions,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // stable, so ties stay ordered by class
+        usage.sort_by_key(|usage| std::cmp::Reverse(usage.total_seconds));
+        usage
+    }
+
+    fn end_session(&mut self, now: Instant) {
+        if let Some((class, since)) = self.current.take() {
+            self.classes.entry(class).or_default().total += now.saturating_duration_since(since);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(class: &str, total_seconds: u64, sessions: u64) -> ClassUsage {
+        ClassUsage { class: class.to_owned(), total_seconds, sessions }
+    }
+
+    #[test]
+    fn focus_sequence() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut tracker = UsageTracker::default();
+
+        tracker.focus(Some("firefox"), at(0));
+        tracker.focus(Some("alacritty"), at(30));
+        // another window of the same class
+        tracker.focus(Some("alacritty"), at(40));
+        tracker.focus(Some("firefox"), at(100));
+        // the desktop
+        tracker.focus(None, at(110));
+        tracker.focus(Some(""), at(200));
+        tracker.focus(Some("firefox"), at(300));
+
+        assert_eq!(tracker.usage(at(305)), [usage("alacritty", 70, 1), usage("firefox", 45, 3)]);
+    }
+
+    #[test]
+    fn nothing_is_counted_while_paused() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut tracker = UsageTracker::default();
+
+        tracker.focus(Some("firefox"), at(0));
+        // pausing focuses nothing, resuming focuses the displayed window again
+        tracker.focus(None, at(10));
+        tracker.focus(Some("firefox"), at(1000));
+
+        assert_eq!(tracker.usage(at(1005)), [usage("firefox", 15, 2)]);
+    }
+
+    #[test]
+    fn ties_are_ordered_by_class() {
+        let start = Instant::now();
+        let mut tracker = UsageTracker::default();
+
+        tracker.focus(Some("b"), start);
+        tracker.focus(Some("a"), start + Duration::from_secs(5));
+        tracker.focus(None, start + Duration::from_secs(10));
+
+        assert_eq!(tracker.usage(start + Duration::from_secs(10)), [usage("a", 5, 1), usage("b", 5, 1)]);
+    }
+
+    #[test]
+    fn persisted_usage_is_added_once() {
+        let start = Instant::now();
+        let mut tracker = UsageTracker::default();
+        let persisted = [usage("firefox", 100, 4)];
+
+        tracker.focus(Some("firefox"), start);
+        tracker.start_saving(&persisted, start);
+        tracker.stop_saving();
+        tracker.start_saving(&persisted, start);
+
+        assert_eq!(tracker.usage(start + Duration::from_secs(20)), [usage("firefox", 120, 5)]);
+    }
+
+    #[test]
+    fn saved_every_interval() {
+        let start = Instant::now();
+        let mut tracker = UsageTracker::default();
+        assert_eq!(tracker.deadline(), None);
+
+        tracker.start_saving(&[], start);
+        tracker.focus(Some("firefox"), start);
+
+        assert_eq!(tracker.on_deadline(start + SAVE_INTERVAL / 2), None);
+        assert_eq!(tracker.on_deadline(start + SAVE_INTERVAL), Some(vec![usage("firefox", SAVE_INTERVAL.as_secs(), 1)]));
+        assert_eq!(tracker.deadline(), Some(start + SAVE_INTERVAL * 2));
+
+        tracker.stop_saving();
+        assert_eq!(tracker.on_deadline(start + SAVE_INTERVAL * 2), None);
+    }
+}
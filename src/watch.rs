@@ -0,0 +1,256 @@
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use crate::Config;
+
+const QUIET_PERIOD: Duration = Duration::from_millis(250);
+
+/// How long to wait before looking again if a config file vanished, which is usually just an editor replacing it.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Watches the config files and reloads the config once they changed.
+///
+/// Editors tend to save in several steps (writing a backup, renaming files around, writing the new file), each of
+/// which triggers the watcher. Triggers are therefore debounced until the files have been quiet for a moment, and
+/// the config is only reloaded if their contents actually differ from what was loaded last.
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    files: Vec<PathBuf>,
+    snapshot: Snapshot,
+    pending: Option<Instant>,
+    retried: bool,
+}
+
+impl ConfigWatcher {
+    pub fn new(files: Vec<PathBuf>) -> anyhow::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .context("failed to initialize inotify")?;
+        let flags = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO;
+
+        // the directories are watched instead of the files, as saving often replaces a file with a new one
+        for file in &files {
+            let directory = directory_of(file);
+
+            if let Err(error) = inotify.add_watch(directory, flags) {
+                tracing::debug!(directory = %directory.display(), "not watching config directory: {error}");
+            }
+        }
+
+        let snapshot = Snapshot::take(&files).context("failed to read config files")?;
+
+        Ok(Self {
+            inotify,
+            files,
+            snapshot,
+            pending: None,
+            retried: false,
+        })
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+    }
+
+    pub fn on_readable(&mut self, now: Instant) -> anyhow::Result<()> {
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return Ok(()),
+                Err(error) => return Err(error).context("failed to read inotify events"),
+            };
+
+            let touched = events
+                .iter()
+                .filter_map(|event| event.name.as_deref())
+                .any(|name| self.files.iter().any(|file| file.file_name() == Some(name)));
+
+            if touched {
+                tracing::debug!("config file touched, waiting for it to settle");
+                self.pending = Some(now + QUIET_PERIOD);
+                self.retried = false;
+            }
+        }
+    }
+
+    /// Reloads the config if it's due and the files changed. Returns the new config, or the reason it couldn't be
+    /// loaded.
+    pub fn on_deadline(&mut self, now: Instant) -> Option<anyhow::Result<Config>> {
+        match self.pending {
+            Some(pending) if now >= pending => self.pending = None,
+            _ => return None,
+        }
+
+        let snapshot = match Snapshot::take(&self.files) {
+            Ok(snapshot) => snapshot,
+            Err(error) => return Some(Err(error).context("failed to read config files")),
+        };
+
+        if snapshot.lost_file_of(&self.snapshot) && !self.retried {
+            tracing::debug!("config file is missing, looking again in case it's being replaced");
+            self.pending = Some(now + RETRY_DELAY);
+            self.retried = true;
+            return None
+        }
+
+        if snapshot.hash == self.snapshot.hash {
+            tracing::debug!("config files didn't change, not reloading");
+            return None
+        }
+
+        tracing::info!("config files changed, reloading");
+        let config = Config::from_sources(snapshot.contents.iter().flatten().map(String::as_str));
+        self.snapshot = snapshot;
+
+        Some(config)
+    }
+}
+
+impl AsRawFd for ConfigWatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
+
+struct Snapshot {
+    contents: Vec<Option<String>>,
+    hash: u64,
+}
+
+impl Snapshot {
+    fn take(files: &[PathBuf]) -> anyhow::Result<Self> {
+        let contents = files
+            .iter()
+            .map(|file| match fs::read_to_string(file) {
+                Ok(content) => Ok(Some(content)),
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error).with_context(|| format!("failed to read {}", file.display())),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+
+        Ok(Self {
+            hash: hasher.finish(),
+            contents,
+        })
+    }
+
+    fn lost_file_of(&self, previous: &Snapshot) -> bool {
+        self.contents
+            .iter()
+            .zip(&previous.contents)
+            .any(|(now, before)| now.is_none() && before.is_some())
+    }
+}
+
+fn directory_of(file: &Path) -> &Path {
+    match file.parent() {
+        Some(parent) if parent != OsStr::new("") => parent,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn config(template: &str) -> String {
+        format!("template = \"{template}\"\n[resolver]\nfilters = {{}}\n")
+    }
+
+    /// Steps through `duration` from `start` like the main loop would, returning the templates of the reloaded configs.
+    fn run(watcher: &mut ConfigWatcher, start: Instant, duration: Duration) -> Vec<String> {
+        let mut reloads = Vec::new();
+        let mut now = start;
+
+        while now < start + duration {
+            watcher.on_readable(now).unwrap();
+
+            if let Some(config) = watcher.on_deadline(now) {
+                reloads.push(config.unwrap().template);
+            }
+
+            now += Duration::from_millis(10);
+        }
+
+        reloads
+    }
+
+    #[test]
+    fn rename_dance_reloads_once() {
+        let dir = TempDir::new("watch-rename");
+        let path = dir.write("config.toml", config("old"));
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]).unwrap();
+        let start = Instant::now();
+
+        // what vim does with `backupcopy=no`
+        fs::rename(&path, dir.path().join("config.toml~")).unwrap();
+        watcher.on_readable(start).unwrap();
+        fs::write(&path, config("new")).unwrap();
+        watcher.on_readable(start + Duration::from_millis(5)).unwrap();
+        fs::remove_file(dir.path().join("config.toml~")).unwrap();
+
+        assert_eq!(run(&mut watcher, start + Duration::from_millis(10), Duration::from_secs(2)), ["new"]);
+    }
+
+    #[test]
+    fn triggers_are_debounced() {
+        let dir = TempDir::new("watch-debounce");
+        let path = dir.write("config.toml", config("old"));
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]).unwrap();
+        let start = Instant::now();
+
+        fs::write(&path, config("first")).unwrap();
+        watcher.on_readable(start).unwrap();
+        assert!(watcher.on_deadline(start + QUIET_PERIOD - Duration::from_millis(1)).is_none());
+
+        fs::write(&path, config("second")).unwrap();
+        watcher.on_readable(start + Duration::from_millis(200)).unwrap();
+        assert!(watcher.on_deadline(start + QUIET_PERIOD).is_none());
+
+        assert_eq!(run(&mut watcher, start + QUIET_PERIOD, Duration::from_secs(1)), ["second"]);
+    }
+
+    #[test]
+    fn missing_file_is_looked_at_again() {
+        let dir = TempDir::new("watch-missing");
+        let path = dir.write("config.toml", config("old"));
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]).unwrap();
+        let start = Instant::now();
+
+        fs::remove_file(&path).unwrap();
+        watcher.on_readable(start).unwrap();
+        assert!(watcher.on_deadline(start + QUIET_PERIOD).is_none());
+
+        // the editor writes the new file before the retry
+        fs::write(&path, config("new")).unwrap();
+        let retry = start + QUIET_PERIOD + RETRY_DELAY;
+        assert_eq!(watcher.on_deadline(retry).map(|config| config.unwrap().template).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn unchanged_contents_dont_reload() {
+        let dir = TempDir::new("watch-unchanged");
+        let path = dir.write("config.toml", config("same"));
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]).unwrap();
+
+        fs::write(&path, config("same")).unwrap();
+        fs::write(dir.path().join("unrelated.toml"), config("other")).unwrap();
+
+        assert!(run(&mut watcher, Instant::now(), Duration::from_secs(1)).is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+//! The `which` command, which tells what a window matches without resolving it, to find out why a filter is
+//! shadowed by another one.
+
+use std::fmt::Write;
+use anyhow::Context;
+use serde::Serialize;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::Window;
+use crate::atoms::Atoms;
+use crate::oneshot::{active_window, connect};
+use crate::machine::MachineInspector;
+use crate::process::ProcessInspector;
+use crate::{Config, Filter, FilterRule, Matched, NonNullWindow, WindowIdentifier, WindowProperties};
+
+#[derive(Serialize, Debug)]
+pub struct Which {
+    pub window: Window,
+    pub class: String,
+    pub instance: String,
+    pub name: String,
+    pub role: Option<String>,
+    pub child_process: Option<String>,
+    pub flatpak_id: Option<String>,
+
+    /// Every match in evaluation order. Only the first one is used.
+    pub matches: Vec<WhichMatch>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WhichMatch {
+    pub identifier: WindowIdentifier,
+    pub by: &'static str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    pub winner: bool,
+}
+
+pub fn which(config: &Config, window: Option<NonNullWindow>) -> anyhow::Result<Which> {
+    let (connection, screen) = connect(config)?;
+
+    let window = match window {
+        Some(window) => window.get(),
+        None => active_window(&connection, connection.setup().roots[screen].root)?,
+    };
+    anyhow::ensure!(window != 0, "no window is active");
+
+    let mut processes = ProcessInspector::new("/proc", true);
+    let machines = MachineInspector::new(true);
+    let atoms = Atoms::new(&connection)?;
+    let resolved = config.resolver
+        .resolve(&connection, &atoms, &mut processes, &machines, window)
+        .context("failed to get properties of window")?;
+
+    Ok(of(config, window, resolved.properties()))
+}
+
+pub fn of(config: &Config, window: Window, properties: WindowProperties) -> Which {
+    let matches = config.resolver
+        .matches(properties)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (identifier, matched))| {
+            let (by, application) = match matched {
+                Matched::Filter(_) => ("filter", None),
+                Matched::Application(id, _) => ("application", Some(id.to_owned())),
+            };
+
+            WhichMatch { identifier, by, application, winner: index == 0 }
+        })
+        .collect();
+
+    Which {
+        window,
+        class: properties.class.to_owned(),
+        instance: properties.instance.to_owned(),
+        name: properties.name.to_owned(),
+        role: properties.role.map(str::to_owned),
+        child_process: properties.child_process.map(str::to_owned),
+        flatpak_id: properties.flatpak_id.map(str::to_owned),
+        matches,
+    }
+}
+
+pub fn human(which: &Which, config: &Config) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "window {:#x}", which.window);
+    let _ = writeln!(report, "  wm_class: {:?}", which.class);
+    let _ = writeln!(report, "  instance: {:?}", which.instance);
+    let _ = writeln!(report, "  wm_name: {:?}", which.name);
+    let _ = writeln!(report, "  role: {}", which.role.as_deref().unwrap_or("none"));
+    let _ = writeln!(report, "  child_process: {}", which.child_process.as_deref().unwrap_or("none"));
+    let _ = writeln!(report, "  flatpak_id: {}", which.flatpak_id.as_deref().unwrap_or("none"));
+    let _ = writeln!(report);
+
+    if which.matches.is_empty() {
+        let fallback = if config.resolver.global_options.is_some() { "global_options apply" } else { "WM_CLASS is kept as is" };
+        let _ = writeln!(report, "nothing matches, {fallback}");
+        return report
+    }
+
+    let _ = writeln!(report, "matches in evaluation order:");
+
+    for matched in &which.matches {
+        let mark = if matched.winner { "*" } else { " " };
+        let description = match (&matched.application, config.resolver.filters.get(&matched.identifier)) {
+            (Some(application), _) => format!("application {application:?}"),
+            (None, Some(FilterRule { method: Filter::NewName(name), options, .. })) => {
+                format!("filter, new name {name}{}", if options.is_some() { " with options" } else { "" })
+            }
+            (None, Some(FilterRule { method: Filter::Template(template), options, .. })) => {
+                format!("filter, template {template}{}", if options.is_some() { " with options" } else { "" })
+            }
+            (None, Some(FilterRule { method: Filter::Ignore, .. })) => "filter, ignored".to_owned(),
+            (None, _) => "filter, options".to_owned(),
+        };
+
+        let _ = writeln!(report, "{mark} {} ({description})", matched.identifier);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADOWING: &str = r#"
+        [resolver.filters]
+        "wm_class=firefox" = { filter = "new_name", value = "Firefox" }
+        "instance=Navigator" = { filter = "new_name", value = "Browser" }
+        "class_regex=^fire" = { filter = "ignore" }
+        "wm_name=*YouTube*" = { filter = "new_name", value = "YouTube", strip_suffixes = [" - YouTube"] }
+        "wm_class=alacritty" = { filter = "new_name", value = "Terminal" }
+    "#;
+
+    fn firefox() -> WindowProperties<'static> {
+        WindowProperties {
+            class: "firefox",
+            instance: "Navigator",
+            name: "Lo-fi beats - YouTube",
+            role: Some("browser"),
+            child_process: None,
+            flatpak_id: None,
+            client_machine: None,
+            remote: None,
+            xwayland: None,
+        }
+    }
+
+    #[test]
+    fn shadowed_filters_human() {
+        let config = Config::from_sources([SHADOWING]).unwrap();
+        let which = of(&config, 0x2a00003, firefox());
+
+        assert_eq!(human(&which, &config), "\
+window 0x2a00003
+  wm_class: \"firefox\"
+  instance: \"Navigator\"
+  wm_name: \"Lo-fi beats - YouTube\"
+  role: browser
+  child_process: none
+  flatpak_id: none
+
+matches in evaluation order:
+* instance=Navigator (filter, new name Browser)
+  wm_class=firefox (filter, new name Firefox)
+  wm_name=*YouTube* (filter, new name YouTube)
+  class_regex=^fire (filter, ignored)
+");
+    }
+
+    #[test]
+    fn shadowed_filters_json() {
+        let config = Config::from_sources([SHADOWING, r#"
+            resolver.match_order = ["name", "class"]
+            resolver.filters."wm_name=Lo-fi beats - YouTube" = { filter = "new_name", value = "Music" }
+        "#]).unwrap();
+        let which = of(&config, 0x2a00003, firefox());
+
+        assert_eq!(serde_json::to_value(&which).unwrap(), serde_json::json!({
+            "window": 0x2a00003,
+            "class": "firefox",
+            "instance": "Navigator",
+            "name": "Lo-fi beats - YouTube",
+            "role": "browser",
+            "child_process": null,
+            "flatpak_id": null,
+            "matches": [
+                { "identifier": "wm_name=Lo-fi beats - YouTube", "by": "filter", "winner": true },
+                { "identifier": "wm_class=firefox", "by": "filter", "winner": false },
+                { "identifier": "wm_name=*YouTube*", "by": "filter", "winner": false },
+                { "identifier": "class_regex=^fire", "by": "filter", "winner": false },
+            ],
+        }));
+    }
+
+    #[test]
+    fn nothing_matches() {
+        let config = Config::from_sources([SHADOWING]).unwrap();
+        let which = of(&config, 1, WindowProperties { class: "chromium", instance: "chromium", name: "New Tab", ..firefox() });
+
+        assert!(which.matches.is_empty());
+        assert!(human(&which, &config).ends_with("\nnothing matches, WM_CLASS is kept as is\n"));
+
+        let config = Config::from_sources([SHADOWING, r#"resolver.global_options = { capitalize = "all_words" }"#]).unwrap();
+        assert!(human(&which, &config).ends_with("\nnothing matches, global_options apply\n"));
+    }
+}
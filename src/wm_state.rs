@@ -0,0 +1,78 @@
+use anyhow::Context;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::intern_atom;
+
+const URGENCY_HINT: u32 = 1 << 8;
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct WmState {
+    pub skip_taskbar: bool,
+    pub fullscreen: bool,
+    pub maximized: bool,
+
+    /// Either `_NET_WM_STATE_DEMANDS_ATTENTION` or the urgency hint is set.
+    pub urgent: bool,
+}
+
+pub struct WmStateReader {
+    net_wm_state: Atom,
+    skip_taskbar: Atom,
+    fullscreen: Atom,
+    maximized_vert: Atom,
+    maximized_horz: Atom,
+    demands_attention: Atom,
+}
+
+impl WmStateReader {
+    pub fn new(connection: &RustConnection) -> anyhow::Result<Self> {
+        Ok(Self {
+            net_wm_state: intern_atom(connection, "_NET_WM_STATE")?,
+            skip_taskbar: intern_atom(connection, "_NET_WM_STATE_SKIP_TASKBAR")?,
+            fullscreen: intern_atom(connection, "_NET_WM_STATE_FULLSCREEN")?,
+            maximized_vert: intern_atom(connection, "_NET_WM_STATE_MAXIMIZED_VERT")?,
+            maximized_horz: intern_atom(connection, "_NET_WM_STATE_MAXIMIZED_HORZ")?,
+            demands_attention: intern_atom(connection, "_NET_WM_STATE_DEMANDS_ATTENTION")?,
+        })
+    }
+
+    pub fn is_state_atom(&self, atom: Atom) -> bool {
+        atom == self.net_wm_state || atom == u32::from(AtomEnum::WM_HINTS)
+    }
+
+    pub fn get(&self, connection: &RustConnection, window: Window) -> anyhow::Result<WmState> {
+        let reply = connection
+            .get_property(false, window, self.net_wm_state, AtomEnum::ATOM, 0, 64)
+            .context("failed to make GetProperty reply for retrieving _NET_WM_STATE")?
+            .reply()
+            .context("GetProperty response for retrieving _NET_WM_STATE failed")?;
+        let mut state = WmState::default();
+        let (mut maximized_vert, mut maximized_horz) = (false, false);
+
+        for atom in reply.value32().into_iter().flatten() {
+            if atom == self.skip_taskbar {
+                state.skip_taskbar = true
+            } else if atom == self.fullscreen {
+                state.fullscreen = true
+            } else if atom == self.maximized_vert {
+                maximized_vert = true
+            } else if atom == self.maximized_horz {
+                maximized_horz = true
+            } else if atom == self.demands_attention {
+                state.urgent = true
+            }
+        }
+
+        state.maximized = maximized_vert && maximized_horz;
+
+        let hints = connection
+            .get_property(false, window, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 1)
+            .context("failed to make GetProperty reply for retrieving WM_HINTS")?
+            .reply()
+            .context("GetProperty response for retrieving WM_HINTS failed")?;
+        let flags = hints.value32().and_then(|mut value| value.next()).unwrap_or_default();
+        state.urgent |= flags & URGENCY_HINT != 0;
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,65 @@
+//! Running the binary from the integration tests. Not every test uses every helper.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::{env, fs, process};
+
+/// A directory to run the module in, which is its home directory too so that nothing of the user's is picked up.
+pub struct Home {
+    path: PathBuf,
+}
+
+impl Home {
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("polybar-title-module-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn write(&self, file: &str, contents: &str) -> PathBuf {
+        let path = self.path.join(file);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn run(&self, config: &str, args: &[&str], input: &str) -> Output {
+        self.run_with_env(config, args, &[], input)
+    }
+
+    pub fn run_with_env(&self, config: &str, args: &[&str], env: &[(&str, &str)], input: &str) -> Output {
+        let config = self.write("config.toml", config);
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_polybar-title-module"))
+            .arg("--config")
+            .arg(&config)
+            .args(args)
+            .current_dir(&self.path)
+            .env("HOME", &self.path)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_STATE_HOME")
+            .env_remove("NO_COLOR")
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
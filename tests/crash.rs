@@ -0,0 +1,51 @@
+//! The line written when the module crashes, before and after the config was loaded.
+
+mod common;
+
+use common::Home;
+
+/// A config which loads fine, but can't connect to the X server.
+const NO_X_SERVER: &str = r#"
+display_name = ":4242"
+crash_template = "crashed: {{ error }}"
+
+[resolver]
+filters = {}
+"#;
+
+fn crash_line(name: &str, config: &str) -> String {
+    let output = Home::new(name).run(config, &[], "");
+    assert!(!output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn crash_before_config_is_loaded() {
+    assert_eq!(crash_line("crash-early", "template = "), "PolyBar title module crashed!\n");
+    // the config which would have made it waybar output is never loaded
+    assert_eq!(crash_line("crash-early-waybar", "output = { waybar = {} }\ntemplate = "), "PolyBar title module crashed!\n");
+}
+
+#[test]
+fn crash_after_config_is_loaded_plain() {
+    let line = crash_line("crash-plain", NO_X_SERVER);
+
+    assert!(line.starts_with("crashed: failed to establish a connection to the X server"), "{line:?}");
+    assert_eq!(line.lines().count(), 1);
+}
+
+#[test]
+fn crash_after_config_is_loaded_waybar() {
+    let line = crash_line("crash-waybar", &format!("output = {{ waybar = {{}} }}\n{NO_X_SERVER}"));
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+    assert!(json["text"].as_str().unwrap().starts_with("crashed: "), "{line:?}");
+    assert_eq!(json["class"], serde_json::json!(["crashed"]));
+}
+
+#[test]
+fn crash_without_template_waybar() {
+    let line = crash_line("crash-waybar-default", "display_name = \":4242\"\noutput = { waybar = {} }\nstyling = false\n[resolver]\nfilters = {}\n");
+
+    assert_eq!(line, "{\"text\":\"PolyBar title module crashed!\",\"class\":[]}\n");
+}
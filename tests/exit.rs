@@ -0,0 +1,46 @@
+//! The exit codes scripts tell failures apart by: 2 without an X server, 3 for an invalid config and 4 when the
+//! control socket can't be reached.
+
+mod common;
+
+use common::Home;
+
+const VALID: &str = "[resolver]\nfilters = {}\n";
+const BROKEN: &str = "template = ";
+
+/// Runs the module with `config` and `args` against an X server which doesn't exist, returning its exit code.
+fn exit_code(name: &str, config: &str, args: &[&str]) -> Option<i32> {
+    Home::new(name).run_with_env(config, args, &[("DISPLAY", ":4242")], "").status.code()
+}
+
+#[test]
+fn no_x_server() {
+    assert_eq!(exit_code("exit-no-x", VALID, &[]), Some(2));
+    assert_eq!(exit_code("exit-no-x-probe", VALID, &["--probe"]), Some(2));
+}
+
+#[test]
+fn broken_config() {
+    assert_eq!(exit_code("exit-broken", BROKEN, &[]), Some(3));
+    assert_eq!(exit_code("exit-broken-probe", BROKEN, &["--probe"]), Some(3));
+    assert_eq!(exit_code("exit-broken-msg", BROKEN, &["msg", "get"]), Some(3));
+}
+
+#[test]
+fn control_socket_unreachable() {
+    let home = Home::new("exit-unreachable");
+    let config = format!("control_socket = \"{}\"\n{VALID}", home.write("socket", "").display());
+
+    assert_eq!(home.run(&config, &["msg", "get"], "").status.code(), Some(4));
+    assert_eq!(exit_code("exit-no-socket", VALID, &["msg", "get"]), Some(3));
+}
+
+#[test]
+fn success() {
+    assert_eq!(exit_code("exit-success", &format!("backend = \"stdin\"\n{VALID}"), &[]), Some(0));
+}
+
+#[test]
+fn anything_else() {
+    assert_eq!(exit_code("exit-unknown", VALID, &["frobnicate"]), Some(1));
+}
@@ -0,0 +1,34 @@
+//! `replay`, which resolves recorded windows again with the current config.
+
+mod common;
+
+use common::{stdout, Home};
+
+const RECORDS: &str = r#"{"timestamp":1700000000000,"trigger":"_NET_ACTIVE_WINDOW","window":4194311,"class":"firefox","instance":"Navigator","name":"Mozilla Firefox","child_process":null,"output":"Firefox"}
+{"timestamp":1700000000500,"trigger":"_NET_WM_NAME","window":8388621,"class":"Alacritty","instance":"Alacritty","name":"vim","child_process":"nvim","output":"Alacritty"}
+"#;
+
+const CONFIG: &str = r#"
+[resolver.filters]
+"wm_class=firefox" = { filter = "new_name", value = "Firefox" }
+"child_process=nvim" = { filter = "new_name", value = "Neovim" }
+"#;
+
+#[test]
+fn replay_prints_every_output() {
+    let home = Home::new("replay");
+    home.write("records.jsonl", RECORDS);
+
+    assert_eq!(stdout(home.run(CONFIG, &["replay", "records.jsonl"], "")), "Firefox\nNeovim\n");
+}
+
+#[test]
+fn replay_diff_prints_changed_outputs() {
+    let home = Home::new("replay-diff");
+    home.write("records.jsonl", RECORDS);
+
+    assert_eq!(
+        stdout(home.run(CONFIG, &["replay", "records.jsonl", "--diff"], "")),
+        "0x80000d (_NET_WM_NAME): \"Alacritty\" -> \"Neovim\"\n1 of 2 records resolve differently\n",
+    );
+}
@@ -0,0 +1,84 @@
+//! Runs the binary with `backend = "stdin"`, which goes through the same filters, templates and outputs as windows
+//! from the X server do, without needing one.
+
+mod common;
+
+use common::{stdout, Home};
+
+fn run(name: &str, config: &str, input: &str) -> String {
+    stdout(Home::new(name).run(&format!("backend = \"stdin\"\n{config}"), &[], input))
+}
+
+const BETWEEN_TWO_WINDOWS: &str = r#"
+{"class": "firefox", "title": "Mozilla Firefox"}
+{"class": "conky", "title": "Conky", "skip_taskbar": true}
+{"class": "alacritty", "title": "Alacritty"}
+"#;
+
+#[test]
+fn skip_taskbar_window_keeps_previous_output() {
+    let config = "template = \"{{ name }}\"\nrespect_skip_taskbar = true\n[resolver]\nfilters = {}\n";
+    assert_eq!(run("skip-taskbar", config, BETWEEN_TWO_WINDOWS), "firefox\nalacritty\n");
+}
+
+#[test]
+fn skip_taskbar_is_displayed_unless_respected() {
+    let config = "template = \"{{ name }}\"\n[resolver]\nfilters = {}\n";
+    assert_eq!(run("no-skip-taskbar", config, BETWEEN_TWO_WINDOWS), "firefox\nconky\nalacritty\n");
+}
+
+const THROUGH_THE_DESKTOP: &str = r#"
+{"class": "firefox"}
+{"window": 0}
+{"class": "alacritty"}
+"#;
+
+fn desktop_behavior(behavior: &str) -> String {
+    format!("template = \"{{{{ name }}}}\"\n[resolver]\ndesktop_name = \"Desktop\"\ndesktop_behavior = \"{behavior}\"\nfilters = {{}}\n")
+}
+
+#[test]
+fn desktop_behavior_name() {
+    assert_eq!(run("desktop-name", &desktop_behavior("name"), THROUGH_THE_DESKTOP), "firefox\nDesktop\nalacritty\n");
+}
+
+#[test]
+fn desktop_behavior_empty() {
+    assert_eq!(run("desktop-empty", &desktop_behavior("empty"), THROUGH_THE_DESKTOP), "firefox\n\nalacritty\n");
+}
+
+#[test]
+fn desktop_behavior_keep_last() {
+    let config = desktop_behavior("keep_last");
+
+    assert_eq!(run("desktop-keep-last", &config, THROUGH_THE_DESKTOP), "firefox\nalacritty\n");
+    // there's nothing to keep yet
+    assert_eq!(run("desktop-keep-last-first", &config, "{\"window\": 0}\n{\"class\": \"firefox\"}\n"), "Desktop\nfirefox\n");
+}
+
+#[test]
+fn pipeline_matches_golden_output() {
+    let config = include_str!("fixtures/pipeline.toml");
+    let input = include_str!("fixtures/pipeline.jsonl");
+
+    assert_eq!(stdout(Home::new("pipeline").run(config, &[], input)), include_str!("fixtures/pipeline.out"));
+}
+
+#[test]
+fn malformed_lines_are_skipped_with_a_warning() {
+    let input = "{\"class\": \"firefox\"}\nnot json\n{\"title\": 1}\n{\"class\": \"alacritty\"}\n";
+    let output = Home::new("malformed").run(
+        "backend = \"stdin\"\ntemplate = \"{{ name }}\"\n[resolver]\nfilters = {}\n",
+        &["--log-level", "warn"],
+        input,
+    );
+    let warnings = String::from_utf8_lossy(&output.stderr).matches("skipping line which isn't a window").count();
+
+    assert_eq!(stdout(output), "firefox\nalacritty\n");
+    assert_eq!(warnings, 2);
+}
+
+#[test]
+fn empty_input_exits_cleanly() {
+    assert_eq!(run("empty", "template = \"{{ name }}\"\n[resolver]\nfilters = {}\n", ""), "");
+}
@@ -0,0 +1,49 @@
+//! Turning off what the module formats on its own (`styling = false`, `--plain` and `NO_COLOR`), which has to leave
+//! what the templates render untouched.
+
+mod common;
+
+use common::{stdout, Home};
+
+const WINDOW: &str = "{\"class\": \"firefox\", \"title\": \"Mozilla Firefox\"}\n";
+
+fn config(styling: bool) -> String {
+    format!(
+        r#"backend = "stdin"
+output_format = "polybar"
+styling = {styling}
+template = "%{{F#ff0000}}{{{{ name }}}}%{{F-}}"
+[action]
+left_click = "xdotool windowactivate {{{{ window }}}}"
+[resolver]
+filters = {{}}
+"#
+    )
+}
+
+const STYLED: &str = "%{A1:xdotool windowactivate 1:}%{F#ff0000}firefox%{F-}%{A}\n";
+const PLAIN: &str = "%{F#ff0000}firefox%{F-}\n";
+
+#[test]
+fn styled_by_default() {
+    assert_eq!(stdout(Home::new("styled").run(&config(true), &[], WINDOW)), STYLED);
+}
+
+#[test]
+fn styling_off_in_config() {
+    assert_eq!(stdout(Home::new("styling-off").run(&config(false), &[], WINDOW)), PLAIN);
+}
+
+#[test]
+fn plain_flag() {
+    assert_eq!(stdout(Home::new("plain-flag").run(&config(true), &["--plain"], WINDOW)), PLAIN);
+}
+
+#[test]
+fn no_color() {
+    let home = Home::new("no-color");
+
+    assert_eq!(stdout(home.run_with_env(&config(true), &[], &[("NO_COLOR", "1")], WINDOW)), PLAIN);
+    // an empty NO_COLOR doesn't count
+    assert_eq!(stdout(home.run_with_env(&config(true), &[], &[("NO_COLOR", "")], WINDOW)), STYLED);
+}